@@ -2,9 +2,12 @@
 
 //! This module defines the `IndexingStream` type, which is used internally by a pipeline  for handling asynchronous streams of `Node` items in the indexing pipeline.
 
-use crate::node::Node;
+use crate::{metadata::Metadata, node::Node};
 use anyhow::Result;
-use futures_util::stream::{self, Stream};
+use futures_util::{
+    stream::{self, Stream},
+    TryStreamExt as _,
+};
 use pin_project_lite::pin_project;
 use std::pin::Pin;
 use tokio::sync::mpsc::Receiver;
@@ -78,6 +81,7 @@ impl Into<IndexingStream> for Receiver<Result<Node>> {
 }
 
 impl IndexingStream {
+    /// Creates an `IndexingStream` that yields nothing.
     pub fn empty() -> Self {
         IndexingStream {
             inner: stream::empty().boxed(),
@@ -98,7 +102,90 @@ impl IndexingStream {
         }
     }
 
-    pub fn from_nodes(nodes: Vec<Node>) -> Self {
+    /// Creates an `IndexingStream` from an iterator of nodes, wrapping each in `Ok`.
+    pub fn from_nodes<I>(nodes: I) -> Self
+    where
+        I: IntoIterator<Item = Node> + Send + 'static,
+        <I as IntoIterator>::IntoIter: Send,
+    {
         IndexingStream::iter(nodes.into_iter().map(Ok))
     }
+
+    /// Creates an `IndexingStream` from any stream of `Result<Node>`, boxing it.
+    pub fn from_stream(stream: impl Stream<Item = Result<Node>> + Send + 'static) -> Self {
+        IndexingStream {
+            inner: stream.boxed(),
+        }
+    }
+
+    /// Stamps the given metadata onto every node in the stream.
+    ///
+    /// Used by loaders to tag the nodes they produce, for instance with their origin. Keys that
+    /// already exist on a node are overwritten.
+    #[must_use]
+    pub fn with_metadata(self, metadata: impl Into<Metadata>) -> Self {
+        let metadata = metadata.into();
+        if metadata.is_empty() {
+            return self;
+        }
+
+        IndexingStream::from_stream(self.map_ok(move |mut node| {
+            node.metadata.extend(metadata.clone());
+            node
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_empty() {
+        let nodes: Vec<Node> = IndexingStream::empty().try_collect().await.unwrap();
+        assert!(nodes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_from_nodes() {
+        let nodes: Vec<Node> = IndexingStream::from_nodes(["a", "b"].map(Node::new))
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(nodes, vec![Node::new("a"), Node::new("b")]);
+    }
+
+    #[tokio::test]
+    async fn test_iter_keeps_errors() {
+        let results = IndexingStream::iter(vec![Ok(Node::new("a")), Err(anyhow::anyhow!("oops"))])
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].as_ref().unwrap(), &Node::new("a"));
+        assert_eq!(results[1].as_ref().unwrap_err().to_string(), "oops");
+    }
+
+    #[tokio::test]
+    async fn test_from_stream() {
+        let stream = stream::iter(0..3).map(|i| Ok(Node::new(format!("chunk {i}"))));
+        let nodes: Vec<Node> = IndexingStream::from_stream(stream)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(nodes.len(), 3);
+        assert_eq!(nodes[2], Node::new("chunk 2"));
+    }
+
+    #[tokio::test]
+    async fn test_with_metadata_tags_every_node() {
+        let nodes: Vec<Node> = IndexingStream::from_nodes(["a", "b"].map(Node::new))
+            .with_metadata([("source", "wiki")])
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert!(nodes
+            .iter()
+            .all(|node| node.metadata.get("source").unwrap() == "wiki"));
+    }
 }
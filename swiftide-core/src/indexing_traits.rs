@@ -3,14 +3,14 @@
 //! All steps defined in the indexing pipeline and the generic transformers can also take a
 //! trait. To bring your own transformers, models and loaders, all you need to do is implement the
 //! trait and it should work out of the box.
-use crate::node::Node;
+use crate::node::{Image, Node};
 use crate::Embeddings;
 use crate::{
     indexing_defaults::IndexingDefaults, indexing_stream::IndexingStream, SparseEmbeddings,
 };
-use std::fmt::Debug;
+use std::{fmt::Debug, path::Path, sync::Arc};
 
-use crate::prompt::Prompt;
+use crate::prompt::{Prompt, PromptResponse, ToolCallOrText, ToolSpec};
 use anyhow::Result;
 use async_trait::async_trait;
 
@@ -89,8 +89,51 @@ where
     }
 }
 
+/// Why a stage of the pipeline dropped a node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropReason {
+    /// The node did not match a filter
+    Filtered,
+    /// The node duplicates a node that was seen or stored before
+    Deduplicated,
+    /// The node failed and the error was skipped
+    Errored,
+}
+
+/// Tracks the nodes of a source until they are stored, i.e. to acknowledge a message of a queue
+/// once all nodes loaded from it are done
+///
+/// Returned by [`Loader::node_observer`] or [`NodeCache::node_observer`], the pipeline then
+/// reports the nodes that will not be stored and the nodes that are added along the way, like
+/// chunks, to the stages after it. Storing is tracked by the observer itself, i.e. by wrapping the
+/// storage. Nodes are tracked by a key, nodes with the same key come from the same source.
+pub trait NodeObserver: Send + Sync + Debug {
+    /// The key the node is tracked by, `None` if the node is not tracked
+    fn key(&self, node: &Node) -> Option<String>;
+
+    /// A stage emitted a node with the key in addition to the node it came from, i.e. a chunk
+    ///
+    /// Called before the node it came from is released.
+    fn added(&self, key: &str);
+
+    /// A node with the key will not be stored, as it was dropped or replaced by its chunks
+    fn released(&self, key: &str);
+
+    /// A node with the key failed in a transformer and the error was skipped
+    ///
+    /// Defaults to [`NodeObserver::released`].
+    fn failed(&self, key: &str) {
+        self.released(key);
+    }
+}
+
 #[async_trait]
 /// Transforms batched single nodes into streams of nodes
+///
+/// Added to a pipeline with `then_in_batch`, which groups nodes into batches of `batch_size`
+/// (or the pipeline default) and calls `batch_transform` once per batch, like
+/// `Persist::batch_store`. Useful when work is cheaper per batch, i.e. a single embedding or
+/// LLM call for many nodes.
 pub trait BatchableTransformer: Send + Sync + DynClone {
     /// Transforms a batch of nodes into a stream of nodes
     async fn batch_transform(&self, nodes: Vec<Node>) -> IndexingStream;
@@ -109,6 +152,14 @@ pub trait BatchableTransformer: Send + Sync + DynClone {
     fn batch_size(&self) -> Option<usize> {
         None
     }
+
+    /// Why nodes missing from the output of a batch were dropped, as reported in the stats of
+    /// the pipeline
+    ///
+    /// Defaults to [`DropReason::Filtered`].
+    fn drop_reason(&self) -> DropReason {
+        DropReason::Filtered
+    }
 }
 
 dyn_clone::clone_trait_object!(BatchableTransformer);
@@ -152,6 +203,9 @@ impl BatchableTransformer for Box<dyn BatchableTransformer> {
     fn name(&self) -> &'static str {
         self.as_ref().name()
     }
+    fn drop_reason(&self) -> DropReason {
+        self.as_ref().drop_reason()
+    }
 }
 
 #[async_trait]
@@ -162,9 +216,34 @@ impl BatchableTransformer for &dyn BatchableTransformer {
     fn concurrency(&self) -> Option<usize> {
         (*self).concurrency()
     }
+    fn drop_reason(&self) -> DropReason {
+        (*self).drop_reason()
+    }
 }
 
 /// Starting point of a stream
+///
+/// Any implementer can be passed to `indexing::Pipeline::from_loader`. Loaders that need async
+/// setup, like connecting to a service, can do so lazily in the returned stream, yielding an error
+/// if it fails.
+///
+/// # Example
+///
+/// ```
+/// # use swiftide_core::{indexing::{IndexingStream, Node}, Loader};
+/// #[derive(Clone)]
+/// struct Lines(String);
+///
+/// impl Loader for Lines {
+///     fn into_stream(self) -> IndexingStream {
+///         IndexingStream::from_nodes(self.0.lines().map(Node::new).collect::<Vec<_>>())
+///     }
+///
+///     fn into_stream_boxed(self: Box<Self>) -> IndexingStream {
+///         self.into_stream()
+///     }
+/// }
+/// ```
 pub trait Loader: DynClone {
     fn into_stream(self) -> IndexingStream;
 
@@ -187,6 +266,13 @@ pub trait Loader: DynClone {
         let name = std::any::type_name::<Self>();
         name.split("::").last().unwrap_or(name)
     }
+
+    /// Observes the nodes of the loader through the pipeline, see [`NodeObserver`]
+    ///
+    /// Defaults to `None`.
+    fn node_observer(&self) -> Option<Arc<dyn NodeObserver>> {
+        None
+    }
 }
 
 dyn_clone::clone_trait_object!(Loader);
@@ -219,6 +305,9 @@ impl Loader for Box<dyn Loader> {
     fn name(&self) -> &'static str {
         self.as_ref().name()
     }
+    fn node_observer(&self) -> Option<Arc<dyn NodeObserver>> {
+        self.as_ref().node_observer()
+    }
 }
 
 impl Loader for &dyn Loader {
@@ -229,10 +318,17 @@ impl Loader for &dyn Loader {
     fn into_stream_boxed(self: Box<Self>) -> IndexingStream {
         Loader::into_stream(*self)
     }
+    fn node_observer(&self) -> Option<Arc<dyn NodeObserver>> {
+        (*self).node_observer()
+    }
 }
 
 #[async_trait]
 /// Turns one node into many nodes
+///
+/// Chunks should inherit the path and metadata of the source node, i.e. by building them with
+/// `Node { chunk, ..node.clone() }`, so that document level metadata like a title is available on
+/// every chunk. Chunk specific metadata is then added on top by later transformers.
 pub trait ChunkerTransformer: Send + Sync + Debug + DynClone {
     async fn transform_node(&self, node: Node) -> IndexingStream;
 
@@ -307,6 +403,14 @@ pub trait NodeCache: Send + Sync + Debug + DynClone {
         let name = std::any::type_name::<Self>();
         name.split("::").last().unwrap_or(name)
     }
+
+    /// Observes the nodes that pass the cache through the rest of the pipeline, see
+    /// [`NodeObserver`]
+    ///
+    /// Defaults to `None`.
+    fn node_observer(&self) -> Option<Arc<dyn NodeObserver>> {
+        None
+    }
 }
 
 dyn_clone::clone_trait_object!(NodeCache);
@@ -344,6 +448,9 @@ impl NodeCache for Box<dyn NodeCache> {
     fn name(&self) -> &'static str {
         self.as_ref().name()
     }
+    fn node_observer(&self) -> Option<Arc<dyn NodeObserver>> {
+        self.as_ref().node_observer()
+    }
 }
 
 #[async_trait]
@@ -357,6 +464,41 @@ impl NodeCache for &dyn NodeCache {
     async fn clear(&self) -> Result<()> {
         (*self).clear().await
     }
+    fn node_observer(&self) -> Option<Arc<dyn NodeObserver>> {
+        (*self).node_observer()
+    }
+}
+
+#[async_trait]
+/// Stores a checksum per file path, i.e. to skip files that did not change since they were
+/// indexed
+///
+/// Like a [`NodeCache`], failures are logged instead of returned.
+pub trait ChecksumStore: Send + Sync + Debug + DynClone {
+    /// Returns the checksum stored for the path, if any
+    async fn get(&self, path: &Path) -> Option<String>;
+    /// Stores the checksum for the path, replacing the previous one
+    async fn set(&self, path: &Path, checksum: &str);
+
+    fn name(&self) -> &'static str {
+        let name = std::any::type_name::<Self>();
+        name.split("::").last().unwrap_or(name)
+    }
+}
+
+dyn_clone::clone_trait_object!(ChecksumStore);
+
+#[async_trait]
+impl ChecksumStore for Box<dyn ChecksumStore> {
+    async fn get(&self, path: &Path) -> Option<String> {
+        self.as_ref().get(path).await
+    }
+    async fn set(&self, path: &Path, checksum: &str) {
+        self.as_ref().set(path, checksum).await;
+    }
+    fn name(&self) -> &'static str {
+        self.as_ref().name()
+    }
 }
 
 #[async_trait]
@@ -369,6 +511,13 @@ pub trait EmbeddingModel: Send + Sync + Debug + DynClone {
         let name = std::any::type_name::<Self>();
         name.split("::").last().unwrap_or(name)
     }
+
+    /// The maximum number of tokens the model accepts per input, if known
+    ///
+    /// Useful to size chunks to the model. Defaults to `None`.
+    fn max_input_tokens(&self) -> Option<usize> {
+        None
+    }
 }
 
 dyn_clone::clone_trait_object!(EmbeddingModel);
@@ -382,6 +531,7 @@ mock! {
     impl EmbeddingModel for EmbeddingModel {
         async fn embed(&self, input: Vec<String>) -> Result<Embeddings>;
         fn name(&self) -> &'static str;
+        fn max_input_tokens(&self) -> Option<usize>;
     }
 
     impl Clone for EmbeddingModel {
@@ -398,6 +548,10 @@ impl EmbeddingModel for Box<dyn EmbeddingModel> {
     fn name(&self) -> &'static str {
         self.as_ref().name()
     }
+
+    fn max_input_tokens(&self) -> Option<usize> {
+        self.as_ref().max_input_tokens()
+    }
 }
 
 #[async_trait]
@@ -405,6 +559,10 @@ impl EmbeddingModel for &dyn EmbeddingModel {
     async fn embed(&self, input: Vec<String>) -> Result<Embeddings> {
         (*self).embed(input).await
     }
+
+    fn max_input_tokens(&self) -> Option<usize> {
+        (*self).max_input_tokens()
+    }
 }
 
 #[async_trait]
@@ -461,6 +619,26 @@ pub trait SimplePrompt: Debug + Send + Sync + DynClone {
     // Takes a simple prompt, prompts the llm and returns the response
     async fn prompt(&self, prompt: Prompt) -> Result<String>;
 
+    /// Prompts the llm and returns the response with the usage and model reported by the
+    /// provider, i.e. for cost accounting
+    ///
+    /// Defaults to the text of [`SimplePrompt::prompt`], without usage or model.
+    async fn prompt_detailed(&self, prompt: Prompt) -> Result<PromptResponse> {
+        self.prompt(prompt).await.map(PromptResponse::from)
+    }
+
+    /// Prompts the llm with tools it can call, returning either a tool call with the parsed
+    /// arguments or the text if the llm answered without calling a tool
+    ///
+    /// Only supported by providers with function calling. Defaults to an error.
+    async fn prompt_with_tools(
+        &self,
+        _prompt: Prompt,
+        _tools: Vec<ToolSpec>,
+    ) -> Result<ToolCallOrText> {
+        anyhow::bail!("Tool calling is not supported by {}", self.name())
+    }
+
     fn name(&self) -> &'static str {
         let name = std::any::type_name::<Self>();
         name.split("::").last().unwrap_or(name)
@@ -491,6 +669,18 @@ impl SimplePrompt for Box<dyn SimplePrompt> {
         self.as_ref().prompt(prompt).await
     }
 
+    async fn prompt_detailed(&self, prompt: Prompt) -> Result<PromptResponse> {
+        self.as_ref().prompt_detailed(prompt).await
+    }
+
+    async fn prompt_with_tools(
+        &self,
+        prompt: Prompt,
+        tools: Vec<ToolSpec>,
+    ) -> Result<ToolCallOrText> {
+        self.as_ref().prompt_with_tools(prompt, tools).await
+    }
+
     fn name(&self) -> &'static str {
         self.as_ref().name()
     }
@@ -501,6 +691,66 @@ impl SimplePrompt for &dyn SimplePrompt {
     async fn prompt(&self, prompt: Prompt) -> Result<String> {
         (*self).prompt(prompt).await
     }
+
+    async fn prompt_detailed(&self, prompt: Prompt) -> Result<PromptResponse> {
+        (*self).prompt_detailed(prompt).await
+    }
+
+    async fn prompt_with_tools(
+        &self,
+        prompt: Prompt,
+        tools: Vec<ToolSpec>,
+    ) -> Result<ToolCallOrText> {
+        (*self).prompt_with_tools(prompt, tools).await
+    }
+}
+
+#[async_trait]
+/// Given a prompt and an image, queries a multimodal LLM
+pub trait MultimodalPrompt: Debug + Send + Sync + DynClone {
+    /// Prompts the llm with the image attached and returns the response
+    async fn prompt_with_image(&self, prompt: Prompt, image: &Image) -> Result<String>;
+
+    fn name(&self) -> &'static str {
+        let name = std::any::type_name::<Self>();
+        name.split("::").last().unwrap_or(name)
+    }
+}
+
+dyn_clone::clone_trait_object!(MultimodalPrompt);
+
+#[cfg(feature = "test-utils")]
+mock! {
+    #[derive(Debug)]
+    pub MultimodalPrompt {}
+
+    #[async_trait]
+    impl MultimodalPrompt for MultimodalPrompt {
+        async fn prompt_with_image(&self, prompt: Prompt, image: &Image) -> Result<String>;
+        fn name(&self) -> &'static str;
+    }
+
+    impl Clone for MultimodalPrompt {
+        fn clone(&self) -> Self;
+    }
+}
+
+#[async_trait]
+impl MultimodalPrompt for Box<dyn MultimodalPrompt> {
+    async fn prompt_with_image(&self, prompt: Prompt, image: &Image) -> Result<String> {
+        self.as_ref().prompt_with_image(prompt, image).await
+    }
+
+    fn name(&self) -> &'static str {
+        self.as_ref().name()
+    }
+}
+
+#[async_trait]
+impl MultimodalPrompt for &dyn MultimodalPrompt {
+    async fn prompt_with_image(&self, prompt: Prompt, image: &Image) -> Result<String> {
+        (*self).prompt_with_image(prompt, image).await
+    }
 }
 
 #[async_trait]
@@ -513,6 +763,46 @@ pub trait Persist: Debug + Send + Sync + DynClone {
         None
     }
 
+    /// Optionally caps batches by the total size of the nodes serialized as JSON, in bytes
+    ///
+    /// Batches of `batch_size` nodes exceeding the cap are split before storing. A single node
+    /// larger than the cap is stored in a batch on its own.
+    fn batch_max_bytes(&self) -> Option<usize> {
+        None
+    }
+
+    /// Returns the number of nodes in the storage
+    ///
+    /// Errors by default, as not every storage supports counting.
+    async fn count(&self) -> Result<u64> {
+        anyhow::bail!("Counting nodes is not supported by {}", self.name())
+    }
+
+    /// Streams all nodes in the storage, i.e. to re-index them with a different model
+    ///
+    /// Errors by default, as not every storage supports iterating over its nodes.
+    async fn stream_all(&self) -> IndexingStream {
+        anyhow::anyhow!("Streaming all nodes is not supported by {}", self.name()).into()
+    }
+
+    /// Returns for every node whether it is already stored, i.e. to skip nodes indexed in an
+    /// earlier run
+    ///
+    /// Errors by default, as not every storage supports looking up nodes.
+    async fn exists(&self, _nodes: &[Node]) -> Result<Vec<bool>> {
+        anyhow::bail!(
+            "Checking for existing nodes is not supported by {}",
+            self.name()
+        )
+    }
+
+    /// Flushes buffered writes, if any
+    ///
+    /// Called by the indexing pipeline once all nodes are processed. Does nothing by default.
+    async fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
     fn name(&self) -> &'static str {
         let name = std::any::type_name::<Self>();
         name.split("::").last().unwrap_or(name)
@@ -532,6 +822,10 @@ mock! {
         async fn store(&self, node: Node) -> Result<Node>;
         async fn batch_store(&self, nodes: Vec<Node>) -> IndexingStream;
         fn batch_size(&self) -> Option<usize>;
+        fn batch_max_bytes(&self) -> Option<usize>;
+        async fn count(&self) -> Result<u64>;
+        async fn stream_all(&self) -> IndexingStream;
+        async fn exists(&self, nodes: &[Node]) -> Result<Vec<bool>>;
 
         fn name(&self) -> &'static str;
     }
@@ -555,6 +849,21 @@ impl Persist for Box<dyn Persist> {
     fn batch_size(&self) -> Option<usize> {
         self.as_ref().batch_size()
     }
+    fn batch_max_bytes(&self) -> Option<usize> {
+        self.as_ref().batch_max_bytes()
+    }
+    async fn count(&self) -> Result<u64> {
+        self.as_ref().count().await
+    }
+    async fn stream_all(&self) -> IndexingStream {
+        self.as_ref().stream_all().await
+    }
+    async fn exists(&self, nodes: &[Node]) -> Result<Vec<bool>> {
+        self.as_ref().exists(nodes).await
+    }
+    async fn flush(&self) -> Result<()> {
+        self.as_ref().flush().await
+    }
     fn name(&self) -> &'static str {
         self.as_ref().name()
     }
@@ -574,6 +883,21 @@ impl Persist for &dyn Persist {
     fn batch_size(&self) -> Option<usize> {
         (*self).batch_size()
     }
+    fn batch_max_bytes(&self) -> Option<usize> {
+        (*self).batch_max_bytes()
+    }
+    async fn count(&self) -> Result<u64> {
+        (*self).count().await
+    }
+    async fn stream_all(&self) -> IndexingStream {
+        (*self).stream_all().await
+    }
+    async fn exists(&self, nodes: &[Node]) -> Result<Vec<bool>> {
+        (*self).exists(nodes).await
+    }
+    async fn flush(&self) -> Result<()> {
+        (*self).flush().await
+    }
 }
 
 /// Allows for passing defaults from the pipeline to the transformer
@@ -9,6 +9,7 @@ mod query_stream;
 pub mod query_traits;
 mod search_strategies;
 pub mod type_aliases;
+mod write_mode;
 
 pub mod prompt;
 pub use type_aliases::*;
@@ -26,6 +27,7 @@ pub mod indexing {
     pub use crate::indexing_traits::*;
     pub use crate::metadata::*;
     pub use crate::node::*;
+    pub use crate::write_mode::WriteMode;
 }
 
 pub mod querying {
@@ -3,7 +3,7 @@
 //! Typically metadata is used to extract or generate additional information about the node
 //!
 //! Internally it uses a `BTreeMap` to store the key-value pairs, to ensure the data is sorted.
-use std::collections::{btree_map::IntoValues, BTreeMap};
+use std::collections::{btree_map::IntoValues, BTreeMap, HashMap};
 
 use serde::Deserializer;
 
@@ -50,6 +50,18 @@ impl Metadata {
         self.inner.get(key.as_ref())
     }
 
+    pub fn get_mut(&mut self, key: impl AsRef<str>) -> Option<&mut serde_json::Value> {
+        self.inner.get_mut(key.as_ref())
+    }
+
+    pub fn remove(&mut self, key: impl AsRef<str>) -> Option<serde_json::Value> {
+        self.inner.remove(key.as_ref())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
     pub fn into_values(self) -> IntoValues<String, serde_json::Value> {
         self.inner.into_values()
     }
@@ -110,6 +122,20 @@ where
     }
 }
 
+impl<K, V> From<HashMap<K, V>> for Metadata
+where
+    K: Into<String>,
+    V: Into<serde_json::Value>,
+{
+    fn from(items: HashMap<K, V>) -> Self {
+        let inner = items
+            .into_iter()
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect();
+        Metadata { inner }
+    }
+}
+
 impl<K: Ord, V, const N: usize> From<[(K, V); N]> for Metadata
 where
     K: Ord + Into<String>,
@@ -183,6 +209,15 @@ mod tests {
         assert_eq!(iter.next(), None);
     }
 
+    #[test]
+    fn test_get_mut_and_remove() {
+        let mut metadata = Metadata::from([("key", "value")]);
+
+        *metadata.get_mut("key").unwrap() = json!("changed");
+        assert_eq!(metadata.remove("key"), Some(json!("changed")));
+        assert_eq!(metadata.get("key"), None);
+    }
+
     #[test]
     fn test_extend() {
         let mut metadata = Metadata::default();
@@ -22,9 +22,12 @@ use std::{
     fmt::Debug,
     hash::{Hash, Hasher},
     os::unix::ffi::OsStrExt,
-    path::PathBuf,
+    path::{Path, PathBuf},
+    str::FromStr,
 };
 
+use anyhow::{Context as _, Result};
+use base64::{prelude::BASE64_STANDARD, Engine as _};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 
@@ -55,6 +58,14 @@ pub struct Node {
     pub original_size: usize,
     /// Offset of the chunk relative to the start of the input this node was originally derived from in bytes
     pub offset: usize,
+    /// Index of the chunk within the node it was chunked from, set when chunking in a pipeline
+    ///
+    /// Chunking a node again replaces it with the index within the new parent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk_index: Option<usize>,
+    /// Optional image the node carries, i.e. to caption with a multimodal model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<Image>,
 }
 
 impl Debug for Node {
@@ -93,6 +104,8 @@ impl Debug for Node {
                     .join(","),
             )
             .field("embed_mode", &self.embed_mode)
+            .field("chunk_index", &self.chunk_index)
+            .field("image", &self.image)
             .finish()
     }
 }
@@ -132,6 +145,11 @@ impl Node {
         self
     }
 
+    pub fn with_image(&mut self, image: impl Into<Image>) -> &mut Self {
+        self.image = Some(image.into());
+        self
+    }
+
     /// Creates embeddable data depending on chosen `EmbedMode`.
     ///
     /// # Returns
@@ -184,6 +202,32 @@ impl Node {
         format!("{}\n{}", metadata, self.chunk)
     }
 
+    /// Combines the chunk with the named metadata fields into a single string.
+    ///
+    /// The fields are formatted as key-value pairs, each on a new line and in the given order,
+    /// followed by the chunk. Missing fields are skipped.
+    ///
+    /// Useful to embed the chunk together with, for instance, its title or headings.
+    pub fn combined_text(&self, fields: &[&str]) -> String {
+        let metadata = fields
+            .iter()
+            .filter_map(|field| {
+                let value = self.metadata.get(field)?;
+                let value = value
+                    .as_str()
+                    .map_or_else(|| value.to_string(), ToString::to_string);
+
+                Some(format!("{field}: {value}"))
+            })
+            .collect::<Vec<String>>();
+
+        if metadata.is_empty() {
+            return self.chunk.clone();
+        }
+
+        format!("{}\n{}", metadata.join("\n"), self.chunk)
+    }
+
     /// Retrieve the identifier of the node.
     ///
     /// Calculates the identifier of the node based on its path and chunk as bytes, returning a
@@ -205,6 +249,28 @@ impl Node {
         self.id = None;
         self.id = Some(self.id());
     }
+
+    /// Returns the identifier of the node composed as configured, i.e. for storages that key
+    /// nodes by a string
+    ///
+    /// See [`ChunkIdFormat`] for the formats.
+    pub fn chunk_id(&self, format: ChunkIdFormat) -> String {
+        match format {
+            ChunkIdFormat::Uuid => self.id().to_string(),
+            ChunkIdFormat::PathAndIndex => match self.metadata.get(FILE_WINDOW) {
+                Some(window) => format!(
+                    "{}#{window}.{}",
+                    self.path.to_string_lossy(),
+                    self.chunk_index.unwrap_or_default()
+                ),
+                None => format!(
+                    "{}#{}",
+                    self.path.to_string_lossy(),
+                    self.chunk_index.unwrap_or_default()
+                ),
+            },
+        }
+    }
 }
 
 impl Hash for Node {
@@ -223,6 +289,97 @@ impl<T: Into<String>> From<T> for Node {
     }
 }
 
+/// Image data carried by a [`Node`]
+///
+/// Either a path to read the image from when it is needed, or the base64 encoded image itself.
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Image {
+    /// Path to an image file, read when the image is needed
+    Path(PathBuf),
+    /// Base64 encoded image with its mime type, i.e. `image/png`
+    Base64 { mime_type: String, data: String },
+}
+
+impl Image {
+    /// Creates a base64 image from raw bytes
+    pub fn from_bytes(mime_type: impl Into<String>, bytes: impl AsRef<[u8]>) -> Self {
+        Image::Base64 {
+            mime_type: mime_type.into(),
+            data: BASE64_STANDARD.encode(bytes),
+        }
+    }
+
+    /// Returns the mime type and the base64 encoded image, reading it from disk if needed
+    ///
+    /// # Errors
+    ///
+    /// Errors if the file cannot be read or its extension is not a known image format.
+    pub async fn to_base64(&self) -> Result<(String, String)> {
+        match self {
+            Image::Base64 { mime_type, data } => Ok((mime_type.clone(), data.clone())),
+            Image::Path(path) => {
+                let mime_type = mime_type_of(path)?;
+                let bytes = tokio::fs::read(path)
+                    .await
+                    .with_context(|| format!("Failed to read image {}", path.display()))?;
+                Ok((mime_type.to_string(), BASE64_STANDARD.encode(bytes)))
+            }
+        }
+    }
+
+    /// Returns the image as a data url, i.e. `data:image/png;base64,...`
+    ///
+    /// # Errors
+    ///
+    /// Errors if the image cannot be read, see [`Image::to_base64`].
+    pub async fn to_data_url(&self) -> Result<String> {
+        let (mime_type, data) = self.to_base64().await?;
+        Ok(format!("data:{mime_type};base64,{data}"))
+    }
+}
+
+fn mime_type_of(path: &Path) -> Result<&'static str> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_lowercase);
+
+    match extension.as_deref() {
+        Some("png") => Ok("image/png"),
+        Some("jpg" | "jpeg") => Ok("image/jpeg"),
+        Some("gif") => Ok("image/gif"),
+        Some("webp") => Ok("image/webp"),
+        _ => anyhow::bail!("Unknown image format of {}", path.display()),
+    }
+}
+
+impl From<PathBuf> for Image {
+    fn from(path: PathBuf) -> Self {
+        Image::Path(path)
+    }
+}
+
+impl From<&Path> for Image {
+    fn from(path: &Path) -> Self {
+        Image::Path(path.to_path_buf())
+    }
+}
+
+impl Debug for Image {
+    /// Formats the image with the length of the data instead of the data itself
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Image::Path(path) => f.debug_tuple("Path").field(path).finish(),
+            Image::Base64 { mime_type, data } => f
+                .debug_struct("Base64")
+                .field("mime_type", mime_type)
+                .field("data", &format!("{} bytes", data.len()))
+                .finish(),
+        }
+    }
+}
+
 /// Embed mode of the pipeline.
 #[derive(Copy, Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
 pub enum EmbedMode {
@@ -235,10 +392,31 @@ pub enum EmbedMode {
     Both,
 }
 
+/// Metadata key of the index of the window of a file a node was read from, set by loaders that
+/// read files in windows
+pub const FILE_WINDOW: &str = "file_window";
+
+/// How the identifier of a node is composed, see [`Node::chunk_id`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChunkIdFormat {
+    /// The uuid of the node, derived from its path and chunk
+    #[default]
+    Uuid,
+    /// The path and the chunk index of the node, i.e. `docs/intro.md#3`, for human readable
+    /// citations
+    ///
+    /// Nodes that were not chunked use index 0. As chunking again restarts the index, ids are
+    /// only unique if each path is chunked in a single step. Nodes read from a window of a file
+    /// also carry the [`FILE_WINDOW`] index, i.e. `logs/app.log#2.3` for the fourth chunk of the
+    /// third window.
+    PathAndIndex,
+}
+
 /// Type of Embeddable stored in model.
-#[derive(
-    Clone, Default, Serialize, Deserialize, PartialEq, Eq, Hash, strum_macros::Display, Debug,
-)]
+///
+/// Serializes as its string representation (i.e. `Metadata: title`), so that it can be used as
+/// a key in maps for any serde format.
+#[derive(Clone, Default, PartialEq, Eq, Hash, strum_macros::Display, Debug)]
 pub enum EmbeddedField {
     #[default]
     /// Embeddable created from Chunk of data combined with Metadata.
@@ -270,6 +448,34 @@ impl Into<String> for EmbeddedField {
     }
 }
 
+impl FromStr for EmbeddedField {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Combined" => Ok(EmbeddedField::Combined),
+            "Chunk" => Ok(EmbeddedField::Chunk),
+            _ => s
+                .strip_prefix("Metadata: ")
+                .map(|name| EmbeddedField::Metadata(name.to_string()))
+                .ok_or_else(|| anyhow::anyhow!("Invalid embedded field: {s}")),
+        }
+    }
+}
+
+impl Serialize for EmbeddedField {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for EmbeddedField {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(serde::de::Error::custom)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -283,6 +489,87 @@ mod tests {
         assert_eq!(embedded_field.sparse_field_name(), expected[1]);
     }
 
+    #[test]
+    fn test_chunk_id_formats() {
+        let mut node = Node::new("chunk");
+        node.path = "docs/intro.md".into();
+        node.chunk_index = Some(3);
+
+        assert_eq!(
+            node.chunk_id(ChunkIdFormat::PathAndIndex),
+            "docs/intro.md#3"
+        );
+        assert_eq!(node.chunk_id(ChunkIdFormat::Uuid), node.id().to_string());
+
+        node.chunk_index = None;
+        assert_eq!(
+            node.chunk_id(ChunkIdFormat::PathAndIndex),
+            "docs/intro.md#0"
+        );
+
+        node.chunk_index = Some(3);
+        node.metadata.insert(FILE_WINDOW, 2);
+        assert_eq!(
+            node.chunk_id(ChunkIdFormat::PathAndIndex),
+            "docs/intro.md#2.3"
+        );
+    }
+
+    #[test_case("Combined", &EmbeddedField::Combined)]
+    #[test_case("Chunk", &EmbeddedField::Chunk)]
+    #[test_case("Metadata: test", &EmbeddedField::Metadata("test".into()))]
+    fn embedded_field_from_str_tests(input: &str, expected: &EmbeddedField) {
+        assert_eq!(&input.parse::<EmbeddedField>().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_embedded_field_from_invalid_str() {
+        assert!("Unknown".parse::<EmbeddedField>().is_err());
+    }
+
+    #[test]
+    fn test_serializing_node_with_multiple_embedded_fields() {
+        let mut node = Node::new("chunk");
+        node.with_metadata(("title", "Title"))
+            .with_vectors([
+                (EmbeddedField::Combined, vec![1.0]),
+                (EmbeddedField::Chunk, vec![2.0]),
+                (EmbeddedField::Metadata("title".into()), vec![3.0]),
+            ])
+            .with_sparse_vectors([(
+                EmbeddedField::Metadata("title".into()),
+                SparseEmbedding {
+                    indices: vec![1],
+                    values: vec![4.0],
+                },
+            )]);
+
+        let serialized = serde_json::to_value(&node).unwrap();
+        assert_eq!(
+            serialized["vectors"]["Metadata: title"],
+            serde_json::json!([3.0])
+        );
+
+        let deserialized: Node = serde_json::from_value(serialized).unwrap();
+        assert_eq!(deserialized, node);
+    }
+
+    #[test]
+    fn test_combined_text() {
+        let mut node = Node::new("chunk");
+        node.with_metadata([
+            ("title", "Title"),
+            ("heading", "Heading"),
+            ("other", "Other"),
+        ]);
+
+        assert_eq!(
+            node.combined_text(&["title", "heading", "missing"]),
+            "title: Title\nheading: Heading\nchunk"
+        );
+        assert_eq!(node.combined_text(&[]), "chunk");
+    }
+
     #[test]
     fn test_debugging_node_with_utf8_char_boundary() {
         let node = Node::new("🦀".repeat(101));
@@ -244,6 +244,106 @@ impl From<String> for Prompt {
     }
 }
 
+/// The response to a prompt, with the metadata reported by the provider
+///
+/// Returned by [`SimplePrompt::prompt_detailed`][crate::SimplePrompt::prompt_detailed]. Usage and
+/// model are `None` if the provider does not report them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PromptResponse {
+    /// The text of the response
+    pub text: String,
+    /// The tokens used by the prompt and the response
+    pub usage: Option<Usage>,
+    /// The model that answered
+    pub model: Option<String>,
+}
+
+impl From<String> for PromptResponse {
+    fn from(text: String) -> Self {
+        PromptResponse {
+            text,
+            ..Default::default()
+        }
+    }
+}
+
+/// Tokens used by a prompt
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// Tokens in the prompt
+    pub prompt_tokens: u32,
+    /// Tokens in the response
+    pub completion_tokens: u32,
+    /// Tokens in total
+    pub total_tokens: u32,
+}
+
+impl Usage {
+    /// Creates a `Usage`, with the total being the sum of prompt and completion tokens
+    pub fn new(prompt_tokens: u32, completion_tokens: u32) -> Self {
+        Usage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+        }
+    }
+}
+
+/// A tool the llm can call, described by a JSON schema of its arguments
+///
+/// Passed to [`SimplePrompt::prompt_with_tools`][crate::SimplePrompt::prompt_with_tools].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolSpec {
+    /// The name the llm calls the tool by
+    pub name: String,
+    /// What the tool does, so the llm knows when to call it
+    pub description: String,
+    /// The JSON schema of the arguments, an object schema
+    pub parameters: serde_json::Value,
+}
+
+impl ToolSpec {
+    pub fn new(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        parameters: serde_json::Value,
+    ) -> Self {
+        ToolSpec {
+            name: name.into(),
+            description: description.into(),
+            parameters,
+        }
+    }
+}
+
+/// A call of a [`ToolSpec`] by the llm, with the parsed arguments
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCall {
+    /// The name of the tool called
+    pub name: String,
+    /// The arguments, as JSON
+    pub arguments: serde_json::Value,
+}
+
+impl ToolCall {
+    /// Deserializes the arguments into `T`
+    ///
+    /// # Errors
+    ///
+    /// Errors if the arguments do not match `T`
+    pub fn arguments_as<T: serde::de::DeserializeOwned>(&self) -> Result<T> {
+        serde_json::from_value(self.arguments.clone())
+            .with_context(|| format!("Invalid arguments for tool {}", self.name))
+    }
+}
+
+/// The answer to a prompt with tools, either a tool call or text
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolCallOrText {
+    ToolCall(ToolCall),
+    Text(String),
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -0,0 +1,183 @@
+use std::{collections::HashMap, hash::Hash};
+
+/// Default `k` for reciprocal rank fusion, as proposed in the original paper
+pub const DEFAULT_RRF_K: f32 = 60.0;
+
+/// How dense and sparse results are combined by [`fuse_scores`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fusion {
+    /// Reciprocal rank fusion, scoring each id with `1 / (k + rank)` per list it appears in
+    ///
+    /// Only the ranks are used, so the scores of both lists do not need to be comparable.
+    ReciprocalRank { k: f32 },
+    /// A weighted sum of the min-max normalized scores of both lists
+    ///
+    /// An id missing from a list contributes nothing for that list.
+    Weighted { dense: f32, sparse: f32 },
+}
+
+impl Default for Fusion {
+    fn default() -> Self {
+        Fusion::ReciprocalRank { k: DEFAULT_RRF_K }
+    }
+}
+
+/// Combines the results of a dense and a sparse search into a single ranked list
+///
+/// Returns every id from either list with its fused score, best first. Ties keep the order in
+/// which the ids were first seen, dense results first.
+///
+/// # Example
+///
+/// ```
+/// # use swiftide_core::querying::search_strategies::{fuse_scores, Fusion};
+/// let dense = [("a", 0.9), ("b", 0.8)];
+/// let sparse = [("b", 12.0), ("c", 3.0)];
+///
+/// let fused = fuse_scores(&dense, &sparse, Fusion::default());
+///
+/// assert_eq!(fused[0].0, "b");
+/// ```
+pub fn fuse_scores<Id: Clone + Eq + Hash>(
+    dense: &[(Id, f32)],
+    sparse: &[(Id, f32)],
+    method: Fusion,
+) -> Vec<(Id, f32)> {
+    let mut order: Vec<Id> = Vec::new();
+    let mut fused: HashMap<Id, f32> = HashMap::new();
+
+    let mut add = |id: &Id, score: f32| {
+        if let Some(total) = fused.get_mut(id) {
+            *total += score;
+        } else {
+            order.push(id.clone());
+            fused.insert(id.clone(), score);
+        }
+    };
+
+    match method {
+        Fusion::ReciprocalRank { k } => {
+            for results in [dense, sparse] {
+                for (rank, (id, _)) in ranked(results).into_iter().enumerate() {
+                    #[allow(clippy::cast_precision_loss)]
+                    add(id, 1.0 / (k + rank as f32 + 1.0));
+                }
+            }
+        }
+        Fusion::Weighted {
+            dense: dense_weight,
+            sparse: sparse_weight,
+        } => {
+            for (results, weight) in [(dense, dense_weight), (sparse, sparse_weight)] {
+                for (id, score) in normalized(results) {
+                    add(id, weight * score);
+                }
+            }
+        }
+    }
+
+    let mut results = order
+        .into_iter()
+        .map(|id| {
+            let score = fused[&id];
+            (id, score)
+        })
+        .collect::<Vec<_>>();
+    results.sort_by(|a, b| b.1.total_cmp(&a.1));
+    results
+}
+
+/// Sorts results by score, best first
+fn ranked<Id>(results: &[(Id, f32)]) -> Vec<&(Id, f32)> {
+    let mut ranked = results.iter().collect::<Vec<_>>();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked
+}
+
+/// Scales scores to `0..=1`, giving every result a score of 1 if all scores are equal
+fn normalized<Id>(results: &[(Id, f32)]) -> impl Iterator<Item = (&Id, f32)> {
+    let min = results
+        .iter()
+        .map(|(_, s)| *s)
+        .fold(f32::INFINITY, f32::min);
+    let max = results
+        .iter()
+        .map(|(_, s)| *s)
+        .fold(f32::NEG_INFINITY, f32::max);
+    let range = max - min;
+
+    results.iter().map(move |(id, score)| {
+        if range > 0.0 {
+            (id, (score - min) / range)
+        } else {
+            (id, 1.0)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(results: &[(&'static str, f32)]) -> Vec<&'static str> {
+        results.iter().map(|(id, _)| *id).collect()
+    }
+
+    #[test]
+    fn test_rrf_rewards_overlap() {
+        let dense = [("a", 0.9), ("b", 0.8), ("c", 0.7)];
+        let sparse = [("b", 20.0), ("d", 10.0), ("c", 5.0)];
+
+        let fused = fuse_scores(&dense, &sparse, Fusion::ReciprocalRank { k: 60.0 });
+
+        assert_eq!(ids(&fused), ["b", "c", "a", "d"]);
+        assert!((fused[0].1 - (1.0 / 62.0 + 1.0 / 61.0)).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn test_rrf_disjoint_interleaves() {
+        let dense = [("a", 0.9), ("b", 0.8)];
+        let sparse = [("c", 20.0), ("d", 10.0)];
+
+        let fused = fuse_scores(&dense, &sparse, Fusion::default());
+
+        assert_eq!(ids(&fused), ["a", "c", "b", "d"]);
+    }
+
+    #[test]
+    fn test_weighted_overlapping() {
+        let dense = [("a", 0.9), ("b", 0.5), ("c", 0.1)];
+        let sparse = [("c", 30.0), ("b", 20.0), ("a", 10.0)];
+
+        let fused = fuse_scores(
+            &dense,
+            &sparse,
+            Fusion::Weighted {
+                dense: 0.7,
+                sparse: 0.3,
+            },
+        );
+
+        assert_eq!(ids(&fused), ["a", "b", "c"]);
+        assert!((fused[0].1 - 0.7).abs() < 1e-6);
+        assert!((fused[1].1 - 0.5).abs() < 1e-6);
+        assert!((fused[2].1 - 0.3).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_weighted_disjoint() {
+        let dense = [("a", 0.9), ("b", 0.1)];
+        let sparse = [("c", 30.0), ("d", 10.0)];
+
+        let fused = fuse_scores(
+            &dense,
+            &sparse,
+            Fusion::Weighted {
+                dense: 0.4,
+                sparse: 0.6,
+            },
+        );
+
+        assert_eq!(ids(&fused), ["c", "a", "b", "d"]);
+    }
+}
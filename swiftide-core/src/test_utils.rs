@@ -1,3 +1,15 @@
+//! Utilities for testing transformers and pipelines
+#![allow(clippy::missing_panics_doc)]
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+
+use crate::{prompt::Prompt, SimplePrompt};
+
 #[macro_export]
 macro_rules! assert_default_prompt_snapshot {
     ($node:expr, $($key:expr => $value:expr),*) => {
@@ -24,3 +36,102 @@ macro_rules! assert_default_prompt_snapshot {
         }
     };
 }
+
+type RespondFn = dyn Fn(&str) -> Result<String> + Send + Sync;
+
+/// A fake LLM for testing transformers, returning canned responses
+///
+/// Unlike the mockall based [`crate::MockSimplePrompt`] no expectations need to be set up.
+/// Every rendered prompt is recorded and can be inspected with
+/// [`FakeSimplePrompt::received_prompts`]. Clones share their responses and recorded prompts.
+///
+/// # Example
+///
+/// ```
+/// # use swiftide_core::{test_utils::FakeSimplePrompt, SimplePrompt};
+/// # async fn test() {
+/// let llm = FakeSimplePrompt::with_responses(["first", "second"]);
+///
+/// assert_eq!(llm.prompt("Hello".into()).await.unwrap(), "first");
+/// assert_eq!(llm.received_prompts(), ["Hello"]);
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct FakeSimplePrompt {
+    respond: Arc<RespondFn>,
+    received: Arc<Mutex<Vec<String>>>,
+}
+
+impl FakeSimplePrompt {
+    /// Returns the responses in order, erroring once they run out
+    pub fn with_responses(responses: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        let responses = Mutex::new(
+            responses
+                .into_iter()
+                .map(Into::into)
+                .collect::<VecDeque<String>>(),
+        );
+
+        Self::with_fn(move |_| {
+            responses
+                .lock()
+                .unwrap()
+                .pop_front()
+                .context("FakeSimplePrompt ran out of responses")
+        })
+    }
+
+    /// Responds with the result of a closure on the rendered prompt
+    pub fn with_fn(respond: impl Fn(&str) -> Result<String> + Send + Sync + 'static) -> Self {
+        Self {
+            respond: Arc::new(respond),
+            received: Arc::default(),
+        }
+    }
+
+    /// Returns the rendered prompts received so far, in order
+    pub fn received_prompts(&self) -> Vec<String> {
+        self.received.lock().unwrap().clone()
+    }
+}
+
+impl std::fmt::Debug for FakeSimplePrompt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FakeSimplePrompt")
+            .field("received", &self.received)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl SimplePrompt for FakeSimplePrompt {
+    async fn prompt(&self, prompt: Prompt) -> Result<String> {
+        let rendered = prompt.render().await?;
+        self.received.lock().unwrap().push(rendered.clone());
+
+        (self.respond)(&rendered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fake_returns_responses_in_order() {
+        let llm = FakeSimplePrompt::with_responses(["first", "second"]);
+
+        assert_eq!(llm.prompt("one".into()).await.unwrap(), "first");
+        assert_eq!(llm.clone().prompt("two".into()).await.unwrap(), "second");
+        assert!(llm.prompt("three".into()).await.is_err());
+        assert_eq!(llm.received_prompts(), ["one", "two", "three"]);
+    }
+
+    #[tokio::test]
+    async fn test_fake_responds_with_closure() {
+        let llm = FakeSimplePrompt::with_fn(|prompt| Ok(prompt.to_uppercase()));
+
+        assert_eq!(llm.prompt("hello".into()).await.unwrap(), "HELLO");
+        assert_eq!(llm.received_prompts(), ["hello"]);
+    }
+}
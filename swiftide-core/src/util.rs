@@ -23,6 +23,86 @@ pub fn debug_long_utf8(s: impl AsRef<str>, max_chars: usize) -> String {
     format!("{} ({})", trunc, s.as_ref().chars().count())
 }
 
+/// Replacement for redacted values in logs
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Keys whose values are redacted, compared case insensitively with `-` read as `_`
+const SECRET_KEYS: &[&str] = &[
+    "api_key",
+    "apikey",
+    "x_api_key",
+    "authorization",
+    "proxy_authorization",
+    "password",
+    "secret",
+    "client_secret",
+    "token",
+    "access_token",
+    "refresh_token",
+    "session_token",
+    "aws_secret_access_key",
+    "aws_session_token",
+];
+
+/// Returns true if a JSON key holds a secret, like `api_key` or `Authorization`
+fn is_secret_key(key: &str) -> bool {
+    let key = key.to_lowercase().replace('-', "_");
+
+    SECRET_KEYS.contains(&key.as_str())
+}
+
+/// Replaces the values of known secret keys with [`REDACTED`], recursively
+///
+/// # Example
+///
+/// ```
+/// # use swiftide_core::util::redact_secrets;
+/// let mut value = serde_json::json!({"model": "gpt-4o", "api_key": "sk-123"});
+/// redact_secrets(&mut value);
+///
+/// assert_eq!(value, serde_json::json!({"model": "gpt-4o", "api_key": "[REDACTED]"}));
+/// ```
+pub fn redact_secrets(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if is_secret_key(key) {
+                    *value = REDACTED.into();
+                } else {
+                    redact_secrets(value);
+                }
+            }
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(redact_secrets),
+        _ => {}
+    }
+}
+
+/// Serializes a request or response body for debug logging, with secrets redacted
+///
+/// Intended for logging the raw bodies of remote integrations. Fields of `tracing` macros are only
+/// evaluated when their level is enabled, so this costs nothing unless debug logging is on.
+pub fn debug_redacted(body: &impl serde::Serialize) -> String {
+    match serde_json::to_value(body) {
+        Ok(mut value) => {
+            redact_secrets(&mut value);
+            value.to_string()
+        }
+        Err(err) => format!("<failed to serialize: {err}>"),
+    }
+}
+
+/// Formats a raw request or response body for debug logging, with secrets redacted
+///
+/// JSON bodies are redacted like [`debug_redacted`], anything else is logged lossily as utf8.
+/// Never fails, so logging can't fail a request.
+pub fn debug_redacted_bytes(body: &[u8]) -> String {
+    match serde_json::from_slice::<serde_json::Value>(body) {
+        Ok(value) => debug_redacted(&value),
+        Err(_) => String::from_utf8_lossy(body).into_owned(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -38,4 +118,55 @@ mod tests {
         let s = "Jürgen".repeat(100);
         assert_eq!(safe_truncate_utf8(&s, 100).chars().count(), 100);
     }
+
+    #[test]
+    fn test_redacts_nested_secrets() {
+        let mut value = serde_json::json!({
+            "model": "model",
+            "max_tokens": 10,
+            "headers": {"Authorization": "Bearer 123", "X-Api-Key": "456"},
+            "items": [{"access_token": "789", "chunk": "text"}],
+        });
+        redact_secrets(&mut value);
+
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "model": "model",
+                "max_tokens": 10,
+                "headers": {"Authorization": REDACTED, "X-Api-Key": REDACTED},
+                "items": [{"access_token": REDACTED, "chunk": "text"}],
+            })
+        );
+    }
+
+    #[test]
+    fn test_keeps_keys_that_are_not_secrets() {
+        let mut value = serde_json::json!({
+            "partition_key": "a",
+            "sort_key": "b",
+            "max_tokens": 10,
+            "api-key": "c",
+        });
+        redact_secrets(&mut value);
+
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "partition_key": "a",
+                "sort_key": "b",
+                "max_tokens": 10,
+                "api-key": REDACTED,
+            })
+        );
+    }
+
+    #[test]
+    fn test_debug_redacted_bytes_never_fails() {
+        assert_eq!(
+            debug_redacted_bytes(br#"{"api_key":"sk-123"}"#),
+            r#"{"api_key":"[REDACTED]"}"#
+        );
+        assert_eq!(debug_redacted_bytes(&[b'a', 0xff]), "a\u{fffd}");
+    }
 }
@@ -0,0 +1,9 @@
+/// Controls how storages treat existing data when they are set up
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WriteMode {
+    /// Keep existing data and add to it
+    #[default]
+    Append,
+    /// Drop and recreate the collection or table on setup
+    Recreate,
+}
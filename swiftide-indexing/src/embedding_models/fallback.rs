@@ -0,0 +1,319 @@
+use std::sync::{
+    atomic::{AtomicU8, Ordering},
+    Arc, OnceLock,
+};
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use swiftide_core::{EmbeddingModel, Embeddings};
+
+/// Which model of a [`Fallback`] produced the embeddings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModelUsed {
+    Primary = 1,
+    Secondary = 2,
+}
+
+/// Embeds with a primary model, falling back to a secondary model if the primary fails.
+///
+/// By default only transient errors of the primary fall back, see [`Fallback::is_transient`].
+/// Other errors, i.e. invalid input, would likely fail on the secondary as well and are returned
+/// as is. Use [`Fallback::with_fallback_if`] to classify errors yourself, or
+/// [`Fallback::with_fallback_on_any_error`] to fall back on every error.
+///
+/// Vectors of both models end up in the same index, so their dimensions must match. The dimension
+/// of the first embeddings returned is recorded, and embeddings of either model with a different
+/// dimension are an error.
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_indexing::embedding_models::Fallback;
+/// # fn run(primary: impl swiftide_core::EmbeddingModel + 'static, secondary: impl swiftide_core::EmbeddingModel + 'static) {
+/// let embedding_model = Fallback::new(primary, secondary)
+///     .with_fallback_if(|err| err.to_string().contains("connection"));
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Fallback {
+    primary: Arc<dyn EmbeddingModel>,
+    secondary: Arc<dyn EmbeddingModel>,
+    classifier: Arc<dyn Fn(&anyhow::Error) -> bool + Send + Sync>,
+    dimensions: Arc<OnceLock<usize>>,
+    /// The last [`ModelUsed`] as `u8`, 0 if nothing was embedded yet
+    last_used: Arc<AtomicU8>,
+}
+
+impl std::fmt::Debug for Fallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Fallback")
+            .field("primary", &self.primary)
+            .field("secondary", &self.secondary)
+            .field("dimensions", &self.dimensions.get())
+            .field("last_used", &self.last_used())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Fallback {
+    /// Embeds with `primary`, falling back to `secondary` on transient errors.
+    pub fn new(
+        primary: impl EmbeddingModel + 'static,
+        secondary: impl EmbeddingModel + 'static,
+    ) -> Self {
+        Self {
+            primary: Arc::new(primary),
+            secondary: Arc::new(secondary),
+            classifier: Arc::new(Self::is_transient),
+            dimensions: Arc::new(OnceLock::new()),
+            last_used: Arc::new(AtomicU8::new(0)),
+        }
+    }
+
+    /// Only falls back on errors for which the classifier returns `true`, overriding the default
+    /// of falling back on transient errors.
+    #[must_use]
+    pub fn with_fallback_if(
+        mut self,
+        fallback_if: impl Fn(&anyhow::Error) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.classifier = Arc::new(fallback_if);
+        self
+    }
+
+    /// Falls back on every error of the primary, overriding the default of falling back on
+    /// transient errors.
+    #[must_use]
+    pub fn with_fallback_on_any_error(self) -> Self {
+        self.with_fallback_if(|_| true)
+    }
+
+    /// The default classifier, `true` for connection errors, timeouts, rate limits and
+    /// unavailable services.
+    ///
+    /// Checks every error in the chain, for an [`std::io::Error`] of a transient kind or a message
+    /// that indicates a transient failure.
+    pub fn is_transient(err: &anyhow::Error) -> bool {
+        err.chain().any(|cause| {
+            if let Some(io_err) = cause.downcast_ref::<std::io::Error>() {
+                if is_transient_io_kind(io_err.kind()) {
+                    return true;
+                }
+            }
+
+            let message = cause.to_string().to_lowercase();
+            TRANSIENT_MESSAGES
+                .iter()
+                .any(|transient| message.contains(transient))
+        })
+    }
+
+    /// The model that produced the most recent embeddings, if any
+    pub fn last_used(&self) -> Option<ModelUsed> {
+        match self.last_used.load(Ordering::Relaxed) {
+            1 => Some(ModelUsed::Primary),
+            2 => Some(ModelUsed::Secondary),
+            _ => None,
+        }
+    }
+
+    /// Checks that all vectors have the dimension of the first embeddings returned
+    fn check_dimensions(&self, embeddings: &Embeddings, used: ModelUsed) -> Result<()> {
+        let Some(first) = embeddings.first() else {
+            return Ok(());
+        };
+        let expected = *self.dimensions.get_or_init(|| first.len());
+
+        if let Some(vector) = embeddings.iter().find(|vector| vector.len() != expected) {
+            bail!(
+                "{used:?} embedding model returned vectors with {} dimensions, expected {expected}",
+                vector.len()
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Lowercase fragments of error messages of transient failures
+const TRANSIENT_MESSAGES: &[&str] = &[
+    "connection",
+    "timed out",
+    "timeout",
+    "rate limit",
+    "too many requests",
+    "429",
+    "502",
+    "503",
+    "504",
+    "service unavailable",
+    "temporarily unavailable",
+    "overloaded",
+];
+
+fn is_transient_io_kind(kind: std::io::ErrorKind) -> bool {
+    use std::io::ErrorKind;
+
+    matches!(
+        kind,
+        ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NotConnected
+            | ErrorKind::BrokenPipe
+            | ErrorKind::TimedOut
+            | ErrorKind::Interrupted
+            | ErrorKind::UnexpectedEof
+    )
+}
+
+#[async_trait]
+impl EmbeddingModel for Fallback {
+    #[tracing::instrument(skip_all, fields(used))]
+    async fn embed(&self, input: Vec<String>) -> Result<Embeddings> {
+        let (embeddings, used) = match self.primary.embed(input.clone()).await {
+            Ok(embeddings) => (embeddings, ModelUsed::Primary),
+            Err(err) if (self.classifier)(&err) => {
+                tracing::warn!(
+                    primary = self.primary.name(),
+                    secondary = self.secondary.name(),
+                    error = ?err,
+                    "Primary embedding model failed, falling back"
+                );
+                (self.secondary.embed(input).await?, ModelUsed::Secondary)
+            }
+            Err(err) => return Err(err),
+        };
+        tracing::Span::current().record("used", format!("{used:?}"));
+
+        self.check_dimensions(&embeddings, used)?;
+        self.last_used.store(used as u8, Ordering::Relaxed);
+
+        Ok(embeddings)
+    }
+
+    /// The smallest input limit of both models, if known
+    fn max_input_tokens(&self) -> Option<usize> {
+        match (
+            self.primary.max_input_tokens(),
+            self.secondary.max_input_tokens(),
+        ) {
+            (Some(primary), Some(secondary)) => Some(primary.min(secondary)),
+            (primary, secondary) => primary.or(secondary),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use swiftide_core::MockEmbeddingModel;
+
+    fn failing() -> MockEmbeddingModel {
+        let mut model = MockEmbeddingModel::new();
+        model
+            .expect_embed()
+            .returning(|_| Err(anyhow::anyhow!("connection refused")));
+        model.expect_name().return_const("Primary");
+        model
+    }
+
+    fn embedding(dimensions: usize) -> MockEmbeddingModel {
+        let mut model = MockEmbeddingModel::new();
+        model
+            .expect_embed()
+            .returning(move |input| Ok(vec![vec![1.0; dimensions]; input.len()]));
+        model.expect_name().return_const("Secondary");
+        model
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_secondary() {
+        let model = Fallback::new(failing(), embedding(3));
+
+        let embeddings = model.embed(vec!["hello".to_string()]).await.unwrap();
+
+        assert_eq!(embeddings, vec![vec![1.0; 3]]);
+        assert_eq!(model.last_used(), Some(ModelUsed::Secondary));
+    }
+
+    #[tokio::test]
+    async fn test_does_not_fall_back_on_permanent_errors_by_default() {
+        let mut primary = MockEmbeddingModel::new();
+        primary
+            .expect_embed()
+            .returning(|_| Err(anyhow::anyhow!("invalid input")));
+        let model = Fallback::new(primary, MockEmbeddingModel::new());
+
+        let err = model.embed(vec!["hello".to_string()]).await.unwrap_err();
+
+        assert_eq!(err.to_string(), "invalid input");
+        assert_eq!(model.last_used(), None);
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_on_any_error_if_opted_in() {
+        let mut primary = MockEmbeddingModel::new();
+        primary
+            .expect_embed()
+            .returning(|_| Err(anyhow::anyhow!("invalid input")));
+        primary.expect_name().return_const("Primary");
+        let model = Fallback::new(primary, embedding(3)).with_fallback_on_any_error();
+
+        model.embed(vec!["hello".to_string()]).await.unwrap();
+
+        assert_eq!(model.last_used(), Some(ModelUsed::Secondary));
+    }
+
+    #[test]
+    fn test_is_transient() {
+        let io_err = anyhow::Error::from(std::io::Error::from(std::io::ErrorKind::ConnectionReset))
+            .context("Failed to embed");
+
+        assert!(Fallback::is_transient(&io_err));
+        assert!(Fallback::is_transient(&anyhow::anyhow!(
+            "Request failed with status 429 Too Many Requests"
+        )));
+        assert!(Fallback::is_transient(&anyhow::anyhow!(
+            "operation timed out"
+        )));
+        assert!(!Fallback::is_transient(&anyhow::anyhow!(
+            "Input is too long for the model"
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_does_not_fall_back_on_other_errors() {
+        let model = Fallback::new(failing(), MockEmbeddingModel::new())
+            .with_fallback_if(|err| err.to_string().contains("timeout"));
+
+        let err = model.embed(vec!["hello".to_string()]).await.unwrap_err();
+
+        assert!(err.to_string().contains("connection refused"), "{err}");
+        assert_eq!(model.last_used(), None);
+    }
+
+    #[tokio::test]
+    async fn test_errors_on_mismatching_dimensions() {
+        let mut primary = MockEmbeddingModel::new();
+        let mut calls = 0;
+        primary.expect_embed().returning(move |input| {
+            calls += 1;
+            if calls == 1 {
+                Ok(vec![vec![1.0; 3]; input.len()])
+            } else {
+                Err(anyhow::anyhow!("connection refused"))
+            }
+        });
+        primary.expect_name().return_const("Primary");
+        let model = Fallback::new(primary, embedding(4));
+
+        model.embed(vec!["hello".to_string()]).await.unwrap();
+        let err = model.embed(vec!["hello".to_string()]).await.unwrap_err();
+
+        assert!(
+            err.to_string().contains("4 dimensions, expected 3"),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,5 @@
+//! Combinators for embedding models
+//!
+//! Embedding models themselves are available as integrations.
+mod fallback;
+pub use fallback::{Fallback, ModelUsed};
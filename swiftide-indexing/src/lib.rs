@@ -1,6 +1,10 @@
+pub mod embedding_models;
 pub mod loaders;
 pub mod persist;
 pub mod transformers;
 
+mod observers;
 mod pipeline;
+mod stats;
 pub use pipeline::Pipeline;
+pub use stats::{DroppedNodes, PipelineStats, StageStats};
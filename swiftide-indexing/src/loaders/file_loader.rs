@@ -1,7 +1,12 @@
 //! Load files from a directory
 use anyhow::Context as _;
-use std::path::{Path, PathBuf};
-use swiftide_core::{indexing::IndexingStream, indexing::Node, Loader};
+use std::{
+    io::BufRead,
+    path::{Path, PathBuf},
+};
+use swiftide_core::{
+    indexing::IndexingStream, indexing::Metadata, indexing::Node, indexing::FILE_WINDOW, Loader,
+};
 
 /// The `FileLoader` struct is responsible for loading files from a specified directory,
 /// filtering them based on their extensions, and creating a stream of these files for further processing.
@@ -19,6 +24,21 @@ use swiftide_core::{indexing::IndexingStream, indexing::Node, Loader};
 pub struct FileLoader {
     pub(crate) path: PathBuf,
     pub(crate) extensions: Option<Vec<String>>,
+    pub(crate) encoding_policy: EncodingPolicy,
+    pub(crate) window_bytes: Option<usize>,
+    pub(crate) metadata: Metadata,
+}
+
+/// Determines how the loader handles files that are not valid UTF-8.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EncodingPolicy {
+    /// Files with invalid UTF-8 result in an error for that file
+    #[default]
+    Strict,
+    /// Invalid UTF-8 sequences are replaced with `U+FFFD`
+    ///
+    /// Affected nodes get `had_invalid_utf8` set to `true` in their metadata.
+    Lossy,
 }
 
 impl FileLoader {
@@ -33,6 +53,9 @@ impl FileLoader {
         Self {
             path: path.into(),
             extensions: None,
+            encoding_policy: EncodingPolicy::default(),
+            window_bytes: None,
+            metadata: Metadata::default(),
         }
     }
 
@@ -55,6 +78,41 @@ impl FileLoader {
         self
     }
 
+    /// Sets how files with invalid UTF-8 are handled. Defaults to [`EncodingPolicy::Strict`].
+    #[must_use]
+    pub fn with_encoding_policy(mut self, encoding_policy: EncodingPolicy) -> Self {
+        self.encoding_policy = encoding_policy;
+        self
+    }
+
+    /// Streams files in windows of at most `window_bytes` instead of reading them whole.
+    ///
+    /// Each window becomes a node, so peak memory is bounded by the window size regardless of
+    /// the file size. Windows end on a line break where possible, so that a following chunker
+    /// rarely splits a line that spans two windows. Lines longer than a window are split.
+    ///
+    /// Useful for very large files, like logs, in combination with a chunker.
+    ///
+    /// Every window keeps the path of the file, gets its index within the file as
+    /// [`FILE_WINDOW`] metadata and its byte offset within the file as `Node::offset`. Features
+    /// that key nodes by path take the window index into account, like
+    /// [`SkipUnchanged`](crate::transformers::SkipUnchanged), which tracks a checksum per window.
+    #[must_use]
+    pub fn with_window_bytes(mut self, window_bytes: usize) -> Self {
+        self.window_bytes = Some(window_bytes);
+        self
+    }
+
+    /// Adds static metadata to every node the loader produces.
+    ///
+    /// Useful to tag nodes by their origin, e.g. `source: "wiki"`, when a pipeline combines
+    /// several sources. Can be called multiple times; later keys overwrite earlier ones.
+    #[must_use]
+    pub fn with_metadata(mut self, metadata: impl Into<Metadata>) -> Self {
+        self.metadata.extend(metadata.into());
+        self
+    }
+
     /// Lists the nodes (files) that match the specified extensions.
     ///
     /// # Returns
@@ -68,20 +126,61 @@ impl FileLoader {
             .filter(|entry| entry.file_type().is_some_and(|ft| ft.is_file()))
             .filter(move |entry| self.file_has_extension(entry.path()))
             .map(ignore::DirEntry::into_path)
-            .map(|entry| {
-                tracing::debug!("Reading file: {:?}", entry);
-                let content = std::fs::read_to_string(&entry).unwrap();
-                let original_size = content.len();
-                Node {
-                    path: entry,
-                    chunk: content,
-                    original_size,
-                    ..Default::default()
-                }
-            })
+            .map(|entry| self.load_node(entry).unwrap())
             .collect()
     }
 
+    // Reads a file into a node, respecting the encoding policy
+    fn load_node(&self, path: PathBuf) -> anyhow::Result<Node> {
+        tracing::debug!("Reading file: {:?}", path);
+        let bytes = std::fs::read(&path).context("Failed to read file")?;
+
+        self.node_from_bytes(bytes, path)
+    }
+
+    // Reads a file lazily into a node per window, respecting the encoding policy
+    fn load_windowed_nodes(
+        &self,
+        path: PathBuf,
+        window_bytes: usize,
+    ) -> Box<dyn Iterator<Item = anyhow::Result<Node>> + Send> {
+        tracing::debug!("Streaming file: {:?}", path);
+        let file = match std::fs::File::open(&path).context("Failed to read file") {
+            Ok(file) => file,
+            Err(err) => return Box::new(std::iter::once(Err(err))),
+        };
+
+        let loader = self.clone();
+        let windows = LineWindows::new(std::io::BufReader::new(file), window_bytes);
+        let mut offset = 0;
+        Box::new(windows.enumerate().map(move |(index, window)| {
+            let window = window?;
+            let size = window.len();
+            let mut node = loader.node_from_bytes(window, path.clone())?;
+            node.metadata.insert(FILE_WINDOW, index);
+            node.offset = offset;
+            offset += size;
+            Ok(node)
+        }))
+    }
+
+    fn node_from_bytes(&self, bytes: Vec<u8>, path: PathBuf) -> anyhow::Result<Node> {
+        let mut node = match String::from_utf8(bytes) {
+            Ok(content) => Node::new(content),
+            Err(err) if self.encoding_policy == EncodingPolicy::Lossy => {
+                tracing::warn!(?path, "File contains invalid utf-8, reading lossy");
+                let mut node = Node::new(String::from_utf8_lossy(err.as_bytes()));
+                node.metadata.insert("had_invalid_utf8", true);
+                node
+            }
+            Err(err) => return Err(err).context("Failed to read file"),
+        };
+        node.path = path;
+        node.metadata.extend(self.metadata.clone());
+
+        Ok(node)
+    }
+
     // Helper function to check if a file has the specified extension.
     // If no extensions are specified, this function will return true.
     // If the file has no extension, this function will return false.
@@ -104,21 +203,14 @@ impl Loader for FileLoader {
     /// # Errors
     /// This method will return an error if it fails to read a file's content.
     fn into_stream(self) -> IndexingStream {
+        let loader = self.clone();
         let files = ignore::Walk::new(&self.path)
             .filter_map(Result::ok)
             .filter(|entry| entry.file_type().is_some_and(|ft| ft.is_file()))
-            .filter(move |entry| self.file_has_extension(entry.path()))
-            .map(|entry| {
-                tracing::debug!("Reading file: {:?}", entry);
-                let content =
-                    std::fs::read_to_string(entry.path()).context("Failed to read file")?;
-                let original_size = content.len();
-                Ok(Node {
-                    path: entry.path().into(),
-                    chunk: content,
-                    original_size,
-                    ..Default::default()
-                })
+            .filter(move |entry| loader.file_has_extension(entry.path()))
+            .flat_map(move |entry| match self.window_bytes {
+                Some(window_bytes) => self.load_windowed_nodes(entry.into_path(), window_bytes),
+                None => Box::new(std::iter::once(self.load_node(entry.into_path()))),
             });
 
         IndexingStream::iter(files)
@@ -129,8 +221,70 @@ impl Loader for FileLoader {
     }
 }
 
+/// Reads at most `max_bytes` at a time from a reader, preferably ending on a line break
+///
+/// The bytes after the last line break of a full window are carried over to the next window, as
+/// are incomplete utf-8 sequences when a window has no line break.
+struct LineWindows<R> {
+    reader: R,
+    max_bytes: usize,
+    carry: Vec<u8>,
+}
+
+impl<R: BufRead> LineWindows<R> {
+    fn new(reader: R, max_bytes: usize) -> Self {
+        Self {
+            reader,
+            max_bytes: max_bytes.max(4),
+            carry: Vec::new(),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for LineWindows<R> {
+    type Item = anyhow::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut window = std::mem::take(&mut self.carry);
+
+        while window.len() < self.max_bytes {
+            let buf = match self.reader.fill_buf() {
+                Ok(buf) => buf,
+                Err(err) => return Some(Err(err).context("Failed to read file")),
+            };
+            if buf.is_empty() {
+                break;
+            }
+
+            let take = buf.len().min(self.max_bytes - window.len());
+            window.extend_from_slice(&buf[..take]);
+            self.reader.consume(take);
+        }
+
+        if window.is_empty() {
+            return None;
+        }
+
+        if window.len() == self.max_bytes {
+            if let Some(pos) = window.iter().rposition(|b| *b == b'\n') {
+                self.carry = window.split_off(pos + 1);
+            } else if let Err(err) = std::str::from_utf8(&window) {
+                if err.error_len().is_none() && err.valid_up_to() > 0 {
+                    self.carry = window.split_off(err.valid_up_to());
+                }
+            }
+        }
+
+        Some(Ok(window))
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use std::io::Read;
+
+    use futures_util::TryStreamExt as _;
+
     use super::*;
 
     #[test]
@@ -138,4 +292,133 @@ mod test {
         let loader = FileLoader::new("/tmp").with_extensions(&["rs"]);
         assert_eq!(loader.extensions, Some(vec!["rs".to_string()]));
     }
+
+    #[test]
+    fn test_encoding_policy() {
+        let tempdir = temp_dir::TempDir::new().unwrap();
+        std::fs::write(tempdir.child("invalid.txt"), b"Hello \xF0\x90\x80World").unwrap();
+
+        let strict = FileLoader::new(tempdir.path());
+        assert_eq!(strict.encoding_policy, EncodingPolicy::Strict);
+        assert!(strict.load_node(tempdir.child("invalid.txt")).is_err());
+
+        let lossy = FileLoader::new(tempdir.path()).with_encoding_policy(EncodingPolicy::Lossy);
+        let node = lossy.load_node(tempdir.child("invalid.txt")).unwrap();
+
+        assert_eq!(node.chunk, "Hello \u{FFFD}World");
+        assert_eq!(node.metadata.get("had_invalid_utf8"), Some(&true.into()));
+    }
+
+    /// Counts the bytes read from the inner reader
+    struct CountingReader<R> {
+        inner: R,
+        read: std::rc::Rc<std::cell::Cell<usize>>,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.read.set(self.read.get() + n);
+            Ok(n)
+        }
+    }
+
+    /// Endlessly repeats a line without holding more than the line in memory
+    struct RepeatLine(&'static [u8], usize);
+
+    impl Read for RepeatLine {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = buf.len().min(self.0.len() - self.1);
+            buf[..n].copy_from_slice(&self.0[self.1..self.1 + n]);
+            self.1 = (self.1 + n) % self.0.len();
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_windows_read_incrementally() {
+        let line = "a log line with some content\n";
+        let size: u64 = 64 * 1024 * 1024;
+        let read = std::rc::Rc::default();
+        let reader = CountingReader {
+            inner: RepeatLine(line.as_bytes(), 0).take(size),
+            read: std::rc::Rc::clone(&read),
+        };
+        let buffer_size = 8 * 1024;
+        let mut windows =
+            LineWindows::new(std::io::BufReader::with_capacity(buffer_size, reader), 1024);
+
+        let first = windows.next().unwrap().unwrap();
+        assert!(read.get() <= 1024 + buffer_size);
+        assert!(first.ends_with(b"\n"));
+
+        let mut total = first.len();
+        for window in windows {
+            let window = window.unwrap();
+            assert!(window.len() <= 1024);
+            total += window.len();
+        }
+        assert_eq!(total, read.get());
+    }
+
+    #[test]
+    fn test_windows_split_long_lines_on_char_boundaries() {
+        let text = "ééééé";
+        let windows = LineWindows::new(text.as_bytes(), 5)
+            .map(|window| String::from_utf8(window.unwrap()).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(windows, ["éé", "éé", "é"]);
+    }
+
+    #[tokio::test]
+    async fn test_loads_files_in_windows() {
+        let tempdir = temp_dir::TempDir::new().unwrap();
+        let content = "a line in a log\n".repeat(100);
+        std::fs::write(tempdir.child("large.log"), &content).unwrap();
+
+        let windows: Vec<Node> = FileLoader::new(tempdir.path())
+            .with_window_bytes(64)
+            .into_stream()
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert!(windows.len() > 1);
+        assert!(windows.iter().all(|node| node.chunk.len() <= 64));
+        assert!(windows.iter().all(|node| node.chunk.ends_with('\n')));
+        assert!(windows
+            .iter()
+            .all(|node| node.path == tempdir.child("large.log")));
+        assert!(windows.iter().enumerate().all(|(index, node)| {
+            node.metadata.get(FILE_WINDOW) == Some(&index.into())
+                && content[node.offset..].starts_with(&node.chunk)
+        }));
+        assert_eq!(
+            windows
+                .into_iter()
+                .map(|node| node.chunk)
+                .collect::<String>(),
+            content
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tags_every_node_with_metadata() {
+        let tempdir = temp_dir::TempDir::new().unwrap();
+        std::fs::write(tempdir.child("first.md"), "first").unwrap();
+        std::fs::write(tempdir.child("second.md"), "second").unwrap();
+
+        let nodes: Vec<Node> = FileLoader::new(tempdir.path())
+            .with_metadata(std::collections::HashMap::from([("source", "wiki")]))
+            .into_stream()
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(nodes.len(), 2);
+        assert!(nodes
+            .iter()
+            .all(|node| node.metadata.get("source").unwrap() == "wiki"));
+    }
 }
@@ -6,4 +6,4 @@
 
 pub mod file_loader;
 
-pub use file_loader::FileLoader;
+pub use file_loader::{EncodingPolicy, FileLoader};
@@ -0,0 +1,207 @@
+//! Reports nodes that will not be stored to the node observers of a pipeline
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use anyhow::Result;
+use futures_util::StreamExt as _;
+use itertools::Itertools as _;
+use swiftide_core::{
+    indexing::{IndexingStream, Node},
+    NodeObserver,
+};
+
+/// The node observers of a pipeline, i.e. of its loader and node caches
+#[derive(Clone, Default)]
+pub(crate) struct NodeObservers(Arc<[Arc<dyn NodeObserver>]>);
+
+/// The observers tracking a node, with the key each tracks it by
+pub(crate) type NodeKeys = Vec<(Arc<dyn NodeObserver>, String)>;
+
+impl NodeObservers {
+    /// Adds an observer, ignoring observers that are already added
+    #[must_use]
+    pub(crate) fn with(self, observers: impl IntoIterator<Item = Arc<dyn NodeObserver>>) -> Self {
+        let mut all = self.0.to_vec();
+        for observer in observers {
+            if !all.iter().any(|added| Arc::ptr_eq(added, &observer)) {
+                all.push(observer);
+            }
+        }
+        Self(all.into())
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Arc<dyn NodeObserver>> {
+        self.0.iter()
+    }
+
+    /// The keys the observers track the node by
+    pub(crate) fn keys(&self, node: &Node) -> NodeKeys {
+        self.0
+            .iter()
+            .filter_map(|observer| Some((Arc::clone(observer), observer.key(node)?)))
+            .collect()
+    }
+
+    /// Tells the observers that the node will not be stored
+    pub(crate) fn release(&self, node: &Node) {
+        for (observer, key) in self.keys(node) {
+            observer.released(&key);
+        }
+    }
+
+    /// Tells the observers that the node of a skipped error will not be stored, if the error is
+    /// tied to a tracked node
+    pub(crate) fn fail(err: &anyhow::Error) {
+        if let Some(failed) = err.downcast_ref::<NodeFailed>() {
+            for (observer, key) in &failed.keys {
+                observer.failed(key);
+            }
+        }
+    }
+
+    /// Keeps the keys of the nodes of a batch, to find the nodes a batch stage dropped or added
+    pub(crate) fn batch(&self, nodes: &[Node]) -> Vec<BatchKeys> {
+        self.0
+            .iter()
+            .map(|observer| BatchKeys::new(Arc::clone(observer), nodes))
+            .collect()
+    }
+}
+
+/// The context of an error of a node that is tracked by node observers, to tell them if the error
+/// is skipped
+#[derive(Debug)]
+struct NodeFailed {
+    context: String,
+    keys: NodeKeys,
+}
+
+impl std::fmt::Display for NodeFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.context)
+    }
+}
+
+/// Adds the context to the error of a node, keeping the keys the node is tracked by if any
+pub(crate) fn with_node_context(
+    err: anyhow::Error,
+    context: String,
+    keys: NodeKeys,
+) -> anyhow::Error {
+    if keys.is_empty() {
+        err.context(context)
+    } else {
+        err.context(NodeFailed { context, keys })
+    }
+}
+
+/// Reports every chunk to the observers as added, and the chunked node as released once all its
+/// chunks are emitted
+pub(crate) fn observe_chunks(
+    stream: IndexingStream,
+    observers: NodeObservers,
+    keys: NodeKeys,
+) -> IndexingStream {
+    if keys.is_empty() {
+        return stream;
+    }
+
+    stream
+        .inspect(move |result| {
+            if let Ok(chunk) = result {
+                for (observer, key) in observers.keys(chunk) {
+                    observer.added(&key);
+                }
+            }
+        })
+        .chain(
+            futures_util::stream::once(async move {
+                for (observer, key) in keys {
+                    observer.released(&key);
+                }
+            })
+            .filter_map(|()| futures_util::future::ready(None)),
+        )
+        .boxed()
+        .into()
+}
+
+/// The keys of the nodes in a batch of an observer, to find the nodes a batch stage dropped or
+/// added
+pub(crate) struct BatchKeys {
+    observer: Arc<dyn NodeObserver>,
+    remaining: HashMap<String, usize>,
+    failed: bool,
+}
+
+impl BatchKeys {
+    fn new(observer: Arc<dyn NodeObserver>, nodes: &[Node]) -> Self {
+        let remaining = nodes.iter().filter_map(|node| observer.key(node)).counts();
+        Self {
+            observer,
+            remaining,
+            failed: false,
+        }
+    }
+
+    /// Matches a result of the output to a node of the batch, or reports it as added
+    fn output(&mut self, result: &Result<Node>) {
+        let Ok(node) = result else {
+            self.failed = true;
+            return;
+        };
+        let Some(key) = self.observer.key(node) else {
+            return;
+        };
+        match self.remaining.get_mut(&key) {
+            Some(remaining) if *remaining > 0 => *remaining -= 1,
+            _ => self.observer.added(&key),
+        }
+    }
+
+    /// Releases the nodes of the batch missing from the output, unless the output has an error as
+    /// the missing nodes might have failed
+    fn release_missing(&self) {
+        if self.failed {
+            return;
+        }
+        for (key, remaining) in &self.remaining {
+            for _ in 0..*remaining {
+                self.observer.released(key);
+            }
+        }
+    }
+}
+
+/// Reports nodes the output of a batch stage has beyond the batch as added, and nodes of the batch
+/// missing from the output as released once the output ends
+pub(crate) fn observe_batch(stream: IndexingStream, keys: Vec<BatchKeys>) -> IndexingStream {
+    if keys.is_empty() {
+        return stream;
+    }
+    let keys = Arc::new(Mutex::new(keys));
+    let on_end = Arc::clone(&keys);
+
+    stream
+        .inspect(move |result| {
+            for keys in keys
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .iter_mut()
+            {
+                keys.output(result);
+            }
+        })
+        .chain(
+            futures_util::stream::once(async move {
+                for keys in on_end.lock().unwrap_or_else(PoisonError::into_inner).iter() {
+                    keys.release_missing();
+                }
+            })
+            .filter_map(|()| futures_util::future::ready(None)),
+        )
+        .boxed()
+        .into()
+}
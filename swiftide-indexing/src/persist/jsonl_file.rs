@@ -0,0 +1,210 @@
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+use derive_builder::Builder;
+use tokio::{
+    fs::{File, OpenOptions},
+    io::{AsyncWriteExt as _, BufWriter},
+    sync::Mutex,
+};
+
+use swiftide_core::{
+    indexing::{IndexingStream, Node, WriteMode},
+    Persist,
+};
+
+/// Writes nodes to a local file, one JSON object per line
+///
+/// Handy for debugging a pipeline and for processing its output offline. The lines of a batch
+/// are buffered and flushed before its nodes are returned as stored, so stored nodes are on disk
+/// even if the pipeline fails later or the storage is used outside of a pipeline.
+///
+/// By default nodes are appended to an existing file. With [`WriteMode::Recreate`] the file is
+/// truncated on setup.
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_indexing::persist::JsonlFile;
+/// let storage = JsonlFile::builder()
+///     .path("nodes.jsonl")
+///     .batch_size(100)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Builder, Clone)]
+#[builder(
+    pattern = "owned",
+    setter(strip_option),
+    build_fn(error = "anyhow::Error")
+)]
+pub struct JsonlFile {
+    /// The file to write to, created if it does not exist
+    #[builder(setter(into))]
+    path: PathBuf,
+    /// Whether to append to or truncate an existing file on setup. Defaults to append.
+    #[builder(default)]
+    write_mode: WriteMode,
+    #[builder(default)]
+    batch_size: Option<usize>,
+    #[builder(setter(skip), default)]
+    writer: Arc<Mutex<Option<BufWriter<File>>>>,
+}
+
+impl JsonlFile {
+    pub fn builder() -> JsonlFileBuilder {
+        JsonlFileBuilder::default()
+    }
+
+    /// Writes the nodes as lines and flushes them, opening the file first if it is not open yet
+    async fn write(&self, nodes: &[Node]) -> Result<()> {
+        let mut guard = self.writer.lock().await;
+        let writer = if let Some(writer) = guard.as_mut() {
+            writer
+        } else {
+            guard.insert(self.open().await?)
+        };
+
+        for node in nodes {
+            let mut line = serde_json::to_vec(node)?;
+            line.push(b'\n');
+            writer
+                .write_all(&line)
+                .await
+                .with_context(|| format!("Failed to write to {}", self.path.display()))?;
+        }
+
+        writer
+            .flush()
+            .await
+            .with_context(|| format!("Failed to flush {}", self.path.display()))
+    }
+
+    async fn open(&self) -> Result<BufWriter<File>> {
+        let mut options = OpenOptions::new();
+        options.create(true);
+        match self.write_mode {
+            WriteMode::Append => options.append(true),
+            WriteMode::Recreate => options.write(true).truncate(true),
+        };
+
+        let file = options
+            .open(&self.path)
+            .await
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        Ok(BufWriter::new(file))
+    }
+}
+
+#[async_trait]
+impl Persist for JsonlFile {
+    /// Opens the file, truncating it with [`WriteMode::Recreate`]
+    async fn setup(&self) -> Result<()> {
+        let mut writer = self.writer.lock().await;
+        if writer.is_none() {
+            *writer = Some(self.open().await?);
+        }
+        Ok(())
+    }
+
+    async fn store(&self, node: Node) -> Result<Node> {
+        self.write(std::slice::from_ref(&node)).await?;
+        Ok(node)
+    }
+
+    async fn batch_store(&self, nodes: Vec<Node>) -> IndexingStream {
+        match self.write(&nodes).await {
+            Ok(()) => IndexingStream::from_nodes(nodes),
+            Err(err) => err.into(),
+        }
+    }
+
+    fn batch_size(&self) -> Option<usize> {
+        self.batch_size
+    }
+
+    async fn flush(&self) -> Result<()> {
+        if let Some(writer) = self.writer.lock().await.as_mut() {
+            writer
+                .flush()
+                .await
+                .with_context(|| format!("Failed to flush {}", self.path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures_util::StreamExt as _;
+
+    use super::*;
+    use crate::Pipeline;
+
+    async fn run_pipeline(storage: JsonlFile) {
+        Pipeline::from_stream(vec![Node::new("first"), Node::new("second")])
+            .then_store_with(storage)
+            .run()
+            .await
+            .unwrap();
+    }
+
+    fn read_nodes(path: &std::path::Path) -> Vec<Node> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_writes_nodes_as_lines() {
+        let dir = temp_dir::TempDir::new().unwrap();
+        let path = dir.child("nodes.jsonl");
+        let storage = JsonlFile::builder()
+            .path(&path)
+            .batch_size(1)
+            .build()
+            .unwrap();
+
+        run_pipeline(storage).await;
+
+        let mut chunks = read_nodes(&path)
+            .into_iter()
+            .map(|node| node.chunk)
+            .collect::<Vec<_>>();
+        chunks.sort();
+        assert_eq!(chunks, ["first", "second"]);
+    }
+
+    #[tokio::test]
+    async fn test_stored_nodes_are_on_disk_without_flushing() {
+        let dir = temp_dir::TempDir::new().unwrap();
+        let path = dir.child("nodes.jsonl");
+        let storage = JsonlFile::builder().path(&path).build().unwrap();
+
+        storage.store(Node::new("first")).await.unwrap();
+        storage
+            .batch_store(vec![Node::new("second")])
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(read_nodes(&path).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_appends_or_recreates() {
+        let dir = temp_dir::TempDir::new().unwrap();
+        let path = dir.child("nodes.jsonl");
+        let storage = || JsonlFile::builder().path(&path);
+
+        run_pipeline(storage().build().unwrap()).await;
+        run_pipeline(storage().build().unwrap()).await;
+        assert_eq!(read_nodes(&path).len(), 4);
+
+        run_pipeline(storage().write_mode(WriteMode::Recreate).build().unwrap()).await;
+        assert_eq!(read_nodes(&path).len(), 2);
+    }
+}
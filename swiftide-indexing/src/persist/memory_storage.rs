@@ -1,4 +1,7 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use anyhow::Result;
 use async_trait::async_trait;
@@ -19,9 +22,13 @@ use swiftide_core::{
 /// By default the storage will use a zero indexed, incremental counter as the key for each node if the node id
 /// is not set.
 pub struct MemoryStorage {
+    #[builder(default)]
     data: Arc<RwLock<HashMap<String, Node>>>,
     #[builder(default)]
     batch_size: Option<usize>,
+    #[builder(default)]
+    /// Caps batches by the serialized size of their nodes, see [`Persist::batch_max_bytes`]
+    batch_max_bytes: Option<usize>,
     #[builder(default = "Arc::new(RwLock::new(0))")]
     node_count: Arc<RwLock<u64>>,
 }
@@ -85,6 +92,7 @@ impl Persist for MemoryStorage {
             lock.insert(last_key.to_string(), node.clone());
             last_key += 1;
         }
+        *self.node_count.write().await = last_key;
 
         IndexingStream::iter(nodes.into_iter().map(Ok))
     }
@@ -92,6 +100,32 @@ impl Persist for MemoryStorage {
     fn batch_size(&self) -> Option<usize> {
         self.batch_size
     }
+
+    fn batch_max_bytes(&self) -> Option<usize> {
+        self.batch_max_bytes
+    }
+
+    async fn count(&self) -> Result<u64> {
+        Ok(self.data.read().await.len() as u64)
+    }
+
+    /// Streams a snapshot of all nodes in the storage
+    async fn stream_all(&self) -> IndexingStream {
+        IndexingStream::from_nodes(self.get_all_values().await)
+    }
+
+    /// Checks if a node with the same id, by default derived from the path and chunk, is stored
+    async fn exists(&self, nodes: &[Node]) -> Result<Vec<bool>> {
+        let ids = self
+            .data
+            .read()
+            .await
+            .values()
+            .map(Node::id)
+            .collect::<HashSet<_>>();
+
+        Ok(nodes.iter().map(|node| ids.contains(&node.id())).collect())
+    }
 }
 
 #[cfg(test)]
@@ -137,5 +171,57 @@ mod test {
         assert_eq!(result.len(), 2);
         assert_eq!(result[0], node1);
         assert_eq!(result[1], node2);
+
+        // A next batch does not overwrite the previous one
+        storage
+            .batch_store(vec![Node::default()])
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(storage.count().await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_stream_all() {
+        let storage = MemoryStorage::default();
+        storage.store(Node::new("first")).await.unwrap();
+        storage.store(Node::new("second")).await.unwrap();
+
+        let mut chunks = storage
+            .stream_all()
+            .await
+            .map_ok(|node| node.chunk)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        chunks.sort();
+
+        assert_eq!(chunks, ["first", "second"]);
+    }
+
+    #[tokio::test]
+    async fn test_exists() {
+        let storage = MemoryStorage::default();
+        storage.store(Node::new("stored")).await.unwrap();
+
+        let exists = storage
+            .exists(&[Node::new("stored"), Node::new("new")])
+            .await
+            .unwrap();
+
+        assert_eq!(exists, [true, false]);
+    }
+
+    #[tokio::test]
+    async fn test_count() {
+        let storage = MemoryStorage::default();
+        assert_eq!(storage.count().await.unwrap(), 0);
+
+        for _ in 0..3 {
+            storage.store(Node::default()).await.unwrap();
+        }
+
+        assert_eq!(storage.count().await.unwrap(), 3);
     }
 }
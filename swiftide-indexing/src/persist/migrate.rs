@@ -0,0 +1,133 @@
+use anyhow::Result;
+use swiftide_core::Persist;
+
+use crate::{transformers::Embed, Pipeline, PipelineStats};
+
+/// Streams all nodes from one storage into another, i.e. to move a corpus to a different
+/// storage without loading and transforming its sources again
+///
+/// Nodes are read with [`Persist::stream_all`] and stored in batches of the destination, as in a
+/// pipeline. The destination is set up first. Nodes are stored as they are, see
+/// [`migrate_with_embed`] to embed nodes that miss a vector.
+///
+/// # Errors
+///
+/// Errors if the source does not support streaming all nodes, or if reading or storing a node
+/// fails.
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_indexing::persist::{migrate, MemoryStorage};
+/// # async fn run() -> anyhow::Result<()> {
+/// let source = MemoryStorage::default();
+/// let destination = MemoryStorage::default();
+///
+/// let stats = migrate(&source, &destination).await?;
+/// println!("Migrated {} nodes", stats.total_nodes);
+/// # Ok(())
+/// # }
+/// ```
+pub async fn migrate(
+    source: &dyn Persist,
+    destination: &(dyn Persist + 'static),
+) -> Result<PipelineStats> {
+    Pipeline::from_stream(source.stream_all().await)
+        .then_store_with(dyn_clone::clone_box(destination))
+        .run()
+        .await
+}
+
+/// Streams all nodes from one storage into another like [`migrate`], embedding the nodes that
+/// miss a vector on the way
+///
+/// Nodes that already have their vectors are not embedded again, see
+/// [`Embed::with_skip_embedded`].
+///
+/// # Errors
+///
+/// Errors if the source does not support streaming all nodes, or if reading, embedding or
+/// storing a node fails.
+pub async fn migrate_with_embed(
+    source: &dyn Persist,
+    destination: &(dyn Persist + 'static),
+    embed: Embed,
+) -> Result<PipelineStats> {
+    Pipeline::from_stream(source.stream_all().await)
+        .then_in_batch(embed.with_skip_embedded(true))
+        .then_store_with(dyn_clone::clone_box(destination))
+        .run()
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use swiftide_core::{
+        indexing::{EmbeddedField, Node},
+        MockEmbeddingModel,
+    };
+
+    use super::*;
+    use crate::persist::MemoryStorage;
+
+    fn node(i: u8, embedded: bool) -> Node {
+        let mut node = Node::new(format!("chunk {i}"));
+        node.path = format!("file_{i}.md").into();
+        node.metadata.insert("index", i);
+        if embedded {
+            node.with_vectors([(EmbeddedField::Combined, vec![f32::from(i)])]);
+        }
+        node.update_id();
+        node
+    }
+
+    async fn sorted_nodes(storage: &MemoryStorage) -> Vec<Node> {
+        let mut nodes = storage.get_all_values().await;
+        nodes.sort_by(|a, b| a.chunk.cmp(&b.chunk));
+        nodes
+    }
+
+    #[tokio::test]
+    async fn test_migrates_all_nodes() {
+        let source = MemoryStorage::default();
+        source
+            .batch_store((0..5).map(|i| node(i, true)).collect())
+            .await;
+        let destination = MemoryStorage::default();
+
+        let stats = migrate(&source, &destination).await.unwrap();
+
+        assert_eq!(stats.total_nodes, 5);
+        assert_eq!(
+            sorted_nodes(&destination).await,
+            sorted_nodes(&source).await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_embeds_nodes_without_vectors() {
+        let source = MemoryStorage::default();
+        source
+            .batch_store(vec![node(0, true), node(1, false)])
+            .await;
+        let destination = MemoryStorage::default();
+
+        let mut model = MockEmbeddingModel::new();
+        model
+            .expect_embed()
+            .withf(|input| input.len() == 1 && input[0].ends_with("chunk 1"))
+            .times(1)
+            .returning(|input| Ok(vec![vec![9.0]; input.len()]));
+
+        migrate_with_embed(&source, &destination, Embed::new(model))
+            .await
+            .unwrap();
+
+        let vectors = sorted_nodes(&destination)
+            .await
+            .into_iter()
+            .map(|node| node.vectors.unwrap()[&EmbeddedField::Combined].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(vectors, [vec![0.0], vec![9.0]]);
+    }
+}
@@ -1,5 +1,11 @@
 //! Storage implementations for persisting data
 //!
 //! More storage implementations are available as integrations.
+mod jsonl_file;
 mod memory_storage;
-pub use memory_storage::MemoryStorage;
+mod migrate;
+mod retry;
+pub use jsonl_file::{JsonlFile, JsonlFileBuilder};
+pub use memory_storage::{MemoryStorage, MemoryStorageBuilder};
+pub use migrate::{migrate, migrate_with_embed};
+pub use retry::Retry;
@@ -0,0 +1,334 @@
+//! Retry failed stores of a storage backend
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures_util::StreamExt as _;
+
+use swiftide_core::{
+    indexing::{IndexingStream, Node},
+    Persist,
+};
+
+const DEFAULT_MAX_RETRIES: usize = 3;
+const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+
+/// Wraps a storage backend and retries failed stores.
+///
+/// When storing a batch, nodes the backend acknowledged are not stored again. Only the remainder
+/// of the batch is retried. Backends have to yield the nodes they stored for this to work, which
+/// is what `Persist::batch_store` does by convention.
+///
+/// By default every error is retried. Use [`Retry::with_retry_if`] to decide which errors are
+/// retryable.
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_indexing::persist::{MemoryStorage, Retry};
+/// # use std::time::Duration;
+/// let storage = Retry::new(MemoryStorage::default())
+///     .with_max_retries(5)
+///     .with_delay(Duration::from_secs(1))
+///     .with_retry_if(|err| !err.to_string().contains("503"));
+/// ```
+#[derive(Clone)]
+pub struct Retry {
+    inner: Arc<dyn Persist>,
+    max_retries: usize,
+    delay: Duration,
+    classifier: Arc<dyn Fn(&anyhow::Error) -> bool + Send + Sync>,
+}
+
+impl std::fmt::Debug for Retry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Retry")
+            .field("inner", &self.inner)
+            .field("max_retries", &self.max_retries)
+            .field("delay", &self.delay)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Retry {
+    /// Wraps a storage backend, retrying at most 3 times with a delay of 100ms.
+    pub fn new(storage: impl Persist + 'static) -> Self {
+        Self {
+            inner: Arc::new(storage),
+            max_retries: DEFAULT_MAX_RETRIES,
+            delay: DEFAULT_DELAY,
+            classifier: Arc::new(|_| true),
+        }
+    }
+
+    /// Sets the maximum number of retries after the initial attempt.
+    #[must_use]
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Sets the delay between attempts.
+    #[must_use]
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Only retries errors for which the classifier returns `true`, overriding the default of
+    /// retrying every error.
+    #[must_use]
+    pub fn with_retry_if(
+        mut self,
+        retry_if: impl Fn(&anyhow::Error) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.classifier = Arc::new(retry_if);
+        self
+    }
+}
+
+#[async_trait]
+impl Persist for Retry {
+    async fn setup(&self) -> Result<()> {
+        self.inner.setup().await
+    }
+
+    async fn store(&self, node: Node) -> Result<Node> {
+        let mut attempt = 0;
+        loop {
+            match self.inner.store(node.clone()).await {
+                Ok(node) => return Ok(node),
+                Err(err) if attempt < self.max_retries && (self.classifier)(&err) => {
+                    attempt += 1;
+                    tracing::warn!(
+                        storage = self.inner.name(),
+                        attempt,
+                        error = ?err,
+                        "Failed to store node, retrying"
+                    );
+                    tokio::time::sleep(self.delay).await;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Stores a batch of nodes, retrying only the nodes that were not stored
+    async fn batch_store(&self, nodes: Vec<Node>) -> IndexingStream {
+        let mut pending = nodes;
+        let mut stored = Vec::with_capacity(pending.len());
+        let mut attempt = 0;
+
+        loop {
+            let results = self
+                .inner
+                .batch_store(pending.clone())
+                .await
+                .collect::<Vec<_>>()
+                .await;
+
+            let mut last_error = None;
+            let mut acked = HashMap::<_, usize>::new();
+            for result in results {
+                match result {
+                    Ok(node) => {
+                        *acked.entry(node.id()).or_default() += 1;
+                        stored.push(node);
+                    }
+                    Err(err) => last_error = Some(err),
+                }
+            }
+
+            pending.retain(|node| match acked.get_mut(&node.id()) {
+                Some(count) if *count > 0 => {
+                    *count -= 1;
+                    false
+                }
+                _ => true,
+            });
+
+            let Some(err) = last_error else {
+                break;
+            };
+
+            // The backend acknowledged every node but still failed, i.e. on a follow up request, so
+            // there is nothing to retry, but the error is passed on
+            if pending.is_empty() || attempt >= self.max_retries || !(self.classifier)(&err) {
+                return IndexingStream::iter(stored.into_iter().map(Ok).chain([Err(err)]));
+            }
+
+            attempt += 1;
+            tracing::warn!(
+                storage = self.inner.name(),
+                attempt,
+                remaining = pending.len(),
+                error = ?err,
+                "Failed to store batch, retrying remaining nodes"
+            );
+            tokio::time::sleep(self.delay).await;
+        }
+
+        IndexingStream::iter(stored.into_iter().map(Ok))
+    }
+
+    fn batch_size(&self) -> Option<usize> {
+        self.inner.batch_size()
+    }
+
+    fn batch_max_bytes(&self) -> Option<usize> {
+        self.inner.batch_max_bytes()
+    }
+
+    async fn count(&self) -> Result<u64> {
+        self.inner.count().await
+    }
+
+    async fn stream_all(&self) -> IndexingStream {
+        self.inner.stream_all().await
+    }
+
+    async fn exists(&self, nodes: &[Node]) -> Result<Vec<bool>> {
+        self.inner.exists(nodes).await
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.inner.flush().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::TryStreamExt as _;
+    use mockall::Sequence;
+    use swiftide_core::indexing::MockPersist;
+
+    fn nodes() -> Vec<Node> {
+        (0..4).map(|i| Node::new(format!("node {i}"))).collect()
+    }
+
+    #[tokio::test]
+    async fn test_retries_only_unacked_nodes() {
+        let mut storage = MockPersist::new();
+        let mut seq = Sequence::new();
+
+        storage
+            .expect_batch_store()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(|nodes| nodes.len() == 4)
+            .returning(|nodes| {
+                vec![
+                    Ok(nodes[0].clone()),
+                    Ok(nodes[1].clone()),
+                    Err(anyhow::anyhow!("connection reset")),
+                ]
+                .into()
+            });
+        storage
+            .expect_batch_store()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(|nodes| *nodes == nodes_slice(2..4))
+            .returning(Into::into);
+        storage.expect_name().returning(|| "mock");
+
+        let retry = Retry::new(storage).with_delay(Duration::ZERO);
+        let stored: Vec<Node> = retry
+            .batch_store(nodes())
+            .await
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(stored, nodes());
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_retries() {
+        let mut storage = MockPersist::new();
+        storage
+            .expect_batch_store()
+            .times(3)
+            .returning(|nodes| vec![Ok(nodes[0].clone()), Err(anyhow::anyhow!("oops"))].into());
+        storage.expect_name().returning(|| "mock");
+
+        let retry = Retry::new(storage)
+            .with_max_retries(2)
+            .with_delay(Duration::ZERO);
+        let results = retry.batch_store(nodes()).await.collect::<Vec<_>>().await;
+
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 3);
+        assert_eq!(
+            results.last().unwrap().as_ref().unwrap_err().to_string(),
+            "oops"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_classifier_decides_what_is_retried() {
+        let mut storage = MockPersist::new();
+        let mut seq = Sequence::new();
+        storage
+            .expect_store()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(|_| Err(anyhow::anyhow!("503 service unavailable")));
+        storage
+            .expect_store()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(|_| Err(anyhow::anyhow!("400 bad request")));
+        storage.expect_name().returning(|| "mock");
+
+        let retry = Retry::new(storage)
+            .with_delay(Duration::ZERO)
+            .with_retry_if(|err| err.to_string().starts_with("503"));
+        let err = retry.store(Node::new("chunk")).await.unwrap_err();
+
+        assert_eq!(err.to_string(), "400 bad request");
+    }
+
+    #[tokio::test]
+    async fn test_classifier_stops_batch_retries() {
+        let mut storage = MockPersist::new();
+        storage
+            .expect_batch_store()
+            .once()
+            .returning(|nodes| vec![Ok(nodes[0].clone()), Err(anyhow::anyhow!("fatal"))].into());
+
+        let retry = Retry::new(storage)
+            .with_delay(Duration::ZERO)
+            .with_retry_if(|_| false);
+        let results = retry.batch_store(nodes()).await.collect::<Vec<_>>().await;
+
+        assert_eq!(results.len(), 2);
+        assert!(results[1].is_err());
+    }
+
+    #[tokio::test]
+    async fn test_passes_on_error_when_all_nodes_are_acked() {
+        let mut storage = MockPersist::new();
+        storage.expect_batch_store().once().returning(|nodes| {
+            nodes
+                .into_iter()
+                .map(Ok)
+                .chain([Err(anyhow::anyhow!("failed to refresh index"))])
+                .collect::<Vec<_>>()
+                .into()
+        });
+
+        let retry = Retry::new(storage).with_delay(Duration::ZERO);
+        let results = retry.batch_store(nodes()).await.collect::<Vec<_>>().await;
+
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 4);
+        assert_eq!(
+            results.last().unwrap().as_ref().unwrap_err().to_string(),
+            "failed to refresh index"
+        );
+    }
+
+    fn nodes_slice(range: std::ops::Range<usize>) -> Vec<Node> {
+        nodes()[range].to_vec()
+    }
+}
@@ -1,19 +1,40 @@
-use anyhow::Result;
+use anyhow::{Context as _, Result};
 use futures_util::{StreamExt, TryFutureExt, TryStreamExt};
+use itertools::Itertools as _;
 use swiftide_core::{
-    indexing::IndexingDefaults, BatchableTransformer, ChunkerTransformer, Loader, NodeCache,
-    Persist, SimplePrompt, Transformer, WithBatchIndexingDefaults, WithIndexingDefaults,
+    indexing::{DropReason, IndexingDefaults},
+    BatchableTransformer, ChunkerTransformer, Loader, NodeCache, Persist, SimplePrompt,
+    Transformer, WithBatchIndexingDefaults, WithIndexingDefaults,
 };
 use tokio::{sync::mpsc, task};
 use tracing::Instrument;
 
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 use swiftide_core::indexing::{EmbedMode, IndexingStream, Node};
 
+use crate::{
+    observers::{observe_batch, observe_chunks, with_node_context, NodeObservers},
+    stats::{DropCollector, DroppedNodes, PipelineStats, StageCollector},
+};
+
 /// The default batch size for batch processing.
 const DEFAULT_BATCH_SIZE: usize = 256;
 
+/// The default time a partial batch of [`Pipeline::batch_by`] waits for more nodes
+const DEFAULT_BATCH_BY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Returns the key of the batch a node is stored in
+type BatchKey = Arc<dyn Fn(&Node) -> String + Send + Sync>;
+
 /// A pipeline for indexing files, adding metadata, chunking, transforming, embedding, and then storing them.
 ///
 /// The `Pipeline` struct orchestrates the entire file indexing process. It is designed to be flexible and
@@ -31,6 +52,20 @@ pub struct Pipeline {
     concurrency: usize,
     indexing_defaults: IndexingDefaults,
     batch_size: usize,
+    stages: Vec<Arc<StageCollector>>,
+    /// Counts nodes dropped outside of a stage, i.e. errors skipped by `filter_errors` or empty
+    /// chunks of the loader
+    unstaged_drops: Vec<Arc<DropCollector>>,
+    /// Read while running, so it applies regardless of where it is set in the builder
+    drop_empty_chunks: Arc<AtomicBool>,
+    skip_existing: bool,
+    ordered: bool,
+    store_concurrency: Option<usize>,
+    store_buffer: Option<usize>,
+    batch_key: Option<BatchKey>,
+    batch_by_timeout: Duration,
+    /// The node observers of the loader and node caches, told about nodes that will not be stored
+    node_observers: NodeObservers,
 }
 
 impl Default for Pipeline {
@@ -42,6 +77,16 @@ impl Default for Pipeline {
             concurrency: num_cpus::get(),
             indexing_defaults: IndexingDefaults::default(),
             batch_size: DEFAULT_BATCH_SIZE,
+            stages: Vec::new(),
+            unstaged_drops: Vec::new(),
+            drop_empty_chunks: Arc::default(),
+            skip_existing: false,
+            ordered: false,
+            store_concurrency: None,
+            store_buffer: None,
+            batch_key: None,
+            batch_by_timeout: DEFAULT_BATCH_BY_TIMEOUT,
+            node_observers: NodeObservers::default(),
         }
     }
 }
@@ -56,12 +101,17 @@ impl Pipeline {
     /// # Returns
     ///
     /// An instance of `Pipeline` initialized with the provided loader.
+    ///
+    /// If the loader has a [`NodeObserver`], it is told about the nodes that will not be stored.
     pub fn from_loader(loader: impl Loader + 'static) -> Self {
+        let node_observers = NodeObservers::default().with(loader.node_observer());
         let stream = loader.into_stream();
         Self {
             stream,
+            node_observers,
             ..Default::default()
         }
+        .filter_empty_chunks(None)
     }
 
     /// Sets the default LLM client to be used for LLM prompts for all transformers in the
@@ -74,6 +124,25 @@ impl Pipeline {
 
     /// Creates a `Pipeline` from a given stream.
     ///
+    /// Useful when nodes are already produced elsewhere and the pipeline is only used to
+    /// transform and persist them, without implementing a `Loader`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use swiftide_core::indexing::{IndexingStream, Node};
+    /// # use swiftide_indexing::{persist::MemoryStorage, Pipeline};
+    /// # async fn run() -> anyhow::Result<()> {
+    /// let nodes = vec![Node::new("first"), Node::new("second")];
+    ///
+    /// Pipeline::from_stream(IndexingStream::from_nodes(nodes))
+    ///     .then_store_with(MemoryStorage::default())
+    ///     .run()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
     /// # Arguments
     ///
     /// * `stream` - An `IndexingStream` containing the nodes to be processed.
@@ -86,6 +155,7 @@ impl Pipeline {
             stream: stream.into(),
             ..Default::default()
         }
+        .filter_empty_chunks(None)
     }
 
     /// Sets the concurrency level for the pipeline. By default the concurrency is set to the
@@ -104,6 +174,30 @@ impl Pipeline {
         self
     }
 
+    /// Sets the maximum number of concurrent `store` or `batch_store` calls per storage, so that a
+    /// slow backend can store several batches at once while transformation continues. Defaults to
+    /// the concurrency of the pipeline.
+    ///
+    /// Every node is still passed on once it is stored. With [`Pipeline::ordered`], nodes are
+    /// passed on in order.
+    #[must_use]
+    pub fn with_store_concurrency(mut self, store_concurrency: usize) -> Self {
+        self.store_concurrency = Some(store_concurrency);
+        self
+    }
+
+    /// Buffers at most `max_nodes` nodes in front of each storage added afterwards.
+    ///
+    /// The previous stages, i.e. embedding, keep running while a slow storage is busy, until the
+    /// buffer is full. They then wait for the storage to catch up, which bounds the memory used
+    /// by embedded nodes waiting to be stored. By default nodes are pulled from the previous
+    /// stages only when the storage is ready for them. A buffer of 0 is treated as 1.
+    #[must_use]
+    pub fn with_store_buffer(mut self, max_nodes: usize) -> Self {
+        self.store_buffer = Some(max_nodes.max(1));
+        self
+    }
+
     /// Sets the embed mode for the pipeline. The embed mode controls what (combination) fields of a [`Node`]
     /// be embedded with a vector when transforming with [`crate::transformers::Embed`]
     ///
@@ -129,6 +223,65 @@ impl Pipeline {
         self
     }
 
+    /// Drops nodes with an empty or whitespace-only chunk.
+    ///
+    /// When enabled, empty chunks are removed where they are produced, as they come from the
+    /// loader and from every chunker, so they never reach an embedding model. Applies to the whole
+    /// pipeline, wherever it is called. Disabled by default.
+    #[must_use]
+    pub fn drop_empty_chunks(self, drop_empty_chunks: bool) -> Self {
+        self.drop_empty_chunks
+            .store(drop_empty_chunks, Ordering::Relaxed);
+        self
+    }
+
+    /// Skips nodes that are already stored, i.e. when indexing the same corpus again.
+    ///
+    /// When enabled, every following `then_store_with` first checks its storage with
+    /// [`Persist::exists`] in batches, and only stores the nodes that do not exist yet. Requires a
+    /// storage that supports `exists`. `then_store_to` skips nodes that exist in all its storages.
+    /// Disabled by default.
+    #[must_use]
+    pub fn skip_existing(mut self, skip_existing: bool) -> Self {
+        self.skip_existing = skip_existing;
+        self
+    }
+
+    /// Preserves the order of nodes through concurrent transformers and chunkers.
+    ///
+    /// When enabled, every following `then`, `then_in_batch` and `then_chunk` still processes
+    /// nodes concurrently, but emits them in the order they came in, i.e. to keep the chunks of a
+    /// document in order. A slow node holds back the nodes after it, so this costs throughput.
+    /// Disabled by default.
+    #[must_use]
+    pub fn ordered(mut self, ordered: bool) -> Self {
+        self.ordered = ordered;
+        self
+    }
+
+    /// Groups the nodes of every batch stored by a following `then_store_with` by a key, i.e. to
+    /// store all nodes of a tenant or collection together.
+    ///
+    /// Batches never mix keys and still hold at most the batch size of the storage. A batch is
+    /// stored once it is full, or once it waited for more nodes longer than the timeout set with
+    /// [`Pipeline::with_batch_by_timeout`], 1 second by default. The remaining partial batches are
+    /// stored when the stream ends. Nodes with different keys are not stored in order.
+    ///
+    /// Has no effect on storages without a batch size, which store nodes one by one.
+    #[must_use]
+    pub fn batch_by(mut self, key: impl Fn(&Node) -> String + Send + Sync + 'static) -> Self {
+        self.batch_key = Some(Arc::new(key));
+        self
+    }
+
+    /// Sets how long a partial batch of [`Pipeline::batch_by`] waits for more nodes of its key
+    /// before it is stored anyway.
+    #[must_use]
+    pub fn with_batch_by_timeout(mut self, timeout: impl Into<Duration>) -> Self {
+        self.batch_by_timeout = timeout.into();
+        self
+    }
+
     /// Filters out cached nodes using the provided cache.
     ///
     /// # Arguments
@@ -141,19 +294,34 @@ impl Pipeline {
     #[must_use]
     pub fn filter_cached(mut self, cache: impl NodeCache + 'static) -> Self {
         let cache = Arc::new(cache);
+        let stage = self.add_stage(cache.name());
+        self.node_observers = self.node_observers.clone().with(cache.node_observer());
+        let observers = self.node_observers.clone();
         self.stream = self
             .stream
             .try_filter_map(move |node| {
                 let cache = Arc::clone(&cache);
+                let stage = Arc::clone(&stage);
+                let observers = observers.clone();
                 let span =
                     tracing::trace_span!("filter_cached", node_cache = ?cache, node = ?node );
                 async move {
-                    if cache.get(&node).await {
+                    let started = Instant::now();
+                    let in_cache = cache.get(&node).await;
+                    if in_cache {
                         tracing::debug!(node = ?node, node_cache = cache.name(), "Node in cache, skipping");
-                        Ok(None)
                     } else {
                         cache.set(&node).await;
                         tracing::debug!(node = ?node, node_cache = cache.name(), "Node not in cache, processing");
+                    }
+                    stage.record_elapsed(started);
+
+                    if in_cache {
+                        stage.record_dropped(DropReason::Deduplicated, 1);
+                        observers.release(&node);
+                        Ok(None)
+                    } else {
+                        stage.record(&Ok::<_, anyhow::Error>(()));
                         Ok(Some(node))
                     }
                 }
@@ -185,23 +353,38 @@ impl Pipeline {
         transformer.with_indexing_defaults(self.indexing_defaults.clone());
 
         let transformer = Arc::new(transformer);
-        self.stream = self
-            .stream
-            .map_ok(move |node| {
+        let stage = self.add_stage(transformer.name());
+        let observers = self.node_observers.clone();
+        let stream = self.stream.map_ok(move |node| {
                 let transformer = transformer.clone();
+                let stage = Arc::clone(&stage);
+                let observers = observers.clone();
                 let span = tracing::trace_span!("then", node = ?node);
 
                 task::spawn(async move {
                     tracing::debug!(node = ?node, transformer = transformer.name(), "Transforming node");
-                    transformer.transform_node(node).await
+                    let started = Instant::now();
+                    let path = node.path.clone();
+                    let keys = observers.keys(&node);
+                    let result = transformer
+                        .transform_node(node)
+                        .await
+                        .map_err(|err| with_node_context(err, node_context(transformer.name(), &path), keys));
+                    stage.record_elapsed(started);
+                    stage.record(&result);
+                    result
                 })
                 .instrument(span)
                 .err_into::<anyhow::Error>()
-            })
-            .try_buffer_unordered(concurrency)
-            .map(|x| x.and_then(|x| x))
-            .boxed()
-            .into();
+            });
+        self.stream = if self.ordered {
+            stream.try_buffered(concurrency).boxed()
+        } else {
+            stream.try_buffer_unordered(concurrency).boxed()
+        }
+        .map(|x| x.and_then(|x| x))
+        .boxed()
+        .into();
 
         self
     }
@@ -227,11 +410,15 @@ impl Pipeline {
         transformer.with_indexing_defaults(self.indexing_defaults.clone());
 
         let transformer = Arc::new(transformer);
-        self.stream = self
+        let stage = self.add_stage(transformer.name());
+        let observers = self.node_observers.clone();
+        let stream = self
             .stream
             .try_chunks(transformer.batch_size().unwrap_or(self.batch_size))
             .map_ok(move |nodes| {
                 let transformer = Arc::clone(&transformer);
+                let stage = Arc::clone(&stage);
+                let observers = observers.clone();
                 let span = tracing::trace_span!("then_in_batch",  nodes = ?nodes );
 
                 tokio::spawn(async move {
@@ -240,16 +427,34 @@ impl Pipeline {
                         num_nodes = nodes.len(),
                         "Batch transforming nodes"
                     );
-                    transformer.batch_transform(nodes).await
+                    let num_nodes = nodes.len();
+                    let name = transformer.name();
+                    let paths = batch_paths(&nodes);
+                    let keys = observers.batch(&nodes);
+                    let started = Instant::now();
+                    let stream = transformer.batch_transform(nodes).await;
+                    stage.record_elapsed(started);
+                    let stream =
+                        record_batch_stream(stream, stage, num_nodes, transformer.drop_reason());
+                    with_error_context(observe_batch(stream, keys), move || {
+                        batch_context(name, &paths)
+                    })
                 })
                 .instrument(span)
                 .map_err(anyhow::Error::from)
             })
-            .err_into::<anyhow::Error>()
-            .try_buffer_unordered(concurrency) // First get the streams from each future
-            .try_flatten_unordered(None) // Then flatten all the streams back into one
-            .boxed()
-            .into();
+            .err_into::<anyhow::Error>();
+        self.stream = if self.ordered {
+            stream.try_buffered(concurrency).try_flatten().boxed()
+        } else {
+            stream
+                .try_buffer_unordered(concurrency) // First get the streams from each future
+                // Then flatten the streams back into one, holding at most `concurrency` finished
+                // batches so that batches are not transformed far ahead of the next stage
+                .try_flatten_unordered(Some(concurrency))
+                .boxed()
+        }
+        .into();
         self
     }
 
@@ -262,30 +467,52 @@ impl Pipeline {
     /// # Returns
     ///
     /// An instance of `Pipeline` with the updated stream that applies the chunker transformer to each node.
+    ///
+    /// Every chunk gets its index within the node it was chunked from as
+    /// [`Node::chunk_index`](swiftide_core::indexing::Node::chunk_index).
     #[must_use]
     pub fn then_chunk(mut self, chunker: impl ChunkerTransformer + 'static) -> Self {
         let chunker = Arc::new(chunker);
         let concurrency = chunker.concurrency().unwrap_or(self.concurrency);
-        self.stream = self
+        let stage = self.add_stage(chunker.name());
+        let chunker_stage = Arc::clone(&stage);
+        let observers = self.node_observers.clone();
+        let stream = self
             .stream
             .map_ok(move |node| {
                 let chunker = Arc::clone(&chunker);
+                let stage = Arc::clone(&chunker_stage);
+                let observers = observers.clone();
                 let span = tracing::trace_span!("then_chunk", chunker = ?chunker, node = ?node );
 
                 tokio::spawn(async move {
                     tracing::debug!(chunker = chunker.name(), "Chunking node");
-                    chunker.transform_node(node).await
+                    let started = Instant::now();
+                    let name = chunker.name();
+                    let path = node.path.clone();
+                    let keys = observers.keys(&node);
+                    let stream = chunker.transform_node(node).await;
+                    stage.record_elapsed(started);
+                    let stream = with_chunk_index(record_stream(stream, stage));
+                    with_error_context(observe_chunks(stream, observers, keys), move || {
+                        node_context(name, &path)
+                    })
                 })
                 .instrument(span)
                 .map_err(anyhow::Error::from)
             })
-            .err_into::<anyhow::Error>()
-            .try_buffer_unordered(concurrency)
-            .try_flatten_unordered(None)
-            .boxed()
-            .into();
+            .err_into::<anyhow::Error>();
+        self.stream = if self.ordered {
+            stream.try_buffered(concurrency).try_flatten().boxed()
+        } else {
+            stream
+                .try_buffer_unordered(concurrency)
+                .try_flatten_unordered(None)
+                .boxed()
+        }
+        .into();
 
-        self
+        self.filter_empty_chunks(Some(stage))
     }
 
     /// Persists indexing nodes using the provided storage backend.
@@ -298,56 +525,272 @@ impl Pipeline {
     ///
     /// An instance of `Pipeline` with the configured storage backend.
     ///
-    /// # Panics
-    ///
-    /// Panics if batch size turns out to be not set and batch storage is still invoked.
-    /// Pipeline only invokes batch storing if the batch size is set, so should be alright.
     #[must_use]
     pub fn then_store_with(mut self, storage: impl Persist + 'static) -> Self {
         let storage = Arc::new(storage);
         self.storage.push(storage.clone());
+        let stage = self.add_stage(storage.name());
+        self = self.before_store(Arc::new([storage.clone() as Arc<dyn Persist>]), &stage);
+        let concurrency = self.store_concurrency.unwrap_or(self.concurrency);
         // add storage to the stream instead of doing it at the end
-        if storage.batch_size().is_some() {
-            self.stream = self
-                .stream
-                .try_chunks(storage.batch_size().unwrap())
+        if let Some(batch_size) = storage.batch_size() {
+            let batches = match self.batch_key.clone() {
+                Some(key) => group_by_key(self.stream, batch_size, self.batch_by_timeout, key),
+                None => self
+                    .stream
+                    .try_chunks(batch_size)
+                    .err_into::<anyhow::Error>()
+                    .boxed(),
+            };
+            let stream = batches
                 .map_ok(move |nodes| {
                     let storage = Arc::clone(&storage);
+                    let stage = Arc::clone(&stage);
                     let span = tracing::trace_span!("then_store_with_batched", storage = ?storage, nodes = ?nodes );
 
                 tokio::spawn(async move {
-                        tracing::debug!(storage = storage.name(), num_nodes = nodes.len(), "Batch Storing nodes");
-                        storage.batch_store(nodes).await
+                        let batches = match storage.batch_max_bytes() {
+                            Some(max_bytes) => split_batch_by_bytes(nodes, max_bytes),
+                            None => vec![nodes],
+                        };
+
+                        let mut streams = Vec::with_capacity(batches.len());
+                        for nodes in batches {
+                            tracing::debug!(storage = storage.name(), num_nodes = nodes.len(), "Batch Storing nodes");
+                            let name = storage.name();
+                            let paths = batch_paths(&nodes);
+                            let started = Instant::now();
+                            let stream = storage.batch_store(nodes).await;
+                            stage.record_elapsed(started);
+                            streams.push(with_error_context(record_stream(stream, Arc::clone(&stage)), move || batch_context(name, &paths)));
+                        }
+
+                        IndexingStream::from_stream(futures_util::stream::iter(streams).flatten())
                     })
                     .instrument(span)
                     .map_err(anyhow::Error::from)
 
                 })
-                .err_into::<anyhow::Error>()
-                .try_buffer_unordered(self.concurrency)
-                .try_flatten_unordered(None)
-                .boxed().into();
+                .err_into::<anyhow::Error>();
+            self.stream = if self.ordered {
+                stream.try_buffered(concurrency).try_flatten().boxed()
+            } else {
+                stream
+                    .try_buffer_unordered(concurrency)
+                    .try_flatten_unordered(None)
+                    .boxed()
+            }
+            .into();
         } else {
-            self.stream = self
-                .stream
-                .map_ok(move |node| {
-                    let storage = Arc::clone(&storage);
-                    let span =
-                        tracing::trace_span!("then_store_with", storage = ?storage, node = ?node );
+            if self.batch_key.is_some() {
+                tracing::warn!(
+                    storage = storage.name(),
+                    "Storage has no batch size and stores nodes one by one, batch_by has no effect"
+                );
+            }
+            let stream = self.stream.map_ok(move |node| {
+                let storage = Arc::clone(&storage);
+                let stage = Arc::clone(&stage);
+                let span =
+                    tracing::trace_span!("then_store_with", storage = ?storage, node = ?node );
+
+                tokio::spawn(async move {
+                    tracing::debug!(storage = storage.name(), "Storing node");
+
+                    let started = Instant::now();
+                    let path = node.path.clone();
+                    let result = storage
+                        .store(node)
+                        .await
+                        .with_context(|| node_context(storage.name(), &path));
+                    stage.record_elapsed(started);
+                    stage.record(&result);
+                    result
+                })
+                .err_into::<anyhow::Error>()
+                .instrument(span)
+            });
+            self.stream = buffer_stored(stream, self.ordered, concurrency);
+        }
+
+        self
+    }
 
-                    tokio::spawn(async move {
-                        tracing::debug!(storage = storage.name(), "Storing node");
+    /// Persists indexing nodes into multiple storage backends at once.
+    ///
+    /// Each node is stored in all backends concurrently and is only passed on once every backend
+    /// has stored it. If one or more backends fail, the errors of all failing backends are
+    /// aggregated into a single error for that node.
+    ///
+    /// Unlike [`Pipeline::then_store_with`], nodes are stored one by one and the batch size of the
+    /// backends is ignored. With [`Pipeline::skip_existing`], nodes that exist in every backend
+    /// are skipped.
+    #[must_use]
+    pub fn then_store_to(mut self, storages: impl IntoIterator<Item = Box<dyn Persist>>) -> Self {
+        let storages: Arc<[Arc<dyn Persist>]> = storages.into_iter().map(Arc::from).collect();
+        self.storage.extend(storages.iter().cloned());
+        if self.batch_key.is_some() {
+            tracing::warn!("then_store_to stores nodes one by one, batch_by has no effect");
+        }
+        let stage = self.add_stage("then_store_to");
+        self = self.before_store(Arc::clone(&storages), &stage);
+        let concurrency = self.store_concurrency.unwrap_or(self.concurrency);
+
+        let stream = self.stream.map_ok(move |node| {
+            let storages = Arc::clone(&storages);
+            let stage = Arc::clone(&stage);
+            let span = tracing::trace_span!("then_store_to", node = ?node);
+
+            tokio::spawn(async move {
+                tracing::debug!(
+                    num_storages = storages.len(),
+                    "Storing node in all storages"
+                );
+                let started = Instant::now();
+                let results = futures_util::future::join_all(
+                    storages.iter().map(|storage| storage.store(node.clone())),
+                )
+                .await;
 
-                        storage.store(node).await
+                let errors = storages
+                    .iter()
+                    .zip(results)
+                    .filter_map(|(storage, result)| {
+                        result
+                            .err()
+                            .map(|err| format!("{}: {err:#}", storage.name()))
                     })
-                    .err_into::<anyhow::Error>()
-                    .instrument(span)
+                    .collect::<Vec<_>>();
+
+                let result = if errors.is_empty() {
+                    Ok(node)
+                } else {
+                    Err(anyhow::anyhow!(
+                        "Failed to store node in {} of {} storages: {}",
+                        errors.len(),
+                        storages.len(),
+                        errors.join(", ")
+                    )
+                    .context(node_context("then_store_to", &node.path)))
+                };
+                stage.record_elapsed(started);
+                stage.record(&result);
+                result
+            })
+            .err_into::<anyhow::Error>()
+            .instrument(span)
+        });
+        self.stream = buffer_stored(stream, self.ordered, concurrency);
+
+        self
+    }
+
+    /// Embeds each batch of nodes and stores it right away, fusing [`Pipeline::then_in_batch`]
+    /// and [`Pipeline::then_store_with`] into a single step per batch.
+    ///
+    /// Embedded nodes are not buffered between embedding and storing, which keeps latency and
+    /// memory low, i.e. for local embedding models. Nodes are stored in the batches of the
+    /// embedder, the batch size of the storage and [`Pipeline::with_store_buffer`] are ignored.
+    /// Embedding and storing are still reported as separate stages.
+    #[must_use]
+    pub fn then_embed_and_store(
+        mut self,
+        mut embed: impl BatchableTransformer + WithBatchIndexingDefaults + 'static,
+        storage: impl Persist + 'static,
+    ) -> Self {
+        let concurrency = embed.concurrency().unwrap_or(self.concurrency);
+        embed.with_indexing_defaults(self.indexing_defaults.clone());
+
+        let embed = Arc::new(embed);
+        let storage = Arc::new(storage);
+        self.storage.push(storage.clone());
+        if self.batch_key.is_some() {
+            tracing::warn!(
+                storage = storage.name(),
+                "then_embed_and_store stores the batches of the embedder, batch_by has no effect"
+            );
+        }
+        let embed_stage = self.add_stage(embed.name());
+        let store_stage = self.add_stage(storage.name());
+        let observers = self.node_observers.clone();
+        if self.skip_existing {
+            self = self.filter_existing(
+                Arc::new([storage.clone() as Arc<dyn Persist>]),
+                Arc::clone(&store_stage),
+            );
+        }
+
+        let stream = self
+            .stream
+            .try_chunks(embed.batch_size().unwrap_or(self.batch_size))
+            .map_ok(move |nodes| {
+                let embed = Arc::clone(&embed);
+                let storage = Arc::clone(&storage);
+                let embed_stage = Arc::clone(&embed_stage);
+                let store_stage = Arc::clone(&store_stage);
+                let observers = observers.clone();
+                let span = tracing::trace_span!("then_embed_and_store", nodes = ?nodes);
+
+                tokio::spawn(async move {
+                    let num_nodes = nodes.len();
+                    let embed_name = embed.name();
+                    let paths = batch_paths(&nodes);
+                    let keys = observers.batch(&nodes);
+                    let started = Instant::now();
+                    let embedded = embed.batch_transform(nodes).await;
+                    embed_stage.record_elapsed(started);
+
+                    let embedded = observe_batch(
+                        record_batch_stream(embedded, embed_stage, num_nodes, embed.drop_reason()),
+                        keys,
+                    );
+                    let (embedded, errors): (Vec<_>, Vec<_>) =
+                        with_error_context(embedded, move || batch_context(embed_name, &paths))
+                            .collect::<Vec<_>>()
+                            .await
+                            .into_iter()
+                            .partition(Result::is_ok);
+                    let embedded = embedded.into_iter().flatten().collect::<Vec<_>>();
+
+                    let batches = match storage.batch_max_bytes() {
+                        Some(max_bytes) => split_batch_by_bytes(embedded, max_bytes),
+                        None => vec![embedded],
+                    };
+
+                    let mut streams = vec![IndexingStream::iter(errors)];
+                    for nodes in batches.into_iter().filter(|nodes| !nodes.is_empty()) {
+                        tracing::debug!(
+                            storage = storage.name(),
+                            num_nodes = nodes.len(),
+                            "Storing embedded nodes"
+                        );
+                        let name = storage.name();
+                        let paths = batch_paths(&nodes);
+                        let started = Instant::now();
+                        let stream = storage.batch_store(nodes).await;
+                        store_stage.record_elapsed(started);
+                        streams.push(with_error_context(
+                            record_stream(stream, Arc::clone(&store_stage)),
+                            move || batch_context(name, &paths),
+                        ));
+                    }
+
+                    IndexingStream::from_stream(futures_util::stream::iter(streams).flatten())
                 })
-                .try_buffer_unordered(self.concurrency)
-                .map(|x| x.and_then(|x| x))
+                .instrument(span)
+                .map_err(anyhow::Error::from)
+            })
+            .err_into::<anyhow::Error>();
+
+        self.stream = if self.ordered {
+            stream.try_buffered(concurrency).try_flatten().boxed()
+        } else {
+            stream
+                .try_buffer_unordered(concurrency)
+                .try_flatten_unordered(Some(concurrency))
                 .boxed()
-                .into();
         }
+        .into();
 
         self
     }
@@ -410,6 +853,16 @@ impl Pipeline {
             concurrency: self.concurrency,
             indexing_defaults: self.indexing_defaults.clone(),
             batch_size: self.batch_size,
+            stages: self.stages.clone(),
+            unstaged_drops: self.unstaged_drops.clone(),
+            drop_empty_chunks: Arc::clone(&self.drop_empty_chunks),
+            skip_existing: self.skip_existing,
+            ordered: self.ordered,
+            store_concurrency: self.store_concurrency,
+            store_buffer: self.store_buffer,
+            batch_key: self.batch_key.clone(),
+            batch_by_timeout: self.batch_by_timeout,
+            node_observers: self.node_observers.clone(),
         };
 
         let right_pipeline = Self {
@@ -418,6 +871,16 @@ impl Pipeline {
             concurrency: self.concurrency,
             indexing_defaults: self.indexing_defaults.clone(),
             batch_size: self.batch_size,
+            stages: self.stages.clone(),
+            unstaged_drops: self.unstaged_drops.clone(),
+            drop_empty_chunks: Arc::clone(&self.drop_empty_chunks),
+            skip_existing: self.skip_existing,
+            ordered: self.ordered,
+            store_concurrency: self.store_concurrency,
+            store_buffer: self.store_buffer,
+            batch_key: self.batch_key.clone(),
+            batch_by_timeout: self.batch_by_timeout,
+            node_observers: self.node_observers.clone(),
         };
 
         (left_pipeline, right_pipeline)
@@ -429,15 +892,59 @@ impl Pipeline {
     ///
     /// The full stream can then be processed using the `run` method.
     #[must_use]
-    pub fn merge(self, other: Self) -> Self {
+    pub fn merge(mut self, other: Self) -> Self {
         let stream = tokio_stream::StreamExt::merge(self.stream, other.stream);
 
+        // Stages added before a split are shared by both pipelines
+        for stage in other.stages {
+            if !self.stages.iter().any(|s| Arc::ptr_eq(s, &stage)) {
+                self.stages.push(stage);
+            }
+        }
+        for skipped in other.unstaged_drops {
+            if !self.unstaged_drops.iter().any(|s| Arc::ptr_eq(s, &skipped)) {
+                self.unstaged_drops.push(skipped);
+            }
+        }
+
         Self {
             stream: stream.boxed().into(),
+            node_observers: self
+                .node_observers
+                .clone()
+                .with(other.node_observers.iter().cloned()),
             ..self
         }
     }
 
+    /// Applies a function to the underlying stream of the pipeline.
+    ///
+    /// This is an escape hatch for stages that do not fit any of the provided traits. Any
+    /// combinator from `futures` or `tokio_stream` can be used, as long as an `IndexingStream` is
+    /// returned.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use swiftide_indexing::Pipeline;
+    /// # use swiftide_core::indexing::IndexingStream;
+    /// # use futures_util::{StreamExt, TryStreamExt};
+    /// Pipeline::from_stream(IndexingStream::empty()).map_stream(|stream| {
+    ///     stream
+    ///         .inspect_ok(|node| println!("{node:?}"))
+    ///         .boxed()
+    ///         .into()
+    /// });
+    /// ```
+    #[must_use]
+    pub fn map_stream<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(IndexingStream) -> IndexingStream,
+    {
+        self.stream = f(self.stream);
+        self
+    }
+
     /// Throttles the stream of nodes, limiting the rate to 1 per duration.
     ///
     /// Useful for rate limiting the indexing pipeline. Uses `tokio_stream::StreamExt::throttle` internally which has a granualarity of 1ms.
@@ -455,13 +962,58 @@ impl Pipeline {
     // Note that errors are not logged.
     #[must_use]
     pub fn filter_errors(mut self) -> Self {
+        let skipped = self.add_unstaged_drops();
         self.stream = self
             .stream
-            .filter_map(|result| async {
-                match result {
+            .filter_map(move |result| {
+                let result = match result {
                     Ok(node) => Some(Ok(node)),
-                    Err(_e) => None,
-                }
+                    Err(err) => {
+                        NodeObservers::fail(&err);
+                        skipped.record(DropReason::Errored, 1);
+                        None
+                    }
+                };
+                futures_util::future::ready(result)
+            })
+            .boxed()
+            .into();
+        self
+    }
+
+    /// Skips errors like [`Pipeline::filter_errors`], but aborts the run once
+    /// `max_consecutive_errors` errors occur in a row.
+    ///
+    /// Guards against silently skipping the entire corpus when everything fails, for instance
+    /// during a provider outage. Any successful node resets the count. Only errors from the steps
+    /// before this call are counted.
+    #[must_use]
+    pub fn max_consecutive_errors(mut self, max_consecutive_errors: usize) -> Self {
+        let skipped = self.add_unstaged_drops();
+        let mut consecutive_errors = 0;
+        self.stream = self
+            .stream
+            .filter_map(move |result| {
+                let result = match result {
+                    Ok(node) => {
+                        consecutive_errors = 0;
+                        Some(Ok(node))
+                    }
+                    Err(err) => {
+                        consecutive_errors += 1;
+                        if consecutive_errors >= max_consecutive_errors {
+                            Some(Err(err.context(format!(
+                                "Aborting after {consecutive_errors} consecutive errors"
+                            ))))
+                        } else {
+                            tracing::warn!(error = ?err, consecutive_errors, "Skipping error");
+                            NodeObservers::fail(&err);
+                            skipped.record(DropReason::Errored, 1);
+                            None
+                        }
+                    }
+                };
+                futures_util::future::ready(result)
             })
             .boxed()
             .into();
@@ -473,20 +1025,23 @@ impl Pipeline {
     /// This allows you to skip specific errors or nodes, or do ad hoc inspection.
     ///
     /// If the closure returns true, the result is kept, otherwise it is skipped.
+    ///
+    /// Skipped nodes are reported as filtered, and skipped errors as errored, in the stats of
+    /// the `filter` stage.
     #[must_use]
     pub fn filter<F>(mut self, filter: F) -> Self
     where
         F: Fn(&Result<Node>) -> bool + Send + Sync + 'static,
     {
-        self.stream = self
-            .stream
-            .filter(move |result| {
-                let will_retain = filter(result);
-
-                async move { will_retain }
-            })
-            .boxed()
-            .into();
+        let stage = self.add_stage("filter");
+        let observers = self.node_observers.clone();
+        self.stream = filter_recording_drops(self.stream, Some(stage), move |result| {
+            let will_retain = filter(result);
+            if let (Ok(node), false) = (result, will_retain) {
+                observers.release(node);
+            }
+            will_retain
+        });
         self
     }
 
@@ -530,18 +1085,20 @@ impl Pipeline {
     ///
     /// # Returns
     ///
-    /// A `Result` indicating the success or failure of the pipeline execution.
+    /// A `Result` with the [`PipelineStats`] of the run, or the error that failed the pipeline.
     ///
     /// # Errors
     ///
     /// Returns an error if no storage backend is configured or if any stage of the pipeline fails.
+    /// Errors of transformers, chunkers and storages name the stage and the path of the node they
+    /// failed on, the original error is kept as its source.
     #[tracing::instrument(skip_all, fields(total_nodes), name = "indexing_pipeline.run")]
-    pub async fn run(mut self) -> Result<()> {
+    pub async fn run(mut self) -> Result<PipelineStats> {
         tracing::info!(
             "Starting indexing pipeline with {} concurrency",
             self.concurrency
         );
-        let now = std::time::Instant::now();
+        let now = Instant::now();
         if self.storage.is_empty() {
             anyhow::bail!("No storage configured for indexing pipeline");
         }
@@ -549,15 +1106,36 @@ impl Pipeline {
         // Ensure all storage backends are set up before processing nodes
         let setup_futures = self
             .storage
-            .into_iter()
+            .iter()
             .map(|storage| async move { storage.setup().await })
             .collect::<Vec<_>>();
         futures_util::future::try_join_all(setup_futures).await?;
 
         let mut total_nodes = 0;
-        while self.stream.try_next().await?.is_some() {
-            total_nodes += 1;
+        let result = loop {
+            match self.stream.try_next().await {
+                Ok(Some(_)) => total_nodes += 1,
+                Ok(None) => break Ok(()),
+                Err(err) => break Err(err),
+            }
+        };
+
+        // Flush storage backends that buffer writes, also when the run failed, so nodes that were
+        // already stored are not lost
+        let flush_futures = self
+            .storage
+            .iter()
+            .map(|storage| async move { storage.flush().await })
+            .collect::<Vec<_>>();
+        let flushed = futures_util::future::try_join_all(flush_futures).await;
+
+        if let Err(err) = result {
+            if let Err(flush_err) = flushed {
+                tracing::error!(error = ?flush_err, "Failed to flush storage of failed pipeline");
+            }
+            return Err(err);
         }
+        flushed?;
 
         let elapsed_in_seconds = now.elapsed().as_secs();
         tracing::warn!(
@@ -568,39 +1146,458 @@ impl Pipeline {
         );
         tracing::Span::current().record("total_nodes", total_nodes);
 
-        Ok(())
+        let stages = self
+            .stages
+            .iter()
+            .map(|stage| stage.stats())
+            .collect::<Vec<_>>();
+        Ok(PipelineStats {
+            total_nodes,
+            elapsed: now.elapsed(),
+            dropped: stages
+                .iter()
+                .map(|stage| stage.dropped)
+                .sum::<DroppedNodes>()
+                + self
+                    .unstaged_drops
+                    .iter()
+                    .map(|skipped| skipped.stats())
+                    .sum(),
+            stages,
+        })
     }
-}
 
-#[cfg(test)]
-mod tests {
+    /// Removes nodes with an empty or whitespace-only chunk once `drop_empty_chunks` is enabled
+    ///
+    /// Applied where chunks are produced, to the loader and after every chunker. The dropped
+    /// nodes are attributed to the stage of the chunker that produced them, which already counted
+    /// them as produced.
+    fn filter_empty_chunks(mut self, stage: Option<Arc<StageCollector>>) -> Self {
+        let enabled = Arc::clone(&self.drop_empty_chunks);
+        let observers = self.node_observers.clone();
+        let record_dropped: Box<dyn Fn() + Send + Sync> = if let Some(stage) = stage {
+            Box::new(move || stage.record_dropped(DropReason::Filtered, 1))
+        } else {
+            let dropped = self.add_unstaged_drops();
+            Box::new(move || dropped.record(DropReason::Filtered, 1))
+        };
 
-    use super::*;
-    use crate::persist::MemoryStorage;
-    use mockall::Sequence;
-    use swiftide_core::indexing::*;
+        self.stream = self
+            .stream
+            .filter(move |result| {
+                let is_empty = matches!(
+                    result,
+                    Ok(node) if enabled.load(Ordering::Relaxed) && node.chunk.trim().is_empty()
+                );
+                if let (true, Ok(node)) = (is_empty, result) {
+                    tracing::debug!(node = ?node, "Dropping node with empty chunk");
+                    record_dropped();
+                    observers.release(node);
+                }
+                futures_util::future::ready(!is_empty)
+            })
+            .boxed()
+            .into();
+        self
+    }
 
-    /// Tests a simple run of the indexing pipeline.
-    #[test_log::test(tokio::test)]
-    async fn test_simple_run() {
-        let mut loader = MockLoader::new();
-        let mut transformer = MockTransformer::new();
-        let mut batch_transformer = MockBatchableTransformer::new();
-        let mut chunker = MockChunkerTransformer::new();
-        let mut storage = MockPersist::new();
+    /// Skips existing nodes and buffers nodes in front of the storages, as configured
+    fn before_store(
+        mut self,
+        storages: Arc<[Arc<dyn Persist>]>,
+        stage: &Arc<StageCollector>,
+    ) -> Self {
+        if self.skip_existing {
+            self = self.filter_existing(storages, Arc::clone(stage));
+        }
+        if let Some(capacity) = self.store_buffer {
+            self.stream = buffer_stream(self.stream, capacity);
+        }
+        self
+    }
 
-        let mut seq = Sequence::new();
+    /// Removes nodes that already exist in all the storages, checking them in batches
+    ///
+    /// Existing nodes are reported as deduplicated by the stage of the storages.
+    fn filter_existing(
+        mut self,
+        storages: Arc<[Arc<dyn Persist>]>,
+        stage: Arc<StageCollector>,
+    ) -> Self {
+        let batch_size = storages
+            .iter()
+            .filter_map(|storage| storage.batch_size())
+            .min()
+            .unwrap_or(self.batch_size);
+        let observers = self.node_observers.clone();
+        let stream = self
+            .stream
+            .try_chunks(batch_size)
+            .err_into::<anyhow::Error>()
+            .map_ok(move |nodes| {
+                let storages = Arc::clone(&storages);
+                let stage = Arc::clone(&stage);
+                let observers = observers.clone();
+                async move {
+                    let mut exists = vec![true; nodes.len()];
+                    for storage in storages.iter() {
+                        let exists_in_storage = storage.exists(&nodes).await?;
+                        if exists_in_storage.len() != nodes.len() {
+                            anyhow::bail!(
+                                "{} checked {} nodes for existence, expected {}",
+                                storage.name(),
+                                exists_in_storage.len(),
+                                nodes.len()
+                            );
+                        }
+                        for (exists, exists_in_storage) in exists.iter_mut().zip(exists_in_storage)
+                        {
+                            *exists &= exists_in_storage;
+                        }
+                    }
 
-        loader
-            .expect_into_stream()
-            .times(1)
-            .in_sequence(&mut seq)
-            .returning(|| vec![Ok(Node::default())].into());
+                    let num_nodes = nodes.len();
+                    let new_nodes = nodes
+                        .into_iter()
+                        .zip(exists)
+                        .filter_map(|(node, exists)| {
+                            if exists {
+                                observers.release(&node);
+                            }
+                            (!exists).then_some(node)
+                        })
+                        .collect::<Vec<_>>();
+                    tracing::debug!(num_new = new_nodes.len(), "Skipping existing nodes");
+                    stage.record_dropped(DropReason::Deduplicated, num_nodes - new_nodes.len());
+                    Ok(IndexingStream::from_nodes(new_nodes))
+                }
+            });
+        self.stream = if self.ordered {
+            stream.try_buffered(self.concurrency).try_flatten().boxed()
+        } else {
+            stream
+                .try_buffer_unordered(self.concurrency)
+                .try_flatten_unordered(None)
+                .boxed()
+        }
+        .into();
+        self
+    }
 
-        transformer.expect_transform_node().returning(|mut node| {
-            node.chunk = "transformed".to_string();
-            Ok(node)
-        });
+    fn add_stage(&mut self, name: impl Into<String>) -> Arc<StageCollector> {
+        let stage = StageCollector::new(name);
+        self.stages.push(Arc::clone(&stage));
+        stage
+    }
+
+    fn add_unstaged_drops(&mut self) -> Arc<DropCollector> {
+        let skipped = Arc::new(DropCollector::default());
+        self.unstaged_drops.push(Arc::clone(&skipped));
+        skipped
+    }
+}
+
+/// Batches nodes per key, emitting a batch once it holds `batch_size` nodes or its first node
+/// waited longer than `timeout`, and the remaining batches once the stream ends
+fn group_by_key(
+    stream: IndexingStream,
+    batch_size: usize,
+    timeout: Duration,
+    key: BatchKey,
+) -> futures_util::stream::BoxStream<'static, Result<Vec<Node>>> {
+    // Partial batches with the time their first node came in
+    let groups = HashMap::<String, (Instant, Vec<Node>)>::new();
+
+    futures_util::stream::unfold(
+        (stream, groups, None::<std::vec::IntoIter<Vec<Node>>>),
+        move |(mut stream, mut groups, mut remaining)| {
+            let key = Arc::clone(&key);
+            async move {
+                if let Some(batches) = remaining.as_mut() {
+                    let batch = batches.next()?;
+                    return Some((Ok(batch), (stream, groups, remaining)));
+                }
+
+                loop {
+                    let oldest = groups.values().map(|(started, _)| *started).min();
+                    let next = match oldest {
+                        Some(started) => {
+                            let deadline = tokio::time::Instant::from_std(started + timeout);
+                            let Ok(next) = tokio::time::timeout_at(deadline, stream.next()).await
+                            else {
+                                let batch = take_oldest_group(&mut groups);
+                                return Some((Ok(batch), (stream, groups, remaining)));
+                            };
+                            next
+                        }
+                        None => stream.next().await,
+                    };
+                    let Some(result) = next else {
+                        break;
+                    };
+                    let node = match result {
+                        Ok(node) => node,
+                        Err(err) => return Some((Err(err), (stream, groups, remaining))),
+                    };
+
+                    let key = key(&node);
+                    let (_, group) = groups
+                        .entry(key.clone())
+                        .or_insert_with(|| (Instant::now(), Vec::new()));
+                    group.push(node);
+                    if group.len() >= batch_size {
+                        let batch = groups
+                            .remove(&key)
+                            .map(|(_, batch)| batch)
+                            .unwrap_or_default();
+                        return Some((Ok(batch), (stream, groups, remaining)));
+                    }
+                }
+
+                let mut batches = groups
+                    .drain()
+                    .map(|(_, (_, batch))| batch)
+                    .collect::<Vec<_>>()
+                    .into_iter();
+                let batch = batches.next()?;
+                Some((Ok(batch), (stream, groups, Some(batches))))
+            }
+        },
+    )
+    .boxed()
+}
+
+/// Removes the partial batch that has waited the longest
+fn take_oldest_group(groups: &mut HashMap<String, (Instant, Vec<Node>)>) -> Vec<Node> {
+    let oldest = groups
+        .iter()
+        .min_by_key(|(_, (started, _))| *started)
+        .map(|(key, _)| key.clone());
+
+    oldest
+        .and_then(|key| groups.remove(&key))
+        .map(|(_, batch)| batch)
+        .unwrap_or_default()
+}
+
+/// Splits a batch so that the nodes of every batch serialize to at most `max_bytes`, keeping
+/// their order
+fn split_batch_by_bytes(nodes: Vec<Node>, max_bytes: usize) -> Vec<Vec<Node>> {
+    let mut batches = Vec::new();
+    let mut batch = Vec::new();
+    let mut batch_bytes = 0;
+
+    for node in nodes {
+        let bytes = serde_json::to_vec(&node).map_or(0, |serialized| serialized.len());
+
+        if !batch.is_empty() && batch_bytes + bytes > max_bytes {
+            batches.push(std::mem::take(&mut batch));
+            batch_bytes = 0;
+        }
+
+        batch_bytes += bytes;
+        batch.push(node);
+    }
+
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+
+    batches
+}
+
+/// Decouples the stream from its consumer with a channel of at most `capacity` nodes
+///
+/// The stream is driven by a task, spawned on the first poll, that waits while the channel is
+/// full and stops once the consumer is dropped.
+fn buffer_stream(stream: IndexingStream, capacity: usize) -> IndexingStream {
+    let (tx, rx) = mpsc::channel(capacity);
+    let forward = async move {
+        let mut stream = stream;
+        while let Some(item) = stream.next().await {
+            if tx.send(item).await.is_err() {
+                break;
+            }
+        }
+    }
+    .instrument(tracing::trace_span!("store_buffer", capacity));
+
+    let spawn = futures_util::stream::once(async move {
+        tokio::spawn(forward);
+    })
+    .filter_map(|()| futures_util::future::ready(None));
+
+    IndexingStream::from_stream(spawn.chain(tokio_stream::wrappers::ReceiverStream::new(rx)))
+}
+
+/// Awaits the tasks storing single nodes, at most `concurrency` at a time and passing on the
+/// stored nodes in order if `ordered`
+fn buffer_stored<S, F>(stream: S, ordered: bool, concurrency: usize) -> IndexingStream
+where
+    S: futures_util::Stream<Item = Result<F>> + Send + 'static,
+    F: std::future::Future<Output = Result<Result<Node>>> + Send + 'static,
+{
+    if ordered {
+        stream.try_buffered(concurrency).boxed()
+    } else {
+        stream.try_buffer_unordered(concurrency).boxed()
+    }
+    .map(|x| x.and_then(|x| x))
+    .boxed()
+    .into()
+}
+
+/// Counts the nodes and errors of a stream produced by a stage
+fn record_stream(stream: IndexingStream, stage: Arc<StageCollector>) -> IndexingStream {
+    stream
+        .inspect(move |result| stage.record(result))
+        .boxed()
+        .into()
+}
+
+/// The number of paths named when describing a failed batch
+const MAX_CONTEXT_PATHS: usize = 3;
+
+/// Describes the node a stage failed on, added as context to its error
+fn node_context(stage: &str, path: &Path) -> String {
+    if path.as_os_str().is_empty() {
+        format!("{stage} failed on a node without a path")
+    } else {
+        format!("{stage} failed on node {}", path.display())
+    }
+}
+
+/// The paths of the nodes in a batch, kept to describe the batch if a stage fails on it
+fn batch_paths(nodes: &[Node]) -> Vec<PathBuf> {
+    nodes.iter().map(|node| node.path.clone()).collect()
+}
+
+/// Describes the batch a stage failed on by the distinct paths of its nodes, added as context to
+/// its errors
+fn batch_context(stage: &str, paths: &[PathBuf]) -> String {
+    let num_nodes = paths.len();
+    let paths = paths
+        .iter()
+        .filter(|path| !path.as_os_str().is_empty())
+        .map(|path| path.display().to_string())
+        .unique()
+        .collect::<Vec<_>>();
+
+    let mut context = format!("{stage} failed on a batch of {num_nodes} nodes");
+    if !paths.is_empty() {
+        context.push_str(" from ");
+        context.push_str(&paths.iter().take(MAX_CONTEXT_PATHS).join(", "));
+    }
+    if paths.len() > MAX_CONTEXT_PATHS {
+        context = format!("{context} and {} more", paths.len() - MAX_CONTEXT_PATHS);
+    }
+    context
+}
+
+/// Adds context to the errors of a stream, only building it if an error occurs
+fn with_error_context(
+    stream: IndexingStream,
+    context: impl Fn() -> String + Send + 'static,
+) -> IndexingStream {
+    stream
+        .map(move |result| result.with_context(&context))
+        .boxed()
+        .into()
+}
+
+/// Sets the index of every chunk within the node it was chunked from
+fn with_chunk_index(stream: IndexingStream) -> IndexingStream {
+    stream
+        .enumerate()
+        .map(|(index, result)| {
+            result.map(|mut node| {
+                node.chunk_index = Some(index);
+                node
+            })
+        })
+        .boxed()
+        .into()
+}
+
+/// Counts the nodes and errors of a stream produced from a batch, recording the nodes missing
+/// from the output as dropped once the stream ends
+fn record_batch_stream(
+    stream: IndexingStream,
+    stage: Arc<StageCollector>,
+    num_nodes: usize,
+    reason: DropReason,
+) -> IndexingStream {
+    let produced = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&produced);
+
+    record_stream(stream, Arc::clone(&stage))
+        .inspect(move |_| {
+            counter.fetch_add(1, Ordering::Relaxed);
+        })
+        .chain(
+            futures_util::stream::once(async move {
+                let produced = produced.load(Ordering::Relaxed);
+                stage.record_dropped(reason, num_nodes.saturating_sub(produced));
+            })
+            .filter_map(|()| futures_util::future::ready(None)),
+        )
+        .boxed()
+        .into()
+}
+
+/// Filters a stream, recording skipped nodes and errors as dropped by the stage
+fn filter_recording_drops(
+    stream: IndexingStream,
+    stage: Option<Arc<StageCollector>>,
+    filter: impl Fn(&Result<Node>) -> bool + Send + Sync + 'static,
+) -> IndexingStream {
+    stream
+        .filter(move |result| {
+            let will_retain = filter(result);
+            if let Some(stage) = &stage {
+                match (result, will_retain) {
+                    (Ok(_), true) => stage.record(&Ok::<_, anyhow::Error>(())),
+                    (Ok(_), false) => stage.record_dropped(DropReason::Filtered, 1),
+                    (Err(_), false) => stage.record_dropped(DropReason::Errored, 1),
+                    (Err(_), true) => {}
+                }
+            }
+
+            async move { will_retain }
+        })
+        .boxed()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::persist::MemoryStorage;
+    use mockall::Sequence;
+    use swiftide_core::indexing::*;
+
+    /// Tests a simple run of the indexing pipeline.
+    #[test_log::test(tokio::test)]
+    async fn test_simple_run() {
+        let mut loader = MockLoader::new();
+        let mut transformer = MockTransformer::new();
+        let mut batch_transformer = MockBatchableTransformer::new();
+        let mut chunker = MockChunkerTransformer::new();
+        let mut storage = MockPersist::new();
+
+        let mut seq = Sequence::new();
+
+        loader
+            .expect_into_stream()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|| vec![Ok(Node::default())].into());
+
+        transformer.expect_transform_node().returning(|mut node| {
+            node.chunk = "transformed".to_string();
+            Ok(node)
+        });
         transformer.expect_concurrency().returning(|| None);
         transformer.expect_name().returning(|| "transformer");
 
@@ -648,6 +1645,59 @@ mod tests {
         pipeline.run().await.unwrap();
     }
 
+    #[tokio::test]
+    async fn test_chunks_get_their_index_for_composed_ids() {
+        let documents = ["a.md", "b.md"].map(|path| {
+            let mut node = Node::new("abcdefgh");
+            node.path = path.into();
+            node
+        });
+        let storage = MemoryStorage::default();
+
+        Pipeline::from_stream(documents.to_vec())
+            .then_chunk(crate::transformers::ChunkSlidingWindow::new(4, 4))
+            .then_store_with(storage.clone())
+            .run()
+            .await
+            .unwrap();
+
+        let mut ids = storage
+            .get_all_values()
+            .await
+            .iter()
+            .map(|node| node.chunk_id(ChunkIdFormat::PathAndIndex))
+            .collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, ["a.md#0", "a.md#1", "b.md#0", "b.md#1"]);
+    }
+
+    #[tokio::test]
+    async fn test_chunks_of_file_windows_get_unique_composed_ids() {
+        let dir = temp_dir::TempDir::new().unwrap();
+        std::fs::write(dir.child("a.log"), "abcdefg\nhijklmn\n").unwrap();
+        let storage = MemoryStorage::default();
+
+        Pipeline::from_loader(crate::loaders::FileLoader::new(dir.path()).with_window_bytes(8))
+            .then_chunk(crate::transformers::ChunkSlidingWindow::new(4, 4))
+            .then_store_with(storage.clone())
+            .run()
+            .await
+            .unwrap();
+
+        let path = dir.child("a.log").to_string_lossy().into_owned();
+        let mut ids = storage
+            .get_all_values()
+            .await
+            .iter()
+            .map(|node| node.chunk_id(ChunkIdFormat::PathAndIndex))
+            .collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(
+            ids,
+            ["0.0", "0.1", "1.0", "1.1"].map(|suffix| format!("{path}#{suffix}"))
+        );
+    }
+
     #[tokio::test]
     async fn test_skipping_errors() {
         let mut loader = MockLoader::new();
@@ -663,8 +1713,10 @@ mod tests {
             .expect_transform_node()
             .returning(|_node| Err(anyhow::anyhow!("Error transforming node")));
         transformer.expect_concurrency().returning(|| None);
+        transformer.expect_name().returning(|| "transformer");
         storage.expect_setup().returning(|| Ok(()));
         storage.expect_batch_size().returning(|| None);
+        storage.expect_name().returning(|| "storage");
         storage.expect_store().times(0).returning(Ok);
         let pipeline = Pipeline::from_loader(loader)
             .then(transformer)
@@ -765,6 +1817,66 @@ mod tests {
         assert_eq!(processed_node.chunk, "transformed");
     }
 
+    #[tokio::test]
+    async fn test_batch_transformer_receives_batches_of_configured_size() {
+        let mut batch_transformer = MockBatchableTransformer::new();
+        batch_transformer.expect_batch_size().returning(|| Some(4));
+        batch_transformer.expect_concurrency().returning(|| None);
+        batch_transformer
+            .expect_name()
+            .returning(|| "batch_size_tagger");
+        batch_transformer
+            .expect_batch_transform()
+            .times(3)
+            .returning(|nodes| {
+                let batch_size = nodes.len();
+                IndexingStream::iter(nodes.into_iter().map(move |mut node| {
+                    node.metadata.insert("batch_size", batch_size);
+                    Ok(node)
+                }))
+            });
+        let storage = MemoryStorage::default();
+
+        Pipeline::from_loader(NumberLoader(10))
+            .then_in_batch(batch_transformer)
+            .then_store_with(storage.clone())
+            .run()
+            .await
+            .unwrap();
+
+        let mut batch_sizes = storage
+            .get_all_values()
+            .await
+            .into_iter()
+            .map(|node| node.metadata.get("batch_size").unwrap().as_u64().unwrap())
+            .collect::<Vec<_>>();
+        batch_sizes.sort_unstable();
+
+        assert_eq!(batch_sizes, [2, 2, 4, 4, 4, 4, 4, 4, 4, 4]);
+    }
+
+    #[tokio::test]
+    async fn test_skip_existing_stores_nothing_the_second_run() {
+        let storage = MemoryStorage::default();
+
+        let first = Pipeline::from_loader(NumberLoader(5))
+            .skip_existing(true)
+            .then_store_with(storage.clone())
+            .run()
+            .await
+            .unwrap();
+        let second = Pipeline::from_loader(NumberLoader(5))
+            .skip_existing(true)
+            .then_store_with(storage.clone())
+            .run()
+            .await
+            .unwrap();
+
+        assert_eq!(first.total_nodes, 5);
+        assert_eq!(second.total_nodes, 0);
+        assert_eq!(storage.count().await.unwrap(), 5);
+    }
+
     #[tokio::test]
     async fn test_filter_closure() {
         let mut loader = MockLoader::new();
@@ -796,97 +1908,431 @@ mod tests {
         assert_eq!(nodes.len(), 2);
     }
 
-    #[test_log::test(tokio::test)]
-    async fn test_split_and_merge() {
+    #[tokio::test]
+    async fn test_store_to_multiple_storages() {
         let mut loader = MockLoader::new();
-        let storage = MemoryStorage::default();
-        let mut seq = Sequence::new();
         loader
             .expect_into_stream()
             .times(1)
-            .in_sequence(&mut seq)
-            .returning(|| {
-                vec![
-                    Ok(Node::default()),
-                    Ok(Node {
-                        chunk: "will go left".to_string(),
-                        ..Node::default()
-                    }),
-                    Ok(Node::default()),
-                ]
-                .into()
-            });
+            .returning(|| vec![Ok(Node::new("first")), Ok(Node::new("second"))].into());
+        let first = MemoryStorage::default();
+        let second = MemoryStorage::default();
+
+        Pipeline::from_loader(loader)
+            .then_store_to([
+                Box::new(first.clone()) as Box<dyn Persist>,
+                Box::new(second.clone()),
+            ])
+            .run()
+            .await
+            .unwrap();
 
-        let pipeline = Pipeline::from_loader(loader);
-        let (mut left, mut right) = pipeline.split_by(|node| {
-            if let Ok(node) = node {
-                node.chunk.starts_with("will go left")
-            } else {
-                false
-            }
+        for storage in [first, second] {
+            let mut chunks = storage
+                .get_all_values()
+                .await
+                .into_iter()
+                .map(|node| node.chunk)
+                .collect::<Vec<_>>();
+            chunks.sort();
+            assert_eq!(chunks, ["first", "second"]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_splits_batches_by_max_bytes() {
+        const MAX_BYTES: usize = 600;
+
+        let batch_bytes = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&batch_bytes);
+        let mut storage = MockPersist::new();
+        storage.expect_setup().returning(|| Ok(()));
+        storage.expect_name().returning(|| "storage");
+        storage.expect_batch_size().returning(|| Some(10));
+        storage
+            .expect_batch_max_bytes()
+            .returning(|| Some(MAX_BYTES));
+        storage.expect_batch_store().returning(move |nodes| {
+            let bytes = nodes
+                .iter()
+                .map(|node| serde_json::to_vec(node).unwrap().len())
+                .sum::<usize>();
+            recorded.lock().unwrap().push((nodes.len(), bytes));
+            IndexingStream::from_nodes(nodes)
         });
 
-        // change the chunk to 'left'
-        left = left
-            .then(move |mut node: Node| {
-                node.chunk = "left".to_string();
+        let nodes = [10, 300, 20, 250, 5, 400, 30, 1000, 15, 200]
+            .into_iter()
+            .map(|size| Node::new("x".repeat(size)))
+            .collect::<Vec<_>>();
 
-                Ok(node)
-            })
-            .log_all();
+        let stats = Pipeline::from_stream(nodes)
+            .then_store_with(storage)
+            .run()
+            .await
+            .unwrap();
 
-        right = right.then(move |mut node: Node| {
-            node.chunk = "right".to_string();
-            Ok(node)
+        let batch_bytes = batch_bytes.lock().unwrap().clone();
+        assert!(batch_bytes.len() > 1);
+        assert_eq!(batch_bytes.iter().map(|(len, _)| len).sum::<usize>(), 10);
+        assert!(batch_bytes
+            .iter()
+            .all(|(len, bytes)| *bytes <= MAX_BYTES || *len == 1));
+        assert_eq!(stats.total_nodes, 10);
+    }
+
+    #[tokio::test]
+    async fn test_batch_by_never_mixes_keys() {
+        let batches = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&batches);
+        let mut storage = MockPersist::new();
+        storage.expect_setup().returning(|| Ok(()));
+        storage.expect_name().returning(|| "storage");
+        storage.expect_batch_size().returning(|| Some(3));
+        storage.expect_batch_max_bytes().returning(|| None);
+        storage.expect_batch_store().returning(move |nodes| {
+            recorded.lock().unwrap().push(
+                nodes
+                    .iter()
+                    .map(|node| node.metadata.get("tenant").unwrap().to_string())
+                    .collect::<Vec<_>>(),
+            );
+            IndexingStream::from_nodes(nodes)
         });
 
-        left.merge(right)
-            .then_store_with(storage.clone())
+        let nodes = (0..10)
+            .map(|i| {
+                let mut node = Node::new(format!("node {i}"));
+                node.metadata
+                    .insert("tenant", if i % 3 == 0 { "a" } else { "b" });
+                node
+            })
+            .collect::<Vec<_>>();
+
+        let stats = Pipeline::from_stream(nodes)
+            .batch_by(|node| node.metadata.get("tenant").unwrap().to_string())
+            .then_store_with(storage)
             .run()
             .await
             .unwrap();
-        dbg!(storage.clone());
 
-        let all_nodes = storage.get_all_values().await;
+        let batches = batches.lock().unwrap().clone();
+        assert_eq!(stats.total_nodes, 10);
+        assert_eq!(batches.iter().map(Vec::len).sum::<usize>(), 10);
+        assert!(batches.iter().all(|batch| batch.len() <= 3));
+        assert!(batches
+            .iter()
+            .all(|batch| batch.iter().all(|tenant| *tenant == batch[0])));
+    }
+
+    #[tokio::test]
+    async fn test_batch_by_stores_partial_batches_after_timeout() {
+        let (sender, receiver) = tokio::sync::mpsc::channel(10);
+        let key: BatchKey = Arc::new(|node: &Node| node.chunk.clone());
+        let mut batches = group_by_key(
+            IndexingStream::from_stream(tokio_stream::wrappers::ReceiverStream::new(receiver)),
+            3,
+            Duration::from_millis(10),
+            key,
+        );
+
+        sender.send(Ok(Node::new("a"))).await.unwrap();
+        sender.send(Ok(Node::new("b"))).await.unwrap();
+        sender.send(Ok(Node::new("a"))).await.unwrap();
+
+        // The stream has not ended, yet the partial batches are emitted
+        let first = tokio::time::timeout(Duration::from_secs(5), batches.next())
+            .await
+            .expect("partial batch was not emitted")
+            .unwrap()
+            .unwrap();
+        let second = batches.next().await.unwrap().unwrap();
         assert_eq!(
-            all_nodes.iter().filter(|node| node.chunk == "left").count(),
-            1
+            first.iter().map(|node| &node.chunk).collect::<Vec<_>>(),
+            ["a", "a"]
         );
         assert_eq!(
-            all_nodes
-                .iter()
-                .filter(|node| node.chunk == "right")
-                .count(),
-            2
+            second.iter().map(|node| &node.chunk).collect::<Vec<_>>(),
+            ["b"]
         );
+
+        drop(sender);
+        assert!(batches.next().await.is_none());
     }
 
+    #[tracing_test::traced_test]
     #[tokio::test]
-    async fn test_all_steps_should_work_as_dyn_box() {
-        let mut loader = MockLoader::new();
-        loader
-            .expect_into_stream_boxed()
-            .returning(|| vec![Ok(Node::default())].into());
+    async fn test_batch_by_warns_without_storage_batch_size() {
+        let mut storage = MockPersist::new();
+        storage.expect_setup().returning(|| Ok(()));
+        storage.expect_name().returning(|| "storage");
+        storage.expect_batch_size().returning(|| None);
+        storage.expect_store().returning(Ok);
+
+        let stats = Pipeline::from_stream(vec![Node::new("a"), Node::new("b")])
+            .batch_by(|node| node.chunk.clone())
+            .then_store_with(storage)
+            .run()
+            .await
+            .unwrap();
+
+        assert_eq!(stats.total_nodes, 2);
+        assert!(logs_contain("batch_by has no effect"));
+    }
+
+    /// Stores nodes one by one and counts how often it is flushed
+    #[derive(Debug, Clone, Default)]
+    struct CountFlushes(Arc<AtomicUsize>);
+
+    #[async_trait::async_trait]
+    impl Persist for CountFlushes {
+        async fn setup(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn store(&self, node: Node) -> Result<Node> {
+            Ok(node)
+        }
+
+        async fn batch_store(&self, nodes: Vec<Node>) -> IndexingStream {
+            IndexingStream::from_nodes(nodes)
+        }
+
+        async fn flush(&self) -> Result<()> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flushes_storage_when_run_fails() {
+        let storage = CountFlushes::default();
+
+        let result = Pipeline::from_stream(vec![
+            Ok(Node::new("stored")),
+            Err(anyhow::anyhow!("Failed")),
+        ])
+        .then_store_with(storage.clone())
+        .run()
+        .await;
+
+        assert_eq!(result.unwrap_err().to_string(), "Failed");
+        assert_eq!(storage.0.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_store_to_aggregates_errors() {
+        let mut loader = MockLoader::new();
+        loader
+            .expect_into_stream()
+            .times(1)
+            .returning(|| vec![Ok(Node::default())].into());
+
+        let mut failing = MockPersist::new();
+        failing.expect_setup().returning(|| Ok(()));
+        failing
+            .expect_store()
+            .returning(|_| Err(anyhow::anyhow!("connection refused")));
+        failing.expect_name().returning(|| "failing");
+
+        let storage = MemoryStorage::default();
+
+        let err = Pipeline::from_loader(loader)
+            .then_store_to([
+                Box::new(failing) as Box<dyn Persist>,
+                Box::new(storage.clone()),
+            ])
+            .run()
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            format!("{err:#}"),
+            "then_store_to failed on a node without a path: Failed to store node in 1 of 2 \
+             storages: failing: connection refused"
+        );
+        assert_eq!(storage.get_all().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_errors_name_the_stage_and_node() {
+        let nodes = ["ok.md", "broken.md"].map(|path| {
+            let mut node = Node::new("chunk");
+            node.path = path.into();
+            Ok(node)
+        });
+
+        let mut transformer = MockTransformer::new();
+        transformer.expect_transform_node().returning(|node| {
+            if node.path.ends_with("broken.md") {
+                Err(anyhow::anyhow!("unexpected token"))
+            } else {
+                Ok(node)
+            }
+        });
+        transformer.expect_concurrency().returning(|| None);
+        transformer.expect_name().returning(|| "parse_markdown");
+
+        let err = Pipeline::from_stream(Vec::from(nodes))
+            .then(transformer)
+            .then_store_with(MemoryStorage::default())
+            .run()
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            format!("{err:#}"),
+            "parse_markdown failed on node broken.md: unexpected token"
+        );
+        assert_eq!(err.root_cause().to_string(), "unexpected token");
+    }
+
+    #[test]
+    fn test_batch_context_names_distinct_paths() {
+        let nodes = ["a.md", "a.md", "b.md", "", "c.md", "d.md"]
+            .map(|path| Node {
+                path: path.into(),
+                ..Default::default()
+            })
+            .to_vec();
+
+        assert_eq!(
+            batch_context("embed", &batch_paths(&nodes)),
+            "embed failed on a batch of 6 nodes from a.md, b.md, c.md and 1 more"
+        );
+        assert_eq!(
+            batch_context("embed", &batch_paths(&[Node::default()])),
+            "embed failed on a batch of 1 nodes"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_map_stream() {
+        let mut loader = MockLoader::new();
+        let storage = MemoryStorage::default();
+        loader
+            .expect_into_stream()
+            .times(1)
+            .returning(|| vec![Ok(Node::default()), Ok(Node::default())].into());
+
+        let inspected = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = Arc::clone(&inspected);
+
+        Pipeline::from_loader(loader)
+            .map_stream(move |stream| {
+                stream
+                    .inspect_ok(move |_| {
+                        counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    })
+                    .boxed()
+                    .into()
+            })
+            .then_store_with(storage.clone())
+            .run()
+            .await
+            .unwrap();
+
+        assert_eq!(inspected.load(std::sync::atomic::Ordering::Relaxed), 2);
+        assert_eq!(storage.get_all().await.len(), 2);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_split_and_merge() {
+        let mut loader = MockLoader::new();
+        let storage = MemoryStorage::default();
+        let mut seq = Sequence::new();
+        loader
+            .expect_into_stream()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|| {
+                vec![
+                    Ok(Node::default()),
+                    Ok(Node {
+                        chunk: "will go left".to_string(),
+                        ..Node::default()
+                    }),
+                    Ok(Node::default()),
+                ]
+                .into()
+            });
+
+        let pipeline = Pipeline::from_loader(loader);
+        let (mut left, mut right) = pipeline.split_by(|node| {
+            if let Ok(node) = node {
+                node.chunk.starts_with("will go left")
+            } else {
+                false
+            }
+        });
+
+        // change the chunk to 'left'
+        left = left
+            .then(move |mut node: Node| {
+                node.chunk = "left".to_string();
+
+                Ok(node)
+            })
+            .log_all();
+
+        right = right.then(move |mut node: Node| {
+            node.chunk = "right".to_string();
+            Ok(node)
+        });
+
+        left.merge(right)
+            .then_store_with(storage.clone())
+            .run()
+            .await
+            .unwrap();
+        dbg!(storage.clone());
+
+        let all_nodes = storage.get_all_values().await;
+        assert_eq!(
+            all_nodes.iter().filter(|node| node.chunk == "left").count(),
+            1
+        );
+        assert_eq!(
+            all_nodes
+                .iter()
+                .filter(|node| node.chunk == "right")
+                .count(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_all_steps_should_work_as_dyn_box() {
+        let mut loader = MockLoader::new();
+        loader
+            .expect_into_stream_boxed()
+            .returning(|| vec![Ok(Node::default())].into());
 
         let mut transformer = MockTransformer::new();
         transformer.expect_transform_node().returning(Ok);
         transformer.expect_concurrency().returning(|| None);
+        transformer.expect_name().returning(|| "transformer");
 
         let mut batch_transformer = MockBatchableTransformer::new();
         batch_transformer
             .expect_batch_transform()
             .returning(std::convert::Into::into);
         batch_transformer.expect_concurrency().returning(|| None);
+        batch_transformer
+            .expect_name()
+            .returning(|| "batch_transformer");
         let mut chunker = MockChunkerTransformer::new();
         chunker
             .expect_transform_node()
             .returning(|node| vec![node].into());
         chunker.expect_concurrency().returning(|| None);
+        chunker.expect_name().returning(|| "chunker");
 
         let mut storage = MockPersist::new();
         storage.expect_setup().returning(|| Ok(()));
         storage.expect_store().returning(Ok);
         storage.expect_batch_size().returning(|| None);
+        storage.expect_name().returning(|| "storage");
 
         let pipeline = Pipeline::from_loader(Box::new(loader) as Box<dyn Loader>)
             .then(Box::new(transformer) as Box<dyn Transformer>)
@@ -895,4 +2341,692 @@ mod tests {
             .then_store_with(Box::new(storage) as Box<dyn Persist>);
         pipeline.run().await.unwrap();
     }
+
+    /// Stores slowly, recording the highest number of concurrent batch stores
+    #[derive(Debug, Clone, Default)]
+    struct SlowStorage {
+        in_flight: Arc<std::sync::atomic::AtomicUsize>,
+        max_in_flight: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl Persist for SlowStorage {
+        async fn setup(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn store(&self, node: Node) -> Result<Node> {
+            Ok(node)
+        }
+
+        async fn batch_store(&self, nodes: Vec<Node>) -> IndexingStream {
+            use std::sync::atomic::Ordering;
+
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            IndexingStream::iter(nodes.into_iter().map(Ok))
+        }
+
+        fn batch_size(&self) -> Option<usize> {
+            Some(2)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_stores_run_concurrently_up_to_the_limit() {
+        let storage = SlowStorage::default();
+
+        let stats = Pipeline::from_loader(NumberLoader(20))
+            .with_concurrency(8)
+            .with_store_concurrency(3)
+            .then_store_with(storage.clone())
+            .run()
+            .await
+            .unwrap();
+
+        assert_eq!(stats.total_nodes, 20);
+        assert_eq!(
+            storage
+                .max_in_flight
+                .load(std::sync::atomic::Ordering::SeqCst),
+            3
+        );
+    }
+
+    /// Stores slowly, recording how many embedded nodes were not stored yet at most
+    #[derive(Debug, Clone, Default)]
+    struct BacklogStorage {
+        embedded: Arc<std::sync::atomic::AtomicUsize>,
+        stored: Arc<std::sync::atomic::AtomicUsize>,
+        max_backlog: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl BacklogStorage {
+        fn record_embedded(&self) {
+            use std::sync::atomic::Ordering;
+
+            let embedded = self.embedded.fetch_add(1, Ordering::SeqCst) + 1;
+            let backlog = embedded - self.stored.load(Ordering::SeqCst);
+            self.max_backlog.fetch_max(backlog, Ordering::SeqCst);
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Persist for BacklogStorage {
+        async fn setup(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn store(&self, node: Node) -> Result<Node> {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            self.stored
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(node)
+        }
+
+        async fn batch_store(&self, nodes: Vec<Node>) -> IndexingStream {
+            IndexingStream::iter(nodes.into_iter().map(Ok))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_store_buffer_bounds_embedded_nodes_waiting_to_be_stored() {
+        const BUFFER: usize = 4;
+        let storage = BacklogStorage::default();
+
+        let mut embedding_model = MockEmbeddingModel::new();
+        let recorder = storage.clone();
+        embedding_model.expect_embed().returning(move |input| {
+            for _ in &input {
+                recorder.record_embedded();
+            }
+            Ok(vec![vec![1.0; 3]; input.len()])
+        });
+
+        let stats = Pipeline::from_loader(NumberLoader(40))
+            .with_concurrency(1)
+            .with_store_buffer(BUFFER)
+            .then_in_batch(crate::transformers::Embed::new(embedding_model).with_batch_size(1))
+            .then_store_with(storage.clone())
+            .run()
+            .await
+            .unwrap();
+
+        assert_eq!(stats.total_nodes, 40);
+        // The buffer, the node being stored, the node being sent and the node being embedded
+        let max_backlog = storage
+            .max_backlog
+            .load(std::sync::atomic::Ordering::SeqCst);
+        assert!(max_backlog <= BUFFER + 3, "{max_backlog} nodes waited");
+        assert!(max_backlog > 1, "Embedding should run ahead of storing");
+    }
+
+    #[tokio::test]
+    async fn test_embed_and_store_matches_staged_embedding_and_storing() {
+        fn embedding_model() -> MockEmbeddingModel {
+            let mut model = MockEmbeddingModel::new();
+            model.expect_embed().returning(|input| {
+                Ok(input
+                    .iter()
+                    .map(|text| vec![f32::from(u16::try_from(text.len()).unwrap()), 1.0])
+                    .collect())
+            });
+            model
+        }
+        let embed = || crate::transformers::Embed::new(embedding_model()).with_batch_size(3);
+        let stored_nodes = |storage: MemoryStorage| async move {
+            let mut nodes = storage.get_all_values().await;
+            nodes.sort_by(|a, b| a.chunk.cmp(&b.chunk));
+            nodes
+        };
+
+        let staged = MemoryStorage::default();
+        let staged_stats = Pipeline::from_loader(NumberLoader(10))
+            .then_in_batch(embed())
+            .then_store_with(staged.clone())
+            .run()
+            .await
+            .unwrap();
+
+        let fused = MemoryStorage::default();
+        let fused_stats = Pipeline::from_loader(NumberLoader(10))
+            .then_embed_and_store(embed(), fused.clone())
+            .run()
+            .await
+            .unwrap();
+
+        let fused_nodes = stored_nodes(fused).await;
+        assert_eq!(fused_nodes.len(), 10);
+        assert!(fused_nodes.iter().all(|node| node.vectors.is_some()));
+        assert_eq!(fused_nodes, stored_nodes(staged).await);
+        assert_eq!(fused_stats.total_nodes, staged_stats.total_nodes);
+        assert_eq!(
+            fused_stats
+                .stages
+                .iter()
+                .map(|stage| (stage.name.as_str(), stage.nodes))
+                .collect::<Vec<_>>(),
+            staged_stats
+                .stages
+                .iter()
+                .map(|stage| (stage.name.as_str(), stage.nodes))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    /// Takes longer the lower the number in the chunk, so that later nodes finish first
+    #[derive(Debug, Clone)]
+    struct DelayedTransformer;
+
+    impl WithIndexingDefaults for DelayedTransformer {}
+
+    #[async_trait::async_trait]
+    impl Transformer for DelayedTransformer {
+        async fn transform_node(&self, node: Node) -> Result<Node> {
+            let number = node.chunk.parse::<u64>()?;
+            tokio::time::sleep(Duration::from_millis(50 - number * 5)).await;
+            Ok(node)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ordered_preserves_input_order() {
+        let mut chunker = MockChunkerTransformer::new();
+        chunker
+            .expect_transform_node()
+            .returning(|node| IndexingStream::iter(vec![Ok(node)]));
+        chunker.expect_concurrency().returning(|| None);
+        chunker.expect_name().returning(|| "chunker");
+
+        let pipeline = Pipeline::from_loader(NumberLoader(10))
+            .with_concurrency(10)
+            .ordered(true)
+            .then(DelayedTransformer)
+            .then_chunk(chunker);
+
+        let chunks: Vec<String> = pipeline
+            .stream
+            .map_ok(|node| node.chunk)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(chunks, (0..10).map(|i| i.to_string()).collect::<Vec<_>>());
+    }
+
+    /// Takes longer to store the lower the number in the chunk, so that later nodes finish first
+    #[derive(Debug, Clone)]
+    struct DelayedStorage;
+
+    #[async_trait::async_trait]
+    impl Persist for DelayedStorage {
+        async fn setup(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn store(&self, node: Node) -> Result<Node> {
+            let number = node.chunk.parse::<u64>()?;
+            tokio::time::sleep(Duration::from_millis(50 - number * 5)).await;
+            Ok(node)
+        }
+
+        async fn batch_store(&self, nodes: Vec<Node>) -> IndexingStream {
+            IndexingStream::iter(nodes.into_iter().map(Ok))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_store_to_preserves_order_when_ordered() {
+        let pipeline = Pipeline::from_loader(NumberLoader(10))
+            .with_concurrency(10)
+            .ordered(true)
+            .then_store_to([Box::new(DelayedStorage) as Box<dyn Persist>]);
+
+        let chunks: Vec<String> = pipeline
+            .stream
+            .map_ok(|node| node.chunk)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(chunks, (0..10).map(|i| i.to_string()).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_store_to_skips_nodes_existing_in_all_storages() {
+        let first = MemoryStorage::default();
+        let second = MemoryStorage::default();
+        Pipeline::from_loader(NumberLoader(3))
+            .then_store_with(first.clone())
+            .run()
+            .await
+            .unwrap();
+
+        let stats = Pipeline::from_loader(NumberLoader(5))
+            .skip_existing(true)
+            .then_store_to([
+                Box::new(first.clone()) as Box<dyn Persist>,
+                Box::new(second.clone()),
+            ])
+            .run()
+            .await
+            .unwrap();
+        assert_eq!(stats.total_nodes, 5);
+        assert_eq!(second.count().await.unwrap(), 5);
+
+        let stats = Pipeline::from_loader(NumberLoader(5))
+            .skip_existing(true)
+            .then_store_to([
+                Box::new(first.clone()) as Box<dyn Persist>,
+                Box::new(second.clone()),
+            ])
+            .run()
+            .await
+            .unwrap();
+        assert_eq!(stats.total_nodes, 0);
+    }
+
+    #[derive(Clone)]
+    struct NumberLoader(usize);
+
+    impl Loader for NumberLoader {
+        fn into_stream(self) -> IndexingStream {
+            IndexingStream::from_nodes((0..self.0).map(|i| Node::new(i.to_string())))
+        }
+
+        fn into_stream_boxed(self: Box<Self>) -> IndexingStream {
+            self.into_stream()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_consecutive_errors_aborts_run() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let mut transformer = MockTransformer::new();
+        transformer.expect_transform_node().returning(move |_| {
+            counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Err(anyhow::anyhow!("Provider unavailable"))
+        });
+        transformer.expect_concurrency().returning(|| None);
+        transformer.expect_name().returning(|| "transformer");
+
+        let storage = MemoryStorage::default();
+        let result = Pipeline::from_stream(
+            (0..100)
+                .map(|i| Node::new(i.to_string()))
+                .collect::<Vec<_>>(),
+        )
+        .with_concurrency(1)
+        .then(transformer)
+        .max_consecutive_errors(5)
+        .then_store_with(storage.clone())
+        .run()
+        .await;
+
+        let err = result.unwrap_err();
+        assert_eq!(err.to_string(), "Aborting after 5 consecutive errors");
+        assert!(calls.load(std::sync::atomic::Ordering::SeqCst) < 10);
+        assert!(storage.get_all_values().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_max_consecutive_errors_resets_on_success() {
+        let mut transformer = MockTransformer::new();
+        transformer.expect_transform_node().returning(|node| {
+            if node.chunk == "ok" {
+                Ok(node)
+            } else {
+                Err(anyhow::anyhow!("Failed"))
+            }
+        });
+        transformer.expect_concurrency().returning(|| None);
+        transformer.expect_name().returning(|| "transformer");
+
+        let chunks = ["fail", "fail", "ok", "fail", "fail", "ok"];
+        let storage = MemoryStorage::default();
+        Pipeline::from_stream(chunks.map(Node::new).to_vec())
+            .with_concurrency(1)
+            .then(transformer)
+            .max_consecutive_errors(3)
+            .then_store_with(storage.clone())
+            .run()
+            .await
+            .unwrap();
+
+        assert_eq!(storage.get_all_values().await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_custom_loader() {
+        let storage = MemoryStorage::default();
+
+        Pipeline::from_loader(NumberLoader(3))
+            .then_store_with(storage.clone())
+            .run()
+            .await
+            .unwrap();
+
+        let mut chunks = storage
+            .get_all_values()
+            .await
+            .into_iter()
+            .map(|node| node.chunk)
+            .collect::<Vec<_>>();
+        chunks.sort();
+        assert_eq!(chunks, ["0", "1", "2"]);
+    }
+
+    #[tokio::test]
+    async fn test_from_stream() {
+        let storage = MemoryStorage::default();
+        let stream = IndexingStream::from_stream(futures_util::stream::iter(vec![
+            Ok(Node::new("first")),
+            Ok(Node::new("second")),
+        ]));
+
+        Pipeline::from_stream(stream)
+            .then(|mut node: Node| {
+                node.chunk = node.chunk.to_uppercase();
+                Ok(node)
+            })
+            .then_store_with(storage.clone())
+            .run()
+            .await
+            .unwrap();
+
+        let mut chunks = storage
+            .get_all_values()
+            .await
+            .into_iter()
+            .map(|node| node.chunk)
+            .collect::<Vec<_>>();
+        chunks.sort();
+        assert_eq!(chunks, ["FIRST", "SECOND"]);
+    }
+
+    #[tokio::test]
+    async fn test_run_returns_stats() {
+        let mut loader = MockLoader::new();
+        loader.expect_into_stream().returning(|| {
+            vec![
+                Ok(Node::new("first")),
+                Ok(Node::new("second")),
+                Ok(Node::new("fail")),
+            ]
+            .into()
+        });
+
+        let mut transformer = MockTransformer::new();
+        transformer.expect_transform_node().returning(|node| {
+            if node.chunk == "fail" {
+                Err(anyhow::anyhow!("Error transforming node"))
+            } else {
+                Ok(node)
+            }
+        });
+        transformer.expect_concurrency().returning(|| None);
+        transformer.expect_name().returning(|| "transformer");
+
+        let stats = Pipeline::from_loader(loader)
+            .then(transformer)
+            .filter_errors()
+            .then_store_with(MemoryStorage::default())
+            .run()
+            .await
+            .unwrap();
+
+        assert_eq!(stats.total_nodes, 2);
+        assert_eq!(stats.stages.len(), 2);
+
+        let transformer = &stats.stages[0];
+        assert_eq!(transformer.name, "transformer");
+        assert_eq!(transformer.nodes, 2);
+        assert_eq!(transformer.errors, 1);
+
+        let storage = &stats.stages[1];
+        assert_eq!(storage.nodes, 2);
+        assert_eq!(storage.errors, 0);
+    }
+
+    #[tokio::test]
+    async fn test_drop_empty_chunks() {
+        let mut chunker = MockChunkerTransformer::new();
+        chunker
+            .expect_transform_node()
+            .returning(|node| vec![node.clone(), Node::new(" \t"), node].into());
+        chunker.expect_concurrency().returning(|| None);
+        chunker.expect_name().returning(|| "chunker");
+
+        let storage = MemoryStorage::default();
+        let stats = Pipeline::from_stream(vec![
+            Node::new("first"),
+            Node::new(""),
+            Node::new("\n  "),
+            Node::new("second"),
+        ])
+        .drop_empty_chunks(true)
+        .then_chunk(chunker)
+        .then_store_with(storage.clone())
+        .run()
+        .await
+        .unwrap();
+
+        let chunks = storage
+            .get_all_values()
+            .await
+            .into_iter()
+            .map(|node| node.chunk)
+            .collect::<Vec<_>>();
+        assert_eq!(chunks.len(), 4);
+        assert!(chunks.iter().all(|chunk| !chunk.trim().is_empty()));
+        // Two empty nodes of the loader and two empty chunks
+        assert_eq!(stats.dropped.filtered, 4);
+    }
+
+    #[tokio::test]
+    async fn test_drop_empty_chunks_regardless_of_order() {
+        let mut chunker = MockChunkerTransformer::new();
+        chunker
+            .expect_transform_node()
+            .returning(|node| vec![node, Node::new(" ")].into());
+        chunker.expect_concurrency().returning(|| None);
+        chunker.expect_name().returning(|| "chunker");
+
+        let storage = MemoryStorage::default();
+        let stats = Pipeline::from_stream(vec![Node::new("first"), Node::new("second")])
+            .then_chunk(chunker)
+            .drop_empty_chunks(true)
+            .then(Ok)
+            // Enabling it again does not add another filter
+            .drop_empty_chunks(true)
+            .then_store_with(storage.clone())
+            .run()
+            .await
+            .unwrap();
+
+        assert_eq!(storage.get_all().await.len(), 2);
+        assert_eq!(stats.stages[0].dropped.filtered, 2);
+        assert_eq!(stats.dropped.filtered, 2);
+        // Kept chunks are only counted once by the chunker that produced them
+        assert_eq!(stats.stages[0].nodes, 4);
+        assert_eq!(stats.stages[1].nodes, 2);
+    }
+
+    #[tokio::test]
+    async fn test_stats_attribute_drops_to_stages() {
+        let node = |chunk: &str, vector: Vec<f32>| {
+            let mut node = Node::new(chunk);
+            node.with_vectors([(EmbeddedField::Combined, vector)]);
+            Ok(node)
+        };
+        let stream = vec![
+            node("keep", vec![1.0, 0.0]),
+            node("keep duplicate", vec![1.0, 0.0]),
+            node("skip", vec![0.0, 1.0]),
+            node("keep other", vec![0.0, 1.0]),
+            Err(anyhow::anyhow!("Failed")),
+        ];
+
+        let stats = Pipeline::from_stream(stream)
+            .filter_errors()
+            .filter(|result| {
+                result
+                    .as_ref()
+                    .map_or(true, |node| node.chunk.starts_with("keep"))
+            })
+            .then_in_batch(crate::transformers::SemanticDedup::new(0.99))
+            .then_store_with(MemoryStorage::default())
+            .run()
+            .await
+            .unwrap();
+
+        assert_eq!(stats.total_nodes, 2);
+
+        let filter = &stats.stages[0];
+        assert_eq!(filter.name, "filter");
+        assert_eq!(filter.nodes, 3);
+        assert_eq!(
+            filter.dropped,
+            DroppedNodes {
+                filtered: 1,
+                ..Default::default()
+            }
+        );
+
+        let dedup = &stats.stages[1];
+        assert_eq!(dedup.name, "SemanticDedup");
+        assert_eq!(dedup.nodes, 2);
+        assert_eq!(
+            dedup.dropped,
+            DroppedNodes {
+                deduplicated: 1,
+                ..Default::default()
+            }
+        );
+
+        assert_eq!(
+            stats.dropped,
+            DroppedNodes {
+                filtered: 1,
+                deduplicated: 1,
+                errored: 1,
+            }
+        );
+    }
+
+    /// Counts the nodes per source that are neither released nor stored yet
+    #[derive(Debug, Default)]
+    struct CountingObserver(std::sync::Mutex<HashMap<String, i64>>);
+
+    impl NodeObserver for CountingObserver {
+        fn key(&self, node: &Node) -> Option<String> {
+            node.metadata
+                .get("source")
+                .and_then(|source| source.as_str())
+                .map(ToString::to_string)
+        }
+
+        fn added(&self, key: &str) {
+            *self.0.lock().unwrap().entry(key.to_string()).or_default() += 1;
+        }
+
+        fn released(&self, key: &str) {
+            *self.0.lock().unwrap().entry(key.to_string()).or_default() -= 1;
+        }
+    }
+
+    #[derive(Clone)]
+    struct ObservedLoader(Arc<CountingObserver>);
+
+    impl Loader for ObservedLoader {
+        fn into_stream(self) -> IndexingStream {
+            IndexingStream::from_nodes(
+                ["chunked", "filtered", "failed", "deduplicated"]
+                    .into_iter()
+                    .map(|source| {
+                        *self
+                            .0
+                             .0
+                            .lock()
+                            .unwrap()
+                            .entry(source.to_string())
+                            .or_default() += 1;
+                        let mut node = Node::new(source);
+                        node.metadata.insert("source", source);
+                        node
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        }
+
+        fn into_stream_boxed(self: Box<Self>) -> IndexingStream {
+            self.into_stream()
+        }
+
+        fn node_observer(&self) -> Option<Arc<dyn NodeObserver>> {
+            Some(Arc::clone(&self.0) as Arc<dyn NodeObserver>)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_node_observer_is_told_about_nodes_that_are_not_stored() {
+        let observer = Arc::new(CountingObserver::default());
+        let mut chunker = MockChunkerTransformer::new();
+        chunker.expect_transform_node().returning(|node| {
+            if node.chunk == "chunked" {
+                let chunks = ["first", "second"].map(|chunk| Node {
+                    chunk: chunk.to_string(),
+                    ..node.clone()
+                });
+                chunks.to_vec().into()
+            } else {
+                vec![node].into()
+            }
+        });
+        chunker.expect_concurrency().returning(|| None);
+        chunker.expect_name().returning(|| "chunker");
+        let dedup = |nodes: Vec<Node>| {
+            IndexingStream::from_nodes(
+                nodes
+                    .into_iter()
+                    .filter(|node| node.chunk != "deduplicated")
+                    .collect::<Vec<_>>(),
+            )
+        };
+        let storage = MemoryStorage::default();
+
+        Pipeline::from_loader(ObservedLoader(Arc::clone(&observer)))
+            .then_chunk(chunker)
+            .filter(|result| {
+                result
+                    .as_ref()
+                    .map_or(true, |node| node.chunk != "filtered")
+            })
+            .then(|node: Node| {
+                if node.chunk == "failed" {
+                    anyhow::bail!("Failed")
+                }
+                Ok(node)
+            })
+            .filter_errors()
+            .then_in_batch(dedup)
+            .then_store_with(storage.clone())
+            .run()
+            .await
+            .unwrap();
+
+        let stored = storage.get_all_values().await;
+        assert_eq!(stored.len(), 2);
+        assert_eq!(
+            *observer.0.lock().unwrap(),
+            HashMap::from([
+                ("chunked".to_string(), 2),
+                ("filtered".to_string(), 0),
+                ("failed".to_string(), 0),
+                ("deduplicated".to_string(), 0),
+            ])
+        );
+    }
 }
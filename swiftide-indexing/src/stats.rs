@@ -0,0 +1,169 @@
+//! Statistics collected while running an indexing pipeline
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use swiftide_core::indexing::DropReason;
+
+/// Statistics of a completed pipeline run, returned by [`crate::Pipeline::run`]
+#[derive(Debug, Clone, Default)]
+pub struct PipelineStats {
+    /// The number of nodes that made it through the pipeline
+    pub total_nodes: usize,
+    /// Wall-clock time of the full run
+    pub elapsed: Duration,
+    /// Statistics per stage, in the order the stages were added
+    pub stages: Vec<StageStats>,
+    /// Nodes dropped by all stages, including errors skipped with
+    /// [`crate::Pipeline::filter_errors`] or [`crate::Pipeline::max_consecutive_errors`]
+    pub dropped: DroppedNodes,
+}
+
+/// Statistics of a single stage in the pipeline
+#[derive(Debug, Clone)]
+pub struct StageStats {
+    /// The name of the transformer, chunker or storage
+    pub name: String,
+    /// Time spent in the stage, summed over all concurrent tasks
+    pub elapsed: Duration,
+    /// Number of nodes produced by the stage
+    pub nodes: usize,
+    /// Number of errors produced by the stage
+    pub errors: usize,
+    /// Nodes dropped by the stage
+    pub dropped: DroppedNodes,
+}
+
+/// Number of dropped nodes by [`DropReason`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DroppedNodes {
+    /// Nodes that did not match a filter
+    pub filtered: usize,
+    /// Nodes that duplicate a node seen or stored before
+    pub deduplicated: usize,
+    /// Errors that were skipped
+    pub errored: usize,
+}
+
+impl DroppedNodes {
+    /// The number of dropped nodes over all reasons
+    pub fn total(&self) -> usize {
+        self.filtered + self.deduplicated + self.errored
+    }
+}
+
+impl std::ops::Add for DroppedNodes {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            filtered: self.filtered + other.filtered,
+            deduplicated: self.deduplicated + other.deduplicated,
+            errored: self.errored + other.errored,
+        }
+    }
+}
+
+impl std::iter::Sum for DroppedNodes {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |total, dropped| total + dropped)
+    }
+}
+
+/// Counts dropped nodes by reason, shared between concurrent tasks
+#[derive(Debug, Default)]
+pub(crate) struct DropCollector {
+    filtered: AtomicUsize,
+    deduplicated: AtomicUsize,
+    errored: AtomicUsize,
+}
+
+impl DropCollector {
+    pub(crate) fn record(&self, reason: DropReason, count: usize) {
+        let counter = match reason {
+            DropReason::Filtered => &self.filtered,
+            DropReason::Deduplicated => &self.deduplicated,
+            DropReason::Errored => &self.errored,
+        };
+        counter.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stats(&self) -> DroppedNodes {
+        DroppedNodes {
+            filtered: self.filtered.load(Ordering::Relaxed),
+            deduplicated: self.deduplicated.load(Ordering::Relaxed),
+            errored: self.errored.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Collects statistics for a stage, shared between concurrent tasks
+#[derive(Debug)]
+pub(crate) struct StageCollector {
+    name: String,
+    nanos: AtomicU64,
+    nodes: AtomicUsize,
+    errors: AtomicUsize,
+    dropped: DropCollector,
+}
+
+impl StageCollector {
+    pub(crate) fn new(name: impl Into<String>) -> Arc<Self> {
+        Arc::new(Self {
+            name: name.into(),
+            nanos: AtomicU64::new(0),
+            nodes: AtomicUsize::new(0),
+            errors: AtomicUsize::new(0),
+            dropped: DropCollector::default(),
+        })
+    }
+
+    pub(crate) fn record_elapsed(&self, started: Instant) {
+        let nanos = u64::try_from(started.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        self.nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record<T>(&self, result: &Result<T>) {
+        if result.is_ok() {
+            self.nodes.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn record_dropped(&self, reason: DropReason, count: usize) {
+        self.dropped.record(reason, count);
+    }
+
+    pub(crate) fn stats(&self) -> StageStats {
+        StageStats {
+            name: self.name.clone(),
+            elapsed: Duration::from_nanos(self.nanos.load(Ordering::Relaxed)),
+            nodes: self.nodes.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            dropped: self.dropped.stats(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stage_sums_elapsed_time() {
+        let stage = StageCollector::new("stage");
+        let started = Instant::now()
+            .checked_sub(Duration::from_millis(5))
+            .unwrap();
+        stage.record_elapsed(started);
+        stage.record_elapsed(started);
+
+        assert!(stage.stats().elapsed >= Duration::from_millis(10));
+    }
+}
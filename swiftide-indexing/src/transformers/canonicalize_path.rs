@@ -0,0 +1,147 @@
+//! Normalize the path of nodes coming from different loaders
+use std::path::PathBuf;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use swiftide_core::{indexing::Node, Transformer, WithIndexingDefaults};
+
+/// The metadata key the original path is stored under
+pub const NAME: &str = "original_path";
+
+/// Rewrites the `path` of nodes to a consistent form, keeping the original as `original_path` in
+/// the metadata
+///
+/// Backslashes become forward slashes, `.` segments and repeated separators are removed and, when
+/// configured, a root prefix is stripped and the path is lowercased. Paths that are not under the
+/// root are otherwise kept as they are.
+///
+/// Useful when paths from different loaders are mixed, so that dedup and citations see the same
+/// path for the same file.
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_indexing::transformers::CanonicalizePath;
+/// let canonicalize = CanonicalizePath::default()
+///     .with_root("/home/me/project")
+///     .with_lowercase(true);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CanonicalizePath {
+    root: Option<String>,
+    lowercase: bool,
+    concurrency: Option<usize>,
+}
+
+impl CanonicalizePath {
+    /// Strips the root prefix from paths under it, making them relative
+    #[must_use]
+    pub fn with_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = Some(normalize(&root.into().to_string_lossy()));
+        self
+    }
+
+    /// Lowercases paths, for sources from case insensitive file systems
+    #[must_use]
+    pub fn with_lowercase(mut self, lowercase: bool) -> Self {
+        self.lowercase = lowercase;
+        self
+    }
+
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
+    fn canonicalize(&self, path: &str) -> String {
+        let mut path = normalize(path);
+
+        if let Some(root) = &self.root {
+            if let Some(relative) = path.strip_prefix(root.as_str()) {
+                if relative.is_empty() || relative.starts_with('/') {
+                    path = relative.trim_start_matches('/').to_string();
+                }
+            }
+        }
+
+        if self.lowercase {
+            path = path.to_lowercase();
+        }
+
+        path
+    }
+}
+
+/// Uses forward slashes and drops empty and `.` segments, keeping a leading `/`
+fn normalize(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let segments = path
+        .split('/')
+        .filter(|segment| !segment.is_empty() && *segment != ".")
+        .collect::<Vec<_>>()
+        .join("/");
+
+    if path.starts_with('/') {
+        format!("/{segments}")
+    } else {
+        segments
+    }
+}
+
+impl WithIndexingDefaults for CanonicalizePath {}
+
+#[async_trait]
+impl Transformer for CanonicalizePath {
+    #[tracing::instrument(skip_all, name = "transformers.canonicalize_path")]
+    async fn transform_node(&self, mut node: Node) -> Result<Node> {
+        let original = node.path.to_string_lossy().to_string();
+
+        node.path = self.canonicalize(&original).into();
+        node.metadata.insert(NAME, original);
+
+        Ok(node)
+    }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.concurrency
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_normalizes_mixed_paths() {
+        let canonicalize = CanonicalizePath::default()
+            .with_root("/home/me/project/")
+            .with_lowercase(true);
+
+        for path in [
+            "/home/me/project/src/Main.rs",
+            "./src/main.rs",
+            "src\\main.rs",
+            "src//./main.rs",
+        ] {
+            let mut node = Node::new("fn main() {}");
+            node.path = path.into();
+
+            let node = canonicalize.transform_node(node).await.unwrap();
+
+            assert_eq!(node.path, PathBuf::from("src/main.rs"));
+            assert_eq!(node.metadata.get(NAME).unwrap(), path);
+        }
+    }
+
+    #[test]
+    fn test_keeps_paths_outside_of_root() {
+        let canonicalize = CanonicalizePath::default().with_root("/home/me/project");
+
+        assert_eq!(
+            canonicalize.canonicalize("/home/me/project-other/Main.rs"),
+            "/home/me/project-other/Main.rs"
+        );
+        assert_eq!(canonicalize.canonicalize("/home/me/project"), "");
+    }
+}
@@ -0,0 +1,168 @@
+//! Caption the image of a node with a multimodal model
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use derive_builder::Builder;
+use swiftide_core::{
+    indexing::Node, prompt::PromptTemplate, MultimodalPrompt, Transformer, WithIndexingDefaults,
+};
+
+/// `CaptionImage` prompts a multimodal model to describe the image of a node and stores the
+/// caption as the chunk, so the image can be embedded and retrieved like text.
+///
+/// Nodes without an image are passed on unchanged. The previous chunk, i.e. the text surrounding
+/// the image, is included in the default prompt as context and then replaced by the caption.
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_core::indexing::{Image, Node};
+/// # use swiftide_indexing::{transformers::CaptionImage, Pipeline};
+/// # fn run(client: impl swiftide_core::MultimodalPrompt + 'static) {
+/// let mut node = Node::default();
+/// node.with_image(std::path::PathBuf::from("diagram.png"));
+///
+/// Pipeline::from_stream(vec![node]).then(CaptionImage::new(client));
+/// # }
+/// ```
+#[derive(Debug, Clone, Builder)]
+#[builder(setter(into, strip_option), build_fn(error = "anyhow::Error"))]
+pub struct CaptionImage {
+    #[builder(setter(custom))]
+    client: Arc<dyn MultimodalPrompt>,
+    #[builder(default = "default_prompt()")]
+    prompt_template: PromptTemplate,
+    #[builder(default)]
+    concurrency: Option<usize>,
+}
+
+impl CaptionImage {
+    /// Creates a new builder for the transformer
+    pub fn builder() -> CaptionImageBuilder {
+        CaptionImageBuilder::default()
+    }
+
+    /// Create a new transformer from a client
+    pub fn new(client: impl MultimodalPrompt + 'static) -> Self {
+        Self {
+            client: Arc::new(client),
+            prompt_template: default_prompt(),
+            concurrency: None,
+        }
+    }
+
+    /// Set the concurrency level for the transformer
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+}
+
+impl CaptionImageBuilder {
+    pub fn client(&mut self, client: impl MultimodalPrompt + 'static) -> &mut Self {
+        self.client = Some(Arc::new(client));
+        self
+    }
+}
+
+fn default_prompt() -> PromptTemplate {
+    include_str!("prompts/caption_image.prompt.md").into()
+}
+
+impl WithIndexingDefaults for CaptionImage {}
+
+#[async_trait]
+impl Transformer for CaptionImage {
+    /// Captions the image of a `Node` and stores the caption as its chunk
+    ///
+    /// # Errors
+    ///
+    /// Errors if the image cannot be read or the client fails to caption it
+    #[tracing::instrument(skip_all, name = "transformers.caption_image")]
+    async fn transform_node(&self, mut node: Node) -> Result<Node> {
+        let Some(image) = &node.image else {
+            return Ok(node);
+        };
+
+        let prompt = self.prompt_template.to_prompt().with_node(&node);
+        let caption = self.client.prompt_with_image(prompt, image).await?;
+
+        node.chunk = caption;
+
+        Ok(node)
+    }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.concurrency
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use swiftide_core::{indexing::Image, MockMultimodalPrompt};
+
+    use super::*;
+
+    fn fixture() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/red_pixel.png")
+    }
+
+    #[tokio::test]
+    async fn test_template() {
+        let template = default_prompt();
+
+        let prompt = template.to_prompt().with_node(&Node::new("test"));
+        insta::assert_snapshot!(prompt.render().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_caption_image() {
+        let mut client = MockMultimodalPrompt::new();
+        client.expect_prompt_with_image().returning(|_, image| {
+            let Image::Path(path) = image else {
+                anyhow::bail!("Expected an image path");
+            };
+            assert!(path.ends_with("red_pixel.png"));
+            Ok("A single red pixel".to_string())
+        });
+
+        let mut node = Node::default();
+        node.with_image(fixture());
+
+        let result = CaptionImage::new(client)
+            .transform_node(node)
+            .await
+            .unwrap();
+
+        assert_eq!(result.chunk, "A single red pixel");
+        assert_eq!(result.image, Some(Image::Path(fixture())));
+    }
+
+    #[tokio::test]
+    async fn test_reads_image_from_path() {
+        let url = Image::Path(fixture()).to_data_url().await.unwrap();
+
+        // Base64 of the png signature
+        assert!(
+            url.starts_with("data:image/png;base64,iVBORw0KGgo"),
+            "{url}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_skips_nodes_without_image() {
+        let mut client = MockMultimodalPrompt::new();
+        client.expect_prompt_with_image().never();
+
+        let result = CaptionImage::new(client)
+            .transform_node(Node::new("text"))
+            .await
+            .unwrap();
+
+        assert_eq!(result.chunk, "text");
+    }
+}
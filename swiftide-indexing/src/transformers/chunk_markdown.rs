@@ -139,6 +139,25 @@ mod test {
         assert_eq!(nodes.len(), 6);
     }
 
+    #[tokio::test]
+    async fn test_chunks_inherit_document_metadata() {
+        let chunker = ChunkMarkdown::from_max_characters(40);
+        let mut node = Node::new(MARKDOWN);
+        node.metadata.insert("title", "Document title");
+
+        let nodes: Vec<Node> = chunker
+            .transform_node(node)
+            .await
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert!(nodes.len() > 1);
+        assert!(nodes
+            .iter()
+            .all(|node| node.metadata.get("title").unwrap() == "Document title"));
+    }
+
     #[tokio::test]
     async fn test_always_within_range() {
         let ranges = vec![(10..15), (20..25), (30..35), (40..45), (50..55)];
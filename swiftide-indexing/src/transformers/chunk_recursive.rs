@@ -0,0 +1,287 @@
+//! Chunk text content by recursively splitting on finer separators
+use std::{collections::VecDeque, ops::Range};
+
+use async_trait::async_trait;
+use derive_builder::Builder;
+use swiftide_core::{indexing::IndexingStream, indexing::Node, ChunkerTransformer};
+
+/// The separators tried in order by default: paragraphs, lines, sentences and words
+pub const DEFAULT_SEPARATORS: [&str; 4] = ["\n\n", "\n", ". ", " "];
+
+#[derive(Debug, Clone, Builder)]
+#[builder(pattern = "owned", setter(strip_option))]
+/// A transformer that chunks text content by recursively splitting it on a priority list of
+/// separators.
+///
+/// Text is split on the first separator, and only pieces that are still larger than
+/// `max_characters` are split on the next one. If no separator is left, pieces are split at
+/// `max_characters`. Pieces are then merged back into chunks of at most `max_characters`, with
+/// up to `overlap` characters of whole pieces repeated at the start of the next chunk.
+///
+/// Separators inside fenced code blocks and urls are skipped, so they are only split if no
+/// coarser separator keeps them under the size.
+pub struct ChunkRecursive {
+    /// The maximum number of characters per chunk.
+    max_characters: usize,
+    /// The maximum number of characters repeated from the end of the previous chunk.
+    #[builder(default)]
+    overlap: usize,
+    /// The separators to split on, from coarse to fine.
+    #[builder(default = "default_separators()")]
+    separators: Vec<String>,
+    #[builder(default)]
+    /// The number of concurrent chunks to process.
+    concurrency: Option<usize>,
+}
+
+fn default_separators() -> Vec<String> {
+    DEFAULT_SEPARATORS.map(String::from).to_vec()
+}
+
+impl ChunkRecursive {
+    /// Create a new transformer with a maximum number of characters per chunk and the number of
+    /// characters chunks overlap by, splitting on [`DEFAULT_SEPARATORS`].
+    pub fn new(max_characters: usize, overlap: usize) -> Self {
+        Self {
+            max_characters,
+            overlap,
+            separators: default_separators(),
+            concurrency: None,
+        }
+    }
+
+    /// Build a custom recursive chunker.
+    pub fn builder() -> ChunkRecursiveBuilder {
+        ChunkRecursiveBuilder::default()
+    }
+
+    /// Set the separators to split on, from coarse to fine.
+    #[must_use]
+    pub fn with_separators(
+        mut self,
+        separators: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.separators = separators.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set the number of concurrent chunks to process.
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
+    fn max_characters(&self) -> usize {
+        self.max_characters.max(1)
+    }
+
+    /// Returns the trimmed, non empty chunks of the text
+    fn chunks(&self, text: &str) -> Vec<String> {
+        let protected = protected_ranges(text);
+        let pieces = self.split(text, 0..text.len(), 0, &protected);
+
+        self.merge(text, pieces)
+            .into_iter()
+            .map(|range| text[range].trim())
+            .filter(|chunk| !chunk.is_empty())
+            .map(String::from)
+            .collect()
+    }
+
+    /// Splits the range of the text into pieces of at most `max_characters`, keeping separators
+    /// at the end of the preceding piece
+    fn split(
+        &self,
+        text: &str,
+        range: Range<usize>,
+        level: usize,
+        protected: &[Range<usize>],
+    ) -> Vec<Range<usize>> {
+        if text[range.clone()].chars().count() <= self.max_characters() {
+            return vec![range];
+        }
+
+        let Some(separator) = self.separators.get(level).filter(|s| !s.is_empty()) else {
+            return self.split_characters(text, range);
+        };
+
+        let mut pieces = Vec::new();
+        let mut start = range.start;
+        for (offset, _) in text[range.clone()].match_indices(separator.as_str()) {
+            let at = range.start + offset;
+            if protected.iter().any(|p| p.start < at && at < p.end) {
+                continue;
+            }
+
+            let end = at + separator.len();
+            pieces.extend(self.split(text, start..end, level + 1, protected));
+            start = end;
+        }
+        if start < range.end {
+            pieces.extend(self.split(text, start..range.end, level + 1, protected));
+        }
+
+        pieces
+    }
+
+    fn split_characters(&self, text: &str, range: Range<usize>) -> Vec<Range<usize>> {
+        let mut pieces = Vec::new();
+        let mut start = range.start;
+        for (i, (offset, _)) in text[range.clone()].char_indices().enumerate() {
+            if i > 0 && i % self.max_characters() == 0 {
+                pieces.push(start..range.start + offset);
+                start = range.start + offset;
+            }
+        }
+        pieces.push(start..range.end);
+        pieces
+    }
+
+    /// Merges consecutive pieces into chunks of at most `max_characters`, starting each chunk
+    /// with the last pieces of the previous chunk that fit in the overlap
+    fn merge(&self, text: &str, pieces: Vec<Range<usize>>) -> Vec<Range<usize>> {
+        let mut chunks = Vec::new();
+        let mut current = VecDeque::<(Range<usize>, usize)>::new();
+        let mut len = 0;
+
+        for piece in pieces {
+            let piece_len = text[piece.clone()].chars().count();
+
+            if len + piece_len > self.max_characters() {
+                if let (Some((first, _)), Some((last, _))) = (current.front(), current.back()) {
+                    chunks.push(first.start..last.end);
+                }
+                while len > self.overlap || (len > 0 && len + piece_len > self.max_characters()) {
+                    let Some((_, removed)) = current.pop_front() else {
+                        break;
+                    };
+                    len -= removed;
+                }
+            }
+
+            len += piece_len;
+            current.push_back((piece, piece_len));
+        }
+
+        if let (Some((first, _)), Some((last, _))) = (current.front(), current.back()) {
+            chunks.push(first.start..last.end);
+        }
+
+        chunks
+    }
+}
+
+/// Byte ranges of fenced code blocks and urls
+fn protected_ranges(text: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+
+    let mut fence_start = None;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        if line.trim_start().starts_with("```") {
+            match fence_start.take() {
+                Some(start) => ranges.push(start..offset + line.trim_end().len()),
+                None => fence_start = Some(offset),
+            }
+        }
+        offset += line.len();
+    }
+
+    for scheme in ["https://", "http://"] {
+        for (start, _) in text.match_indices(scheme) {
+            let rest = &text[start..];
+            let url = &rest[..rest.find(char::is_whitespace).unwrap_or(rest.len())];
+            let url = url.trim_end_matches(['.', ',', ';', ':', '!', '?', ')']);
+            ranges.push(start..start + url.len());
+        }
+    }
+
+    ranges
+}
+
+#[async_trait]
+impl ChunkerTransformer for ChunkRecursive {
+    #[tracing::instrument(skip_all, name = "transformers.chunk_recursive")]
+    async fn transform_node(&self, node: Node) -> IndexingStream {
+        let chunks = self.chunks(&node.chunk);
+
+        IndexingStream::iter(chunks.into_iter().map(move |chunk| {
+            Ok(Node {
+                chunk,
+                ..node.clone()
+            })
+        }))
+    }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.concurrency
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures_util::stream::TryStreamExt;
+
+    const PARAGRAPHS: [&str; 3] = [
+        "The first paragraph has a few words.",
+        "The second paragraph is a bit longer than that.",
+        "And a third.",
+    ];
+
+    #[tokio::test]
+    async fn test_prefers_paragraphs_over_words() {
+        let chunker = ChunkRecursive::new(60, 10);
+
+        let nodes: Vec<Node> = chunker
+            .transform_node(Node::new(PARAGRAPHS.join("\n\n")))
+            .await
+            .try_collect()
+            .await
+            .unwrap();
+
+        let chunks = nodes.iter().map(|n| n.chunk.as_str()).collect::<Vec<_>>();
+        assert_eq!(chunks, PARAGRAPHS);
+    }
+
+    #[test]
+    fn test_falls_back_to_words_with_overlap() {
+        let chunker = ChunkRecursive::new(12, 6);
+
+        assert_eq!(
+            chunker.chunks("one two three four five six"),
+            [
+                "one two",
+                "two three",
+                "three four",
+                "four five",
+                "five six"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_does_not_split_code_fences_or_urls() {
+        let code = "```rust\nfn first() {}\n\nfn second() {}\n```";
+        let text = format!("Some code.\n\n{code}\n\nMore text.");
+
+        assert_eq!(
+            ChunkRecursive::new(45, 0).chunks(&text),
+            ["Some code.", code, "More text."]
+        );
+
+        let chunker = ChunkRecursive::new(40, 0).with_separators(["/", " "]);
+        assert_eq!(
+            chunker.chunks("Read https://docs.rs/swiftide first/then the guides"),
+            ["Read https://docs.rs/swiftide first/", "then the guides"]
+        );
+    }
+
+    #[test]
+    fn test_splits_characters_without_separators() {
+        let chunker = ChunkRecursive::new(4, 0).with_separators(Vec::<String>::new());
+
+        assert_eq!(chunker.chunks("abcdefghij"), ["abcd", "efgh", "ij"]);
+    }
+}
@@ -0,0 +1,247 @@
+//! Chunk text content where the meaning of consecutive sentences diverges
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use swiftide_core::{
+    indexing::{IndexingStream, Node},
+    ChunkerTransformer, EmbeddingModel,
+};
+use unicode_segmentation::UnicodeSegmentation as _;
+
+use super::semantic_dedup::cosine_similarity;
+
+/// A transformer that splits text where consecutive sentences stop being about the same thing
+///
+/// Every sentence is embedded, and a chunk is split wherever the cosine similarity between two
+/// adjacent sentences drops below the threshold. Chunks are additionally capped at
+/// `max_characters`, if set.
+///
+/// This is compute heavy: every sentence of every node results in an embedding, in one request
+/// per node. Prefer [`super::ChunkSentences`] or [`super::ChunkMarkdown`] when the structure of
+/// the document already carries the topic boundaries.
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_core::EmbeddingModel;
+/// # use swiftide_indexing::transformers::ChunkSemantic;
+/// # fn example(embed_model: impl EmbeddingModel + 'static) {
+/// let chunker = ChunkSemantic::new(embed_model, 0.5).with_max_characters(2000);
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ChunkSemantic {
+    embed_model: Arc<dyn EmbeddingModel>,
+    threshold: f32,
+    max_characters: Option<usize>,
+    concurrency: Option<usize>,
+}
+
+impl std::fmt::Debug for ChunkSemantic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChunkSemantic")
+            .field("threshold", &self.threshold)
+            .field("max_characters", &self.max_characters)
+            .field("concurrency", &self.concurrency)
+            .finish()
+    }
+}
+
+impl ChunkSemantic {
+    /// Creates a new chunker splitting where adjacent sentences have a cosine similarity below
+    /// `threshold`
+    pub fn new(model: impl EmbeddingModel + 'static, threshold: f32) -> Self {
+        Self {
+            embed_model: Arc::new(model),
+            threshold,
+            max_characters: None,
+            concurrency: None,
+        }
+    }
+
+    /// Caps chunks at a maximum number of characters
+    ///
+    /// A single sentence longer than `max_characters` is emitted as a whole.
+    #[must_use]
+    pub fn with_max_characters(mut self, max_characters: usize) -> Self {
+        self.max_characters = Some(max_characters);
+        self
+    }
+
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
+    fn chunks(&self, sentences: &[&str], embeddings: &[Vec<f32>]) -> Vec<String> {
+        let mut chunks = Vec::new();
+        let mut current = String::new();
+        // The number of characters in `current`, which can be fewer than its bytes
+        let mut current_len = 0;
+
+        for (i, sentence) in sentences.iter().enumerate() {
+            let sentence_len = sentence.chars().count();
+            let diverges =
+                i > 0 && cosine_similarity(&embeddings[i - 1], &embeddings[i]) < self.threshold;
+            let too_long = self
+                .max_characters
+                .is_some_and(|max| current_len + 1 + sentence_len > max);
+
+            if !current.is_empty() && (diverges || too_long) {
+                chunks.push(std::mem::take(&mut current));
+                current_len = 0;
+            }
+
+            if !current.is_empty() {
+                current.push(' ');
+                current_len += 1;
+            }
+            current.push_str(sentence);
+            current_len += sentence_len;
+        }
+
+        if !current.is_empty() {
+            chunks.push(current);
+        }
+
+        chunks
+    }
+}
+
+#[async_trait]
+impl ChunkerTransformer for ChunkSemantic {
+    /// Embeds the sentences of the node and splits it at similarity valleys
+    ///
+    /// # Errors
+    ///
+    /// Errors if embedding fails or the model returns a different number of embeddings than
+    /// sentences.
+    #[tracing::instrument(skip_all, name = "transformers.chunk_semantic")]
+    async fn transform_node(&self, node: Node) -> IndexingStream {
+        let sentences = node
+            .chunk
+            .split_sentence_bounds()
+            .map(str::trim)
+            .filter(|sentence| !sentence.is_empty())
+            .collect::<Vec<_>>();
+
+        if sentences.is_empty() {
+            return IndexingStream::empty();
+        }
+
+        let embeddings = match self
+            .embed_model
+            .embed(sentences.iter().map(ToString::to_string).collect())
+            .await
+        {
+            Ok(embeddings) => embeddings,
+            Err(err) => return err.into(),
+        };
+
+        if embeddings.len() != sentences.len() {
+            return anyhow::anyhow!(
+                "Expected {} embeddings, got {}",
+                sentences.len(),
+                embeddings.len()
+            )
+            .into();
+        }
+
+        let chunks = self.chunks(&sentences, &embeddings);
+
+        IndexingStream::iter(chunks.into_iter().map(move |chunk| {
+            Ok(Node {
+                chunk,
+                ..node.clone()
+            })
+        }))
+    }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.concurrency
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures_util::stream::TryStreamExt;
+    use swiftide_core::MockEmbeddingModel;
+
+    use super::*;
+
+    /// Embeds sentences about cats and sentences about rust in orthogonal directions
+    fn topic_model() -> MockEmbeddingModel {
+        let mut model = MockEmbeddingModel::new();
+        model.expect_embed().returning(|input| {
+            Ok(input
+                .iter()
+                .map(|sentence| {
+                    if sentence.contains("cat") {
+                        vec![1.0, 0.1]
+                    } else {
+                        vec![0.1, 1.0]
+                    }
+                })
+                .collect())
+        });
+        model
+    }
+
+    #[tokio::test]
+    async fn test_splits_at_topic_boundary() {
+        let chunker = ChunkSemantic::new(topic_model(), 0.5);
+        let node = Node::new(
+            "My cat sleeps all day. The cat purrs loudly. \
+            Rust has a borrow checker. Cargo builds the code.",
+        );
+
+        let nodes: Vec<Node> = chunker
+            .transform_node(node)
+            .await
+            .try_collect()
+            .await
+            .unwrap();
+
+        let chunks = nodes.iter().map(|n| n.chunk.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            chunks,
+            [
+                "My cat sleeps all day. The cat purrs loudly.",
+                "Rust has a borrow checker. Cargo builds the code."
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_caps_chunks_at_max_characters() {
+        let chunker = ChunkSemantic::new(topic_model(), 0.5).with_max_characters(30);
+        let node = Node::new("My cat sleeps all day. The cat purrs loudly.");
+
+        let nodes: Vec<Node> = chunker
+            .transform_node(node)
+            .await
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(nodes.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_counts_characters_not_bytes() {
+        // 45 characters, but 56 bytes
+        let text = "Mön cät slëëps äll däy. Thé cät pürrs lóüdly.";
+        let chunker = ChunkSemantic::new(topic_model(), 0.5).with_max_characters(50);
+
+        let nodes: Vec<Node> = chunker
+            .transform_node(Node::new(text))
+            .await
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].chunk, text);
+    }
+}
@@ -0,0 +1,164 @@
+//! Chunk text content on sentence boundaries
+use async_trait::async_trait;
+use derive_builder::Builder;
+use swiftide_core::{indexing::IndexingStream, indexing::Node, ChunkerTransformer};
+use unicode_segmentation::UnicodeSegmentation as _;
+
+#[derive(Debug, Clone, Builder)]
+#[builder(pattern = "owned", setter(strip_option))]
+/// A transformer that chunks text content without cutting sentences in half.
+///
+/// Sentences are detected using the unicode sentence boundary rules, which handle common
+/// abbreviations like "e.g." reasonably. Consecutive sentences are packed into a chunk until
+/// `max_characters` would be exceeded.
+///
+/// A single sentence longer than `max_characters` is emitted as a whole.
+pub struct ChunkSentences {
+    /// The maximum number of characters per chunk.
+    max_characters: usize,
+    #[builder(default)]
+    /// The number of concurrent chunks to process.
+    concurrency: Option<usize>,
+}
+
+impl ChunkSentences {
+    /// Create a new transformer with a maximum number of characters per chunk.
+    pub fn from_max_characters(max_characters: usize) -> Self {
+        Self {
+            max_characters,
+            concurrency: None,
+        }
+    }
+
+    /// Build a custom sentence chunker.
+    pub fn builder() -> ChunkSentencesBuilder {
+        ChunkSentencesBuilder::default()
+    }
+
+    /// Set the number of concurrent chunks to process.
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
+    fn chunks(&self, text: &str) -> Vec<String> {
+        let mut chunks = Vec::new();
+        let mut current = String::new();
+        // The number of characters in `current`, which can be fewer than its bytes
+        let mut current_len = 0;
+
+        for sentence in text.split_sentence_bounds() {
+            let sentence = sentence.trim();
+            if sentence.is_empty() {
+                continue;
+            }
+
+            let sentence_len = sentence.chars().count();
+            if sentence_len > self.max_characters {
+                tracing::warn!(
+                    max_characters = self.max_characters,
+                    length = sentence_len,
+                    "Sentence exceeds maximum chunk size, emitting it whole"
+                );
+            }
+
+            if !current.is_empty() && current_len + 1 + sentence_len > self.max_characters {
+                chunks.push(std::mem::take(&mut current));
+                current_len = 0;
+            }
+
+            if !current.is_empty() {
+                current.push(' ');
+                current_len += 1;
+            }
+            current.push_str(sentence);
+            current_len += sentence_len;
+        }
+
+        if !current.is_empty() {
+            chunks.push(current);
+        }
+
+        chunks
+    }
+}
+
+#[async_trait]
+impl ChunkerTransformer for ChunkSentences {
+    #[tracing::instrument(skip_all, name = "transformers.chunk_sentences")]
+    async fn transform_node(&self, node: Node) -> IndexingStream {
+        let chunks = self.chunks(&node.chunk);
+
+        IndexingStream::iter(chunks.into_iter().map(move |chunk| {
+            Ok(Node {
+                chunk,
+                ..node.clone()
+            })
+        }))
+    }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.concurrency
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures_util::stream::TryStreamExt;
+
+    const TEXT: &str = "Chunking is hard. Some tools, e.g. this one, try harder than others! \
+        Do they succeed? Sentences are never cut in half. Even when they are long.";
+
+    #[tokio::test]
+    async fn test_never_ends_mid_sentence() {
+        let chunker = ChunkSentences::from_max_characters(40);
+
+        let node = Node::new(TEXT);
+
+        let nodes: Vec<Node> = chunker
+            .transform_node(node)
+            .await
+            .try_collect()
+            .await
+            .unwrap();
+
+        let chunks = nodes.iter().map(|n| n.chunk.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            chunks,
+            vec![
+                "Chunking is hard.",
+                "Some tools, e.g. this one, try harder than others!",
+                "Do they succeed?",
+                "Sentences are never cut in half.",
+                "Even when they are long."
+            ]
+        );
+        assert!(chunks.iter().all(|chunk| chunk.ends_with(['.', '!', '?'])));
+    }
+
+    #[test]
+    fn test_packs_sentences_up_to_max_characters() {
+        let chunker = ChunkSentences::from_max_characters(80);
+
+        assert_eq!(
+            chunker.chunks(TEXT),
+            vec![
+                "Chunking is hard. Some tools, e.g. this one, try harder than others!",
+                "Do they succeed? Sentences are never cut in half. Even when they are long."
+            ]
+        );
+    }
+
+    #[test]
+    fn test_counts_characters_not_bytes() {
+        // Both sentences are 10 characters, but 20 bytes each
+        let chunker = ChunkSentences::from_max_characters(21);
+
+        assert_eq!(
+            chunker.chunks("Ééééé ééé. Ööööö ööö."),
+            vec!["Ééééé ééé. Ööööö ööö."]
+        );
+    }
+}
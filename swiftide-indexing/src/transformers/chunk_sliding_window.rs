@@ -0,0 +1,131 @@
+//! Chunk text content into overlapping windows of a fixed size
+use async_trait::async_trait;
+use derive_builder::Builder;
+use swiftide_core::{indexing::IndexingStream, indexing::Node, ChunkerTransformer};
+
+/// The metadata key holding the character offset of the window in the original chunk
+pub const NAME: &str = "window_start";
+
+#[derive(Debug, Clone, Builder)]
+#[builder(pattern = "owned", setter(strip_option))]
+/// A transformer that chunks text content into fixed size, overlapping windows.
+///
+/// A window of `window` characters is emitted every `stride` characters, so consecutive windows
+/// overlap by `window - stride` characters. The last window is emitted even if it is shorter
+/// than `window`. The character offset of each window is stored in the metadata under
+/// `window_start`.
+///
+/// Useful for dense retrieval setups that prefer fixed size windows over semantic chunks.
+pub struct ChunkSlidingWindow {
+    /// The number of characters per window.
+    window: usize,
+    /// The number of characters between the start of consecutive windows.
+    stride: usize,
+    #[builder(default)]
+    /// The number of concurrent chunks to process.
+    concurrency: Option<usize>,
+}
+
+impl ChunkSlidingWindow {
+    /// Create a new transformer with a window size and stride in characters.
+    ///
+    /// Both are at least one character.
+    pub fn new(window: usize, stride: usize) -> Self {
+        Self {
+            window,
+            stride,
+            concurrency: None,
+        }
+    }
+
+    /// Build a custom sliding window chunker.
+    pub fn builder() -> ChunkSlidingWindowBuilder {
+        ChunkSlidingWindowBuilder::default()
+    }
+
+    /// Set the number of concurrent chunks to process.
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
+    /// Returns the windows with their start offset in characters
+    fn windows(&self, text: &str) -> Vec<(usize, String)> {
+        let window = self.window.max(1);
+        let stride = self.stride.max(1);
+        let chars = text.chars().collect::<Vec<_>>();
+
+        let mut windows = Vec::new();
+        let mut start = 0;
+        while start < chars.len() {
+            let end = (start + window).min(chars.len());
+            windows.push((start, chars[start..end].iter().collect()));
+
+            if end == chars.len() {
+                break;
+            }
+            start += stride;
+        }
+
+        windows
+    }
+}
+
+#[async_trait]
+impl ChunkerTransformer for ChunkSlidingWindow {
+    #[tracing::instrument(skip_all, name = "transformers.chunk_sliding_window")]
+    async fn transform_node(&self, node: Node) -> IndexingStream {
+        let windows = self.windows(&node.chunk);
+
+        IndexingStream::iter(windows.into_iter().map(move |(start, chunk)| {
+            let mut node = Node {
+                chunk,
+                ..node.clone()
+            };
+            node.metadata.insert(NAME, start);
+            Ok(node)
+        }))
+    }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.concurrency
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures_util::stream::TryStreamExt;
+
+    #[tokio::test]
+    async fn test_emits_overlapping_windows() {
+        let chunker = ChunkSlidingWindow::new(4, 3);
+
+        let nodes: Vec<Node> = chunker
+            .transform_node(Node::new("abcdefghijk"))
+            .await
+            .try_collect()
+            .await
+            .unwrap();
+
+        let chunks = nodes.iter().map(|n| n.chunk.as_str()).collect::<Vec<_>>();
+        assert_eq!(chunks, ["abcd", "defg", "ghij", "jk"]);
+
+        let starts = nodes
+            .iter()
+            .map(|n| n.metadata.get(NAME).unwrap().as_u64().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(starts, [0, 3, 6, 9]);
+    }
+
+    #[test]
+    fn test_stops_at_the_window_reaching_the_end() {
+        let chunker = ChunkSlidingWindow::new(4, 2);
+
+        let windows = chunker.windows("abcdef");
+
+        assert_eq!(windows, [(0, "abcd".into()), (2, "cdef".into())].to_vec());
+        assert!(chunker.windows("").is_empty());
+    }
+}
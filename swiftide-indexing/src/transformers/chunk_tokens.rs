@@ -0,0 +1,244 @@
+//! Chunk text content into pieces of a maximum number of tokens
+use std::sync::Arc;
+
+use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+use swiftide_core::{indexing::IndexingStream, indexing::Node, ChunkerTransformer, EmbeddingModel};
+use text_splitter::{ChunkConfig, ChunkSizer, TextSplitter};
+
+/// The percentage of the input limit of an embedding model used as chunk budget, leaving room
+/// for differences between tokenizers and for metadata embedded with the chunk
+pub const EMBEDDING_MODEL_BUDGET_PERCENT: usize = 90;
+
+/// The tiktoken encoding used by [`ChunkTokens::for_model`] for models it does not know
+#[cfg(feature = "tiktoken")]
+pub const DEFAULT_ENCODING: tiktoken_rs::tokenizer::Tokenizer =
+    tiktoken_rs::tokenizer::Tokenizer::O200kBase;
+
+/// Returns the tiktoken encoding of an `OpenAI` model, i.e. `o200k_base` for `gpt-4o` and
+/// `cl100k_base` for `text-embedding-3-small`, or [`DEFAULT_ENCODING`] for unknown models
+#[cfg(feature = "tiktoken")]
+pub fn encoding_for_model(model: &str) -> tiktoken_rs::tokenizer::Tokenizer {
+    tiktoken_rs::tokenizer::get_tokenizer(model).unwrap_or(DEFAULT_ENCODING)
+}
+
+/// A transformer that chunks text content into pieces of at most `max_tokens` tokens.
+///
+/// Like [`super::CountTokens`], the tokenizer is a closure, so chunks can be sized for whichever
+/// model they are meant for. Uses `text_splitter` under the hood, which splits on the largest
+/// semantic unit that fits, i.e. paragraphs before sentences.
+///
+/// Use [`ChunkTokens::for_embedding_model`] to size chunks to the input limit of an embedding
+/// model. With the `tiktoken` feature, `ChunkTokens::for_model` counts tokens with the encoding
+/// of an `OpenAI` model.
+///
+/// # Example
+///
+/// ```
+/// # use swiftide_indexing::transformers::ChunkTokens;
+/// // A rough approximation, use a real tokenizer for accurate counts
+/// let chunker = ChunkTokens::new(512, |text| text.split_whitespace().count());
+/// ```
+#[derive(Clone)]
+pub struct ChunkTokens {
+    chunker: Arc<TextSplitter<Tokenizer>>,
+    max_tokens: usize,
+    concurrency: Option<usize>,
+}
+
+/// Sizes chunks with the tokenizer closure
+struct Tokenizer(Arc<dyn Fn(&str) -> usize + Send + Sync>);
+
+impl ChunkSizer for Tokenizer {
+    fn size(&self, chunk: &str) -> usize {
+        (self.0)(chunk)
+    }
+}
+
+impl ChunkTokens {
+    /// Creates a new transformer with a maximum number of tokens per chunk and a closure
+    /// returning the number of tokens of a text
+    pub fn new(
+        max_tokens: usize,
+        tokenizer: impl Fn(&str) -> usize + Send + Sync + 'static,
+    ) -> Self {
+        let config = ChunkConfig::new(max_tokens).with_sizer(Tokenizer(Arc::new(tokenizer)));
+
+        Self {
+            chunker: Arc::new(TextSplitter::new(config)),
+            max_tokens,
+            concurrency: None,
+        }
+    }
+
+    /// Creates a new transformer with chunks sized to the input limit of an embedding model
+    ///
+    /// The chunk budget is [`EMBEDDING_MODEL_BUDGET_PERCENT`] of
+    /// [`EmbeddingModel::max_input_tokens`].
+    ///
+    /// # Errors
+    ///
+    /// Errors if the model does not advertise its input limit.
+    pub fn for_embedding_model(
+        model: &dyn EmbeddingModel,
+        tokenizer: impl Fn(&str) -> usize + Send + Sync + 'static,
+    ) -> Result<Self> {
+        let max_input_tokens = model.max_input_tokens().with_context(|| {
+            format!(
+                "{} does not advertise its maximum input tokens, use `ChunkTokens::new`",
+                model.name()
+            )
+        })?;
+
+        Ok(Self::new(
+            max_input_tokens * EMBEDDING_MODEL_BUDGET_PERCENT / 100,
+            tokenizer,
+        ))
+    }
+
+    /// Creates a new transformer counting tokens with the tiktoken encoding of an `OpenAI` model
+    ///
+    /// The encoding is selected with [`encoding_for_model`], falling back to
+    /// [`DEFAULT_ENCODING`] for unknown models.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the encoding can not be loaded.
+    #[cfg(feature = "tiktoken")]
+    pub fn for_model(max_tokens: usize, model: &str) -> Result<Self> {
+        let encoding = encoding_for_model(model);
+        let bpe = tiktoken_rs::get_bpe_from_tokenizer(encoding)
+            .with_context(|| format!("Failed to load tiktoken encoding {encoding:?}"))?;
+
+        Ok(Self::new(max_tokens, move |text| {
+            bpe.encode_ordinary(text).len()
+        }))
+    }
+
+    /// The maximum number of tokens per chunk
+    pub fn max_tokens(&self) -> usize {
+        self.max_tokens
+    }
+
+    /// Set the number of concurrent chunks to process.
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+}
+
+impl std::fmt::Debug for ChunkTokens {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChunkTokens")
+            .field("max_tokens", &self.max_tokens)
+            .field("concurrency", &self.concurrency)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl ChunkerTransformer for ChunkTokens {
+    #[tracing::instrument(skip_all, name = "transformers.chunk_tokens")]
+    async fn transform_node(&self, node: Node) -> IndexingStream {
+        let chunks = self
+            .chunker
+            .chunks(&node.chunk)
+            .filter(|chunk| !chunk.trim().is_empty())
+            .map(str::to_string)
+            .collect::<Vec<String>>();
+
+        IndexingStream::iter(chunks.into_iter().map(move |chunk| {
+            Ok(Node {
+                chunk,
+                ..node.clone()
+            })
+        }))
+    }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.concurrency
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures_util::stream::TryStreamExt;
+    use swiftide_core::MockEmbeddingModel;
+
+    fn word_tokenizer(text: &str) -> usize {
+        text.split_whitespace().count()
+    }
+
+    #[tokio::test]
+    async fn test_chunks_within_token_budget() {
+        let chunker = ChunkTokens::new(4, word_tokenizer);
+
+        let nodes: Vec<Node> = chunker
+            .transform_node(Node::new("one two three. four five six. seven"))
+            .await
+            .try_collect()
+            .await
+            .unwrap();
+
+        let chunks = nodes.iter().map(|n| n.chunk.as_str()).collect::<Vec<_>>();
+        assert_eq!(chunks, ["one two three. four", "five six. seven"]);
+        assert!(chunks.iter().all(|chunk| word_tokenizer(chunk) <= 4));
+    }
+
+    #[test]
+    fn test_budget_from_embedding_model() {
+        let mut model = MockEmbeddingModel::new();
+        model.expect_max_input_tokens().return_const(Some(512));
+
+        let chunker = ChunkTokens::for_embedding_model(&model, word_tokenizer).unwrap();
+
+        assert_eq!(chunker.max_tokens(), 460);
+    }
+
+    #[cfg(feature = "tiktoken")]
+    #[test]
+    fn test_selects_encoding_for_model() {
+        use tiktoken_rs::tokenizer::Tokenizer;
+
+        assert_eq!(encoding_for_model("gpt-4o"), Tokenizer::O200kBase);
+        assert_eq!(
+            encoding_for_model("text-embedding-3-small"),
+            Tokenizer::Cl100kBase
+        );
+        assert_eq!(encoding_for_model("unknown-model"), DEFAULT_ENCODING);
+    }
+
+    #[cfg(feature = "tiktoken")]
+    #[tokio::test]
+    async fn test_chunks_with_model_encoding() {
+        let bpe = tiktoken_rs::o200k_base().unwrap();
+        let chunker = ChunkTokens::for_model(8, "gpt-4o").unwrap();
+
+        let nodes: Vec<Node> = chunker
+            .transform_node(Node::new(
+                "Swiftide is a data pipeline. It indexes and queries data. It is fast.",
+            ))
+            .await
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert!(nodes.len() > 1);
+        assert!(nodes
+            .iter()
+            .all(|node| bpe.encode_ordinary(&node.chunk).len() <= 8));
+    }
+
+    #[test]
+    fn test_errors_without_model_limit() {
+        let mut model = MockEmbeddingModel::new();
+        model.expect_max_input_tokens().return_const(None);
+        model.expect_name().return_const("MockEmbeddingModel");
+
+        let err = ChunkTokens::for_embedding_model(&model, word_tokenizer).unwrap_err();
+
+        assert!(err.to_string().contains("maximum input tokens"), "{err}");
+    }
+}
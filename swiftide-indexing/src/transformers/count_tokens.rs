@@ -0,0 +1,93 @@
+//! Record the number of tokens of each chunk
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use swiftide_core::{indexing::Node, Transformer, WithIndexingDefaults};
+
+/// The metadata key the token count is stored under
+pub const NAME: &str = "token_count";
+
+/// Adds the number of tokens in the chunk to the metadata as `token_count`
+///
+/// The tokenizer is a closure, so the count matches whichever model the chunks are meant for,
+/// i.e. with `tiktoken-rs` for `OpenAI` models. Useful for estimating costs and for packing
+/// context windows downstream. Use after chunking, as the count is of the chunk at this point.
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_indexing::transformers::CountTokens;
+/// // A rough approximation, use a real tokenizer for accurate counts
+/// let count_tokens = CountTokens::new(|text| text.split_whitespace().count());
+/// ```
+#[derive(Clone)]
+pub struct CountTokens {
+    tokenizer: Arc<dyn Fn(&str) -> usize + Send + Sync>,
+    concurrency: Option<usize>,
+}
+
+impl CountTokens {
+    /// Creates a new `CountTokens` with a closure returning the number of tokens of a text
+    pub fn new(tokenizer: impl Fn(&str) -> usize + Send + Sync + 'static) -> Self {
+        Self {
+            tokenizer: Arc::new(tokenizer),
+            concurrency: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+}
+
+impl std::fmt::Debug for CountTokens {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CountTokens")
+            .field("concurrency", &self.concurrency)
+            .finish_non_exhaustive()
+    }
+}
+
+impl WithIndexingDefaults for CountTokens {}
+
+#[async_trait]
+impl Transformer for CountTokens {
+    #[tracing::instrument(skip_all, name = "transformers.count_tokens")]
+    async fn transform_node(&self, mut node: Node) -> Result<Node> {
+        let count = (self.tokenizer)(&node.chunk);
+        node.metadata.insert(NAME, count);
+
+        Ok(node)
+    }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.concurrency
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use unicode_segmentation::UnicodeSegmentation as _;
+
+    use super::*;
+
+    fn word_tokenizer(text: &str) -> usize {
+        text.unicode_words().count()
+    }
+
+    #[tokio::test]
+    async fn test_records_token_count() {
+        let text = "Swiftide indexes, queries and runs pipelines quickly.";
+
+        let node = CountTokens::new(word_tokenizer)
+            .transform_node(Node::new(text))
+            .await
+            .unwrap();
+
+        assert_eq!(node.metadata.get(NAME).unwrap(), word_tokenizer(text));
+        assert_eq!(node.metadata.get(NAME).unwrap(), 7);
+    }
+}
@@ -0,0 +1,158 @@
+//! Guard storages against broken embeddings
+use async_trait::async_trait;
+use swiftide_core::{
+    indexing::{IndexingStream, Node},
+    BatchableTransformer, WithBatchIndexingDefaults, WithIndexingDefaults,
+};
+
+/// What to do with a node that has a bad vector
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BadVectorPolicy {
+    /// Drop the whole node
+    #[default]
+    DropNode,
+    /// Only remove the bad vectors, keeping the node and its other vectors
+    SkipVector,
+}
+
+/// Detects vectors that contain `NaN` or infinite values, or only zeroes
+///
+/// Some providers return these on odd input, and storing them pollutes search results. Use after
+/// embedding, before storing. Every bad vector is logged with the path of its node.
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_indexing::transformers::{drop_bad_vectors::BadVectorPolicy, DropBadVectors};
+/// let guard = DropBadVectors::default().with_policy(BadVectorPolicy::SkipVector);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DropBadVectors {
+    policy: BadVectorPolicy,
+    concurrency: Option<usize>,
+}
+
+impl DropBadVectors {
+    /// Sets what to do with nodes that have a bad vector. Defaults to dropping the node.
+    #[must_use]
+    pub fn with_policy(mut self, policy: BadVectorPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
+    /// Applies the policy, returning `None` if the node is dropped
+    fn check(&self, mut node: Node) -> Option<Node> {
+        let Some(vectors) = node.vectors.as_mut() else {
+            return Some(node);
+        };
+
+        let bad_fields = vectors
+            .iter()
+            .filter(|(_, vector)| is_bad_vector(vector))
+            .map(|(field, _)| field.clone())
+            .collect::<Vec<_>>();
+
+        if bad_fields.is_empty() {
+            return Some(node);
+        }
+
+        for field in &bad_fields {
+            tracing::warn!(path = ?node.path, %field, policy = ?self.policy, "Bad vector");
+        }
+
+        match self.policy {
+            BadVectorPolicy::DropNode => None,
+            BadVectorPolicy::SkipVector => {
+                for field in &bad_fields {
+                    vectors.remove(field);
+                }
+                Some(node)
+            }
+        }
+    }
+}
+
+fn is_bad_vector(vector: &[f32]) -> bool {
+    vector.iter().any(|value| !value.is_finite()) || vector.iter().all(|value| *value == 0.0)
+}
+
+impl WithBatchIndexingDefaults for DropBadVectors {}
+impl WithIndexingDefaults for DropBadVectors {}
+
+#[async_trait]
+impl BatchableTransformer for DropBadVectors {
+    #[tracing::instrument(skip_all, name = "transformers.drop_bad_vectors")]
+    async fn batch_transform(&self, nodes: Vec<Node>) -> IndexingStream {
+        let kept = nodes
+            .into_iter()
+            .filter_map(|node| self.check(node))
+            .collect::<Vec<_>>();
+
+        IndexingStream::from_nodes(kept)
+    }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.concurrency
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures_util::TryStreamExt as _;
+    use swiftide_core::indexing::EmbeddedField;
+
+    use super::*;
+
+    fn nodes() -> Vec<Node> {
+        let mut nan = Node::new("nan");
+        nan.with_vectors([
+            (EmbeddedField::Combined, vec![0.1, f32::NAN]),
+            (EmbeddedField::Chunk, vec![0.1, 0.2]),
+        ]);
+        let mut zero = Node::new("zero");
+        zero.with_vectors([(EmbeddedField::Combined, vec![0.0, 0.0])]);
+        let mut good = Node::new("good");
+        good.with_vectors([(EmbeddedField::Combined, vec![0.1, 0.2])]);
+
+        vec![nan, zero, good, Node::new("not embedded")]
+    }
+
+    #[tokio::test]
+    async fn test_drops_nodes_with_bad_vectors() {
+        let kept: Vec<Node> = DropBadVectors::default()
+            .batch_transform(nodes())
+            .await
+            .try_collect()
+            .await
+            .unwrap();
+
+        let chunks = kept
+            .iter()
+            .map(|node| node.chunk.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(chunks, ["good", "not embedded"]);
+    }
+
+    #[tokio::test]
+    async fn test_skips_bad_vectors() {
+        let kept: Vec<Node> = DropBadVectors::default()
+            .with_policy(BadVectorPolicy::SkipVector)
+            .batch_transform(nodes())
+            .await
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(kept.len(), 4);
+        let nan_vectors = kept[0].vectors.as_ref().unwrap();
+        assert!(!nan_vectors.contains_key(&EmbeddedField::Combined));
+        assert!(nan_vectors.contains_key(&EmbeddedField::Chunk));
+        assert!(kept[1].vectors.as_ref().unwrap().is_empty());
+    }
+}
@@ -1,11 +1,16 @@
 //! Generic embedding transformer
-use std::{collections::VecDeque, sync::Arc};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, OnceLock},
+};
 
-use anyhow::bail;
+use anyhow::{bail, Result};
 use async_trait::async_trait;
+use futures_util::{stream, StreamExt as _, TryStreamExt as _};
 use swiftide_core::{
-    indexing::{IndexingStream, Node},
-    BatchableTransformer, EmbeddingModel, WithBatchIndexingDefaults, WithIndexingDefaults,
+    indexing::{EmbeddedField, IndexingStream, Node},
+    BatchableTransformer, EmbeddingModel, Embeddings, WithBatchIndexingDefaults,
+    WithIndexingDefaults,
 };
 
 /// A transformer that can generate embeddings for an `Node`
@@ -16,6 +21,10 @@ pub struct Embed {
     embed_model: Arc<dyn EmbeddingModel>,
     concurrency: Option<usize>,
     batch_size: Option<usize>,
+    combined_fields: Option<Vec<String>>,
+    concurrent_fields: bool,
+    dimensions: Option<Arc<OnceLock<usize>>>,
+    skip_embedded: bool,
 }
 
 impl std::fmt::Debug for Embed {
@@ -23,6 +32,10 @@ impl std::fmt::Debug for Embed {
         f.debug_struct("Embed")
             .field("concurrency", &self.concurrency)
             .field("batch_size", &self.batch_size)
+            .field("combined_fields", &self.combined_fields)
+            .field("concurrent_fields", &self.concurrent_fields)
+            .field("dimensions", &self.dimensions)
+            .field("skip_embedded", &self.skip_embedded)
             .finish()
     }
 }
@@ -42,6 +55,10 @@ impl Embed {
             embed_model: Arc::new(model),
             concurrency: None,
             batch_size: None,
+            combined_fields: None,
+            concurrent_fields: false,
+            dimensions: None,
+            skip_embedded: false,
         }
     }
 
@@ -53,6 +70,9 @@ impl Embed {
 
     /// Sets the batch size for the transformer.
     /// If the batch size is not set, the transformer will use the default batch size set by the pipeline
+    ///
+    /// The batch size is independent of the batch size of the storage. Nodes are buffered until a
+    /// batch is full, and the final partial batch is embedded when the stream ends.
     /// # Parameters
     ///
     /// * `batch_size` - The batch size to use for the transformer.
@@ -65,6 +85,130 @@ impl Embed {
         self.batch_size = Some(batch_size);
         self
     }
+
+    /// Only combines the given metadata fields with the chunk when embedding
+    /// [`EmbeddedField::Combined`], instead of all metadata.
+    ///
+    /// See [`Node::combined_text`] for the format.
+    ///
+    /// [`EmbeddedField::Combined`]: swiftide_core::indexing::EmbeddedField::Combined
+    #[must_use]
+    pub fn with_combined_fields(
+        mut self,
+        fields: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.combined_fields = Some(fields.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Embeds every [`EmbeddedField`] of a batch in a separate, concurrent request, instead of
+    /// all fields in a single request.
+    ///
+    /// Speeds up nodes with multiple vectors, i.e. with [`EmbedMode::PerField`]. At most
+    /// `concurrency` requests run at the same time if set, otherwise all fields at once.
+    ///
+    /// [`EmbedMode::PerField`]: swiftide_core::indexing::EmbedMode::PerField
+    #[must_use]
+    pub fn with_concurrent_fields(mut self, concurrent_fields: bool) -> Self {
+        self.concurrent_fields = concurrent_fields;
+        self
+    }
+
+    /// Records the dimension of the first vector the model returns, and errors the batch if any
+    /// later vector has a different dimension.
+    ///
+    /// Guards against mixing models, i.e. after a configuration change mid-run. The dimension is
+    /// shared between clones of the transformer.
+    #[must_use]
+    pub fn with_dimension_check(mut self, check: bool) -> Self {
+        self.dimensions = check.then(Arc::default);
+        self
+    }
+
+    /// Only embeds the fields of a node that do not have a vector yet, keeping the existing
+    /// vectors, i.e. for nodes loaded from a storage.
+    ///
+    /// Empty vectors are embedded again. If no node in a batch misses a vector, the model is not
+    /// called at all.
+    #[must_use]
+    pub fn with_skip_embedded(mut self, skip_embedded: bool) -> Self {
+        self.skip_embedded = skip_embedded;
+        self
+    }
+
+    fn check_dimensions(&self, embeddings: &Embeddings) -> Result<()> {
+        let Some(dimensions) = &self.dimensions else {
+            return Ok(());
+        };
+        let Some(first) = embeddings.first() else {
+            return Ok(());
+        };
+
+        let expected = *dimensions.get_or_init(|| first.len());
+        if let Some(embedding) = embeddings.iter().find(|e| e.len() != expected) {
+            bail!(
+                "Embedding model {} returned a vector of dimension {}, expected {expected}",
+                self.embed_model.name(),
+                embedding.len()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Embeds the data of every field in its own request, returning the embeddings in the order
+    /// of the data
+    async fn embed_per_field<'a>(
+        &self,
+        keys: impl Iterator<Item = &'a EmbeddedField>,
+        data: Vec<String>,
+    ) -> Result<Embeddings> {
+        let total = data.len();
+        let mut groups: Vec<(Vec<usize>, Vec<String>)> = Vec::new();
+        let mut group_of_field: HashMap<&EmbeddedField, usize> = HashMap::new();
+
+        for (position, (key, data)) in keys.zip(data).enumerate() {
+            let group = *group_of_field.entry(key).or_insert_with(|| {
+                groups.push((Vec::new(), Vec::new()));
+                groups.len() - 1
+            });
+            groups[group].0.push(position);
+            groups[group].1.push(data);
+        }
+
+        let limit = self.concurrency.unwrap_or(groups.len()).max(1);
+        let results: Vec<(Vec<usize>, Embeddings)> = stream::iter(groups)
+            .map(|(positions, data)| async move {
+                let embeddings = self.embed_model.embed(data).await?;
+                Ok::<_, anyhow::Error>((positions, embeddings))
+            })
+            .buffered(limit)
+            .try_collect()
+            .await?;
+
+        let mut embeddings = vec![Vec::new(); total];
+        for (positions, group_embeddings) in results {
+            if positions.len() != group_embeddings.len() {
+                bail!(
+                    "Expected {} embeddings, got {}",
+                    positions.len(),
+                    group_embeddings.len()
+                );
+            }
+            for (position, embedding) in positions.into_iter().zip(group_embeddings) {
+                embeddings[position] = embedding;
+            }
+        }
+
+        Ok(embeddings)
+    }
+}
+
+fn has_vector(node: &Node, field: &EmbeddedField) -> bool {
+    node.vectors
+        .as_ref()
+        .and_then(|vectors| vectors.get(field))
+        .is_some_and(|vector| !vector.is_empty())
 }
 
 impl WithBatchIndexingDefaults for Embed {}
@@ -89,6 +233,11 @@ impl BatchableTransformer for Embed {
     async fn batch_transform(&self, mut nodes: Vec<Node>) -> IndexingStream {
         // TODO: We should drop chunks that go over the token limit of the EmbedModel
 
+        let combined_fields = self
+            .combined_fields
+            .as_ref()
+            .map(|fields| fields.iter().map(String::as_str).collect::<Vec<_>>());
+
         // EmbeddedFields grouped by node stored in order of processed nodes.
         let mut embeddings_keys_groups = VecDeque::with_capacity(nodes.len());
         // Embeddable data of every node stored in order of processed nodes.
@@ -97,7 +246,15 @@ impl BatchableTransformer for Embed {
             .fold(Vec::new(), |mut embeddables_data, node| {
                 let embeddables = node.as_embeddables();
                 let mut embeddables_keys = Vec::with_capacity(embeddables.len());
-                for (embeddable_key, embeddable_data) in embeddables {
+                for (embeddable_key, mut embeddable_data) in embeddables {
+                    if self.skip_embedded && has_vector(node, &embeddable_key) {
+                        continue;
+                    }
+                    if let (EmbeddedField::Combined, Some(fields)) =
+                        (&embeddable_key, &combined_fields)
+                    {
+                        embeddable_data = node.combined_text(fields);
+                    }
                     embeddables_keys.push(embeddable_key);
                     embeddables_data.push(embeddable_data);
                 }
@@ -106,24 +263,42 @@ impl BatchableTransformer for Embed {
             });
 
         // Embeddings vectors of every node stored in order of processed nodes.
-        let mut embeddings = match self.embed_model.embed(embeddables_data).await {
+        let embeddings = if self.skip_embedded && embeddables_data.is_empty() {
+            Ok(Vec::new())
+        } else if self.concurrent_fields {
+            self.embed_per_field(embeddings_keys_groups.iter().flatten(), embeddables_data)
+                .await
+        } else {
+            self.embed_model.embed(embeddables_data).await
+        };
+        let mut embeddings = match embeddings
+            .and_then(|embeddings| self.check_dimensions(&embeddings).map(|()| embeddings))
+        {
             Ok(embeddngs) => VecDeque::from(embeddngs),
             Err(err) => return err.into(),
         };
 
         // Iterator of nodes with embeddings vectors map.
+        let skip_embedded = self.skip_embedded;
         let nodes_iter = nodes.into_iter().map(move |mut node| {
             let Some(embedding_keys) = embeddings_keys_groups.pop_front() else {
                 bail!("Missing embedding data");
             };
-            node.vectors = embedding_keys
+            let vectors = embedding_keys
                 .into_iter()
                 .map(|embedded_field| {
                     embeddings
                         .pop_front()
                         .map(|embedding| (embedded_field, embedding))
                 })
-                .collect();
+                .collect::<Option<HashMap<_, _>>>();
+            node.vectors = match (node.vectors.take(), vectors) {
+                (Some(mut existing), Some(vectors)) if skip_embedded => {
+                    existing.extend(vectors);
+                    Some(existing)
+                }
+                (_, vectors) => vectors,
+            };
             Ok(node)
         });
 
@@ -133,16 +308,31 @@ impl BatchableTransformer for Embed {
     fn concurrency(&self) -> Option<usize> {
         self.concurrency
     }
+
+    fn batch_size(&self) -> Option<usize> {
+        self.batch_size
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use swiftide_core::indexing::{EmbedMode, EmbeddedField, Metadata, Node};
-    use swiftide_core::{BatchableTransformer, MockEmbeddingModel};
+    use swiftide_core::{BatchableTransformer, EmbeddingModel, Embeddings, MockEmbeddingModel};
 
     use super::Embed;
+    use crate::{persist::MemoryStorageBuilder, Pipeline};
+
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
 
-    use futures_util::StreamExt;
+    use async_trait::async_trait;
+
+    use futures_util::{StreamExt, TryStreamExt as _};
     use mockall::predicate::*;
     use test_case::test_case;
 
@@ -319,4 +509,182 @@ mod tests {
 
         assert_eq!(error.to_string(), "error");
     }
+
+    #[tokio::test]
+    async fn test_embeds_combined_fields() {
+        let mut model_mock = MockEmbeddingModel::new();
+        model_mock
+            .expect_embed()
+            .withf(|input| *input == ["title: Title\nchunk"])
+            .returning(|input| Ok(vec![vec![1.0]; input.len()]));
+
+        let mut node = Node::new("chunk");
+        node.with_metadata([("title", "Title"), ("summary", "Summary")]);
+
+        let nodes: Vec<Node> = Embed::new(model_mock)
+            .with_combined_fields(["title"])
+            .batch_transform(vec![node])
+            .await
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            nodes[0].vectors.as_ref().unwrap()[&EmbeddedField::Combined],
+            vec![1.0]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_embeds_in_own_batch_size() {
+        let batch_sizes = Arc::new(Mutex::new(Vec::new()));
+        let mut model_mock = MockEmbeddingModel::new();
+        let recorded = Arc::clone(&batch_sizes);
+        model_mock.expect_embed().returning(move |input| {
+            recorded.lock().unwrap().push(input.len());
+            Ok(vec![vec![1.0]; input.len()])
+        });
+
+        let storage = MemoryStorageBuilder::default()
+            .batch_size(Some(5))
+            .build()
+            .unwrap();
+        Pipeline::from_stream(
+            (0..5)
+                .map(|i| Node::new(format!("chunk {i}")))
+                .collect::<Vec<_>>(),
+        )
+        .then_in_batch(Embed::new(model_mock).with_batch_size(2))
+        .then_store_with(storage.clone())
+        .run()
+        .await
+        .unwrap();
+
+        let mut batch_sizes = batch_sizes.lock().unwrap().clone();
+        batch_sizes.sort_unstable();
+        assert_eq!(batch_sizes, vec![1, 2, 2]);
+        assert_eq!(storage.get_all_values().await.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_errors_when_dimension_changes() {
+        let mut model_mock = MockEmbeddingModel::new();
+        let mut seq = mockall::Sequence::new();
+        model_mock
+            .expect_embed()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|input| Ok(vec![vec![1.0, 2.0]; input.len()]));
+        model_mock
+            .expect_embed()
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|input| Ok(vec![vec![1.0, 2.0, 3.0]; input.len()]));
+        model_mock.expect_name().returning(|| "mock");
+
+        let embed = Embed::new(model_mock).with_dimension_check(true);
+
+        let first: Vec<Node> = embed
+            .batch_transform(vec![Node::new("first")])
+            .await
+            .try_collect()
+            .await
+            .unwrap();
+        let err = embed
+            .batch_transform(vec![Node::new("second")])
+            .await
+            .try_collect::<Vec<Node>>()
+            .await
+            .unwrap_err();
+
+        assert_eq!(first.len(), 1);
+        assert_eq!(
+            err.to_string(),
+            "Embedding model mock returned a vector of dimension 3, expected 2"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_only_embeds_nodes_missing_a_vector() {
+        let mut model_mock = MockEmbeddingModel::new();
+        model_mock
+            .expect_embed()
+            .withf(|input| *input == ["\nmissing", "\nempty"])
+            .times(1)
+            .returning(|input| Ok(vec![vec![2.0]; input.len()]));
+
+        let mut embedded = Node::new("embedded");
+        embedded.with_vectors([(EmbeddedField::Combined, vec![1.0])]);
+        let mut empty = Node::new("empty");
+        empty.with_vectors([(EmbeddedField::Combined, vec![])]);
+
+        let embed = Embed::new(model_mock).with_skip_embedded(true);
+        let nodes: Vec<Node> = embed
+            .batch_transform(vec![embedded.clone(), Node::new("missing"), empty])
+            .await
+            .try_collect()
+            .await
+            .unwrap();
+
+        let vectors = nodes
+            .iter()
+            .map(|node| node.vectors.as_ref().unwrap()[&EmbeddedField::Combined].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(vectors, [vec![1.0], vec![2.0], vec![2.0]]);
+
+        // A batch without missing vectors does not call the model
+        let nodes: Vec<Node> = embed
+            .batch_transform(vec![embedded])
+            .await
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(nodes.len(), 1);
+    }
+
+    /// Embeds slowly, keeping track of the number of requests in flight
+    #[derive(Debug, Clone, Default)]
+    struct OverlapModel {
+        in_flight: Arc<AtomicUsize>,
+        max_in_flight: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl EmbeddingModel for OverlapModel {
+        async fn embed(&self, input: Vec<String>) -> anyhow::Result<Embeddings> {
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            #[allow(clippy::cast_precision_loss)]
+            Ok(input.iter().map(|text| vec![text.len() as f32]).collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_embeds_fields_concurrently() {
+        let model = OverlapModel::default();
+        let mut node = Node::new("chunk");
+        node.embed_mode = EmbedMode::PerField;
+        node.with_metadata(("summary", "a summary"));
+
+        let nodes: Vec<Node> = Embed::new(model.clone())
+            .with_concurrent_fields(true)
+            .batch_transform(vec![node.clone(), node])
+            .await
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(model.max_in_flight.load(Ordering::SeqCst), 2);
+        for node in nodes {
+            let vectors = node.vectors.unwrap();
+            assert_eq!(vectors[&EmbeddedField::Chunk], vec![5.0]);
+            assert_eq!(
+                vectors[&EmbeddedField::Metadata("summary".into())],
+                vec![9.0]
+            );
+        }
+    }
 }
@@ -0,0 +1,78 @@
+//! Generate a hypothetical passage for a chunk and add it as metadata
+use anyhow::Result;
+use async_trait::async_trait;
+use swiftide_core::{indexing::Node, Transformer};
+
+/// `Expand` generates a short hypothetical passage for each chunk and stores it in the `hyde`
+/// metadata field, leaving the original chunk intact.
+///
+/// Precomputes the expansions for retrieval with hypothetical document embeddings during
+/// ingestion. To embed the passages into their own named vector, configure the storage with a
+/// vector for `EmbeddedField::Metadata("hyde")` and embed with `EmbedMode::PerField` or
+/// `EmbedMode::Both`.
+#[swiftide_macros::indexing_transformer(
+    metadata_field_name = "hyde",
+    default_prompt_file = "prompts/expand.prompt.md"
+)]
+pub struct Expand {}
+
+#[async_trait]
+impl Transformer for Expand {
+    /// Generates a hypothetical passage for the chunk of a `Node` and adds it as metadata
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the client fails to generate a passage
+    #[tracing::instrument(skip_all, name = "transformers.expand")]
+    async fn transform_node(&self, mut node: Node) -> Result<Node> {
+        let prompt = self.prompt_template.to_prompt().with_node(&node);
+
+        let response = self.prompt(prompt).await?;
+
+        node.metadata.insert(NAME, response);
+
+        Ok(node)
+    }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.concurrency
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use swiftide_core::MockSimplePrompt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_template() {
+        let template = default_prompt();
+
+        let prompt = template.to_prompt().with_node(&Node::new("test"));
+        insta::assert_snapshot!(prompt.render().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_expand() {
+        let mut client = MockSimplePrompt::new();
+
+        client
+            .expect_prompt()
+            .returning(|_| Ok("Swiftide indexes data in streaming pipelines.".to_string()));
+
+        let transformer = Expand::builder().client(client).build().unwrap();
+        let node = Node::new("Swiftide is a library for streaming indexing pipelines");
+
+        let result = transformer.transform_node(node).await.unwrap();
+
+        assert_eq!(
+            result.metadata.get("hyde").unwrap(),
+            "Swiftide indexes data in streaming pipelines."
+        );
+        assert_eq!(
+            result.chunk,
+            "Swiftide is a library for streaming indexing pipelines"
+        );
+    }
+}
@@ -0,0 +1,138 @@
+//! Extract named entities from a node and add them as metadata
+use std::collections::HashSet;
+
+use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use swiftide_core::{indexing::Node, Transformer};
+
+/// The kind of a named entity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntityKind {
+    Person,
+    #[serde(alias = "org", alias = "organisation")]
+    Organization,
+    #[serde(alias = "place")]
+    Location,
+}
+
+/// A named entity mentioned in a chunk
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entity {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: EntityKind,
+}
+
+/// `ExtractEntities` prompts for the people, organizations and locations mentioned in a chunk
+/// and stores them in the `entities` metadata field, i.e. for filtering and faceting.
+///
+/// Entities are stored as a list of objects with a `name` and a `type`, in the order they were
+/// returned. Entities mentioned more than once are stored once, comparing names case
+/// insensitively. Entities of other types are skipped.
+#[swiftide_macros::indexing_transformer(
+    metadata_field_name = "entities",
+    default_prompt_file = "prompts/extract_entities.prompt.md"
+)]
+pub struct ExtractEntities {}
+
+/// Parses the entities from a JSON list, optionally wrapped in a code block, deduplicating them
+fn parse_entities(response: &str) -> Result<Vec<Entity>> {
+    let json = response
+        .trim()
+        .trim_start_matches("```json")
+        .trim_matches('`')
+        .trim();
+    let values: Vec<serde_json::Value> =
+        serde_json::from_str(json).with_context(|| format!("Invalid entities: {response}"))?;
+
+    let mut seen = HashSet::new();
+    Ok(values
+        .into_iter()
+        .filter_map(|value| match serde_json::from_value::<Entity>(value) {
+            Ok(entity) => Some(entity),
+            Err(err) => {
+                tracing::debug!(%err, "Skipping entity");
+                None
+            }
+        })
+        .map(|entity| Entity {
+            name: entity.name.trim().to_string(),
+            kind: entity.kind,
+        })
+        .filter(|entity| {
+            !entity.name.is_empty() && seen.insert((entity.kind, entity.name.to_lowercase()))
+        })
+        .collect())
+}
+
+#[async_trait]
+impl Transformer for ExtractEntities {
+    /// Extracts the named entities of the chunk and adds them as metadata
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the client fails or does not respond with a list
+    /// of entities
+    #[tracing::instrument(skip_all, name = "transformers.extract_entities")]
+    async fn transform_node(&self, mut node: Node) -> Result<Node> {
+        let prompt = self.prompt_template.to_prompt().with_node(&node);
+        let response = self.prompt(prompt).await?;
+
+        let entities = parse_entities(&response)?;
+        node.metadata.insert(NAME, serde_json::to_value(entities)?);
+
+        Ok(node)
+    }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.concurrency
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use swiftide_core::MockSimplePrompt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_template() {
+        let template = default_prompt();
+
+        let prompt = template.to_prompt().with_node(&Node::new("test"));
+        insta::assert_snapshot!(prompt.render().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_stores_deduplicated_entities() {
+        let mut client = MockSimplePrompt::new();
+        client.expect_prompt().returning(|_| {
+            Ok(r#"```json
+[
+    {"name": "Ada Lovelace", "type": "person"},
+    {"name": "London", "type": "location"},
+    {"name": "ada lovelace", "type": "person"},
+    {"name": "Analytical Society", "type": "org"},
+    {"name": "Tuesday", "type": "date"}
+]
+```"#
+                .to_string())
+        });
+
+        let transformer = ExtractEntities::builder().client(client).build().unwrap();
+        let node = Node::new("Ada Lovelace lived in London. Ada Lovelace ...");
+
+        let result = transformer.transform_node(node).await.unwrap();
+
+        assert_eq!(
+            result.metadata.get("entities").unwrap(),
+            &serde_json::json!([
+                {"name": "Ada Lovelace", "type": "person"},
+                {"name": "London", "type": "location"},
+                {"name": "Analytical Society", "type": "organization"},
+            ])
+        );
+    }
+}
@@ -0,0 +1,531 @@
+//! Record a checksum of the content of a file, to skip unchanged files
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex, PoisonError},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures_util::StreamExt as _;
+use itertools::Itertools as _;
+use sha2::{Digest as _, Sha256};
+use swiftide_core::{
+    indexing::{IndexingStream, Node, FILE_WINDOW},
+    ChecksumStore, NodeCache, NodeObserver, Persist, Transformer, WithIndexingDefaults,
+};
+
+/// The metadata key the checksum is stored under
+pub const NAME: &str = "file_checksum";
+
+/// The metadata key the modification time of the file is stored under, in seconds since the unix
+/// epoch
+pub const MTIME: &str = "file_mtime";
+
+/// Adds a sha256 checksum of the chunk to the metadata as `file_checksum`, and the modification
+/// time of the file at the path of the node as `file_mtime`, if it exists
+///
+/// Use this directly after loading, before chunking, so that the checksum covers the full file and
+/// is carried by every chunk.
+///
+/// To skip unchanged files on subsequent runs, follow it with [`SkipUnchanged`] before chunking,
+/// and store with [`SkipUnchanged::record_after`].
+#[derive(Debug, Clone, Default)]
+pub struct FileChecksum {
+    concurrency: Option<usize>,
+}
+
+impl FileChecksum {
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+}
+
+impl WithIndexingDefaults for FileChecksum {}
+
+#[async_trait]
+impl Transformer for FileChecksum {
+    #[tracing::instrument(skip_all, name = "transformers.file_checksum")]
+    async fn transform_node(&self, mut node: Node) -> Result<Node> {
+        let checksum = format!("{:x}", Sha256::digest(node.chunk.as_bytes()));
+        node.metadata.insert(NAME, checksum);
+
+        let mtime = tokio::fs::metadata(&node.path)
+            .await
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok());
+        if let Some(mtime) = mtime {
+            node.metadata.insert(MTIME, mtime.as_secs());
+        }
+
+        Ok(node)
+    }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.concurrency
+    }
+}
+
+/// Filters out files whose checksum and modification time match the ones stored for their path,
+/// used with `Pipeline::filter_cached` after [`FileChecksum`]
+///
+/// The checksums are kept in a [`ChecksumStore`], i.e. in Redis, so that unchanged files are
+/// dropped before chunking on subsequent runs. Nodes without a checksum are never skipped. Files
+/// loaded in windows get a checksum per window, stored under the path suffixed with `#` and the
+/// [`FILE_WINDOW`] index, so that windows of a file do not overwrite each other.
+///
+/// The checksum of a file is only stored once all its nodes are stored, so a file is indexed
+/// again on the next run if chunking, embedding or storing it fails, or the run is cancelled. Wrap
+/// the storage with [`SkipUnchanged::record_after`] to store the checksums, they are written when
+/// the storage is flushed at the end of the run.
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_core::{ChecksumStore, Persist};
+/// # use swiftide_indexing::{loaders::FileLoader, transformers::{FileChecksum, SkipUnchanged}, Pipeline};
+/// # fn pipeline(store: impl ChecksumStore + 'static, storage: impl Persist + 'static) -> Pipeline {
+/// let skip_unchanged = SkipUnchanged::new(store);
+///
+/// Pipeline::from_loader(FileLoader::new("./docs"))
+///     .then(FileChecksum::default())
+///     .filter_cached(skip_unchanged.clone())
+///     .then_store_with(skip_unchanged.record_after(storage))
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SkipUnchanged {
+    store: Box<dyn ChecksumStore>,
+    files: Arc<PendingFiles>,
+}
+
+impl SkipUnchanged {
+    pub fn new(store: impl ChecksumStore + 'static) -> Self {
+        Self {
+            store: Box::new(store),
+            files: Arc::default(),
+        }
+    }
+
+    /// Wraps a storage so that the checksums of files are stored once all their nodes are stored
+    pub fn record_after(&self, storage: impl Persist + 'static) -> RecordChecksums {
+        RecordChecksums {
+            inner: Arc::new(storage),
+            skip_unchanged: self.clone(),
+        }
+    }
+}
+
+/// The path the checksum of the node is stored under, including the window of the file if any
+fn checksum_path(node: &Node) -> PathBuf {
+    match node.metadata.get(FILE_WINDOW) {
+        Some(window) => format!("{}#{window}", node.path.to_string_lossy()).into(),
+        None => node.path.clone(),
+    }
+}
+
+/// The checksum and, if known, the modification time of the file of the node
+fn fingerprint(node: &Node) -> Option<String> {
+    let checksum = node.metadata.get(NAME)?.as_str()?;
+
+    Some(match node.metadata.get(MTIME) {
+        Some(mtime) => format!("{checksum}:{mtime}"),
+        None => checksum.to_string(),
+    })
+}
+
+#[async_trait]
+impl NodeCache for SkipUnchanged {
+    /// Returns true if the file of the node is unchanged since its checksum was stored, otherwise
+    /// tracks the file until all its nodes are stored
+    async fn get(&self, node: &Node) -> bool {
+        let Some(fingerprint) = fingerprint(node) else {
+            return false;
+        };
+
+        if self.store.get(&checksum_path(node)).await.as_deref() == Some(fingerprint.as_str()) {
+            return true;
+        }
+
+        self.files.changed(node, fingerprint);
+        false
+    }
+
+    /// Does nothing, the checksum is stored by [`SkipUnchanged::record_after`] once all nodes of
+    /// the file are stored
+    async fn set(&self, _node: &Node) {}
+
+    fn name(&self) -> &'static str {
+        "skip_unchanged"
+    }
+
+    fn node_observer(&self) -> Option<Arc<dyn NodeObserver>> {
+        Some(Arc::clone(&self.files) as Arc<dyn NodeObserver>)
+    }
+}
+
+/// Changed files that are not fully stored yet, and the fingerprints of files that are
+#[derive(Debug, Default)]
+struct PendingFiles(Mutex<FileState>);
+
+#[derive(Debug, Default)]
+struct FileState {
+    pending: HashMap<String, PendingFile>,
+    /// Fingerprints of files of which all nodes are stored, to store once the storage is flushed
+    done: Vec<(PathBuf, String)>,
+}
+
+#[derive(Debug)]
+struct PendingFile {
+    path: PathBuf,
+    fingerprint: String,
+    /// The nodes of the file that are neither stored nor dropped yet
+    nodes: usize,
+    failed: bool,
+}
+
+impl PendingFiles {
+    fn state(&self) -> std::sync::MutexGuard<'_, FileState> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Tracks a node of a changed file until it is stored
+    fn changed(&self, node: &Node, fingerprint: String) {
+        let Some(key) = self.key(node) else {
+            return;
+        };
+        self.state()
+            .pending
+            .entry(key)
+            .and_modify(|file| file.nodes += 1)
+            .or_insert_with(|| PendingFile {
+                path: checksum_path(node),
+                fingerprint,
+                nodes: 1,
+                failed: false,
+            });
+    }
+
+    /// Marks a node of the file as stored or dropped, the file is done once all its nodes are
+    fn done(&self, key: &str) {
+        let mut state = self.state();
+        let Some(file) = state.pending.get_mut(key) else {
+            return;
+        };
+        file.nodes = file.nodes.saturating_sub(1);
+        if file.nodes > 0 {
+            return;
+        }
+
+        if let Some(file) = state.pending.remove(key) {
+            if !file.failed {
+                state.done.push((file.path, file.fingerprint));
+            }
+        }
+    }
+
+    /// Marks a node of the file as failed, so that the checksum of the file is not stored
+    fn fail(&self, key: &str) {
+        let mut state = self.state();
+        if let Some(file) = state.pending.get_mut(key) {
+            file.failed = true;
+        }
+        drop(state);
+        self.done(key);
+    }
+}
+
+impl NodeObserver for PendingFiles {
+    fn key(&self, node: &Node) -> Option<String> {
+        fingerprint(node)?;
+        Some(checksum_path(node).to_string_lossy().into_owned())
+    }
+
+    fn added(&self, key: &str) {
+        if let Some(file) = self.state().pending.get_mut(key) {
+            file.nodes += 1;
+        }
+    }
+
+    fn released(&self, key: &str) {
+        self.done(key);
+    }
+
+    fn failed(&self, key: &str) {
+        self.fail(key);
+    }
+}
+
+/// Wraps a storage and stores the checksums of files once all their nodes are stored
+///
+/// Created with [`SkipUnchanged::record_after`]. The checksums are stored once the storage is
+/// flushed, so that checksums of nodes still buffered by the storage are not stored. A node that
+/// fails to store keeps the checksum of its file from being stored, so the file is indexed again
+/// on the next run.
+#[derive(Debug, Clone)]
+pub struct RecordChecksums {
+    inner: Arc<dyn Persist>,
+    skip_unchanged: SkipUnchanged,
+}
+
+#[async_trait]
+impl Persist for RecordChecksums {
+    async fn setup(&self) -> Result<()> {
+        self.inner.setup().await
+    }
+
+    async fn store(&self, node: Node) -> Result<Node> {
+        let files = &self.skip_unchanged.files;
+        let key = files.key(&node);
+        let result = self.inner.store(node).await;
+
+        if let Some(key) = key {
+            match &result {
+                Ok(_) => files.done(&key),
+                Err(_) => files.fail(&key),
+            }
+        }
+        result
+    }
+
+    /// Stores the batch, nodes missing from the output count as failed
+    async fn batch_store(&self, nodes: Vec<Node>) -> IndexingStream {
+        let files = &self.skip_unchanged.files;
+        let mut keys = nodes.iter().filter_map(|node| files.key(node)).counts();
+        let results = self
+            .inner
+            .batch_store(nodes)
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        for node in results.iter().filter_map(|result| result.as_ref().ok()) {
+            let Some(key) = files.key(node) else {
+                continue;
+            };
+            if let Some(remaining) = keys.get_mut(&key).filter(|remaining| **remaining > 0) {
+                *remaining -= 1;
+                files.done(&key);
+            }
+        }
+        for (key, remaining) in keys {
+            for _ in 0..remaining {
+                files.fail(&key);
+            }
+        }
+
+        IndexingStream::iter(results)
+    }
+
+    fn batch_size(&self) -> Option<usize> {
+        self.inner.batch_size()
+    }
+
+    fn batch_max_bytes(&self) -> Option<usize> {
+        self.inner.batch_max_bytes()
+    }
+
+    async fn count(&self) -> Result<u64> {
+        self.inner.count().await
+    }
+
+    async fn stream_all(&self) -> IndexingStream {
+        self.inner.stream_all().await
+    }
+
+    async fn exists(&self, nodes: &[Node]) -> Result<Vec<bool>> {
+        self.inner.exists(nodes).await
+    }
+
+    /// Flushes the storage, then stores the checksums of the files of which all nodes are stored
+    async fn flush(&self) -> Result<()> {
+        self.inner.flush().await?;
+
+        let done = std::mem::take(&mut self.skip_unchanged.files.state().done);
+        for (path, fingerprint) in done {
+            self.skip_unchanged.store.set(&path, &fingerprint).await;
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashMap,
+        path::{Path, PathBuf},
+        sync::Arc,
+        time::{Duration, SystemTime},
+    };
+
+    use tokio::sync::Mutex;
+
+    use super::*;
+    use swiftide_core::MockPersist;
+
+    use crate::{loaders::FileLoader, persist::MemoryStorage, transformers::ChunkText, Pipeline};
+
+    #[tokio::test]
+    async fn test_adds_checksum() {
+        let node = FileChecksum::default()
+            .transform_node(Node::new("Hello"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            node.metadata.get(NAME).unwrap(),
+            "185f8db32271fe25f561a6fc938b2e264306ec304eda518007d1764826381969"
+        );
+        assert!(node.metadata.get(MTIME).is_none());
+    }
+
+    /// Keeps checksums in memory, like a persistent store would between runs
+    #[derive(Debug, Clone, Default)]
+    struct MemoryChecksums(Arc<Mutex<HashMap<PathBuf, String>>>);
+
+    #[async_trait]
+    impl ChecksumStore for MemoryChecksums {
+        async fn get(&self, path: &Path) -> Option<String> {
+            self.0.lock().await.get(path).cloned()
+        }
+
+        async fn set(&self, path: &Path, checksum: &str) {
+            self.0
+                .lock()
+                .await
+                .insert(path.to_path_buf(), checksum.to_string());
+        }
+    }
+
+    async fn run(dir: &Path, checksums: &MemoryChecksums) -> Vec<String> {
+        let storage = MemoryStorage::default();
+        let skip_unchanged = SkipUnchanged::new(checksums.clone());
+        Pipeline::from_loader(FileLoader::new(dir))
+            .then(FileChecksum::default())
+            .filter_cached(skip_unchanged.clone())
+            .then_store_with(skip_unchanged.record_after(storage.clone()))
+            .run()
+            .await
+            .unwrap();
+
+        storage
+            .get_all_values()
+            .await
+            .into_iter()
+            .map(|node| node.chunk)
+            .collect()
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_skips_unchanged_files() {
+        let dir = temp_dir::TempDir::new().unwrap();
+        let path = dir.child("file.md");
+        std::fs::write(&path, "Hello world").unwrap();
+        let checksums = MemoryChecksums::default();
+
+        assert_eq!(run(dir.path(), &checksums).await, ["Hello world"]);
+        assert!(run(dir.path(), &checksums).await.is_empty());
+
+        // A changed file passes
+        std::fs::write(&path, "Hello again").unwrap();
+        assert_eq!(run(dir.path(), &checksums).await, ["Hello again"]);
+        assert!(run(dir.path(), &checksums).await.is_empty());
+
+        // So does a file with the same content but a different modification time
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_hours(1))
+            .unwrap();
+        assert_eq!(run(dir.path(), &checksums).await, ["Hello again"]);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_indexes_files_again_when_storing_fails() {
+        let dir = temp_dir::TempDir::new().unwrap();
+        std::fs::write(dir.child("file.md"), "Hello world").unwrap();
+        let checksums = MemoryChecksums::default();
+
+        let mut failing = MockPersist::new();
+        failing.expect_setup().returning(|| Ok(()));
+        failing.expect_name().returning(|| "failing");
+        failing.expect_batch_size().returning(|| Some(10));
+        failing.expect_batch_max_bytes().returning(|| None);
+        failing
+            .expect_batch_store()
+            .returning(|_| IndexingStream::iter(vec![Err(anyhow::anyhow!("Storage down"))]));
+        let skip_unchanged = SkipUnchanged::new(checksums.clone());
+        let result = Pipeline::from_loader(FileLoader::new(dir.path()))
+            .then(FileChecksum::default())
+            .filter_cached(skip_unchanged.clone())
+            .then_store_with(skip_unchanged.record_after(failing))
+            .run()
+            .await;
+        assert!(result.is_err());
+        assert!(checksums.0.lock().await.is_empty());
+
+        assert_eq!(run(dir.path(), &checksums).await, ["Hello world"]);
+        assert!(run(dir.path(), &checksums).await.is_empty());
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_stores_checksum_once_all_chunks_are_stored() {
+        let dir = temp_dir::TempDir::new().unwrap();
+        std::fs::write(dir.child("file.md"), "first\n\nsecond").unwrap();
+        let checksums = MemoryChecksums::default();
+        let storage = MemoryStorage::default();
+
+        let skip_unchanged = SkipUnchanged::new(checksums.clone());
+        let result = Pipeline::from_loader(FileLoader::new(dir.path()))
+            .then(FileChecksum::default())
+            .filter_cached(skip_unchanged.clone())
+            .then_chunk(ChunkText::from_chunk_range(1..7))
+            .then(|node: Node| {
+                if node.chunk == "second" {
+                    anyhow::bail!("Failed to embed")
+                }
+                Ok(node)
+            })
+            .filter_errors()
+            .then_store_with(skip_unchanged.record_after(storage.clone()))
+            .run()
+            .await;
+
+        assert!(result.is_ok());
+        assert_eq!(storage.get_all_values().await.len(), 1);
+        assert!(checksums.0.lock().await.is_empty());
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_tracks_windows_of_a_file_separately() {
+        let dir = temp_dir::TempDir::new().unwrap();
+        std::fs::write(dir.child("file.log"), "first\nsecond\n").unwrap();
+        let checksums = MemoryChecksums::default();
+
+        let run = || async {
+            let storage = MemoryStorage::default();
+            let skip_unchanged = SkipUnchanged::new(checksums.clone());
+            Pipeline::from_loader(FileLoader::new(dir.path()).with_window_bytes(7))
+                .then(FileChecksum::default())
+                .filter_cached(skip_unchanged.clone())
+                .then_store_with(skip_unchanged.record_after(storage.clone()))
+                .run()
+                .await
+                .unwrap();
+            storage.get_all_values().await.len()
+        };
+
+        assert_eq!(run().await, 2);
+        assert_eq!(checksums.0.lock().await.len(), 2);
+        assert_eq!(run().await, 0);
+    }
+}
@@ -0,0 +1,200 @@
+//! Keep the serialized metadata of a node under a size limit
+use anyhow::Result;
+use async_trait::async_trait;
+use swiftide_core::{indexing::Node, Transformer, WithIndexingDefaults};
+
+/// The metadata key under which the names of dropped fields are recorded
+pub const NAME: &str = "dropped_metadata";
+
+/// Keeps the JSON serialized metadata of each node within `max_bytes`
+///
+/// Some storage backends reject metadata over a size limit, failing the whole batch. Use this as
+/// the last step before storing.
+///
+/// When the metadata is too large, the configured truncatable string fields are shortened first,
+/// in order. If that is not enough, the largest fields are dropped until the metadata fits. The
+/// names of dropped fields are recorded as a list under `dropped_metadata`.
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_indexing::transformers::GuardMetadataSize;
+/// let guard = GuardMetadataSize::new(40 * 1024).with_truncate_fields(["Summary"]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct GuardMetadataSize {
+    max_bytes: usize,
+    truncate_fields: Vec<String>,
+    concurrency: Option<usize>,
+}
+
+impl GuardMetadataSize {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            truncate_fields: Vec::new(),
+            concurrency: None,
+        }
+    }
+
+    /// String fields that may be truncated before any fields are dropped
+    #[must_use]
+    pub fn with_truncate_fields(
+        mut self,
+        fields: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.truncate_fields = fields.into_iter().map(Into::into).collect();
+        self
+    }
+
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
+    fn truncate(&self, node: &mut Node) -> Result<()> {
+        for field in &self.truncate_fields {
+            let excess = metadata_size(node)?.saturating_sub(self.max_bytes);
+            if excess == 0 {
+                break;
+            }
+
+            let Some(serde_json::Value::String(value)) = node.metadata.get_mut(field) else {
+                continue;
+            };
+
+            // Every removed byte shrinks the serialized value by at least one byte
+            let mut len = value.len().saturating_sub(excess);
+            while !value.is_char_boundary(len) {
+                len -= 1;
+            }
+            value.truncate(len);
+
+            tracing::debug!(field, "Truncated metadata field");
+        }
+
+        Ok(())
+    }
+
+    fn drop_largest(&self, node: &mut Node) -> Result<()> {
+        let mut dropped = Vec::new();
+
+        while metadata_size(node)? > self.max_bytes {
+            let largest = node
+                .metadata
+                .iter()
+                .filter(|(key, _)| *key != NAME)
+                .map(|(key, value)| (key.len() + value.to_string().len(), key))
+                .max()
+                .map(|(_, key)| key.clone());
+
+            let Some(key) = largest else {
+                tracing::warn!(
+                    max_bytes = self.max_bytes,
+                    "Metadata exceeds the limit after dropping all fields"
+                );
+                break;
+            };
+
+            node.metadata.remove(&key);
+            dropped.push(key);
+            node.metadata.insert(NAME, dropped.clone());
+        }
+
+        if !dropped.is_empty() {
+            tracing::debug!(?dropped, "Dropped metadata fields");
+        }
+
+        Ok(())
+    }
+}
+
+fn metadata_size(node: &Node) -> Result<usize> {
+    Ok(serde_json::to_vec(&node.metadata)?.len())
+}
+
+impl WithIndexingDefaults for GuardMetadataSize {}
+
+#[async_trait]
+impl Transformer for GuardMetadataSize {
+    #[tracing::instrument(skip_all, name = "transformers.guard_metadata_size")]
+    async fn transform_node(&self, mut node: Node) -> Result<Node> {
+        if metadata_size(&node)? <= self.max_bytes {
+            return Ok(node);
+        }
+
+        self.truncate(&mut node)?;
+        self.drop_largest(&mut node)?;
+
+        Ok(node)
+    }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.concurrency
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn oversized_node() -> Node {
+        let mut node = Node::new("chunk");
+        node.metadata.insert("Summary", "s".repeat(200));
+        node.metadata.insert("Questions", "q".repeat(150));
+        node.metadata.insert("Title", "A title");
+        node
+    }
+
+    #[tokio::test]
+    async fn test_leaves_small_metadata_alone() {
+        let node = oversized_node();
+
+        let result = GuardMetadataSize::new(1024)
+            .transform_node(node.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(result, node);
+    }
+
+    #[tokio::test]
+    async fn test_drops_largest_fields() {
+        let result = GuardMetadataSize::new(250)
+            .transform_node(oversized_node())
+            .await
+            .unwrap();
+
+        assert!(metadata_size(&result).unwrap() <= 250);
+        assert!(result.metadata.get("Summary").is_none());
+        assert_eq!(result.metadata.get("Questions").unwrap(), &"q".repeat(150));
+        assert_eq!(
+            result.metadata.get(NAME).unwrap(),
+            &serde_json::json!(["Summary"])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_truncates_fields_before_dropping() {
+        let result = GuardMetadataSize::new(250)
+            .with_truncate_fields(["Summary"])
+            .transform_node(oversized_node())
+            .await
+            .unwrap();
+
+        assert_eq!(metadata_size(&result).unwrap(), 250);
+        assert!(result.metadata.get(NAME).is_none());
+        assert_eq!(result.metadata.get("Questions").unwrap(), &"q".repeat(150));
+        assert!(
+            result
+                .metadata
+                .get("Summary")
+                .unwrap()
+                .as_str()
+                .unwrap()
+                .len()
+                < 200
+        );
+    }
+}
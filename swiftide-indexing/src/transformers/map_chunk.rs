@@ -0,0 +1,159 @@
+//! Rewrite the chunk of each node with a closure
+use std::{future::Future, sync::Arc};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures_util::{future::BoxFuture, FutureExt as _};
+use swiftide_core::{indexing::Node, Transformer, WithIndexingDefaults};
+
+/// Rewrites the chunk of every node with a closure
+///
+/// Handy for quick cleanups like lowercasing, normalizing whitespace or stripping boilerplate,
+/// without writing a full transformer. See [`MapChunkAsync`] for closures that need to await.
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_indexing::{transformers::MapChunk, Pipeline};
+/// # use swiftide_indexing::loaders::FileLoader;
+/// Pipeline::from_loader(FileLoader::new("."))
+///     .then(MapChunk::new(|chunk| chunk.to_lowercase()))
+///     .then(MapChunk::new(|chunk| chunk.replace("Copyright", "")));
+/// ```
+#[derive(Clone)]
+pub struct MapChunk {
+    map: Arc<dyn Fn(String) -> String + Send + Sync>,
+    concurrency: Option<usize>,
+}
+
+impl MapChunk {
+    pub fn new(map: impl Fn(String) -> String + Send + Sync + 'static) -> Self {
+        Self {
+            map: Arc::new(map),
+            concurrency: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+}
+
+impl std::fmt::Debug for MapChunk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MapChunk")
+            .field("concurrency", &self.concurrency)
+            .finish_non_exhaustive()
+    }
+}
+
+impl WithIndexingDefaults for MapChunk {}
+
+#[async_trait]
+impl Transformer for MapChunk {
+    #[tracing::instrument(skip_all, name = "transformers.map_chunk")]
+    async fn transform_node(&self, mut node: Node) -> Result<Node> {
+        node.chunk = (self.map)(std::mem::take(&mut node.chunk));
+
+        Ok(node)
+    }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.concurrency
+    }
+}
+
+/// Rewrites the chunk of every node with an async closure
+///
+/// Like [`MapChunk`], but the closure returns a future that can fail. Errors are yielded by the
+/// pipeline like those of any other transformer.
+#[derive(Clone)]
+pub struct MapChunkAsync {
+    map: Arc<dyn Fn(String) -> BoxFuture<'static, Result<String>> + Send + Sync>,
+    concurrency: Option<usize>,
+}
+
+impl MapChunkAsync {
+    pub fn new<F, Fut>(map: F) -> Self
+    where
+        F: Fn(String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String>> + Send + 'static,
+    {
+        Self {
+            map: Arc::new(move |chunk| map(chunk).boxed()),
+            concurrency: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+}
+
+impl std::fmt::Debug for MapChunkAsync {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MapChunkAsync")
+            .field("concurrency", &self.concurrency)
+            .finish_non_exhaustive()
+    }
+}
+
+impl WithIndexingDefaults for MapChunkAsync {}
+
+#[async_trait]
+impl Transformer for MapChunkAsync {
+    #[tracing::instrument(skip_all, name = "transformers.map_chunk_async")]
+    async fn transform_node(&self, mut node: Node) -> Result<Node> {
+        node.chunk = (self.map)(std::mem::take(&mut node.chunk)).await?;
+
+        Ok(node)
+    }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.concurrency
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn normalize_whitespace(chunk: &str) -> String {
+        chunk.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    #[tokio::test]
+    async fn test_map_chunk() {
+        let node = Node::new("  Some \t text\n\n with   whitespace ");
+
+        let result = MapChunk::new(|chunk| normalize_whitespace(&chunk))
+            .transform_node(node)
+            .await
+            .unwrap();
+
+        assert_eq!(result.chunk, "Some text with whitespace");
+    }
+
+    #[tokio::test]
+    async fn test_map_chunk_async() {
+        let transformer = MapChunkAsync::new(|chunk: String| async move {
+            if chunk.is_empty() {
+                anyhow::bail!("Empty chunk");
+            }
+            Ok(normalize_whitespace(&chunk))
+        });
+
+        let result = transformer
+            .transform_node(Node::new(" Some  text "))
+            .await
+            .unwrap();
+        assert_eq!(result.chunk, "Some text");
+
+        let error = transformer.transform_node(Node::new("")).await.unwrap_err();
+        assert_eq!(error.to_string(), "Empty chunk");
+    }
+}
@@ -8,20 +8,66 @@
 //!
 //!  See [`swiftide_core::prompt::Prompt`] and [`swiftide_core::prompt::PromptTemplate`]
 
+pub mod canonicalize_path;
+pub mod caption_image;
 pub mod chunk_markdown;
+pub mod chunk_recursive;
+pub mod chunk_semantic;
+pub mod chunk_sentences;
+pub mod chunk_sliding_window;
 pub mod chunk_text;
+pub mod chunk_tokens;
+pub mod count_tokens;
+pub mod drop_bad_vectors;
 pub mod embed;
+pub mod expand;
+pub mod extract_entities;
+pub mod file_checksum;
+pub mod guard_metadata_size;
+pub mod map_chunk;
 pub mod metadata_keywords;
 pub mod metadata_qa_text;
 pub mod metadata_summary;
 pub mod metadata_title;
+pub mod noop;
+pub mod normalize_metadata_keys;
+pub mod prepend_metadata;
+pub mod semantic_dedup;
+pub mod simhash_dedup;
 pub mod sparse_embed;
+pub mod tap;
+pub mod translate;
+#[cfg(feature = "validate-metadata")]
+pub mod validate_metadata;
 
+pub use canonicalize_path::CanonicalizePath;
+pub use caption_image::CaptionImage;
 pub use chunk_markdown::ChunkMarkdown;
+pub use chunk_recursive::ChunkRecursive;
+pub use chunk_semantic::ChunkSemantic;
+pub use chunk_sentences::ChunkSentences;
+pub use chunk_sliding_window::ChunkSlidingWindow;
 pub use chunk_text::ChunkText;
+pub use chunk_tokens::ChunkTokens;
+pub use count_tokens::CountTokens;
+pub use drop_bad_vectors::DropBadVectors;
 pub use embed::Embed;
+pub use expand::Expand;
+pub use extract_entities::ExtractEntities;
+pub use file_checksum::{FileChecksum, RecordChecksums, SkipUnchanged};
+pub use guard_metadata_size::GuardMetadataSize;
+pub use map_chunk::{MapChunk, MapChunkAsync};
 pub use metadata_keywords::MetadataKeywords;
 pub use metadata_qa_text::MetadataQAText;
 pub use metadata_summary::MetadataSummary;
 pub use metadata_title::MetadataTitle;
+pub use noop::Noop;
+pub use normalize_metadata_keys::NormalizeMetadataKeys;
+pub use prepend_metadata::PrependMetadata;
+pub use semantic_dedup::SemanticDedup;
+pub use simhash_dedup::SimHashDedup;
 pub use sparse_embed::SparseEmbed;
+pub use tap::Tap;
+pub use translate::Translate;
+#[cfg(feature = "validate-metadata")]
+pub use validate_metadata::ValidateMetadata;
@@ -0,0 +1,35 @@
+//! Pass nodes through unchanged
+use anyhow::Result;
+use async_trait::async_trait;
+use swiftide_core::{indexing::Node, Transformer, WithIndexingDefaults};
+
+/// Passes every node through unchanged
+///
+/// Useful as a placeholder while assembling a pipeline, i.e. for a step that is only added
+/// conditionally.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Noop;
+
+impl WithIndexingDefaults for Noop {}
+
+#[async_trait]
+impl Transformer for Noop {
+    async fn transform_node(&self, node: Node) -> Result<Node> {
+        Ok(node)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_passes_nodes_through() {
+        let mut node = Node::new("Some text");
+        node.metadata.insert("key", "value");
+
+        let result = Noop.transform_node(node.clone()).await.unwrap();
+
+        assert_eq!(result, node);
+    }
+}
@@ -0,0 +1,247 @@
+//! Normalize the casing of metadata keys
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use swiftide_core::{indexing::Node, Transformer, WithIndexingDefaults};
+
+/// The casing metadata keys are normalized to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyCase {
+    /// Lowercases keys, i.e. `Title` becomes `title`
+    #[default]
+    Lower,
+    /// Lowercases keys and separates words with underscores, i.e. `Questions and Answers` and
+    /// `questionsAndAnswers` both become `questions_and_answers`
+    Snake,
+}
+
+impl KeyCase {
+    fn apply(self, key: &str) -> String {
+        match self {
+            KeyCase::Lower => key.to_lowercase(),
+            KeyCase::Snake => to_snake_case(key),
+        }
+    }
+}
+
+/// What to do when several keys of a node normalize to the same key
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    /// Keeps the value of the key that is already normalized. If there is none, keeps the value
+    /// of the first key in sorted order.
+    #[default]
+    KeepNormalized,
+    /// Merges the values into a list, ordered by their original keys. Values that are lists are
+    /// flattened into it.
+    Merge,
+    /// Fails the node
+    Error,
+}
+
+/// Normalizes the keys of the metadata of each node, i.e. so that `Title` and `title` can be
+/// filtered on the same key
+///
+/// Metadata keys are processed in sorted order, so collisions are resolved the same way for
+/// every node.
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_indexing::transformers::normalize_metadata_keys::*;
+/// let normalize = NormalizeMetadataKeys::new(KeyCase::Snake)
+///     .with_collision_policy(CollisionPolicy::Merge);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct NormalizeMetadataKeys {
+    case: KeyCase,
+    collision_policy: CollisionPolicy,
+    concurrency: Option<usize>,
+}
+
+impl NormalizeMetadataKeys {
+    pub fn new(case: KeyCase) -> Self {
+        Self {
+            case,
+            ..Default::default()
+        }
+    }
+
+    /// Sets how keys that normalize to the same key are resolved. Defaults to
+    /// [`CollisionPolicy::KeepNormalized`].
+    #[must_use]
+    pub fn with_collision_policy(mut self, collision_policy: CollisionPolicy) -> Self {
+        self.collision_policy = collision_policy;
+        self
+    }
+
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
+    fn resolve(
+        &self,
+        normalized: &str,
+        mut values: Vec<(String, serde_json::Value)>,
+    ) -> Result<serde_json::Value> {
+        if values.len() == 1 {
+            return Ok(values.swap_remove(0).1);
+        }
+
+        match self.collision_policy {
+            CollisionPolicy::KeepNormalized => {
+                let index = values
+                    .iter()
+                    .position(|(key, _)| key == normalized)
+                    .unwrap_or(0);
+                Ok(values.swap_remove(index).1)
+            }
+            CollisionPolicy::Merge => Ok(values
+                .into_iter()
+                .flat_map(|(_, value)| match value {
+                    serde_json::Value::Array(values) => values,
+                    value => vec![value],
+                })
+                .collect()),
+            CollisionPolicy::Error => {
+                let keys = values.into_iter().map(|(key, _)| key).collect::<Vec<_>>();
+                bail!(
+                    "Metadata keys {} all normalize to {normalized}",
+                    keys.join(", ")
+                )
+            }
+        }
+    }
+}
+
+/// Splits words on non alphanumeric characters and on changes from lower to upper case, keeping
+/// acronyms together, i.e. `HTTPServer` becomes `http_server`
+fn to_snake_case(key: &str) -> String {
+    let chars = key.chars().collect::<Vec<_>>();
+    let mut snake = String::with_capacity(key.len());
+    let mut separate = false;
+
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            separate = true;
+            continue;
+        }
+
+        let prev = i.checked_sub(1).map(|i| chars[i]);
+        let next = chars.get(i + 1);
+        let starts_word = c.is_uppercase()
+            && prev.is_some_and(|prev| {
+                prev.is_lowercase()
+                    || prev.is_numeric()
+                    || (prev.is_uppercase() && next.is_some_and(|next| next.is_lowercase()))
+            });
+
+        if (separate || starts_word) && !snake.is_empty() {
+            snake.push('_');
+        }
+        separate = false;
+        snake.extend(c.to_lowercase());
+    }
+
+    snake
+}
+
+impl WithIndexingDefaults for NormalizeMetadataKeys {}
+
+#[async_trait]
+impl Transformer for NormalizeMetadataKeys {
+    /// Normalizes the metadata keys of the node
+    ///
+    /// # Errors
+    ///
+    /// Errors on colliding keys with [`CollisionPolicy::Error`]
+    #[tracing::instrument(skip_all, name = "transformers.normalize_metadata_keys")]
+    async fn transform_node(&self, mut node: Node) -> Result<Node> {
+        let mut normalized = BTreeMap::<String, Vec<_>>::new();
+        for (key, value) in std::mem::take(&mut node.metadata) {
+            normalized
+                .entry(self.case.apply(&key))
+                .or_default()
+                .push((key, value));
+        }
+
+        for (key, values) in normalized {
+            let value = self.resolve(&key, values)?;
+            node.metadata.insert(key, value);
+        }
+
+        Ok(node)
+    }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.concurrency
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mixed_case_node() -> Node {
+        let mut node = Node::new("chunk");
+        node.metadata.insert("Title", "From a transformer");
+        node.metadata.insert("title", "From a loader");
+        node.metadata.insert("TITLE", "Shouting");
+        node.metadata.insert("Questions and Answers", "Q & A");
+        node.metadata.insert("fileName", "main.rs");
+        node
+    }
+
+    #[test]
+    fn test_to_snake_case() {
+        assert_eq!(
+            to_snake_case("Questions and Answers"),
+            "questions_and_answers"
+        );
+        assert_eq!(to_snake_case("fileName"), "file_name");
+        assert_eq!(to_snake_case("HTTPServer"), "http_server");
+        assert_eq!(to_snake_case("  already_snake "), "already_snake");
+        assert_eq!(to_snake_case("Summary (code)"), "summary_code");
+    }
+
+    #[tokio::test]
+    async fn test_normalizes_keys_and_resolves_collisions() {
+        let result = NormalizeMetadataKeys::new(KeyCase::Snake)
+            .transform_node(mixed_case_node())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            result.metadata.iter().collect::<Vec<_>>(),
+            [
+                (&"file_name".to_string(), &"main.rs".into()),
+                (&"questions_and_answers".to_string(), &"Q & A".into()),
+                (&"title".to_string(), &"From a loader".into()),
+            ]
+        );
+
+        let result = NormalizeMetadataKeys::new(KeyCase::Lower)
+            .with_collision_policy(CollisionPolicy::Merge)
+            .transform_node(mixed_case_node())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            result.metadata.get("title").unwrap(),
+            &serde_json::json!(["Shouting", "From a transformer", "From a loader"])
+        );
+        assert!(result.metadata.get("questions and answers").is_some());
+
+        let err = NormalizeMetadataKeys::default()
+            .with_collision_policy(CollisionPolicy::Error)
+            .transform_node(mixed_case_node())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Metadata keys TITLE, Title, title all normalize to title"
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! Prepend selected metadata to the chunk as a header
+use anyhow::Result;
+use async_trait::async_trait;
+use swiftide_core::{indexing::Node, Transformer, WithIndexingDefaults};
+
+/// Renders the given metadata fields as a header at the top of the chunk
+///
+/// The fields are rendered as `field: value` lines in the configured order, followed by the
+/// chunk, like [`Node::combined_text`]. Missing fields are skipped. Prepending context like the
+/// title or headings helps the embedding capture what a chunk is about.
+///
+/// Optionally keeps the original chunk in a metadata field, i.e. to show it without the header
+/// when retrieved. As the header is now part of the chunk, embed with `EmbedMode::PerField` or
+/// leave the fields out of the metadata to avoid embedding them twice.
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_indexing::transformers::PrependMetadata;
+/// let prepend = PrependMetadata::new(&["title", "headings"]).with_original_chunk_field("original");
+/// ```
+#[derive(Debug, Clone)]
+pub struct PrependMetadata {
+    fields: Vec<String>,
+    original_chunk_field: Option<String>,
+    concurrency: Option<usize>,
+}
+
+impl PrependMetadata {
+    /// Creates a new `PrependMetadata` prepending the fields in the given order
+    pub fn new(fields: &[&str]) -> Self {
+        Self {
+            fields: fields.iter().map(ToString::to_string).collect(),
+            original_chunk_field: None,
+            concurrency: None,
+        }
+    }
+
+    /// Keeps the chunk without the header in the given metadata field
+    #[must_use]
+    pub fn with_original_chunk_field(mut self, field: impl Into<String>) -> Self {
+        self.original_chunk_field = Some(field.into());
+        self
+    }
+
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+}
+
+impl WithIndexingDefaults for PrependMetadata {}
+
+#[async_trait]
+impl Transformer for PrependMetadata {
+    #[tracing::instrument(skip_all, name = "transformers.prepend_metadata")]
+    async fn transform_node(&self, mut node: Node) -> Result<Node> {
+        let fields = self.fields.iter().map(String::as_str).collect::<Vec<_>>();
+        let chunk = node.combined_text(&fields);
+
+        if let Some(field) = &self.original_chunk_field {
+            let original = std::mem::replace(&mut node.chunk, chunk);
+            node.metadata.insert(field, original);
+        } else {
+            node.chunk = chunk;
+        }
+
+        Ok(node)
+    }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.concurrency
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn node() -> Node {
+        Node::new("Pipelines are built from loaders, transformers and storages.")
+            .with_metadata([("headings", "Usage > Pipelines"), ("title", "Swiftide")])
+            .to_owned()
+    }
+
+    #[tokio::test]
+    async fn test_prepends_fields_in_order() {
+        let transformer = PrependMetadata::new(&["title", "missing", "headings"]);
+
+        let result = transformer.transform_node(node()).await.unwrap();
+
+        assert_eq!(
+            result.chunk,
+            "title: Swiftide\nheadings: Usage > Pipelines\nPipelines are built from loaders, transformers and storages."
+        );
+        assert!(result.metadata.get("original").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_keeps_original_chunk() {
+        let transformer = PrependMetadata::new(&["title"]).with_original_chunk_field("original");
+
+        let result = transformer.transform_node(node()).await.unwrap();
+
+        assert!(result.chunk.starts_with("title: Swiftide\n"));
+        assert_eq!(
+            result.metadata.get("original").unwrap(),
+            "Pipelines are built from loaders, transformers and storages."
+        );
+    }
+}
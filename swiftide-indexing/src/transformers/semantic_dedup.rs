@@ -0,0 +1,184 @@
+//! Drop near-duplicate nodes by comparing their embeddings
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use swiftide_core::{
+    indexing::{DropReason, EmbeddedField, IndexingStream, Node},
+    BatchableTransformer, Embedding, WithBatchIndexingDefaults, WithIndexingDefaults,
+};
+
+/// Drops nodes whose vector is nearly identical to that of a node seen before
+///
+/// Use after embedding. A node is dropped when the cosine similarity of its vector to any
+/// previously kept vector exceeds the threshold. This catches near-duplicates that exact hashing
+/// misses, like boilerplate footers with tiny differences.
+///
+/// Seen vectors are kept in memory and compared by brute force, so this is suited for corpora of
+/// moderate size. Nodes without the vector are passed through.
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_indexing::transformers::SemanticDedup;
+/// let dedup = SemanticDedup::new(0.98);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SemanticDedup {
+    threshold: f32,
+    embedded_field: EmbeddedField,
+    seen: Arc<Mutex<Vec<Embedding>>>,
+    concurrency: Option<usize>,
+}
+
+impl SemanticDedup {
+    /// Creates a new `SemanticDedup` dropping nodes with a cosine similarity above `threshold`
+    ///
+    /// Compares the [`EmbeddedField::Combined`] vector by default.
+    pub fn new(threshold: f32) -> Self {
+        Self {
+            threshold,
+            embedded_field: EmbeddedField::Combined,
+            seen: Arc::default(),
+            concurrency: None,
+        }
+    }
+
+    /// Sets which vector of the node is compared
+    #[must_use]
+    pub fn with_embedded_field(mut self, embedded_field: EmbeddedField) -> Self {
+        self.embedded_field = embedded_field;
+        self
+    }
+
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
+    fn is_duplicate(&self, seen: &[Embedding], vector: &[f32]) -> bool {
+        seen.iter()
+            .any(|other| cosine_similarity(other, vector) > self.threshold)
+    }
+}
+
+pub(super) fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+
+    let dot = a.iter().zip(b).map(|(a, b)| a * b).sum::<f32>();
+    let norm_a = a.iter().map(|a| a * a).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|b| b * b).sum::<f32>().sqrt();
+
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+
+    dot / (norm_a * norm_b)
+}
+
+impl WithBatchIndexingDefaults for SemanticDedup {}
+impl WithIndexingDefaults for SemanticDedup {}
+
+#[async_trait]
+impl BatchableTransformer for SemanticDedup {
+    #[tracing::instrument(skip_all, name = "transformers.semantic_dedup")]
+    async fn batch_transform(&self, nodes: Vec<Node>) -> IndexingStream {
+        let Ok(mut seen) = self.seen.lock() else {
+            return anyhow::anyhow!("Seen vectors lock poisoned").into();
+        };
+
+        let kept = nodes
+            .into_iter()
+            .filter(|node| {
+                let Some(vector) = node
+                    .vectors
+                    .as_ref()
+                    .and_then(|vectors| vectors.get(&self.embedded_field))
+                else {
+                    return true;
+                };
+
+                if self.is_duplicate(&seen, vector) {
+                    tracing::debug!(path = ?node.path, "Dropping near-duplicate node");
+                    return false;
+                }
+
+                seen.push(vector.clone());
+                true
+            })
+            .collect::<Vec<_>>();
+
+        IndexingStream::from_nodes(kept)
+    }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.concurrency
+    }
+
+    fn drop_reason(&self) -> DropReason {
+        DropReason::Deduplicated
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures_util::TryStreamExt as _;
+
+    use super::*;
+
+    fn node_with_vector(chunk: &str, vector: Vec<f32>) -> Node {
+        let mut node = Node::new(chunk);
+        node.with_vectors([(EmbeddedField::Combined, vector)]);
+        node
+    }
+
+    #[tokio::test]
+    async fn test_drops_near_duplicates() {
+        let nodes = vec![
+            node_with_vector("Copyright 2023 Acme", vec![1.0, 0.0, 0.01]),
+            node_with_vector("Copyright 2024 Acme", vec![1.0, 0.0, 0.02]),
+            node_with_vector("Something else", vec![0.0, 1.0, 0.0]),
+            Node::new("Not embedded"),
+        ];
+
+        let kept: Vec<Node> = SemanticDedup::new(0.99)
+            .batch_transform(nodes)
+            .await
+            .try_collect()
+            .await
+            .unwrap();
+
+        let chunks = kept
+            .iter()
+            .map(|node| node.chunk.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            chunks,
+            ["Copyright 2023 Acme", "Something else", "Not embedded"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_remembers_across_batches() {
+        let dedup = SemanticDedup::new(0.99);
+
+        let first: Vec<Node> = dedup
+            .batch_transform(vec![node_with_vector("first", vec![1.0, 1.0])])
+            .await
+            .try_collect()
+            .await
+            .unwrap();
+        let second: Vec<Node> = dedup
+            .clone()
+            .batch_transform(vec![node_with_vector("second", vec![2.0, 2.0])])
+            .await
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(first.len(), 1);
+        assert!(second.is_empty());
+    }
+}
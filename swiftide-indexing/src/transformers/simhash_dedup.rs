@@ -0,0 +1,200 @@
+//! Drop near-duplicate nodes by comparing the `SimHash` of their chunks
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use swiftide_core::{
+    indexing::{DropReason, IndexingStream, Node},
+    BatchableTransformer, WithBatchIndexingDefaults, WithIndexingDefaults,
+};
+
+/// Drops nodes whose chunk is nearly identical to the chunk of a node seen before
+///
+/// Computes a 64-bit `SimHash` over the character shingles of each chunk, ignoring case and
+/// punctuation. A node is dropped when the Hamming distance of its hash to any previously kept
+/// hash is at most `max_distance` bits. Similar chunks have hashes that differ in few bits, so
+/// this catches boilerplate like headers and footers with tiny differences, without embedding
+/// anything.
+///
+/// A distance of 0 only drops chunks with the same text, ignoring case and punctuation.
+/// Boilerplate with small differences, like a year or page number, is typically within 6 bits,
+/// while unrelated chunks differ in about half of the bits. Seen hashes are kept in memory and
+/// compared by brute force.
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_indexing::transformers::SimHashDedup;
+/// let dedup = SimHashDedup::new(6);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SimHashDedup {
+    max_distance: u32,
+    seen: Arc<Mutex<Vec<u64>>>,
+    concurrency: Option<usize>,
+}
+
+impl SimHashDedup {
+    /// Creates a new `SimHashDedup` dropping nodes within `max_distance` bits of a seen node
+    pub fn new(max_distance: u32) -> Self {
+        Self {
+            max_distance,
+            seen: Arc::default(),
+            concurrency: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
+    fn is_duplicate(&self, seen: &[u64], hash: u64) -> bool {
+        seen.iter()
+            .any(|other| (other ^ hash).count_ones() <= self.max_distance)
+    }
+}
+
+/// Length of the character shingles hashed into the `SimHash`
+const SHINGLE_SIZE: usize = 4;
+
+/// Computes the `SimHash` of the character shingles of a text, ignoring case and punctuation
+fn simhash(text: &str) -> u64 {
+    let normalized = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .collect::<Vec<_>>();
+
+    let mut weights = [0i64; 64];
+    for shingle in normalized.windows(SHINGLE_SIZE.min(normalized.len()).max(1)) {
+        let hash = hash_shingle(shingle);
+
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            if hash >> bit & 1 == 1 {
+                *weight += 1;
+            } else {
+                *weight -= 1;
+            }
+        }
+    }
+
+    weights
+        .iter()
+        .enumerate()
+        .filter(|(_, weight)| **weight > 0)
+        .fold(0, |hash, (bit, _)| hash | 1 << bit)
+}
+
+/// Hashes a shingle with FNV-1a and a splitmix64 finalizer, so that hashes are stable across
+/// Rust versions and well distributed over all bits
+fn hash_shingle(shingle: &[char]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    for c in shingle {
+        for byte in u32::from(*c).to_le_bytes() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+impl WithBatchIndexingDefaults for SimHashDedup {}
+impl WithIndexingDefaults for SimHashDedup {}
+
+#[async_trait]
+impl BatchableTransformer for SimHashDedup {
+    #[tracing::instrument(skip_all, name = "transformers.simhash_dedup")]
+    async fn batch_transform(&self, nodes: Vec<Node>) -> IndexingStream {
+        let Ok(mut seen) = self.seen.lock() else {
+            return anyhow::anyhow!("Seen hashes lock poisoned").into();
+        };
+
+        let kept = nodes
+            .into_iter()
+            .filter(|node| {
+                let hash = simhash(&node.chunk);
+
+                if self.is_duplicate(&seen, hash) {
+                    tracing::debug!(path = ?node.path, "Dropping near-duplicate node");
+                    return false;
+                }
+
+                seen.push(hash);
+                true
+            })
+            .collect::<Vec<_>>();
+
+        IndexingStream::from_nodes(kept)
+    }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.concurrency
+    }
+
+    fn drop_reason(&self) -> DropReason {
+        DropReason::Deduplicated
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures_util::TryStreamExt as _;
+
+    use super::*;
+
+    const BOILERPLATE: &str = "Home | Products | Pricing | Blog | Contact. Subscribe to our newsletter for product updates and tips. Copyright {year} Acme Corporation. All rights reserved. Read our privacy policy and terms of service. Page {page}.";
+
+    fn boilerplate(year: u32, page: u32) -> String {
+        BOILERPLATE
+            .replace("{year}", &year.to_string())
+            .replace("{page}", &page.to_string())
+    }
+
+    async fn dedup(dedup: SimHashDedup, chunks: &[String]) -> Vec<String> {
+        dedup
+            .batch_transform(chunks.iter().map(Node::new).collect())
+            .await
+            .map_ok(|node| node.chunk)
+            .try_collect()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_collapses_near_duplicate_boilerplate() {
+        let unrelated = "Swiftide is a library for streaming indexing pipelines, written in Rust. \
+            It loads, transforms and stores data."
+            .to_string();
+        let chunks = [
+            boilerplate(2023, 1),
+            boilerplate(2023, 2),
+            boilerplate(2024, 1),
+            boilerplate(2024, 12),
+            unrelated.clone(),
+        ];
+
+        let kept = dedup(SimHashDedup::new(6), &chunks).await;
+
+        assert_eq!(kept, [boilerplate(2023, 1), unrelated]);
+    }
+
+    #[tokio::test]
+    async fn test_zero_distance_only_drops_the_same_text() {
+        let chunks = [
+            boilerplate(2023, 1),
+            boilerplate(2023, 1).to_uppercase(),
+            boilerplate(2024, 1),
+        ];
+
+        let kept = dedup(SimHashDedup::new(0), &chunks).await;
+
+        assert_eq!(kept, [boilerplate(2023, 1), boilerplate(2024, 1)]);
+    }
+}
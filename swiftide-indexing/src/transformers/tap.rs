@@ -0,0 +1,88 @@
+//! Observe nodes with a closure without modifying them
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use swiftide_core::{indexing::Node, Transformer, WithIndexingDefaults};
+
+/// Runs a closure on every node and passes the node on unchanged
+///
+/// Handy to observe a pipeline at a specific step, i.e. to log or count nodes.
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_indexing::{transformers::{ChunkMarkdown, Tap}, Pipeline};
+/// # use swiftide_indexing::loaders::FileLoader;
+/// Pipeline::from_loader(FileLoader::new("."))
+///     .then_chunk(ChunkMarkdown::from_chunk_range(10..2048))
+///     .then(Tap::new(|node| tracing::info!(path = ?node.path, "Chunked")));
+/// ```
+#[derive(Clone)]
+pub struct Tap {
+    tap: Arc<dyn Fn(&Node) + Send + Sync>,
+    concurrency: Option<usize>,
+}
+
+impl Tap {
+    pub fn new(tap: impl Fn(&Node) + Send + Sync + 'static) -> Self {
+        Self {
+            tap: Arc::new(tap),
+            concurrency: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+}
+
+impl std::fmt::Debug for Tap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tap")
+            .field("concurrency", &self.concurrency)
+            .finish_non_exhaustive()
+    }
+}
+
+impl WithIndexingDefaults for Tap {}
+
+#[async_trait]
+impl Transformer for Tap {
+    #[tracing::instrument(skip_all, name = "transformers.tap")]
+    async fn transform_node(&self, node: Node) -> Result<Node> {
+        (self.tap)(&node);
+
+        Ok(node)
+    }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.concurrency
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_taps_every_node_once() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let tap = Tap::new(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let nodes = vec![Node::new("first"), Node::new("second")];
+        for node in &nodes {
+            let result = tap.transform_node(node.clone()).await.unwrap();
+            assert_eq!(&result, node);
+        }
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
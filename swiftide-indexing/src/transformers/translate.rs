@@ -0,0 +1,116 @@
+//! Translate a chunk to a target language and add it as metadata
+use anyhow::Result;
+use async_trait::async_trait;
+use swiftide_core::{indexing::Node, Transformer};
+
+/// Metadata key holding the detected language of a chunk, if any
+pub const LANGUAGE_KEY: &str = "language";
+
+/// `Translate` translates the chunk of a node to a target language and stores the
+/// translation in the `translation` metadata field, leaving the original chunk intact.
+///
+/// Useful for cross-lingual retrieval, where translations are indexed alongside the originals.
+///
+/// Nodes whose `language` metadata already matches the target language (case insensitive) are
+/// passed through without prompting.
+#[swiftide_macros::indexing_transformer(
+    metadata_field_name = "translation",
+    default_prompt_file = "prompts/translate.prompt.md"
+)]
+pub struct Translate {
+    /// The language to translate to, defaults to English
+    #[builder(default = "\"English\".to_string()")]
+    target_language: String,
+}
+
+impl Translate {
+    fn is_target_language(&self, node: &Node) -> bool {
+        node.metadata
+            .get(LANGUAGE_KEY)
+            .and_then(serde_json::Value::as_str)
+            .is_some_and(|language| language.eq_ignore_ascii_case(&self.target_language))
+    }
+}
+
+#[async_trait]
+impl Transformer for Translate {
+    /// Translates the chunk of a `Node` and adds the translation as metadata
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the client fails to translate the chunk
+    #[tracing::instrument(skip_all, name = "transformers.translate")]
+    async fn transform_node(&self, mut node: Node) -> Result<Node> {
+        if self.is_target_language(&node) {
+            return Ok(node);
+        }
+
+        let prompt = self
+            .prompt_template
+            .to_prompt()
+            .with_node(&node)
+            .with_context_value("target_language", self.target_language.as_str());
+
+        let response = self.prompt(prompt).await?;
+
+        node.metadata.insert(NAME, response);
+
+        Ok(node)
+    }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.concurrency
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use swiftide_core::MockSimplePrompt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_template() {
+        let template = default_prompt();
+
+        let prompt = template
+            .to_prompt()
+            .with_node(&Node::new("test"))
+            .with_context_value("target_language", "English");
+        insta::assert_snapshot!(prompt.render().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_translate() {
+        let mut client = MockSimplePrompt::new();
+
+        client
+            .expect_prompt()
+            .returning(|_| Ok("Hallo wereld".to_string()));
+
+        let transformer = Translate::builder()
+            .client(client)
+            .target_language("Dutch")
+            .build()
+            .unwrap();
+        let node = Node::new("Hello world");
+
+        let result = transformer.transform_node(node).await.unwrap();
+
+        assert_eq!(result.metadata.get("translation").unwrap(), "Hallo wereld");
+        assert_eq!(result.chunk, "Hello world");
+    }
+
+    #[tokio::test]
+    async fn test_skips_nodes_in_target_language() {
+        let client = MockSimplePrompt::new();
+
+        let transformer = Translate::builder().client(client).build().unwrap();
+        let mut node = Node::new("Hello world");
+        node.metadata.insert(LANGUAGE_KEY, "english");
+
+        let result = transformer.transform_node(node).await.unwrap();
+
+        assert!(result.metadata.get("translation").is_none());
+    }
+}
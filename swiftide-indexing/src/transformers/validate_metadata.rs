@@ -0,0 +1,188 @@
+//! Check the metadata of nodes against a JSON Schema
+use std::sync::Arc;
+
+use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+use swiftide_core::{
+    indexing::{IndexingStream, Node},
+    BatchableTransformer, WithBatchIndexingDefaults, WithIndexingDefaults,
+};
+
+/// What to do with a node whose metadata does not conform to the schema
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InvalidMetadataPolicy {
+    /// Emit an error for the node, listing every violation
+    #[default]
+    Error,
+    /// Drop the node with a warning
+    DropNode,
+}
+
+/// Validates the metadata of each node against a JSON Schema
+///
+/// Downstream consumers often expect specific metadata keys and types. Validating before storing
+/// catches transformers that produce unexpected metadata early. By default a node that does not
+/// conform results in an error, use [`InvalidMetadataPolicy::DropNode`] to drop it instead.
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_indexing::transformers::ValidateMetadata;
+/// let validate = ValidateMetadata::try_new(&serde_json::json!({
+///     "type": "object",
+///     "required": ["title"],
+///     "properties": { "title": { "type": "string" } }
+/// }))
+/// .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ValidateMetadata {
+    validator: Arc<jsonschema::Validator>,
+    policy: InvalidMetadataPolicy,
+    concurrency: Option<usize>,
+}
+
+impl ValidateMetadata {
+    /// Creates a new `ValidateMetadata` from a JSON Schema
+    ///
+    /// # Errors
+    ///
+    /// Errors if the schema is not a valid JSON Schema.
+    pub fn try_new(schema: &serde_json::Value) -> Result<Self> {
+        let validator = jsonschema::validator_for(schema)
+            .map_err(|err| anyhow::anyhow!("{err}"))
+            .context("Invalid metadata schema")?;
+
+        Ok(Self {
+            validator: Arc::new(validator),
+            policy: InvalidMetadataPolicy::default(),
+            concurrency: None,
+        })
+    }
+
+    /// Sets what to do with nodes that do not conform. Defaults to an error.
+    #[must_use]
+    pub fn with_policy(mut self, policy: InvalidMetadataPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
+    /// Applies the policy, returning `None` if the node is dropped
+    fn check(&self, node: Node) -> Option<Result<Node>> {
+        let metadata = match serde_json::to_value(&node.metadata) {
+            Ok(metadata) => metadata,
+            Err(err) => return Some(Err(err.into())),
+        };
+
+        let violations = self
+            .validator
+            .iter_errors(&metadata)
+            .map(|err| format!("{}: {err}", err.instance_path))
+            .collect::<Vec<_>>();
+
+        if violations.is_empty() {
+            return Some(Ok(node));
+        }
+
+        match self.policy {
+            InvalidMetadataPolicy::Error => Some(Err(anyhow::anyhow!(
+                "Metadata of node {path:?} does not match the schema: {violations}",
+                path = node.path,
+                violations = violations.join(", ")
+            ))),
+            InvalidMetadataPolicy::DropNode => {
+                tracing::warn!(path = ?node.path, ?violations, "Dropping node with invalid metadata");
+                None
+            }
+        }
+    }
+}
+
+impl WithBatchIndexingDefaults for ValidateMetadata {}
+impl WithIndexingDefaults for ValidateMetadata {}
+
+#[async_trait]
+impl BatchableTransformer for ValidateMetadata {
+    #[tracing::instrument(skip_all, name = "transformers.validate_metadata")]
+    async fn batch_transform(&self, nodes: Vec<Node>) -> IndexingStream {
+        let checked = nodes
+            .into_iter()
+            .filter_map(|node| self.check(node))
+            .collect::<Vec<_>>();
+
+        IndexingStream::iter(checked)
+    }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.concurrency
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures_util::StreamExt as _;
+
+    use super::*;
+
+    fn validate_metadata() -> ValidateMetadata {
+        ValidateMetadata::try_new(&serde_json::json!({
+            "type": "object",
+            "required": ["title"],
+            "properties": { "title": { "type": "string" } }
+        }))
+        .unwrap()
+    }
+
+    fn nodes() -> Vec<Node> {
+        let mut titled = Node::new("titled");
+        titled.metadata.insert("title", "A title");
+        let mut numbered = Node::new("numbered");
+        numbered.metadata.insert("title", 42);
+
+        vec![titled, Node::new("untitled"), numbered]
+    }
+
+    #[tokio::test]
+    async fn test_flags_nodes_without_a_string_title() {
+        let results = validate_metadata()
+            .batch_transform(nodes())
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(results[0].as_ref().unwrap().chunk, "titled");
+
+        let missing = results[1].as_ref().unwrap_err().to_string();
+        assert!(
+            missing.contains("\"title\" is a required property"),
+            "{missing}"
+        );
+
+        let wrong_type = results[2].as_ref().unwrap_err().to_string();
+        assert!(wrong_type.contains("/title"), "{wrong_type}");
+    }
+
+    #[tokio::test]
+    async fn test_drops_nodes_that_do_not_conform() {
+        let kept = validate_metadata()
+            .with_policy(InvalidMetadataPolicy::DropNode)
+            .batch_transform(nodes())
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].as_ref().unwrap().chunk, "titled");
+    }
+
+    #[test]
+    fn test_rejects_invalid_schemas() {
+        assert!(ValidateMetadata::try_new(&serde_json::json!({ "type": 42 })).is_err());
+    }
+}
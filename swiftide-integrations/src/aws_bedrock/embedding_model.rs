@@ -0,0 +1,206 @@
+use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+use aws_sdk_bedrockruntime::primitives::Blob;
+use swiftide_core::{EmbeddingModel, Embeddings};
+
+use super::{
+    models::{
+        CohereEmbedRequest, CohereEmbedResponse, ModelFamily, COHERE_MAX_TEXTS,
+        COHERE_MAX_TEXT_CHARS,
+    },
+    AwsBedrock, Truncate,
+};
+
+#[async_trait]
+impl EmbeddingModel for AwsBedrock {
+    /// Embeds the input with the Cohere model family
+    ///
+    /// Cohere accepts at most 96 texts per request. Larger inputs are split into requests of up
+    /// to 96 texts that run concurrently, and the embeddings are returned in input order.
+    ///
+    /// Over-long inputs are truncated as configured with `truncate`, see [`super::Truncate`].
+    #[tracing::instrument(skip_all, err)]
+    async fn embed(&self, input: Vec<String>) -> Result<Embeddings> {
+        if !matches!(self.model_family, ModelFamily::Cohere) {
+            anyhow::bail!("Embedding is only supported for the Cohere model family");
+        }
+
+        let responses = futures_util::future::try_join_all(
+            input
+                .chunks(COHERE_MAX_TEXTS)
+                .map(|texts| self.embed_batch(texts)),
+        )
+        .await?;
+
+        let embeddings = responses.into_iter().flatten().collect::<Embeddings>();
+        if embeddings.len() != input.len() {
+            anyhow::bail!(
+                "Expected {} embeddings, got {}",
+                input.len(),
+                embeddings.len()
+            );
+        }
+
+        Ok(embeddings)
+    }
+}
+
+impl AwsBedrock {
+    async fn embed_batch(&self, texts: &[String]) -> Result<Embeddings> {
+        let request = CohereEmbedRequest {
+            texts: texts
+                .iter()
+                .map(|text| self.truncate.apply(text, COHERE_MAX_TEXT_CHARS))
+                .collect(),
+            input_type: "search_document",
+            truncate: Some(self.truncate).filter(|truncate| *truncate != Truncate::None),
+        };
+        let blob = serde_json::to_vec(&request)
+            .map(Blob::new)
+            .context("Failed to serialize request")?;
+
+        let response_bytes = self.client.prompt_u8(&self.model_id, blob).await?;
+        let response: CohereEmbedResponse =
+            serde_json::from_slice(&response_bytes).context("Failed to parse response")?;
+
+        Ok(response.embeddings)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+    use crate::aws_bedrock::MockBedrockPrompt;
+
+    #[test_log::test(tokio::test)]
+    async fn test_embeds_in_batches_of_96_preserving_order() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut bedrock_mock = MockBedrockPrompt::new();
+        let counter = Arc::clone(&calls);
+        bedrock_mock.expect_prompt_u8().returning(move |_, blob| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let request: serde_json::Value = serde_json::from_slice(blob.as_ref()).unwrap();
+            let texts = request["texts"].as_array().unwrap();
+            assert!(texts.len() <= COHERE_MAX_TEXTS);
+
+            let embeddings = texts
+                .iter()
+                .map(|text| vec![text.as_str().unwrap().parse::<f32>().unwrap()])
+                .collect();
+            serde_json::to_vec(&CohereEmbedResponse { embeddings })
+                .context("Failed to serialize response")
+        });
+
+        let bedrock = AwsBedrock::build_cohere_family("cohere.embed-english-v3")
+            .test_client(bedrock_mock)
+            .build()
+            .unwrap();
+
+        let input = (0..200_u8).map(|i| i.to_string()).collect::<Vec<_>>();
+        let embeddings = bedrock.embed(input).await.unwrap();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(
+            embeddings,
+            (0..200_u8).map(|i| vec![f32::from(i)]).collect::<Vec<_>>()
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_truncates_over_long_inputs_at_the_end() {
+        let mut bedrock_mock = MockBedrockPrompt::new();
+        bedrock_mock.expect_prompt_u8().once().returning(|_, blob| {
+            let request: serde_json::Value = serde_json::from_slice(blob.as_ref()).unwrap();
+            assert_eq!(request["truncate"], "END");
+            assert_eq!(request["texts"][0], "é".repeat(COHERE_MAX_TEXT_CHARS));
+            assert_eq!(request["texts"][1], "short");
+
+            serde_json::to_vec(&CohereEmbedResponse {
+                embeddings: vec![vec![1.0, 2.0], vec![3.0]],
+            })
+            .context("Failed to serialize response")
+        });
+
+        let bedrock = AwsBedrock::build_cohere_family("cohere.embed-english-v3")
+            .truncate(Truncate::End)
+            .test_client(bedrock_mock)
+            .build()
+            .unwrap();
+
+        let long = format!("{}end", "é".repeat(COHERE_MAX_TEXT_CHARS));
+        let embeddings = bedrock.embed(vec![long, "short".into()]).await.unwrap();
+
+        assert_eq!(embeddings, vec![vec![1.0, 2.0], vec![3.0]]);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_truncates_over_long_inputs_at_the_start() {
+        let mut bedrock_mock = MockBedrockPrompt::new();
+        bedrock_mock.expect_prompt_u8().once().returning(|_, blob| {
+            let request: serde_json::Value = serde_json::from_slice(blob.as_ref()).unwrap();
+            assert_eq!(request["truncate"], "START");
+            assert_eq!(
+                request["texts"][0],
+                format!("{}end", "é".repeat(COHERE_MAX_TEXT_CHARS - 3))
+            );
+
+            serde_json::to_vec(&CohereEmbedResponse {
+                embeddings: vec![vec![1.0]],
+            })
+            .context("Failed to serialize response")
+        });
+
+        let bedrock = AwsBedrock::build_cohere_family("cohere.embed-english-v3")
+            .truncate(Truncate::Start)
+            .test_client(bedrock_mock)
+            .build()
+            .unwrap();
+
+        let long = format!("start{}end", "é".repeat(COHERE_MAX_TEXT_CHARS));
+        bedrock.embed(vec![long]).await.unwrap();
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_does_not_truncate_by_default() {
+        let mut bedrock_mock = MockBedrockPrompt::new();
+        bedrock_mock.expect_prompt_u8().once().returning(|_, blob| {
+            let request: serde_json::Value = serde_json::from_slice(blob.as_ref()).unwrap();
+            assert!(request.get("truncate").is_none());
+            assert_eq!(request["texts"][0], "é".repeat(COHERE_MAX_TEXT_CHARS + 1));
+
+            serde_json::to_vec(&CohereEmbedResponse {
+                embeddings: vec![vec![1.0]],
+            })
+            .context("Failed to serialize response")
+        });
+
+        let bedrock = AwsBedrock::build_cohere_family("cohere.embed-english-v3")
+            .test_client(bedrock_mock)
+            .build()
+            .unwrap();
+
+        bedrock
+            .embed(vec!["é".repeat(COHERE_MAX_TEXT_CHARS + 1)])
+            .await
+            .unwrap();
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_embedding_requires_cohere() {
+        let bedrock = AwsBedrock::build_titan_family("my_model")
+            .test_client(MockBedrockPrompt::new())
+            .build()
+            .unwrap();
+
+        let error = bedrock.embed(vec!["text".into()]).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Embedding is only supported for the Cohere model family"
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use anyhow::{Context as _, Result};
+use serde::de::DeserializeOwned;
+use swiftide_core::{indexing::SimplePrompt, prompt::Prompt};
+
+use super::AwsBedrock;
+
+/// Extracts the first balanced JSON object or array from a model response
+///
+/// Models often wrap JSON in markdown fences (```` ```json ````) or surround it with prose. This
+/// skips anything before the first `{` or `[` that starts valid JSON and anything after its
+/// matching closing bracket. Brackets inside strings are ignored.
+///
+/// # Errors
+///
+/// Errors if the response does not contain a valid JSON object or array
+pub fn extract_json(response: &str) -> Result<&str> {
+    for (start, c) in response.char_indices() {
+        if c != '{' && c != '[' {
+            continue;
+        }
+
+        if let Some(end) = balanced_end(&response[start..]) {
+            let candidate = &response[start..start + end];
+            if serde_json::from_str::<serde_json::Value>(candidate).is_ok() {
+                return Ok(candidate);
+            }
+        }
+    }
+
+    anyhow::bail!("No JSON object or array found in response")
+}
+
+/// Returns the byte length of the bracketed value `text` starts with, if it is closed
+fn balanced_end(text: &str) -> Option<usize> {
+    let mut stack = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match c {
+            '"' => in_string = true,
+            '{' => stack.push('}'),
+            '[' => stack.push(']'),
+            '}' | ']' => {
+                if stack.pop() != Some(c) {
+                    return None;
+                }
+                if stack.is_empty() {
+                    return Some(i + c.len_utf8());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+impl AwsBedrock {
+    /// Prompts the model and deserializes the first JSON object or array in the response
+    ///
+    /// See [`extract_json`] for how the JSON is located.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the prompt fails, the response contains no JSON, or the JSON does not
+    /// deserialize into `T`
+    pub async fn prompt_json<T: DeserializeOwned>(&self, prompt: Prompt) -> Result<T> {
+        let response = self.prompt(prompt).await?;
+        let json = extract_json(&response)?;
+
+        serde_json::from_str(json).context("Failed to deserialize JSON from response")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_extracts_fenced_json() {
+        let response = "```json\n{\"answer\": 42}\n```";
+        assert_eq!(extract_json(response).unwrap(), "{\"answer\": 42}");
+    }
+
+    #[test]
+    fn test_extracts_bare_json() {
+        assert_eq!(extract_json("[1, 2, 3]").unwrap(), "[1, 2, 3]");
+    }
+
+    #[test]
+    fn test_extracts_prose_wrapped_json() {
+        let response = "Sure! Here is the result: {\"items\": [\"a}\", {\"b\": []}]} Let me know [if] that helps.";
+        assert_eq!(
+            extract_json(response).unwrap(),
+            "{\"items\": [\"a}\", {\"b\": []}]}"
+        );
+    }
+
+    #[test]
+    fn test_skips_brackets_that_are_not_json() {
+        let response = "Options [a] or {b}: {\"choice\": \"a\"}";
+        assert_eq!(extract_json(response).unwrap(), "{\"choice\": \"a\"}");
+    }
+
+    #[test]
+    fn test_errors_without_json() {
+        let error = extract_json("I could not find an answer {sorry").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "No JSON object or array found in response"
+        );
+    }
+}
@@ -1,10 +1,12 @@
 //! An integration with the AWS Bedrock service.
 //!
 //! Supports various model families for prompting.
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use anyhow::Result;
 use async_trait::async_trait;
+use aws_config::Region;
+use aws_credential_types::Credentials;
 use aws_sdk_bedrockruntime::{error::SdkError, primitives::Blob, Client};
 use derive_builder::Builder;
 use serde::Serialize;
@@ -13,18 +15,24 @@ use tokio::runtime::Handle;
 #[cfg(test)]
 use mockall::{automock, predicate::*};
 
+mod embedding_model;
+mod json;
 mod models;
 mod simple_prompt;
 
+pub use json::extract_json;
+
 /// An integration with the AWS Bedrock service.
 ///
-/// Can be used as `SimplePrompt`.
+/// Can be used as `SimplePrompt`, and as `EmbeddingModel` with the Cohere model family.
 ///
 /// To use Bedrock, you need to have a model id and access to the service.
 /// By default, the aws sdk will be configured from the environment.
 /// If you have the aws cli properly configured with a region set, it should work out of the box.
 ///
-/// Otherwise, you can use the builder for customization.
+/// Otherwise, you can use the builder for customization. An explicit `region`, `profile_name`
+/// or static `credentials` take precedence over the environment, or pass a fully configured
+/// `client`.
 ///
 /// See the aws cli documentation for more information on how to get access to the service.
 #[derive(Debug, Builder)]
@@ -32,16 +40,67 @@ mod simple_prompt;
 pub struct AwsBedrock {
     #[builder(setter(into))]
     /// The model id or arn of the model to use
+    ///
+    /// Cross-region inference profiles are also supported, either by their id (i.e.
+    /// `eu.anthropic.claude-3-5-sonnet-20240620-v1:0`) or their full arn. The value is passed to
+    /// Bedrock unchanged; the region is taken from the client configuration.
     model_id: String,
     #[builder(default = "self.default_client()", setter(custom))]
 
     /// The bedrock runtime client
     client: Arc<dyn BedrockPrompt>,
     #[builder(default)]
-    /// The model configuration to use
+    /// The model configuration to use, see [`ModelConfig`] for the defaults
     model_config: ModelConfig,
+    #[builder(default, setter(into))]
+    /// The system prompt to use, if the model family supports it
+    ///
+    /// When not set, the model's default system behaviour applies.
+    system_prompt: Option<String>,
     /// The model family to use. In bedrock, families share their api.
     model_family: ModelFamily,
+    #[builder(default, setter(into))]
+    /// The region to use instead of the one from the environment
+    region: Option<String>,
+    #[builder(default, setter(into))]
+    /// The profile to load the configuration from instead of the default profile
+    profile_name: Option<String>,
+    #[builder(default)]
+    /// Static credentials to use instead of the default credential chain
+    credentials: Option<Credentials>,
+    #[builder(default)]
+    /// How embedding inputs longer than the limits of Cohere are truncated
+    ///
+    /// Defaults to [`Truncate::None`], which errors on over-long inputs.
+    truncate: Truncate,
+    #[builder(default, setter(custom))]
+    /// Parsers for the responses of custom models, by model id
+    ///
+    /// Takes precedence over the model family when prompting, see
+    /// [`AwsBedrockBuilder::response_parser`].
+    response_parsers: HashMap<String, ResponseParser>,
+}
+
+/// Parses the raw response body of a model into the response text
+#[derive(Clone)]
+pub struct ResponseParser(Arc<ParseFn>);
+
+type ParseFn = dyn Fn(&[u8]) -> Result<String> + Send + Sync;
+
+impl ResponseParser {
+    pub fn new(parser: impl Fn(&[u8]) -> Result<String> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(parser))
+    }
+
+    fn parse(&self, response: &[u8]) -> Result<String> {
+        (self.0)(response)
+    }
+}
+
+impl std::fmt::Debug for ResponseParser {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ResponseParser").finish_non_exhaustive()
+    }
 }
 
 #[cfg_attr(test, automock)]
@@ -71,7 +130,13 @@ impl Clone for AwsBedrock {
             model_id: self.model_id.clone(),
             client: self.client.clone(),
             model_config: self.model_config.clone(),
+            system_prompt: self.system_prompt.clone(),
             model_family: self.model_family.clone(),
+            region: self.region.clone(),
+            profile_name: self.profile_name.clone(),
+            credentials: self.credentials.clone(),
+            truncate: self.truncate,
+            response_parsers: self.response_parsers.clone(),
         }
     }
 }
@@ -90,6 +155,16 @@ impl AwsBedrock {
     pub fn build_anthropic_family(model_id: impl Into<String>) -> AwsBedrockBuilder {
         Self::builder().anthropic().model_id(model_id).to_owned()
     }
+
+    /// Build a new `AwsBedrock` instance with the Mistral model family
+    pub fn build_mistral_family(model_id: impl Into<String>) -> AwsBedrockBuilder {
+        Self::builder().mistral().model_id(model_id).to_owned()
+    }
+
+    /// Build a new `AwsBedrock` instance with the Cohere model family, for embeddings
+    pub fn build_cohere_family(model_id: impl Into<String>) -> AwsBedrockBuilder {
+        Self::builder().cohere().model_id(model_id).to_owned()
+    }
 }
 impl AwsBedrockBuilder {
     /// Set the model family to Anthropic
@@ -104,16 +179,65 @@ impl AwsBedrockBuilder {
         self
     }
 
-    #[allow(clippy::unused_self)]
+    /// Set the model family to Mistral
+    pub fn mistral(&mut self) -> &mut Self {
+        self.model_family = Some(ModelFamily::Mistral);
+        self
+    }
+
+    /// Set the model family to Cohere
+    pub fn cohere(&mut self) -> &mut Self {
+        self.model_family = Some(ModelFamily::Cohere);
+        self
+    }
+
     fn default_config(&self) -> aws_config::SdkConfig {
-        tokio::task::block_in_place(|| {
-            Handle::current().block_on(async { aws_config::from_env().load().await })
-        })
+        let mut loader = aws_config::from_env();
+
+        if let Some(Some(region)) = &self.region {
+            loader = loader.region(Region::new(region.clone()));
+        }
+        if let Some(Some(profile_name)) = &self.profile_name {
+            loader = loader.profile_name(profile_name);
+        }
+        if let Some(Some(credentials)) = &self.credentials {
+            loader = loader.credentials_provider(credentials.clone());
+        }
+
+        tokio::task::block_in_place(|| Handle::current().block_on(loader.load()))
     }
     fn default_client(&self) -> Arc<Client> {
         Arc::new(Client::new(&self.default_config()))
     }
 
+    /// Parse the responses of the given model id with a custom parser, i.e. for custom or fine
+    /// tuned models with a response shape that differs from their family
+    ///
+    /// Requests are still built by the model family.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use swiftide_integrations::aws_bedrock::AwsBedrock;
+    /// let bedrock = AwsBedrock::build_anthropic_family("my-fine-tuned-model")
+    ///     .response_parser("my-fine-tuned-model", |response| {
+    ///         let response: serde_json::Value = serde_json::from_slice(response)?;
+    ///         Ok(response["generation"].as_str().unwrap_or_default().to_string())
+    ///     })
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn response_parser(
+        &mut self,
+        model_id: impl Into<String>,
+        parser: impl Fn(&[u8]) -> Result<String> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.response_parsers
+            .get_or_insert_with(HashMap::new)
+            .insert(model_id.into(), ResponseParser::new(parser));
+        self
+    }
+
     /// Set the aws bedrock runtime client
     pub fn client(&mut self, client: Client) -> &mut Self {
         self.client = Some(Arc::new(client));
@@ -130,22 +254,131 @@ impl AwsBedrockBuilder {
 
 use self::models::ModelFamily;
 
-#[derive(Serialize, Debug, Clone)]
+const DEFAULT_TEMPERATURE: f32 = 0.5;
+const DEFAULT_MAX_TOKEN_COUNT: i32 = 8192;
+
+/// Generation options used for every prompt
+///
+/// The options map onto the request of each model family:
+///
+/// * `max_token_count` - `max_tokens` for Anthropic and Mistral, `maxTokenCount` for Titan
+/// * `temperature` and `top_p` - as is for all families, `top_p` only if set
+/// * `stop_sequences` - `stop_sequences` for Anthropic, `stopSequences` for Titan, `stop` for
+///   Mistral
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_integrations::aws_bedrock::{AwsBedrock, ModelConfig};
+/// let bedrock = AwsBedrock::build_anthropic_family("anthropic.claude-3-haiku-20240307-v1:0")
+///     .model_config(
+///         ModelConfig::builder()
+///             .max_token_count(1024)
+///             .temperature(0.0)
+///             .build()
+///             .unwrap(),
+///     )
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Serialize, Debug, Clone, Builder)]
 #[serde(rename_all = "camelCase")]
+#[builder(build_fn(error = "anyhow::Error"))]
 pub struct ModelConfig {
+    /// The sampling temperature. Defaults to 0.5.
+    #[builder(default = "DEFAULT_TEMPERATURE")]
     temperature: f32,
-    top_p: f32,
+    /// Nucleus sampling probability. Only sent if set, otherwise the default of the model applies.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    /// The maximum number of tokens to generate. Defaults to 8192.
+    #[builder(default = "DEFAULT_MAX_TOKEN_COUNT")]
     max_token_count: i32,
+    /// Sequences that stop generation. Defaults to none.
+    #[builder(default, setter(into))]
     stop_sequences: Vec<String>,
 }
 
+impl ModelConfig {
+    pub fn builder() -> ModelConfigBuilder {
+        ModelConfigBuilder::default()
+    }
+}
+
 impl Default for ModelConfig {
     fn default() -> Self {
         Self {
-            temperature: 0.5,
-            top_p: 0.9,
-            max_token_count: 8192,
+            temperature: DEFAULT_TEMPERATURE,
+            top_p: None,
+            max_token_count: DEFAULT_MAX_TOKEN_COUNT,
             stop_sequences: vec![],
         }
     }
 }
+
+/// How over-long inputs are truncated when embedding with Cohere
+///
+/// Inputs are cut to the 2048 characters Cohere accepts per text before they are sent. Cohere
+/// then truncates the rest to the token limit of the model in the same direction.
+///
+/// Only the Bedrock Cohere embeddings support this; the other embedders send inputs as is.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Truncate {
+    /// Return an error for over-long inputs
+    #[default]
+    None,
+    /// Drop from the start of the input
+    Start,
+    /// Drop from the end of the input
+    End,
+}
+
+impl Truncate {
+    /// Truncates the text to at most `max_chars` characters
+    pub(crate) fn apply(self, text: &str, max_chars: usize) -> &str {
+        let len = text.chars().count();
+        if len <= max_chars {
+            return text;
+        }
+
+        match self {
+            Truncate::None => text,
+            Truncate::Start => {
+                let (start, _) = text.char_indices().nth(len - max_chars).unwrap_or_default();
+                &text[start..]
+            }
+            Truncate::End => {
+                let (end, _) = text.char_indices().nth(max_chars).unwrap_or_default();
+                &text[..end]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_credential_types::provider::ProvideCredentials as _;
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_explicit_region_and_credentials() {
+        let mut builder = AwsBedrock::build_anthropic_family("model");
+        builder
+            .region("eu-west-3")
+            .credentials(Credentials::from_keys("access", "secret", None));
+
+        let config = builder.default_config();
+
+        assert_eq!(config.region().unwrap().as_ref(), "eu-west-3");
+        let credentials = config
+            .credentials_provider()
+            .unwrap()
+            .provide_credentials()
+            .await
+            .unwrap();
+        assert_eq!(credentials.access_key_id(), "access");
+    }
+}
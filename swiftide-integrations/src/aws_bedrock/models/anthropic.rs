@@ -7,7 +7,7 @@ pub(crate) struct AnthropicRequest {
     pub(crate) messages: Vec<AnthropicMessage>,
 
     // Optional fields
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "system", skip_serializing_if = "Option::is_none")]
     pub(crate) system_prompt: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) stop_sequences: Option<Vec<String>>,
@@ -17,6 +17,15 @@ pub(crate) struct AnthropicRequest {
     pub(crate) top_p: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) top_k: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) tools: Option<Vec<AnthropicTool>>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct AnthropicTool {
+    pub(crate) name: String,
+    pub(crate) description: String,
+    pub(crate) input_schema: serde_json::Value,
 }
 
 #[derive(Serialize)]
@@ -50,6 +59,26 @@ pub(crate) struct AnthropicResponse {
 
 #[derive(Serialize, Deserialize)]
 pub(crate) struct AnthropicUsage {
-    pub(crate) input_tokens: i32,
-    pub(crate) output_tokens: i32,
+    pub(crate) input_tokens: u32,
+    pub(crate) output_tokens: u32,
+}
+
+/// A response to a request with tools, where content blocks are either text or tool calls
+#[derive(Deserialize)]
+pub(crate) struct AnthropicToolResponse {
+    pub(crate) content: Vec<AnthropicContentBlock>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum AnthropicContentBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        name: String,
+        input: serde_json::Value,
+    },
+    #[serde(other)]
+    Other,
 }
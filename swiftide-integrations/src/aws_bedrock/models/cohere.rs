@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+
+use crate::aws_bedrock::Truncate;
+
+/// The maximum number of texts Cohere accepts in a single embed request
+pub(crate) const COHERE_MAX_TEXTS: usize = 96;
+
+/// The maximum number of characters Cohere accepts per text
+pub(crate) const COHERE_MAX_TEXT_CHARS: usize = 2048;
+
+#[derive(Serialize, Debug)]
+pub(crate) struct CohereEmbedRequest<'a> {
+    pub(crate) texts: Vec<&'a str>,
+    pub(crate) input_type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) truncate: Option<Truncate>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct CohereEmbedResponse {
+    pub(crate) embeddings: Vec<Vec<f32>>,
+}
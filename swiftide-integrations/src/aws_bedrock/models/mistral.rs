@@ -0,0 +1,27 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize)]
+pub(crate) struct MistralRequest {
+    pub(crate) prompt: String, // wrapped in instruction tags
+    pub(crate) max_tokens: i32,
+
+    // Optional fields
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) top_p: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct MistralResponse {
+    pub(crate) outputs: Vec<MistralOutput>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct MistralOutput {
+    pub(crate) text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) stop_reason: Option<String>,
+}
@@ -1,11 +1,16 @@
 use anyhow::{Context as _, Result};
+use swiftide_core::prompt::{PromptResponse, ToolCall, ToolCallOrText, ToolSpec, Usage};
 
 use super::ModelConfig;
 
 pub mod anthropic;
+pub mod cohere;
+pub mod mistral;
 pub mod titan;
 
 pub(crate) use anthropic::*;
+pub(crate) use cohere::*;
+pub(crate) use mistral::*;
 pub(crate) use titan::*;
 
 #[derive(Clone, Debug)]
@@ -17,6 +22,10 @@ pub enum ModelFamily {
     Anthropic,
     /// The titan model family
     Titan,
+    /// The mistral model family, using the text completion API
+    Mistral,
+    /// The cohere model family, only embeddings are supported
+    Cohere,
 }
 
 impl ModelFamily {
@@ -24,50 +33,69 @@ impl ModelFamily {
     pub(crate) fn build_request_to_bytes(
         &self,
         input_text: impl AsRef<str>,
+        system_prompt: Option<&str>,
         model_config: &ModelConfig,
     ) -> Result<Vec<u8>> {
         match self {
             ModelFamily::Anthropic => {
-                let request = AnthropicRequest {
-                    anthropic_version: "bedrock-2023-05-31",
-                    max_tokens: model_config.max_token_count,
-                    messages: vec![AnthropicMessage {
-                        role: "user",
-                        content: vec![AnthropicMessageContent {
-                            _type: "text".to_string(),
-                            text: input_text.as_ref().to_string(),
-                        }],
-                    }],
-                    system_prompt: None,
-                    stop_sequences: None,
-                    temperature: Some(model_config.temperature),
-                    top_p: None,
-                    top_k: None,
-                };
+                let request = anthropic_request(input_text.as_ref(), system_prompt, model_config);
                 serde_json::to_vec(&request).context("Failed to serialize request")
             }
             ModelFamily::Titan => {
+                if system_prompt.is_some() {
+                    tracing::warn!("Titan does not support system prompts, ignoring");
+                }
                 let request = TitanRequest {
                     input_text: input_text.as_ref().to_string(),
                     text_generation_config: model_config.clone(),
                 };
                 serde_json::to_vec(&request).context("Failed to serialize request")
             }
+            ModelFamily::Mistral => {
+                // Mistral has no separate system prompt, it is prepended to the instruction
+                let instruction = match system_prompt {
+                    Some(system_prompt) => format!("{system_prompt}\n\n{}", input_text.as_ref()),
+                    None => input_text.as_ref().to_string(),
+                };
+                let request = MistralRequest {
+                    prompt: format!("<s>[INST] {instruction} [/INST]"),
+                    max_tokens: model_config.max_token_count,
+                    stop: (!model_config.stop_sequences.is_empty())
+                        .then(|| model_config.stop_sequences.clone()),
+                    temperature: Some(model_config.temperature),
+                    top_p: model_config.top_p,
+                };
+                serde_json::to_vec(&request).context("Failed to serialize request")
+            }
+            ModelFamily::Cohere => {
+                anyhow::bail!("Prompting is not supported for the Cohere model family")
+            }
         }
     }
 
+    /// Parses the response, with the usage if the model family reports it
     #[tracing::instrument(skip_all)]
-    pub(crate) fn output_message_from_bytes(&self, response_bytes: &[u8]) -> Result<String> {
+    pub(crate) fn output_response_from_bytes(
+        &self,
+        response_bytes: &[u8],
+    ) -> Result<PromptResponse> {
         match self {
             ModelFamily::Anthropic => {
                 let mut response: AnthropicResponse =
                     serde_json::from_slice(response_bytes).context("Failed to parse response")?;
 
                 if response.content.is_empty() {
-                    Err(anyhow::anyhow!("No results returned"))
-                } else {
-                    Ok(response.content.swap_remove(0).text)
+                    return Err(anyhow::anyhow!("No results returned"));
                 }
+
+                Ok(PromptResponse {
+                    text: response.content.swap_remove(0).text,
+                    usage: Some(Usage::new(
+                        response.usage.input_tokens,
+                        response.usage.output_tokens,
+                    )),
+                    model: Some(response.model),
+                })
             }
             ModelFamily::Titan => {
                 let mut response: TitanResponse =
@@ -77,8 +105,206 @@ impl ModelFamily {
                     return Err(anyhow::anyhow!("No results returned"));
                 }
 
-                Ok(response.results.swap_remove(0).output_text)
+                let result = response.results.swap_remove(0);
+                Ok(PromptResponse {
+                    text: result.output_text,
+                    usage: Some(Usage::new(
+                        response.input_text_token_count,
+                        result.token_count,
+                    )),
+                    model: None,
+                })
+            }
+            ModelFamily::Mistral => {
+                let mut response: MistralResponse =
+                    serde_json::from_slice(response_bytes).context("Failed to parse response")?;
+
+                if response.outputs.is_empty() {
+                    return Err(anyhow::anyhow!("No results returned"));
+                }
+
+                Ok(response.outputs.swap_remove(0).text.into())
+            }
+            ModelFamily::Cohere => {
+                anyhow::bail!("Prompting is not supported for the Cohere model family")
+            }
+        }
+    }
+
+    /// Builds a request with tools the model can call, only supported by Anthropic
+    #[tracing::instrument(skip_all)]
+    pub(crate) fn build_tool_request_to_bytes(
+        &self,
+        input_text: impl AsRef<str>,
+        system_prompt: Option<&str>,
+        model_config: &ModelConfig,
+        tools: Vec<ToolSpec>,
+    ) -> Result<Vec<u8>> {
+        let ModelFamily::Anthropic = self else {
+            anyhow::bail!("Tool calling is not supported for the {self:?} model family")
+        };
+
+        let mut request = anthropic_request(input_text.as_ref(), system_prompt, model_config);
+        request.tools = Some(
+            tools
+                .into_iter()
+                .map(|tool| AnthropicTool {
+                    name: tool.name,
+                    description: tool.description,
+                    input_schema: tool.parameters,
+                })
+                .collect(),
+        );
+        serde_json::to_vec(&request).context("Failed to serialize request")
+    }
+
+    /// Parses the response to a request with tools into the first tool call, or the text if the
+    /// model did not call a tool
+    #[tracing::instrument(skip_all)]
+    pub(crate) fn tool_response_from_bytes(&self, response_bytes: &[u8]) -> Result<ToolCallOrText> {
+        let ModelFamily::Anthropic = self else {
+            anyhow::bail!("Tool calling is not supported for the {self:?} model family")
+        };
+
+        let response: AnthropicToolResponse =
+            serde_json::from_slice(response_bytes).context("Failed to parse response")?;
+
+        let mut text = None;
+        for block in response.content {
+            match block {
+                AnthropicContentBlock::ToolUse { name, input } => {
+                    return Ok(ToolCallOrText::ToolCall(ToolCall {
+                        name,
+                        arguments: input,
+                    }))
+                }
+                AnthropicContentBlock::Text { text: block } => {
+                    text.get_or_insert(block);
+                }
+                AnthropicContentBlock::Other => {}
             }
         }
+
+        text.map(ToolCallOrText::Text)
+            .context("No results returned")
+    }
+}
+
+fn anthropic_request(
+    input_text: &str,
+    system_prompt: Option<&str>,
+    model_config: &ModelConfig,
+) -> AnthropicRequest {
+    AnthropicRequest {
+        anthropic_version: "bedrock-2023-05-31",
+        max_tokens: model_config.max_token_count,
+        messages: vec![AnthropicMessage {
+            role: "user",
+            content: vec![AnthropicMessageContent {
+                _type: "text".to_string(),
+                text: input_text.to_string(),
+            }],
+        }],
+        system_prompt: system_prompt.map(ToString::to_string),
+        stop_sequences: (!model_config.stop_sequences.is_empty())
+            .then(|| model_config.stop_sequences.clone()),
+        temperature: Some(model_config.temperature),
+        top_p: model_config.top_p,
+        top_k: None,
+        tools: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anthropic_request_with_system_prompt() {
+        let bytes = ModelFamily::Anthropic
+            .build_request_to_bytes("Hello", Some("You are a pirate"), &ModelConfig::default())
+            .unwrap();
+        let request: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+
+        assert_eq!(request["system"], "You are a pirate");
+    }
+
+    #[test]
+    fn test_anthropic_request_without_system_prompt() {
+        let bytes = ModelFamily::Anthropic
+            .build_request_to_bytes("Hello", None, &ModelConfig::default())
+            .unwrap();
+        let request: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+
+        assert!(request.get("system").is_none());
+    }
+
+    #[test]
+    fn test_top_p_is_only_sent_if_set() {
+        for family in [ModelFamily::Anthropic, ModelFamily::Mistral] {
+            let bytes = family
+                .build_request_to_bytes("Hello", None, &ModelConfig::default())
+                .unwrap();
+            let request: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+            assert!(request.get("top_p").is_none());
+            assert_eq!(request["temperature"], 0.5);
+        }
+
+        let bytes = ModelFamily::Titan
+            .build_request_to_bytes("Hello", None, &ModelConfig::default())
+            .unwrap();
+        let request: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert!(request["textGenerationConfig"].get("topP").is_none());
+    }
+
+    #[test]
+    fn test_requests_carry_generation_options() {
+        let model_config = ModelConfig::builder()
+            .max_token_count(256)
+            .temperature(0.1)
+            .top_p(0.5)
+            .stop_sequences(vec!["STOP".to_string()])
+            .build()
+            .unwrap();
+
+        let bytes = ModelFamily::Anthropic
+            .build_request_to_bytes("Hello", None, &model_config)
+            .unwrap();
+        let request: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(request["max_tokens"], 256);
+        assert_eq!(request["top_p"], 0.5);
+        assert_eq!(request["stop_sequences"], serde_json::json!(["STOP"]));
+
+        let bytes = ModelFamily::Titan
+            .build_request_to_bytes("Hello", None, &model_config)
+            .unwrap();
+        let request: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(request["textGenerationConfig"]["maxTokenCount"], 256);
+        assert_eq!(
+            request["textGenerationConfig"]["stopSequences"],
+            serde_json::json!(["STOP"])
+        );
+
+        let bytes = ModelFamily::Mistral
+            .build_request_to_bytes("Hello", None, &model_config)
+            .unwrap();
+        let request: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(request["max_tokens"], 256);
+        assert_eq!(request["top_p"], 0.5);
+        assert_eq!(request["stop"], serde_json::json!(["STOP"]));
+    }
+
+    #[test]
+    fn test_mistral_request_wraps_prompt_in_instruction() {
+        let bytes = ModelFamily::Mistral
+            .build_request_to_bytes("Hello", Some("You are a pirate"), &ModelConfig::default())
+            .unwrap();
+        let request: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+
+        assert_eq!(
+            request["prompt"],
+            "<s>[INST] You are a pirate\n\nHello [/INST]"
+        );
+        assert!(request.get("stop").is_none());
     }
 }
@@ -11,14 +11,14 @@ pub(crate) struct TitanRequest {
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct TitanResponse {
-    pub(crate) input_text_token_count: i32,
+    pub(crate) input_text_token_count: u32,
     pub(crate) results: Vec<TitanTextResult>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct TitanTextResult {
-    pub(crate) token_count: i32,
+    pub(crate) token_count: u32,
     pub(crate) output_text: String,
     pub(crate) completion_reason: String,
 }
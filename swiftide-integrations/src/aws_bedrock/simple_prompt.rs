@@ -1,7 +1,11 @@
 use anyhow::Result;
 use async_trait::async_trait;
 use aws_sdk_bedrockruntime::primitives::Blob;
-use swiftide_core::{indexing::SimplePrompt, prompt::Prompt};
+use swiftide_core::{
+    indexing::SimplePrompt,
+    prompt::{Prompt, PromptResponse, ToolCallOrText, ToolSpec},
+    util::debug_redacted_bytes,
+};
 
 use super::AwsBedrock;
 
@@ -9,26 +13,78 @@ use super::AwsBedrock;
 impl SimplePrompt for AwsBedrock {
     #[tracing::instrument(skip_all, err)]
     async fn prompt(&self, prompt: Prompt) -> Result<String> {
-        let blob = self
-            .model_family
-            .build_request_to_bytes(prompt.render().await?, &self.model_config)
-            .map(Blob::new)?;
+        Ok(self.prompt_detailed(prompt).await?.text)
+    }
+
+    /// Prompts the model, with the usage if the model family reports it
+    ///
+    /// Anthropic reports the model that answered, for other families it is the configured model
+    /// id. Responses of models with a custom [`ResponseParser`](super::ResponseParser) only have the text.
+    #[tracing::instrument(skip_all, err)]
+    async fn prompt_detailed(&self, prompt: Prompt) -> Result<PromptResponse> {
+        let request = self.model_family.build_request_to_bytes(
+            prompt.render().await?,
+            self.system_prompt.as_deref(),
+            &self.model_config,
+        )?;
+
+        tracing::debug!(request = debug_redacted_bytes(&request), "Sending request");
+        let blob = Blob::new(request);
 
         let response_bytes = self.client.prompt_u8(&self.model_id, blob).await?;
 
         tracing::debug!(
-            "Received response: {:?}",
-            std::str::from_utf8(&response_bytes)?
+            response = debug_redacted_bytes(&response_bytes),
+            "Received response"
         );
 
-        self.model_family.output_message_from_bytes(&response_bytes)
+        let mut response = match self.response_parsers.get(&self.model_id) {
+            Some(parser) => parser.parse(&response_bytes)?.into(),
+            None => self
+                .model_family
+                .output_response_from_bytes(&response_bytes)?,
+        };
+        response.model.get_or_insert_with(|| self.model_id.clone());
+        Ok(response)
+    }
+
+    /// Prompts the model with tools it can call, only supported by the Anthropic model family
+    #[tracing::instrument(skip_all, err)]
+    async fn prompt_with_tools(
+        &self,
+        prompt: Prompt,
+        tools: Vec<ToolSpec>,
+    ) -> Result<ToolCallOrText> {
+        let request = self.model_family.build_tool_request_to_bytes(
+            prompt.render().await?,
+            self.system_prompt.as_deref(),
+            &self.model_config,
+            tools,
+        )?;
+
+        tracing::debug!(
+            request = debug_redacted_bytes(&request),
+            "Sending request with tools"
+        );
+
+        let response_bytes = self
+            .client
+            .prompt_u8(&self.model_id, Blob::new(request))
+            .await?;
+
+        tracing::debug!(
+            response = debug_redacted_bytes(&response_bytes),
+            "Received response"
+        );
+
+        self.model_family.tool_response_from_bytes(&response_bytes)
     }
 }
 
 #[cfg(test)]
 mod test {
     use crate::aws_bedrock::models::*;
-    use crate::aws_bedrock::MockBedrockPrompt;
+    use crate::aws_bedrock::{MockBedrockPrompt, ModelConfig};
 
     use super::*;
     use anyhow::Context as _;
@@ -89,4 +145,183 @@ mod test {
         let response = bedrock.prompt("Hello".into()).await.unwrap();
         assert_eq!(response, "Hello, world!");
     }
+
+    #[test_log::test(tokio::test)]
+    async fn test_prompt_with_custom_response_parser() {
+        let mut bedrock_mock = MockBedrockPrompt::new();
+        bedrock_mock
+            .expect_prompt_u8()
+            .once()
+            .returning(|_, _| Ok(br#"{"generation": {"answer": "Hello, world!"}}"#.to_vec()));
+
+        let bedrock = AwsBedrock::build_anthropic_family("custom.fine-tuned-v1")
+            .response_parser("custom.fine-tuned-v1", |response| {
+                let response: serde_json::Value = serde_json::from_slice(response)?;
+                response["generation"]["answer"]
+                    .as_str()
+                    .map(str::to_string)
+                    .context("Missing answer")
+            })
+            .response_parser("other.model", |_| anyhow::bail!("Wrong parser"))
+            .test_client(bedrock_mock)
+            .build()
+            .unwrap();
+
+        let response = bedrock.prompt_detailed("Hello".into()).await.unwrap();
+
+        assert_eq!(response.text, "Hello, world!");
+        assert_eq!(response.usage, None);
+        assert_eq!(response.model.as_deref(), Some("custom.fine-tuned-v1"));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_prompt_detailed_parses_usage() {
+        let mut bedrock_mock = MockBedrockPrompt::new();
+        bedrock_mock.expect_prompt_u8().once().returning(|_, _| {
+            Ok(br#"{
+                "id": "msg_01",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-3-haiku-20240307",
+                "content": [{ "type": "text", "text": "Hello, world!" }],
+                "stop_reason": "end_turn",
+                "stop_sequence": null,
+                "usage": { "input_tokens": 12, "output_tokens": 5 }
+            }"#
+            .to_vec())
+        });
+        let bedrock = AwsBedrock::build_anthropic_family("my_model")
+            .test_client(bedrock_mock)
+            .build()
+            .unwrap();
+
+        let response = bedrock.prompt_detailed("Hello".into()).await.unwrap();
+
+        assert_eq!(response.text, "Hello, world!");
+        assert_eq!(
+            response.usage,
+            Some(swiftide_core::prompt::Usage {
+                prompt_tokens: 12,
+                completion_tokens: 5,
+                total_tokens: 17,
+            })
+        );
+        assert_eq!(response.model.as_deref(), Some("claude-3-haiku-20240307"));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_prompt_with_tools_parses_tool_use() {
+        let mut bedrock_mock = MockBedrockPrompt::new();
+        bedrock_mock.expect_prompt_u8().once().returning(|_, blob| {
+            let request: serde_json::Value = serde_json::from_slice(blob.as_ref()).unwrap();
+            assert_eq!(request["tools"][0]["name"], "split_document");
+            assert_eq!(request["tools"][0]["input_schema"]["type"], "object");
+
+            Ok(br#"{
+                "id": "msg_01",
+                "type": "message",
+                "role": "assistant",
+                "model": "claude-3-haiku-20240307",
+                "content": [
+                    { "type": "text", "text": "Splitting by markdown headings." },
+                    {
+                        "type": "tool_use",
+                        "id": "toolu_01",
+                        "name": "split_document",
+                        "input": { "strategy": "markdown", "max_size": 512 }
+                    }
+                ],
+                "stop_reason": "tool_use",
+                "stop_sequence": null,
+                "usage": { "input_tokens": 12, "output_tokens": 5 }
+            }"#
+            .to_vec())
+        });
+        let bedrock = AwsBedrock::build_anthropic_family("my_model")
+            .test_client(bedrock_mock)
+            .build()
+            .unwrap();
+        let tool = ToolSpec::new(
+            "split_document",
+            "Splits the document into chunks",
+            serde_json::json!({ "type": "object" }),
+        );
+
+        let answer = bedrock
+            .prompt_with_tools("How should I split this?".into(), vec![tool])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            answer,
+            ToolCallOrText::ToolCall(swiftide_core::prompt::ToolCall {
+                name: "split_document".to_string(),
+                arguments: serde_json::json!({ "strategy": "markdown", "max_size": 512 }),
+            })
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_prompt_with_mistral_sends_stop_sequences() {
+        let mut bedrock_mock = MockBedrockPrompt::new();
+        bedrock_mock.expect_prompt_u8().once().returning(|_, blob| {
+            let request: serde_json::Value = serde_json::from_slice(blob.as_ref()).unwrap();
+            assert_eq!(request["stop"], serde_json::json!(["</answer>"]));
+
+            serde_json::to_vec(&MistralResponse {
+                outputs: vec![MistralOutput {
+                    text: "Hello, world!".to_string(),
+                    stop_reason: Some("stop".to_string()),
+                }],
+            })
+            .context("Failed to serialize response")
+        });
+        let bedrock = AwsBedrock::build_mistral_family("mistral.mistral-large-2402-v1:0")
+            .model_config(
+                ModelConfig::builder()
+                    .stop_sequences(vec!["</answer>".to_string()])
+                    .build()
+                    .unwrap(),
+            )
+            .test_client(bedrock_mock)
+            .build()
+            .unwrap();
+        let response = bedrock.prompt("Hello".into()).await.unwrap();
+        assert_eq!(response, "Hello, world!");
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_inference_profile_arn_is_passed_unchanged() {
+        let arn = "arn:aws:bedrock:eu-central-1:123456789012:inference-profile/eu.anthropic.claude-3-5-sonnet-20240620-v1:0";
+        let mut bedrock_mock = MockBedrockPrompt::new();
+        bedrock_mock
+            .expect_prompt_u8()
+            .once()
+            .withf(move |model_id, _| model_id == arn)
+            .returning(|_, _| {
+                serde_json::to_vec(&AnthropicResponse {
+                    content: vec![AnthropicMessageContent {
+                        _type: "text".to_string(),
+                        text: "Hello, world!".to_string(),
+                    }],
+                    id: "id".to_string(),
+                    model: "model".to_string(),
+                    _type: "text".to_string(),
+                    role: "user".to_string(),
+                    stop_reason: None,
+                    stop_sequence: None,
+                    usage: AnthropicUsage {
+                        input_tokens: 10,
+                        output_tokens: 10,
+                    },
+                })
+                .context("Failed to serialize response")
+            });
+        let bedrock = AwsBedrock::build_anthropic_family(arn)
+            .test_client(bedrock_mock)
+            .build()
+            .unwrap();
+        let response = bedrock.prompt("Hello".into()).await.unwrap();
+        assert_eq!(response, "Hello, world!");
+    }
 }
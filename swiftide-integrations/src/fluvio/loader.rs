@@ -1,7 +1,7 @@
 use std::string::ToString;
 
 use anyhow::Context as _;
-use futures_util::{StreamExt as _, TryStreamExt as _};
+use futures_util::TryStreamExt as _;
 use swiftide_core::{indexing::IndexingStream, indexing::Node, Loader};
 use tokio::runtime::Handle;
 
@@ -36,7 +36,7 @@ impl Loader for Fluvio {
             })
             .map_err(anyhow::Error::from);
 
-        swiftide_stream.boxed().into()
+        IndexingStream::from_stream(swiftide_stream).with_metadata(self.metadata)
     }
 
     fn into_stream_boxed(self: Box<Self>) -> IndexingStream {
@@ -24,6 +24,7 @@ use derive_builder::Builder;
 /// Re-export the fluvio config builder
 pub use fluvio::consumer::{ConsumerConfigExt, ConsumerConfigExtBuilder};
 use fluvio::FluvioConfig;
+use swiftide_core::indexing::Metadata;
 
 mod loader;
 
@@ -34,8 +35,13 @@ pub struct Fluvio {
     consumer_config_ext: ConsumerConfigExt,
 
     #[builder(default, setter(custom))]
+    #[allow(clippy::struct_field_names)]
     /// Custom connection configuration
     fluvio_config: Option<FluvioConfig>,
+
+    #[builder(default)]
+    /// Static metadata added to every node, i.e. to tag nodes with their source
+    metadata: Metadata,
 }
 
 impl Fluvio {
@@ -44,6 +50,7 @@ impl Fluvio {
         Fluvio {
             consumer_config_ext: config.into(),
             fluvio_config: None,
+            metadata: Metadata::default(),
         }
     }
 
@@ -0,0 +1,160 @@
+use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+use secrecy::ExposeSecret as _;
+use serde::{Deserialize, Serialize};
+use swiftide_core::{util::debug_redacted, EmbeddingModel, Embeddings};
+
+use super::{model_name, simple_prompt::Content, Gemini};
+
+#[derive(Debug, Serialize)]
+struct BatchEmbedContentsRequest {
+    requests: Vec<EmbedContentRequest>,
+}
+
+#[derive(Debug, Serialize)]
+struct EmbedContentRequest {
+    model: String,
+    content: Content,
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchEmbedContentsResponse {
+    #[serde(default)]
+    embeddings: Vec<ContentEmbedding>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContentEmbedding {
+    values: Vec<f32>,
+}
+
+#[async_trait]
+impl EmbeddingModel for Gemini {
+    /// Embeds the input with `batchEmbedContents`, in batches of at most `max_batch_size`
+    ///
+    /// Batches run concurrently and the embeddings are returned in input order.
+    #[tracing::instrument(skip_all)]
+    async fn embed(&self, input: Vec<String>) -> Result<Embeddings> {
+        let responses = futures_util::future::try_join_all(
+            input
+                .chunks(self.max_batch_size.max(1))
+                .map(|texts| self.embed_batch(texts)),
+        )
+        .await?;
+
+        let embeddings = responses.into_iter().flatten().collect::<Embeddings>();
+        if embeddings.len() != input.len() {
+            anyhow::bail!(
+                "Expected {} embeddings, got {}",
+                input.len(),
+                embeddings.len()
+            );
+        }
+
+        Ok(embeddings)
+    }
+}
+
+impl Gemini {
+    async fn embed_batch(&self, texts: &[String]) -> Result<Embeddings> {
+        let model = model_name(&self.embed_model);
+        let request = BatchEmbedContentsRequest {
+            requests: texts
+                .iter()
+                .map(|text| EmbedContentRequest {
+                    model: model.clone(),
+                    content: Content::text(None, text),
+                })
+                .collect(),
+        };
+        tracing::debug!(
+            model = self.embed_model,
+            num_input = texts.len(),
+            request = debug_redacted(&request),
+            "[Embed] Request to gemini"
+        );
+
+        let response: BatchEmbedContentsResponse = self
+            .client
+            .post(self.model_url(&self.embed_model, "batchEmbedContents"))
+            .header("x-goog-api-key", self.api_key.expose_secret())
+            .json(&request)
+            .send()
+            .await
+            .context("Request to Gemini failed")?
+            .error_for_status()
+            .context("Gemini returned an error")?
+            .json()
+            .await
+            .context("Failed to parse response from Gemini")?;
+        tracing::debug!(
+            num_embeddings = response.embeddings.len(),
+            "[Embed] Response gemini"
+        );
+
+        Ok(response
+            .embeddings
+            .into_iter()
+            .map(|embedding| embedding.values)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{
+        matchers::{body_partial_json, header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    async fn mock_batch(server: &MockServer, input: &[&str], embeddings: &[f32]) {
+        let requests = input
+            .iter()
+            .map(|text| {
+                serde_json::json!({
+                    "model": "models/text-embedding-004",
+                    "content": { "parts": [{ "text": text }] }
+                })
+            })
+            .collect::<Vec<_>>();
+        let embeddings = embeddings
+            .iter()
+            .map(|embedding| serde_json::json!({ "values": [embedding] }))
+            .collect::<Vec<_>>();
+
+        Mock::given(method("POST"))
+            .and(path("/models/text-embedding-004:batchEmbedContents"))
+            .and(header("x-goog-api-key", "test"))
+            .and(body_partial_json(
+                serde_json::json!({ "requests": requests }),
+            ))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "embeddings": embeddings })),
+            )
+            .expect(1)
+            .mount(server)
+            .await;
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_embeds_in_batches_in_order() {
+        let server = MockServer::start().await;
+        mock_batch(&server, &["first", "second"], &[1.0, 2.0]).await;
+        mock_batch(&server, &["third"], &[3.0]).await;
+
+        let gemini = Gemini::builder()
+            .api_base(server.uri())
+            .api_key("test".to_string())
+            .max_batch_size(2_usize)
+            .build()
+            .unwrap();
+        let embeddings = gemini
+            .embed(vec!["first".into(), "second".into(), "third".into()])
+            .await
+            .unwrap();
+
+        assert_eq!(embeddings, vec![vec![1.0], vec![2.0], vec![3.0]]);
+    }
+}
@@ -0,0 +1,119 @@
+//! This module provides integration with Google's `Gemini` models via the Generative Language
+//! API. The module is conditionally compiled based on the "gemini" feature flag.
+
+use derive_builder::Builder;
+use secrecy::Secret;
+
+mod embed;
+mod simple_prompt;
+
+const GEMINI_API_BASE: &str = "https://generativelanguage.googleapis.com/v1beta";
+const DEFAULT_PROMPT_MODEL: &str = "gemini-1.5-flash";
+const DEFAULT_EMBED_MODEL: &str = "text-embedding-004";
+const DEFAULT_MAX_BATCH_SIZE: usize = 100;
+
+/// The `Gemini` struct implements [`swiftide_core::SimplePrompt`] and
+/// [`swiftide_core::EmbeddingModel`] using the Generative Language API.
+///
+/// By default it will look for a `GEMINI_API_KEY` environment variable, prompt `gemini-1.5-flash`
+/// and embed with `text-embedding-004`.
+///
+/// Embeddings are requested in batches of at most `max_batch_size` texts, which is also the
+/// limit of the api.
+///
+/// If Gemini blocks a prompt or response, for instance because of its safety settings, prompting
+/// fails with a [`GeminiError::Blocked`].
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_integrations::gemini::Gemini;
+/// let gemini = Gemini::builder()
+///     .prompt_model("gemini-1.5-pro")
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Builder, Clone)]
+#[builder(setter(into, strip_option))]
+pub struct Gemini {
+    /// The http client to use
+    #[builder(default)]
+    client: reqwest::Client,
+    /// The base url of the api. Defaults to `https://generativelanguage.googleapis.com/v1beta`.
+    #[builder(default = "GEMINI_API_BASE.to_string()")]
+    api_base: String,
+    /// The api key. Defaults to the `GEMINI_API_KEY` environment variable.
+    #[builder(default = "default_api_key()")]
+    api_key: Secret<String>,
+    /// The model to prompt. Defaults to `gemini-1.5-flash`.
+    #[builder(default = "DEFAULT_PROMPT_MODEL.to_string()")]
+    prompt_model: String,
+    /// The model to embed with. Defaults to `text-embedding-004`.
+    #[builder(default = "DEFAULT_EMBED_MODEL.to_string()")]
+    embed_model: String,
+    /// The maximum number of texts per embedding request. Defaults to 100.
+    #[builder(default = "DEFAULT_MAX_BATCH_SIZE")]
+    max_batch_size: usize,
+}
+
+impl Default for Gemini {
+    fn default() -> Self {
+        Self {
+            client: reqwest::Client::default(),
+            api_base: GEMINI_API_BASE.to_string(),
+            api_key: default_api_key(),
+            prompt_model: DEFAULT_PROMPT_MODEL.to_string(),
+            embed_model: DEFAULT_EMBED_MODEL.to_string(),
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+        }
+    }
+}
+
+impl Gemini {
+    /// Creates a new `GeminiBuilder` for constructing `Gemini` instances.
+    pub fn builder() -> GeminiBuilder {
+        GeminiBuilder::default()
+    }
+
+    /// The url of a method on a model, i.e. `models/gemini-1.5-flash:generateContent`
+    fn model_url(&self, model: &str, method: &str) -> String {
+        format!("{}/{}:{method}", self.api_base, model_name(model))
+    }
+}
+
+/// Errors specific to Gemini
+///
+/// Returned wrapped in an [`anyhow::Error`], use `downcast_ref` to match on them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GeminiError {
+    /// The prompt or the response was blocked, with the reason given by Gemini (i.e. `SAFETY`)
+    Blocked {
+        /// The block or finish reason
+        reason: String,
+    },
+}
+
+impl std::fmt::Display for GeminiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GeminiError::Blocked { reason } => write!(f, "Gemini blocked the request: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for GeminiError {}
+
+/// Models are addressed as `models/{model}`; a name that is already prefixed is kept
+fn model_name(model: &str) -> String {
+    if model.starts_with("models/") {
+        model.to_string()
+    } else {
+        format!("models/{model}")
+    }
+}
+
+fn default_api_key() -> Secret<String> {
+    std::env::var("GEMINI_API_KEY")
+        .unwrap_or_else(|_| String::new())
+        .into()
+}
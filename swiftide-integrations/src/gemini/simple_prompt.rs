@@ -0,0 +1,206 @@
+//! This module provides an implementation of the `SimplePrompt` trait for the `Gemini` struct,
+//! using the `generateContent` method.
+use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+use secrecy::ExposeSecret as _;
+use serde::{Deserialize, Serialize};
+use swiftide_core::{prompt::Prompt, util::debug_redacted, SimplePrompt};
+
+use super::{Gemini, GeminiError};
+
+/// Finish reasons that mean the response was blocked
+const BLOCKED_FINISH_REASONS: [&str; 4] = ["SAFETY", "BLOCKLIST", "PROHIBITED_CONTENT", "SPII"];
+
+#[derive(Debug, Serialize)]
+struct GenerateContentRequest {
+    contents: Vec<Content>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct Content {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<String>,
+    #[serde(default)]
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct Part {
+    #[serde(default)]
+    text: String,
+}
+
+impl Content {
+    pub(super) fn text(role: Option<&str>, text: impl Into<String>) -> Self {
+        Self {
+            role: role.map(str::to_string),
+            parts: vec![Part { text: text.into() }],
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerateContentResponse {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+    prompt_feedback: Option<PromptFeedback>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Candidate {
+    content: Option<Content>,
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PromptFeedback {
+    block_reason: Option<String>,
+}
+
+#[async_trait]
+impl SimplePrompt for Gemini {
+    /// Prompts the configured model and returns the text of the first candidate
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`GeminiError::Blocked`] if the prompt or the response was blocked, and an error
+    /// if the request fails or the response has no text.
+    #[tracing::instrument(skip_all, err)]
+    async fn prompt(&self, prompt: Prompt) -> Result<String> {
+        let request = GenerateContentRequest {
+            contents: vec![Content::text(Some("user"), prompt.render().await?)],
+        };
+        tracing::debug!(
+            model = self.prompt_model,
+            request = debug_redacted(&request),
+            "[SimplePrompt] Request to gemini"
+        );
+
+        let response: GenerateContentResponse = self
+            .client
+            .post(self.model_url(&self.prompt_model, "generateContent"))
+            .header("x-goog-api-key", self.api_key.expose_secret())
+            .json(&request)
+            .send()
+            .await
+            .context("Request to Gemini failed")?
+            .error_for_status()
+            .context("Gemini returned an error")?
+            .json()
+            .await
+            .context("Failed to parse response from Gemini")?;
+        tracing::debug!(
+            response = debug_redacted(&response),
+            "[SimplePrompt] Response from gemini"
+        );
+
+        if let Some(reason) = response.prompt_feedback.and_then(|f| f.block_reason) {
+            return Err(GeminiError::Blocked { reason }.into());
+        }
+
+        let candidate = response
+            .candidates
+            .into_iter()
+            .next()
+            .context("Gemini returned no candidates")?;
+        if let Some(reason) = candidate
+            .finish_reason
+            .filter(|reason| BLOCKED_FINISH_REASONS.contains(&reason.as_str()))
+        {
+            return Err(GeminiError::Blocked { reason }.into());
+        }
+
+        let text = candidate
+            .content
+            .map(|content| {
+                content
+                    .parts
+                    .into_iter()
+                    .map(|part| part.text)
+                    .collect::<String>()
+            })
+            .filter(|text| !text.is_empty())
+            .context("Gemini returned no text")?;
+
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{
+        matchers::{body_partial_json, header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    async fn gemini_responding(response: serde_json::Value) -> (MockServer, Gemini) {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/models/gemini-1.5-flash:generateContent"))
+            .and(header("x-goog-api-key", "test"))
+            .and(body_partial_json(serde_json::json!({
+                "contents": [{ "role": "user", "parts": [{ "text": "Hello" }] }]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(response))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let gemini = Gemini::builder()
+            .api_base(server.uri())
+            .api_key("test".to_string())
+            .build()
+            .unwrap();
+        (server, gemini)
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_prompt() {
+        let (_server, gemini) = gemini_responding(serde_json::json!({
+            "candidates": [{
+                "content": { "role": "model", "parts": [{ "text": "Hi " }, { "text": "there" }] },
+                "finishReason": "STOP"
+            }]
+        }))
+        .await;
+
+        let response = gemini.prompt("Hello".into()).await.unwrap();
+
+        assert_eq!(response, "Hi there");
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_blocked_prompt() {
+        let (_server, gemini) = gemini_responding(serde_json::json!({
+            "promptFeedback": { "blockReason": "SAFETY" }
+        }))
+        .await;
+
+        let err = gemini.prompt("Hello".into()).await.unwrap_err();
+
+        assert_eq!(
+            err.downcast_ref::<GeminiError>(),
+            Some(&GeminiError::Blocked {
+                reason: "SAFETY".to_string()
+            })
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_blocked_response() {
+        let (_server, gemini) = gemini_responding(serde_json::json!({
+            "candidates": [{ "finishReason": "SAFETY" }]
+        }))
+        .await;
+
+        let err = gemini.prompt("Hello".into()).await.unwrap_err();
+
+        assert!(
+            matches!(err.downcast_ref(), Some(GeminiError::Blocked { reason }) if reason == "SAFETY")
+        );
+    }
+}
@@ -3,7 +3,7 @@
 //! and generating responses as part of the Swiftide system.
 use async_openai::types::{ChatCompletionRequestUserMessageArgs, CreateChatCompletionRequestArgs};
 use async_trait::async_trait;
-use swiftide_core::{prompt::Prompt, SimplePrompt};
+use swiftide_core::{prompt::Prompt, util::debug_redacted, SimplePrompt};
 
 use super::Groq;
 use anyhow::{Context as _, Result};
@@ -44,7 +44,7 @@ impl SimplePrompt for Groq {
 
         // Log the request for debugging purposes.
         tracing::debug!(
-            messages = serde_json::to_string_pretty(&request)?,
+            request = debug_redacted(&request),
             "[SimplePrompt] Request to groq"
         );
 
@@ -53,7 +53,7 @@ impl SimplePrompt for Groq {
 
         // Log the response for debugging purposes.
         tracing::debug!(
-            response = serde_json::to_string_pretty(&response)?,
+            response = debug_redacted(&response),
             "[SimplePrompt] Response from groq"
         );
 
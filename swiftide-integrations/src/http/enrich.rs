@@ -0,0 +1,184 @@
+use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+use derive_builder::Builder;
+use serde::Serialize;
+use swiftide_core::{indexing::Node, util::debug_redacted, Transformer, WithIndexingDefaults};
+
+/// Enriches nodes with metadata from an external http service
+///
+/// Each node is sent as a JSON `POST` with its `path` and `chunk`. The service is expected to
+/// respond with a JSON object, of which every field is merged into the metadata of the node.
+///
+/// Responses with a non-2xx status fail the node. Use `Pipeline::filter_errors` to skip them.
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_integrations::http::HttpEnrich;
+/// let enrich = HttpEnrich::builder()
+///     .url("http://localhost:8080/enrich")
+///     .concurrency(4)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Builder)]
+#[builder(
+    pattern = "owned",
+    setter(strip_option),
+    build_fn(error = "anyhow::Error")
+)]
+pub struct HttpEnrich {
+    /// The http client to use
+    #[builder(default)]
+    client: reqwest::Client,
+    /// The url nodes are posted to
+    #[builder(setter(into))]
+    url: String,
+    /// The maximum number of concurrent requests
+    #[builder(default)]
+    concurrency: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct EnrichRequest<'a> {
+    path: &'a str,
+    chunk: &'a str,
+}
+
+impl HttpEnrich {
+    pub fn builder() -> HttpEnrichBuilder {
+        HttpEnrichBuilder::default()
+    }
+}
+
+impl WithIndexingDefaults for HttpEnrich {}
+
+#[async_trait]
+impl Transformer for HttpEnrich {
+    /// Posts the node to the service and merges the response into the metadata
+    ///
+    /// # Errors
+    ///
+    /// Errors if the request fails, the service responds with a non-2xx status or the response is
+    /// not a JSON object.
+    #[tracing::instrument(skip_all, name = "transformers.http_enrich")]
+    async fn transform_node(&self, mut node: Node) -> Result<Node> {
+        let request = EnrichRequest {
+            path: &node.path.to_string_lossy(),
+            chunk: &node.chunk,
+        };
+
+        tracing::debug!(
+            url = &self.url,
+            request = debug_redacted(&request),
+            "[HttpEnrich] Request"
+        );
+
+        let response = self
+            .client
+            .post(&self.url)
+            .json(&request)
+            .send()
+            .await
+            .with_context(|| format!("Request to {} failed", self.url))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            anyhow::bail!("{} responded with {status}", self.url);
+        }
+
+        let metadata: serde_json::Map<String, serde_json::Value> = response
+            .json()
+            .await
+            .with_context(|| format!("Expected a JSON object from {}", self.url))?;
+
+        tracing::debug!(
+            response = debug_redacted(&metadata),
+            "[HttpEnrich] Response"
+        );
+
+        for (key, value) in metadata {
+            node.metadata.insert(key, value);
+        }
+
+        Ok(node)
+    }
+
+    fn concurrency(&self) -> Option<usize> {
+        self.concurrency
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tracing_test::traced_test;
+    use wiremock::{
+        matchers::{body_json, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_merges_response_into_metadata() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/enrich"))
+            .and(body_json(json!({"path": "doc.md", "chunk": "Hello"})))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"language": "en", "sentiment": 0.8})),
+            )
+            .mount(&server)
+            .await;
+
+        let enrich = HttpEnrich::builder()
+            .url(format!("{}/enrich", server.uri()))
+            .build()
+            .unwrap();
+        let mut node = Node::new("Hello");
+        node.path = "doc.md".into();
+        node.metadata.insert("existing", "value");
+
+        let node = enrich.transform_node(node).await.unwrap();
+
+        assert_eq!(node.metadata.get("language").unwrap(), "en");
+        assert_eq!(node.metadata.get("sentiment").unwrap(), 0.8);
+        assert_eq!(node.metadata.get("existing").unwrap(), "value");
+    }
+
+    #[tokio::test]
+    async fn test_errors_on_non_success_status() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+
+        let enrich = HttpEnrich::builder().url(server.uri()).build().unwrap();
+
+        let err = enrich.transform_node(Node::new("Hello")).await.unwrap_err();
+
+        assert!(err.to_string().contains("503"));
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_redacts_secrets_in_debug_logs() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"language": "en", "api_key": "sekret"})),
+            )
+            .mount(&server)
+            .await;
+
+        let enrich = HttpEnrich::builder().url(server.uri()).build().unwrap();
+        enrich.transform_node(Node::new("Hello")).await.unwrap();
+
+        assert!(logs_contain(r#"\"api_key\":\"[REDACTED]\""#));
+        assert!(!logs_contain("sekret"));
+    }
+}
@@ -0,0 +1,4 @@
+//! Generic transformers calling external http services
+mod enrich;
+
+pub use enrich::{HttpEnrich, HttpEnrichBuilder};
@@ -0,0 +1,137 @@
+use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+use secrecy::ExposeSecret as _;
+use serde::{Deserialize, Serialize};
+use swiftide_core::{util::debug_redacted, EmbeddingModel, Embeddings};
+
+use super::{Jina, Task};
+
+#[derive(Debug, Serialize)]
+struct EmbeddingsRequest<'a> {
+    model: &'a str,
+    task: Task,
+    late_chunking: bool,
+    input: &'a [String],
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[async_trait]
+impl EmbeddingModel for Jina {
+    #[tracing::instrument(skip_all)]
+    async fn embed(&self, input: Vec<String>) -> Result<Embeddings> {
+        let request = EmbeddingsRequest {
+            model: &self.model,
+            task: self.task,
+            late_chunking: self.late_chunking,
+            input: &input,
+        };
+        tracing::debug!(
+            model = self.model,
+            num_input = input.len(),
+            request = debug_redacted(&request),
+            "[Embed] Request to jina"
+        );
+
+        let mut response: EmbeddingsResponse = self
+            .client
+            .post(format!("{}/embeddings", self.api_base))
+            .bearer_auth(self.api_key.expose_secret())
+            .json(&request)
+            .send()
+            .await
+            .context("Request to Jina failed")?
+            .error_for_status()
+            .context("Jina returned an error")?
+            .json()
+            .await
+            .context("Failed to parse response from Jina")?;
+
+        tracing::debug!(
+            num_embeddings = response.data.len(),
+            "[Embed] Response jina"
+        );
+
+        // Embeddings are not guaranteed to be returned in order
+        response.data.sort_by_key(|data| data.index);
+        Ok(response
+            .data
+            .into_iter()
+            .map(|data| data.embedding)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{
+        matchers::{body_partial_json, header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    async fn mock_embeddings(server: &MockServer, task: &str) {
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .and(header("authorization", "Bearer test"))
+            .and(body_partial_json(serde_json::json!({
+                "model": "jina-embeddings-v3",
+                "task": task,
+                "late_chunking": false,
+                "input": ["first", "second"]
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": [
+                    { "index": 1, "embedding": [2.0] },
+                    { "index": 0, "embedding": [1.0] }
+                ]
+            })))
+            .expect(1)
+            .mount(server)
+            .await;
+    }
+
+    fn jina(server: &MockServer, task: Task) -> Jina {
+        Jina::builder()
+            .api_base(server.uri())
+            .api_key("test".to_string())
+            .task(task)
+            .build()
+            .unwrap()
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_embed_passages_in_order() {
+        let server = MockServer::start().await;
+        mock_embeddings(&server, "retrieval.passage").await;
+
+        let embeddings = jina(&server, Task::RetrievalPassage)
+            .embed(vec!["first".into(), "second".into()])
+            .await
+            .unwrap();
+
+        assert_eq!(embeddings, vec![vec![1.0], vec![2.0]]);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_embed_queries() {
+        let server = MockServer::start().await;
+        mock_embeddings(&server, "retrieval.query").await;
+
+        let embeddings = jina(&server, Task::RetrievalQuery)
+            .embed(vec!["first".into(), "second".into()])
+            .await
+            .unwrap();
+
+        assert_eq!(embeddings.len(), 2);
+    }
+}
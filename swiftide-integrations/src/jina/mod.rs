@@ -0,0 +1,100 @@
+//! This module provides integration with `Jina AI`'s embeddings API.
+//! The module is conditionally compiled based on the "jina" feature flag.
+
+use derive_builder::Builder;
+use secrecy::Secret;
+use serde::Serialize;
+
+mod embed;
+
+const JINA_API_BASE: &str = "https://api.jina.ai/v1";
+const DEFAULT_MODEL: &str = "jina-embeddings-v3";
+
+/// The `Jina` struct implements [`swiftide_core::EmbeddingModel`] using the `Jina AI` embeddings
+/// API.
+///
+/// By default it will look for a `JINA_API_KEY` environment variable and use the
+/// `jina-embeddings-v3` model.
+///
+/// Jina optimizes embeddings for a task. For indexing the default `retrieval.passage` is
+/// usually right, while a query pipeline should use a client with `retrieval.query`.
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_integrations::jina::{Jina, Task};
+/// let jina = Jina::builder()
+///     .task(Task::RetrievalQuery)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Builder, Clone)]
+#[builder(setter(into, strip_option))]
+pub struct Jina {
+    /// The http client to use
+    #[builder(default)]
+    client: reqwest::Client,
+    /// The base url of the api. Defaults to `https://api.jina.ai/v1`.
+    #[builder(default = "JINA_API_BASE.to_string()")]
+    api_base: String,
+    /// The api key. Defaults to the `JINA_API_KEY` environment variable.
+    #[builder(default = "default_api_key()")]
+    api_key: Secret<String>,
+    /// The embedding model to use. Defaults to `jina-embeddings-v3`.
+    #[builder(default = "DEFAULT_MODEL.to_string()")]
+    model: String,
+    /// The task the embeddings are optimized for. Defaults to `retrieval.passage`.
+    #[builder(default)]
+    task: Task,
+    /// Embed the whole input as a single context before chunking the embeddings, so that each
+    /// embedding carries the context of the other inputs. Defaults to false.
+    #[builder(default)]
+    late_chunking: bool,
+}
+
+/// The downstream task embeddings are optimized for
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Task {
+    /// Embedding documents for retrieval
+    #[default]
+    #[serde(rename = "retrieval.passage")]
+    RetrievalPassage,
+    /// Embedding queries for retrieval
+    #[serde(rename = "retrieval.query")]
+    RetrievalQuery,
+    /// Embedding for symmetric similarity
+    #[serde(rename = "text-matching")]
+    TextMatching,
+    /// Embedding for classification
+    #[serde(rename = "classification")]
+    Classification,
+    /// Embedding for clustering
+    #[serde(rename = "separation")]
+    Separation,
+}
+
+impl Default for Jina {
+    fn default() -> Self {
+        Self {
+            client: reqwest::Client::default(),
+            api_base: JINA_API_BASE.to_string(),
+            api_key: default_api_key(),
+            model: DEFAULT_MODEL.to_string(),
+            task: Task::default(),
+            late_chunking: false,
+        }
+    }
+}
+
+impl Jina {
+    /// Creates a new `JinaBuilder` for constructing `Jina` instances.
+    pub fn builder() -> JinaBuilder {
+        JinaBuilder::default()
+    }
+}
+
+fn default_api_key() -> Secret<String> {
+    std::env::var("JINA_API_KEY")
+        .unwrap_or_else(|_| String::new())
+        .into()
+}
@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use swiftide_core::{indexing::IndexingStream, indexing::Node, Loader, NodeObserver};
+
+use super::Kafka;
+
+impl Loader for Kafka {
+    /// Consumes the topic continuously; the stream only ends on an error receiving a message
+    #[tracing::instrument]
+    fn into_stream(self) -> IndexingStream {
+        let stream = futures_util::stream::unfold(
+            (Arc::clone(&self.consumer), Arc::clone(&self.offsets), false),
+            |(consumer, offsets, failed)| async move {
+                if failed {
+                    return None;
+                }
+
+                loop {
+                    match consumer.recv().await {
+                        Ok(message) => {
+                            let Some(value) = message.value else {
+                                tracing::debug!(
+                                    topic = message.topic,
+                                    offset = message.offset,
+                                    "Skipping Kafka message without a value"
+                                );
+                                continue;
+                            };
+
+                            offsets.received(&message.topic, message.partition, message.offset);
+                            let mut node = Node::new(value);
+                            node.metadata.insert("kafka_topic", message.topic);
+                            node.metadata.insert("kafka_partition", message.partition);
+                            node.metadata.insert("kafka_offset", message.offset);
+                            node.metadata.insert("kafka_key", message.key);
+
+                            return Some((Ok(node), (consumer, offsets, false)));
+                        }
+                        Err(err) => return Some((Err(err), (consumer, offsets, true))),
+                    }
+                }
+            },
+        );
+
+        IndexingStream::from_stream(stream).with_metadata(self.metadata)
+    }
+
+    fn into_stream_boxed(self: Box<Self>) -> IndexingStream {
+        self.into_stream()
+    }
+
+    /// Tracks the nodes of each message, so that offsets are committed once all nodes of a
+    /// message are stored or dropped
+    fn node_observer(&self) -> Option<Arc<dyn NodeObserver>> {
+        Some(Arc::clone(&self.offsets) as Arc<dyn NodeObserver>)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::StreamExt as _;
+
+    use super::*;
+    use crate::kafka::{KafkaMessage, MockKafkaConsumer};
+
+    fn message(offset: i64, value: Option<&str>) -> KafkaMessage {
+        KafkaMessage {
+            topic: "documents".to_string(),
+            partition: 0,
+            offset,
+            key: Some(format!("key-{offset}")),
+            value: value.map(ToString::to_string),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_messages_become_nodes() {
+        let mut consumer = MockKafkaConsumer::new();
+        let mut messages = vec![
+            Ok(message(0, Some("first"))),
+            Ok(message(1, None)),
+            Ok(message(2, Some("second"))),
+        ]
+        .into_iter();
+        consumer
+            .expect_recv()
+            .returning(move || messages.next().unwrap());
+
+        let nodes = Kafka::from_consumer(Arc::new(consumer))
+            .into_stream()
+            .take(2)
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(nodes[0].chunk, "first");
+        assert_eq!(nodes[1].chunk, "second");
+        assert_eq!(nodes[1].metadata.get("kafka_offset").unwrap(), 2);
+        assert_eq!(nodes[1].metadata.get("kafka_key").unwrap(), "key-2");
+        assert_eq!(nodes[1].metadata.get("kafka_topic").unwrap(), "documents");
+        assert_eq!(nodes[1].metadata.get("kafka_partition").unwrap(), 0);
+    }
+}
@@ -0,0 +1,166 @@
+//! Kafka is a distributed event streaming platform.
+//!
+//! This module provides a Kafka loader for Swiftide, consuming messages from a topic
+//! continuously and using them for RAG.
+//!
+//! Offsets are not committed automatically. Wrap the storage with [`Kafka::commit_offsets_after`]
+//! so that an offset is only committed after all nodes of its message are stored or dropped.
+//! Dropped nodes and chunks are reported by the pipeline when it is created with
+//! `Pipeline::from_loader`.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use swiftide_integrations::kafka::*;
+//! # fn run(storage: impl swiftide_core::Persist + 'static) -> anyhow::Result<()> {
+//! let mut config = ClientConfig::new();
+//! config
+//!     .set("bootstrap.servers", "localhost:9092")
+//!     .set("group.id", "swiftide");
+//!
+//! let kafka = Kafka::try_from_client_config(&config, "documents")?;
+//! // Store to this storage in the pipeline loading from `kafka`
+//! let storage = kafka.commit_offsets_after(storage);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::Arc;
+
+use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+use rdkafka::{
+    consumer::{CommitMode, Consumer as _, StreamConsumer},
+    message::Message as _,
+    Offset, TopicPartitionList,
+};
+use swiftide_core::{indexing::Metadata, Persist};
+
+#[cfg(test)]
+use mockall::automock;
+
+/// Re-export the rdkafka client config
+pub use rdkafka::ClientConfig;
+
+mod loader;
+mod persist;
+
+pub use persist::CommitOffsets;
+use persist::OffsetTracker;
+
+/// Loads messages from a Kafka topic as nodes
+///
+/// The value of each message becomes the chunk, and its topic, partition, offset and key
+/// are added to the metadata. Messages without a value are skipped.
+#[derive(Debug, Clone)]
+pub struct Kafka {
+    consumer: Arc<dyn KafkaConsumer>,
+    /// Static metadata added to every node, i.e. to tag nodes with their source
+    metadata: Metadata,
+    /// The offsets of received messages, to only commit offsets of stored messages
+    offsets: Arc<OffsetTracker>,
+}
+
+impl Kafka {
+    /// Creates a consumer from the config and subscribes it to the topic
+    ///
+    /// Automatic offset commits are disabled, regardless of the config.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the consumer can not be created or can not subscribe to the topic.
+    pub fn try_from_client_config(config: &ClientConfig, topic: impl AsRef<str>) -> Result<Self> {
+        let consumer: StreamConsumer = config
+            .clone()
+            .set("enable.auto.commit", "false")
+            .create()
+            .context("Failed to create Kafka consumer")?;
+        consumer
+            .subscribe(&[topic.as_ref()])
+            .with_context(|| format!("Failed to subscribe to topic {}", topic.as_ref()))?;
+
+        Ok(Self::from_consumer(Arc::new(consumer)))
+    }
+
+    fn from_consumer(consumer: Arc<dyn KafkaConsumer>) -> Self {
+        Self {
+            consumer,
+            metadata: Metadata::default(),
+            offsets: Arc::default(),
+        }
+    }
+
+    /// Adds static metadata to every node, i.e. to tag nodes with their source
+    #[must_use]
+    pub fn with_metadata(mut self, metadata: impl Into<Metadata>) -> Self {
+        self.metadata = metadata.into();
+        self
+    }
+
+    /// Wraps a storage so that the offsets of nodes are committed once they are stored
+    pub fn commit_offsets_after(&self, storage: impl Persist + 'static) -> CommitOffsets {
+        CommitOffsets::new(
+            storage,
+            Arc::clone(&self.consumer),
+            Arc::clone(&self.offsets),
+        )
+    }
+}
+
+/// A message as consumed from Kafka
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct KafkaMessage {
+    topic: String,
+    partition: i32,
+    offset: i64,
+    key: Option<String>,
+    value: Option<String>,
+}
+
+#[cfg_attr(test, automock)]
+#[async_trait]
+trait KafkaConsumer: Send + Sync {
+    /// Waits for the next message
+    async fn recv(&self) -> Result<KafkaMessage>;
+
+    /// Commits the offset of the next message to consume for each topic and partition
+    fn commit(&self, offsets: &[(String, i32, i64)]) -> Result<()>;
+}
+
+impl std::fmt::Debug for dyn KafkaConsumer + '_ {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("KafkaConsumer")
+    }
+}
+
+#[async_trait]
+impl KafkaConsumer for StreamConsumer {
+    async fn recv(&self) -> Result<KafkaMessage> {
+        let message = StreamConsumer::recv(self)
+            .await
+            .context("Failed to receive Kafka message")?;
+
+        Ok(KafkaMessage {
+            topic: message.topic().to_string(),
+            partition: message.partition(),
+            offset: message.offset(),
+            key: message
+                .key()
+                .map(|key| String::from_utf8_lossy(key).into_owned()),
+            value: message
+                .payload()
+                .map(|value| String::from_utf8_lossy(value).into_owned()),
+        })
+    }
+
+    fn commit(&self, offsets: &[(String, i32, i64)]) -> Result<()> {
+        let mut list = TopicPartitionList::new();
+        for (topic, partition, offset) in offsets {
+            list.add_partition_offset(topic, *partition, Offset::Offset(*offset))
+                .context("Invalid Kafka offset")?;
+        }
+
+        rdkafka::consumer::Consumer::commit(self, &list, CommitMode::Async)
+            .context("Failed to commit Kafka offsets")
+    }
+}
@@ -0,0 +1,557 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures_util::StreamExt as _;
+use swiftide_core::{
+    indexing::{IndexingStream, Node},
+    NodeObserver, Persist,
+};
+
+use super::KafkaConsumer;
+
+/// Wraps a storage and commits the Kafka offsets of nodes once they are stored
+///
+/// Created with [`super::Kafka::commit_offsets_after`]. The storage is flushed before committing,
+/// so offsets of nodes still buffered by the storage are not committed.
+///
+/// Offsets are committed per partition up to the first message received by the loader that is
+/// not done yet, regardless of the order batches are stored in. A message is done once all its
+/// nodes are stored or dropped: the pipeline reports chunks and dropped nodes through the
+/// [`NodeObserver`] of the loader, so a message chunked into several nodes is only done once all
+/// chunks are. Nodes dropped by filters, deduplication or empty chunk removal, and nodes failing
+/// in a transformer with the error skipped, count as done.
+///
+/// A node that fails to store holds back the commits of its partition, even if the error is
+/// skipped, so it and all later messages of the partition are consumed again after a restart.
+/// So do nodes dropped by a batch transformer that also yields an error for the batch, as the
+/// dropped nodes might have failed. Messages that are only dropped are committed with the next
+/// stored batch. Nodes that do not come from Kafka are stored without committing anything.
+#[derive(Debug, Clone)]
+pub struct CommitOffsets {
+    inner: Arc<dyn Persist>,
+    consumer: Arc<dyn KafkaConsumer>,
+    offsets: Arc<OffsetTracker>,
+}
+
+impl CommitOffsets {
+    pub(super) fn new(
+        storage: impl Persist + 'static,
+        consumer: Arc<dyn KafkaConsumer>,
+        offsets: Arc<OffsetTracker>,
+    ) -> Self {
+        Self {
+            inner: Arc::new(storage),
+            consumer,
+            offsets,
+        }
+    }
+
+    /// Marks the offsets of the nodes as stored, and commits the partitions whose contiguously
+    /// stored offsets advanced
+    fn commit<'a>(&self, nodes: impl IntoIterator<Item = &'a Node>) -> Result<()> {
+        let stored = nodes
+            .into_iter()
+            .filter_map(kafka_offset)
+            .collect::<Vec<_>>();
+        if stored.is_empty() {
+            return Ok(());
+        }
+
+        // Committing while holding the lock keeps commits of concurrent batches in order
+        let mut partitions = self
+            .offsets
+            .partitions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        for (topic, partition, offset) in stored {
+            partitions
+                .entry((topic, partition))
+                .or_default()
+                .done(offset);
+        }
+
+        let commits = partitions
+            .iter()
+            .filter_map(|((topic, partition), offsets)| {
+                let watermark = offsets.watermark()?;
+                (offsets.committed < Some(watermark))
+                    .then(|| (topic.clone(), *partition, watermark))
+            })
+            .collect::<Vec<_>>();
+        if commits.is_empty() {
+            return Ok(());
+        }
+
+        tracing::debug!(offsets = ?commits, "Committing Kafka offsets");
+        self.consumer.commit(&commits)?;
+        for (topic, partition, offset) in commits {
+            if let Some(offsets) = partitions.get_mut(&(topic, partition)) {
+                offsets.committed = Some(offset);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Tracks the offsets per topic and partition, shared by the loader and [`CommitOffsets`]
+#[derive(Debug, Default)]
+pub(super) struct OffsetTracker {
+    partitions: Mutex<HashMap<(String, i32), PartitionOffsets>>,
+}
+
+impl OffsetTracker {
+    /// Registers a message received by the loader, holding back commits until it is done
+    pub(super) fn received(&self, topic: &str, partition: i32, offset: i64) {
+        let mut partitions = self
+            .partitions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let offsets = partitions
+            .entry((topic.to_string(), partition))
+            .or_default();
+        offsets.pending.insert(offset, 1);
+        // The consumer already starts from the first received message
+        offsets.committed.get_or_insert(offset);
+    }
+
+    /// Applies `f` to the offsets of the partition of the message with the key
+    fn with_message(&self, key: &str, f: impl FnOnce(&mut PartitionOffsets, i64)) {
+        let Some((topic, partition, offset)) = parse_key(key) else {
+            return;
+        };
+        let mut partitions = self
+            .partitions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        f(partitions.entry((topic, partition)).or_default(), offset);
+    }
+}
+
+/// Tracks the nodes of each message, committing is left to [`CommitOffsets`]
+impl NodeObserver for OffsetTracker {
+    fn key(&self, node: &Node) -> Option<String> {
+        let (topic, partition, offset) = kafka_offset(node)?;
+        Some(format!("{partition}:{offset}:{topic}"))
+    }
+
+    fn added(&self, key: &str) {
+        self.with_message(key, |offsets, offset| {
+            if let Some(nodes) = offsets.pending.get_mut(&offset) {
+                *nodes += 1;
+            }
+        });
+    }
+
+    fn released(&self, key: &str) {
+        self.with_message(key, PartitionOffsets::done);
+    }
+}
+
+/// The topic, partition and offset of a key of [`OffsetTracker`]
+fn parse_key(key: &str) -> Option<(String, i32, i64)> {
+    let mut parts = key.splitn(3, ':');
+    let partition = parts.next()?.parse().ok()?;
+    let offset = parts.next()?.parse().ok()?;
+    let topic = parts.next()?;
+
+    Some((topic.to_string(), partition, offset))
+}
+
+#[derive(Debug, Default)]
+struct PartitionOffsets {
+    /// Offsets received by the loader that are not done yet, with the number of their nodes that
+    /// are neither stored nor dropped
+    pending: BTreeMap<i64, usize>,
+    /// The highest offset that is done
+    done: Option<i64>,
+    /// The offset of the next message to consume as last committed, or the first received offset
+    committed: Option<i64>,
+}
+
+impl PartitionOffsets {
+    /// Marks a node of the message as stored or dropped, the message is done once all its nodes
+    /// are
+    fn done(&mut self, offset: i64) {
+        if let Some(nodes) = self.pending.get_mut(&offset) {
+            *nodes = nodes.saturating_sub(1);
+            if *nodes > 0 {
+                return;
+            }
+            self.pending.remove(&offset);
+        }
+        self.done = self.done.max(Some(offset));
+    }
+
+    /// The offset of the next message to consume, the first one that is not done yet
+    fn watermark(&self) -> Option<i64> {
+        self.pending
+            .first_key_value()
+            .map(|(offset, _)| *offset)
+            .or_else(|| self.done.map(|offset| offset + 1))
+    }
+}
+
+/// The topic, partition and offset of a node loaded from Kafka
+fn kafka_offset(node: &Node) -> Option<(String, i32, i64)> {
+    let topic = node.metadata.get("kafka_topic")?.as_str()?;
+    let partition = node.metadata.get("kafka_partition")?.as_i64()?;
+    let offset = node.metadata.get("kafka_offset")?.as_i64()?;
+
+    Some((topic.to_string(), i32::try_from(partition).ok()?, offset))
+}
+
+#[async_trait]
+impl Persist for CommitOffsets {
+    async fn setup(&self) -> Result<()> {
+        self.inner.setup().await
+    }
+
+    /// Stores and flushes the node, then commits its offset
+    async fn store(&self, node: Node) -> Result<Node> {
+        let node = self.inner.store(node).await?;
+        self.inner.flush().await?;
+        self.commit([&node])?;
+        Ok(node)
+    }
+
+    /// Stores and flushes the batch, then commits the offsets of the nodes that were stored
+    ///
+    /// Nothing is committed if flushing fails, as the nodes might not be stored.
+    async fn batch_store(&self, nodes: Vec<Node>) -> IndexingStream {
+        let results = self
+            .inner
+            .batch_store(nodes)
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        let commit =
+            self.inner.flush().await.and_then(|()| {
+                self.commit(results.iter().filter_map(|result| result.as_ref().ok()))
+            });
+        IndexingStream::iter(results.into_iter().chain(commit.err().map(Err)))
+    }
+
+    fn batch_size(&self) -> Option<usize> {
+        self.inner.batch_size()
+    }
+
+    fn batch_max_bytes(&self) -> Option<usize> {
+        self.inner.batch_max_bytes()
+    }
+
+    async fn count(&self) -> Result<u64> {
+        self.inner.count().await
+    }
+
+    async fn stream_all(&self) -> IndexingStream {
+        self.inner.stream_all().await
+    }
+
+    async fn exists(&self, nodes: &[Node]) -> Result<Vec<bool>> {
+        self.inner.exists(nodes).await
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.inner.flush().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use futures_util::TryStreamExt as _;
+    use swiftide_core::{Loader as _, MockPersist};
+
+    use super::*;
+    use crate::kafka::{Kafka, KafkaMessage, MockKafkaConsumer};
+
+    #[tokio::test]
+    async fn test_commits_offsets_after_store() {
+        let stored = Arc::new(Mutex::new(false));
+        let committed = Arc::new(Mutex::new(Vec::new()));
+
+        let mut consumer = MockKafkaConsumer::new();
+        let mut offset = 0;
+        consumer.expect_recv().returning(move || {
+            offset += 1;
+            Ok(KafkaMessage {
+                topic: "documents".to_string(),
+                partition: 0,
+                offset,
+                key: None,
+                value: Some(format!("message {offset}")),
+            })
+        });
+        let is_stored = Arc::clone(&stored);
+        let commits = Arc::clone(&committed);
+        consumer.expect_commit().returning(move |offsets| {
+            assert!(*is_stored.lock().unwrap(), "Committed before storing");
+            commits.lock().unwrap().extend_from_slice(offsets);
+            Ok(())
+        });
+
+        let mut storage = MockPersist::new();
+        let is_stored = Arc::clone(&stored);
+        storage.expect_batch_store().returning(move |nodes| {
+            *is_stored.lock().unwrap() = true;
+            IndexingStream::iter(nodes.into_iter().map(Ok))
+        });
+
+        let kafka = Kafka::from_consumer(Arc::new(consumer));
+        let storage = kafka.commit_offsets_after(storage);
+        let nodes = kafka
+            .into_stream()
+            .take(3)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        let stored = storage
+            .batch_store(nodes)
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(stored.len(), 3);
+        assert_eq!(
+            *committed.lock().unwrap(),
+            [("documents".to_string(), 0, 4)]
+        );
+    }
+
+    fn consumer_of(
+        offsets: impl IntoIterator<Item = i64, IntoIter: Send + 'static>,
+        committed: Arc<Mutex<Vec<(String, i32, i64)>>>,
+    ) -> MockKafkaConsumer {
+        let mut consumer = MockKafkaConsumer::new();
+        let mut offsets = offsets.into_iter();
+        consumer.expect_recv().returning(move || {
+            let offset = offsets.next().unwrap();
+            Ok(KafkaMessage {
+                topic: "documents".to_string(),
+                partition: 0,
+                offset,
+                key: None,
+                value: Some(format!("message {offset}")),
+            })
+        });
+        consumer.expect_commit().returning(move |offsets| {
+            committed.lock().unwrap().extend_from_slice(offsets);
+            Ok(())
+        });
+        consumer
+    }
+
+    #[tokio::test]
+    async fn test_commits_contiguously_stored_offsets() {
+        let committed = Arc::new(Mutex::new(Vec::new()));
+        let kafka = Kafka::from_consumer(Arc::new(consumer_of(1..=3, Arc::clone(&committed))));
+        let mut inner = MockPersist::new();
+        inner.expect_store().returning(Ok);
+        let storage = kafka.commit_offsets_after(inner);
+        let nodes = kafka
+            .into_stream()
+            .take(3)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        // Storing later messages first does not commit past the first message
+        storage.store(nodes[2].clone()).await.unwrap();
+        storage.store(nodes[1].clone()).await.unwrap();
+        assert!(committed.lock().unwrap().is_empty());
+
+        storage.store(nodes[0].clone()).await.unwrap();
+        assert_eq!(
+            *committed.lock().unwrap(),
+            [("documents".to_string(), 0, 4)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_node_holds_back_commits() {
+        let committed = Arc::new(Mutex::new(Vec::new()));
+        let kafka =
+            Kafka::from_consumer(Arc::new(consumer_of([10, 12, 13], Arc::clone(&committed))));
+
+        let mut inner = MockPersist::new();
+        inner.expect_batch_store().returning(|nodes| {
+            IndexingStream::iter(nodes.into_iter().map(|node| {
+                if node.chunk == "message 10" {
+                    Err(anyhow::anyhow!("Storage down"))
+                } else {
+                    Ok(node)
+                }
+            }))
+        });
+        let storage = kafka.commit_offsets_after(inner);
+        let nodes = kafka
+            .into_stream()
+            .take(3)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        let results = storage.batch_store(nodes).await.collect::<Vec<_>>().await;
+
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 2);
+        assert!(committed.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_dropped_node_does_not_hold_back_commits() {
+        let committed = Arc::new(Mutex::new(Vec::new()));
+        let kafka = Kafka::from_consumer(Arc::new(consumer_of(1..=3, Arc::clone(&committed))));
+        let observer = kafka.node_observer().unwrap();
+        let mut inner = MockPersist::new();
+        inner.expect_store().returning(Ok);
+        let storage = kafka.commit_offsets_after(inner);
+        let nodes = kafka
+            .into_stream()
+            .take(3)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        // The first message is filtered out by the pipeline
+        observer.released(&observer.key(&nodes[0]).unwrap());
+        storage.store(nodes[1].clone()).await.unwrap();
+        storage.store(nodes[2].clone()).await.unwrap();
+
+        assert_eq!(
+            committed.lock().unwrap().last().unwrap(),
+            &("documents".to_string(), 0, 4)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_later_chunk_holds_back_commits() {
+        let committed = Arc::new(Mutex::new(Vec::new()));
+        let kafka = Kafka::from_consumer(Arc::new(consumer_of(1..=1, Arc::clone(&committed))));
+        let observer = kafka.node_observer().unwrap();
+        let mut inner = MockPersist::new();
+        inner.expect_batch_store().returning(|nodes| {
+            IndexingStream::iter(nodes.into_iter().map(|node| {
+                if node.chunk_index == Some(1) {
+                    Err(anyhow::anyhow!("Storage down"))
+                } else {
+                    Ok(node)
+                }
+            }))
+        });
+        inner.expect_store().returning(Ok);
+        let storage = kafka.commit_offsets_after(inner);
+        let message = kafka
+            .into_stream()
+            .take(1)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap()
+            .remove(0);
+
+        // The pipeline chunks the message into two chunks
+        let key = observer.key(&message).unwrap();
+        let chunks = (0..2)
+            .map(|index| {
+                observer.added(&key);
+                Node {
+                    chunk_index: Some(index),
+                    ..message.clone()
+                }
+            })
+            .collect::<Vec<_>>();
+        observer.released(&key);
+
+        let results = storage
+            .batch_store(chunks.clone())
+            .await
+            .collect::<Vec<_>>()
+            .await;
+        assert!(results[1].is_err());
+        assert!(committed.lock().unwrap().is_empty());
+
+        storage.store(chunks[1].clone()).await.unwrap();
+        assert_eq!(
+            *committed.lock().unwrap(),
+            [("documents".to_string(), 0, 2)]
+        );
+    }
+
+    /// Stores every node, but fails to flush them
+    #[derive(Debug, Clone)]
+    struct FailingFlush;
+
+    #[async_trait]
+    impl Persist for FailingFlush {
+        async fn setup(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn store(&self, node: Node) -> Result<Node> {
+            Ok(node)
+        }
+
+        async fn batch_store(&self, nodes: Vec<Node>) -> IndexingStream {
+            IndexingStream::from_nodes(nodes)
+        }
+
+        async fn flush(&self) -> Result<()> {
+            anyhow::bail!("Disk full")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_does_not_commit_when_flush_fails() {
+        let mut consumer = MockKafkaConsumer::new();
+        consumer.expect_commit().never();
+
+        let mut node = Node::new("message");
+        node.metadata.insert("kafka_topic", "documents");
+        node.metadata.insert("kafka_partition", 0);
+        node.metadata.insert("kafka_offset", 7);
+
+        let storage = CommitOffsets::new(FailingFlush, Arc::new(consumer), Arc::default());
+        let results = storage
+            .batch_store(vec![node.clone()])
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            results.last().unwrap().as_ref().unwrap_err().to_string(),
+            "Disk full"
+        );
+        assert!(storage.store(node).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_does_not_commit_failed_nodes() {
+        let mut consumer = MockKafkaConsumer::new();
+        consumer.expect_commit().never();
+
+        let mut storage = MockPersist::new();
+        storage
+            .expect_batch_store()
+            .returning(|_| IndexingStream::iter(vec![Err(anyhow::anyhow!("Storage down"))]));
+
+        let mut node = Node::new("message");
+        node.metadata.insert("kafka_topic", "documents");
+        node.metadata.insert("kafka_partition", 0);
+        node.metadata.insert("kafka_offset", 7);
+
+        let results = CommitOffsets::new(storage, Arc::new(consumer), Arc::default())
+            .batch_store(vec![node])
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert!(results[0].is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use anyhow::Context as _;
 use anyhow::Result;
@@ -7,11 +7,14 @@ use connection_pool::LanceDBPoolManager;
 use deadpool::managed::Object;
 use derive_builder::Builder;
 use lancedb::arrow::arrow_schema::{DataType, Field, Schema};
-use swiftide_core::indexing::EmbeddedField;
+use swiftide_core::indexing::{EmbeddedField, WriteMode};
 pub mod connection_pool;
 pub mod persist;
 pub mod retrieve;
 
+/// The default number of nodes stored per batch
+pub const DEFAULT_BATCH_SIZE: usize = 256;
+
 /**
 `LanceDB` is a columnar database that separates data and compute.
 
@@ -28,7 +31,7 @@ active connection via `get_connection`.
 
 ```no_run
 # use swiftide_integrations::lancedb::{LanceDB};
-# use swiftide_core::indexing::EmbeddedField;
+# use swiftide_core::indexing::{EmbeddedField, WriteMode};
     LanceDB::builder()
     .uri("/my/lancedb")
     .vector_size(1536)
@@ -64,6 +67,16 @@ pub struct LanceDB {
     /// Storage options
     #[builder(default)]
     storage_options: Vec<(String, String)>,
+    /// Fails connecting to cloud storage, i.e. S3, if it takes longer. Optional.
+    ///
+    /// Sets the `connect_timeout` storage option of the object store.
+    #[builder(default)]
+    connect_timeout: Option<Duration>,
+    /// Fails a request to cloud storage, i.e. S3, if it takes longer. Optional.
+    ///
+    /// Sets the `timeout` storage option of the object store.
+    #[builder(default)]
+    request_timeout: Option<Duration>,
 
     #[builder(private, default = "self.default_schema_from_fields()")]
     schema: Arc<Schema>,
@@ -77,15 +90,21 @@ pub struct LanceDB {
     /// sizes by specifying the size in the vector configuration.
     vector_size: Option<i32>,
 
-    /// Batch size for storing nodes in `LanceDB`. Default is 256.
-    #[builder(default = "256")]
-    batch_size: usize,
+    /// The number of nodes stored per batch. Defaults to [`DEFAULT_BATCH_SIZE`].
+    ///
+    /// See [`LanceDBBuilder::without_batching`] to store nodes one by one.
+    #[builder(default = "Some(DEFAULT_BATCH_SIZE)")]
+    batch_size: Option<usize>,
 
     /// Field configuration for `LanceDB`, will result in the eventual schema.
     ///
     /// Supports multiple field types, see [`FieldConfig`] for more details.
     #[builder(default = "self.default_fields()")]
     fields: Vec<FieldConfig>,
+
+    /// Whether to keep or recreate an existing table on setup. Defaults to append.
+    #[builder(default)]
+    write_mode: WriteMode,
 }
 
 impl std::fmt::Debug for LanceDB {
@@ -116,6 +135,12 @@ impl LanceDB {
 }
 
 impl LanceDBBuilder {
+    /// Stores nodes one by one instead of in batches, overriding the default batch size
+    pub fn without_batching(&mut self) -> &mut Self {
+        self.batch_size = Some(None);
+        self
+    }
+
     #[allow(clippy::missing_panics_doc)]
     pub fn with_vector(&mut self, config: impl Into<VectorConfig>) -> &mut Self {
         if self.fields.is_none() {
@@ -190,7 +215,7 @@ impl LanceDBBuilder {
             .uri(self.uri.clone().flatten().context("URI should be set")?)
             .api_key(self.api_key.clone().flatten())
             .region(self.region.clone().flatten())
-            .storage_options(self.storage_options.clone().unwrap_or_default())
+            .storage_options(self.storage_options_with_timeouts())
             .build()?;
 
         LanceDBConnectionPool::builder(mgr)
@@ -199,6 +224,23 @@ impl LanceDBBuilder {
             .map(Arc::new)
             .map_err(Into::into)
     }
+
+    /// Returns the storage options, with the timeouts as object store options
+    fn storage_options_with_timeouts(&self) -> Vec<(String, String)> {
+        let mut storage_options = self.storage_options.clone().unwrap_or_default();
+        let timeouts = [
+            ("connect_timeout", self.connect_timeout.flatten()),
+            ("timeout", self.request_timeout.flatten()),
+        ];
+
+        for (key, timeout) in timeouts {
+            if let Some(timeout) = timeout {
+                storage_options.push((key.to_string(), format!("{}ms", timeout.as_millis())));
+            }
+        }
+
+        storage_options
+    }
 }
 
 #[derive(Clone)]
@@ -264,3 +306,27 @@ pub(crate) fn normalize_field_name(field: &str) -> String {
         .to_lowercase()
         .replace(|c: char| !c.is_alphanumeric(), "_")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeouts_are_storage_options() {
+        let mut builder = LanceDB::builder();
+        builder
+            .uri("s3://bucket/lancedb")
+            .storage_options(vec![("region".to_string(), "eu-west-1".to_string())])
+            .connect_timeout(Duration::from_secs(1))
+            .request_timeout(Duration::from_millis(500));
+
+        assert_eq!(
+            builder.storage_options_with_timeouts(),
+            vec![
+                ("region".to_string(), "eu-west-1".to_string()),
+                ("connect_timeout".to_string(), "1000ms".to_string()),
+                ("timeout".to_string(), "500ms".to_string()),
+            ]
+        );
+    }
+}
@@ -11,8 +11,11 @@ use arrow_array::GenericByteArray;
 use arrow_array::RecordBatch;
 use arrow_array::RecordBatchIterator;
 use async_trait::async_trait;
+use lancedb::arrow::arrow_schema::{DataType, Schema};
+use lancedb::connection::CreateTableMode;
 use swiftide_core::indexing::IndexingStream;
 use swiftide_core::indexing::Node;
+use swiftide_core::indexing::WriteMode;
 use swiftide_core::Persist;
 
 use super::FieldConfig;
@@ -25,16 +28,24 @@ impl Persist for LanceDB {
         let conn = self.get_connection().await?;
         let schema = self.schema.clone();
 
-        if let Err(err) = conn.open_table(&self.table_name).execute().await {
-            if matches!(err, lancedb::Error::TableNotFound { .. }) {
+        if self.write_mode == WriteMode::Recreate {
+            conn.create_empty_table(&self.table_name, schema)
+                .mode(CreateTableMode::Overwrite)
+                .execute()
+                .await?;
+            return Ok(());
+        }
+
+        match conn.open_table(&self.table_name).execute().await {
+            Ok(table) => self.check_existing_schema(&table.schema().await?)?,
+            Err(lancedb::Error::TableNotFound { .. }) => {
                 conn.create_empty_table(&self.table_name, schema)
                     .execute()
                     .await
                     .map(|_| ())
                     .map_err(anyhow::Error::from)?;
-            } else {
-                return Err(err.into());
             }
+            Err(err) => return Err(err.into()),
         }
 
         Ok(())
@@ -56,11 +67,54 @@ impl Persist for LanceDB {
     }
 
     fn batch_size(&self) -> Option<usize> {
-        Some(self.batch_size)
+        self.batch_size
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn count(&self) -> Result<u64> {
+        let conn = self.get_connection().await?;
+        let table = conn.open_table(&self.table_name).execute().await?;
+
+        Ok(table.count_rows(None).await? as u64)
     }
 }
 
 impl LanceDB {
+    /// Checks that an existing table has the configured vector fields with the same sizes,
+    /// instead of storing nodes in a table of a different shape
+    fn check_existing_schema(&self, existing: &Schema) -> Result<()> {
+        for field in self.schema.fields() {
+            let DataType::FixedSizeList(_, size) = field.data_type() else {
+                continue;
+            };
+
+            let existing_size = existing
+                .fields()
+                .iter()
+                .find(|existing| existing.name() == field.name())
+                .map(|existing| match existing.data_type() {
+                    DataType::FixedSizeList(_, size) => Some(*size),
+                    _ => None,
+                });
+
+            let detail = match existing_size {
+                Some(Some(existing_size)) if existing_size == *size => continue,
+                Some(Some(existing_size)) => format!(
+                    "has vectors of size {existing_size} in {}, but {size} is configured",
+                    field.name()
+                ),
+                Some(None) => format!("has a field {} that is not a vector", field.name()),
+                None => format!("has no field {}", field.name()),
+            };
+            anyhow::bail!(
+                "Table {} {detail}, recreate it with `WriteMode::Recreate` to migrate",
+                self.table_name
+            );
+        }
+
+        Ok(())
+    }
+
     async fn store_nodes(&self, nodes: &[Node]) -> Result<()> {
         let schema = self.schema.clone();
 
@@ -191,4 +245,53 @@ mod test {
             .await
             .expect("Should not error if table exists");
     }
+
+    #[tokio::test]
+    async fn test_setup_errors_on_mismatching_vector_size() {
+        let tempdir = TempDir::new().unwrap();
+        let lancedb = |vector_size: i32| {
+            LanceDB::builder()
+                .uri(tempdir.child("lancedb").to_str().unwrap())
+                .vector_size(vector_size)
+                .with_vector(EmbeddedField::Combined)
+                .table_name("swiftide_test")
+                .build()
+                .unwrap()
+        };
+
+        lancedb(3).setup().await.unwrap();
+        lancedb(3).setup().await.unwrap();
+
+        let err = lancedb(4).setup().await.unwrap_err();
+        assert!(err.to_string().contains("has vectors of size 3"), "{err}");
+        assert!(err.to_string().contains("but 4 is configured"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_recreate_does_not_keep_previous_runs() {
+        let tempdir = TempDir::new().unwrap();
+        let lancedb = LanceDB::builder()
+            .uri(tempdir.child("lancedb").to_str().unwrap())
+            .vector_size(384)
+            .with_vector(EmbeddedField::Combined)
+            .table_name("swiftide_test")
+            .write_mode(WriteMode::Recreate)
+            .build()
+            .unwrap();
+
+        for run in 0..2 {
+            let nodes = (0..3)
+                .map(|i| {
+                    let mut node = Node::new(format!("run {run} node {i}"));
+                    node.vectors = Some([(EmbeddedField::Combined, vec![1.0; 384])].into());
+                    node
+                })
+                .collect::<Vec<_>>();
+
+            lancedb.setup().await.unwrap();
+            lancedb.store_nodes(&nodes).await.unwrap();
+        }
+
+        assert_eq!(lancedb.count().await.unwrap(), 3);
+    }
 }
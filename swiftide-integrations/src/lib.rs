@@ -6,10 +6,20 @@ pub mod aws_bedrock;
 pub mod fastembed;
 #[cfg(feature = "fluvio")]
 pub mod fluvio;
+#[cfg(feature = "gemini")]
+pub mod gemini;
 #[cfg(feature = "groq")]
 pub mod groq;
+#[cfg(feature = "http")]
+pub mod http;
+#[cfg(feature = "jina")]
+pub mod jina;
+#[cfg(feature = "kafka")]
+pub mod kafka;
 #[cfg(feature = "lancedb")]
 pub mod lancedb;
+#[cfg(feature = "milvus")]
+pub mod milvus;
 #[cfg(feature = "ollama")]
 pub mod ollama;
 #[cfg(feature = "openai")]
@@ -24,5 +34,13 @@ pub mod redb;
 pub mod redis;
 #[cfg(feature = "scraping")]
 pub mod scraping;
+#[cfg(feature = "sqlx")]
+pub mod sqlx;
+#[cfg(any(feature = "milvus", feature = "qdrant", feature = "redis"))]
+mod timeout;
 #[cfg(feature = "tree-sitter")]
 pub mod treesitter;
+#[cfg(feature = "typesense")]
+pub mod typesense;
+#[cfg(feature = "voyage")]
+pub mod voyage;
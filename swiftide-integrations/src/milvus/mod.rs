@@ -0,0 +1,348 @@
+//! This module provides integration with the Milvus vector database.
+//!
+//! Milvus can be used as storage in an `indexing::Pipeline`.
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use anyhow::{Context as _, Result};
+use derive_builder::Builder;
+#[allow(deprecated)]
+use milvus::proto::common::ErrorCode;
+use milvus::{
+    client::{Client, ClientBuilder},
+    index::{IndexInfo, IndexParams, IndexType, MetricType},
+    schema::{CollectionSchema, CollectionSchemaBuilder, FieldSchema},
+};
+use swiftide_core::indexing::EmbeddedField;
+use tokio::sync::OnceCell;
+
+use crate::timeout::with_timeout;
+
+mod persist;
+
+const DEFAULT_MILVUS_URL: &str = "http://localhost:19530";
+const DEFAULT_COLLECTION_NAME: &str = "swiftide";
+/// The default number of nodes inserted per batch
+pub const DEFAULT_BATCH_SIZE: usize = 50;
+
+const ID_FIELD: &str = "id";
+const PATH_FIELD: &str = "path";
+const CHUNK_FIELD: &str = "chunk";
+const METADATA_FIELD: &str = "metadata";
+const VECTOR_FIELD: &str = "vector";
+
+/// Maximum length of the varchar fields, the maximum Milvus supports
+const MAX_VARCHAR_LENGTH: i32 = 65_535;
+
+/// Stores nodes in a Milvus collection
+///
+/// On setup a collection is created with `id`, `path`, `chunk`, `metadata` (json) and `vector`
+/// fields, if it does not exist. Nodes are inserted in batches, after which the vector index is
+/// created if it does not exist yet.
+///
+/// Only a single embedded field is stored, `EmbeddedField::Combined` by default.
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_integrations::milvus::Milvus;
+/// let milvus = Milvus::builder()
+///     .url("http://localhost:19530")
+///     .collection_name("swiftide")
+///     .vector_size(1536)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Builder, Clone)]
+#[builder(
+    pattern = "owned",
+    setter(strip_option),
+    build_fn(error = "anyhow::Error")
+)]
+pub struct Milvus {
+    /// The url of the Milvus server. Defaults to `MILVUS_URL` or `http://localhost:19530`.
+    #[builder(setter(into), default = "default_url()")]
+    url: String,
+    /// The name of the collection. Defaults to "swiftide".
+    #[builder(setter(into), default = "DEFAULT_COLLECTION_NAME.to_string()")]
+    collection_name: String,
+    /// The dimension of the stored vectors
+    vector_size: i64,
+    /// The embedded field of the node to store as vector. Defaults to `EmbeddedField::Combined`.
+    #[builder(default)]
+    vector_field: EmbeddedField,
+    /// The metric of the vector index. Defaults to cosine.
+    #[builder(default = "MetricType::COSINE")]
+    metric_type: MetricType,
+    /// The vector index to create. Defaults to HNSW.
+    #[builder(default)]
+    index: Index,
+    /// The number of nodes inserted per batch. Defaults to [`DEFAULT_BATCH_SIZE`].
+    ///
+    /// See [`MilvusBuilder::without_batching`] to insert nodes one by one.
+    #[builder(default = "Some(DEFAULT_BATCH_SIZE)")]
+    batch_size: Option<usize>,
+    /// Fails connecting if it takes longer, i.e. when Milvus is unreachable. Optional.
+    #[builder(default)]
+    connect_timeout: Option<Duration>,
+    /// Fails a request to Milvus, i.e. inserting a batch, if it takes longer. Defaults to the
+    /// timeout of the client.
+    #[builder(default)]
+    request_timeout: Option<Duration>,
+    #[builder(setter(skip), default)]
+    client: Arc<OnceCell<Client>>,
+    #[builder(setter(skip), default)]
+    index_created: Arc<OnceCell<()>>,
+}
+
+/// The vector index created on the collection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Index {
+    /// Hierarchical navigable small world graph index
+    Hnsw { m: u32, ef_construction: u32 },
+    /// Inverted file index storing raw vectors
+    IvfFlat { nlist: u32 },
+}
+
+impl Default for Index {
+    fn default() -> Self {
+        Index::Hnsw {
+            m: 16,
+            ef_construction: 200,
+        }
+    }
+}
+
+impl Index {
+    fn params(self, metric_type: MetricType) -> IndexParams {
+        let (index_type, params) = match self {
+            Index::Hnsw { m, ef_construction } => (
+                IndexType::HNSW,
+                HashMap::from([
+                    ("M".to_string(), m.to_string()),
+                    ("efConstruction".to_string(), ef_construction.to_string()),
+                ]),
+            ),
+            Index::IvfFlat { nlist } => (
+                IndexType::IvfFlat,
+                HashMap::from([("nlist".to_string(), nlist.to_string())]),
+            ),
+        };
+
+        IndexParams::new(
+            format!("{VECTOR_FIELD}_index"),
+            index_type,
+            metric_type,
+            params,
+        )
+    }
+}
+
+impl MilvusBuilder {
+    /// Stores nodes one by one instead of in batches, overriding the default batch size
+    #[must_use]
+    pub fn without_batching(mut self) -> Self {
+        self.batch_size = Some(None);
+        self
+    }
+}
+
+impl Milvus {
+    pub fn builder() -> MilvusBuilder {
+        MilvusBuilder::default()
+    }
+
+    /// Lazily connects to Milvus and returns the client
+    async fn client(&self) -> Result<&Client> {
+        self.client
+            .get_or_try_init(|| {
+                let mut builder = ClientBuilder::new(self.url.clone());
+                if let Some(request_timeout) = self.request_timeout {
+                    builder = builder.timeout(request_timeout);
+                }
+
+                with_timeout(self.connect_timeout, "connecting to Milvus", async {
+                    builder
+                        .build()
+                        .await
+                        .with_context(|| format!("Failed to connect to Milvus at {}", self.url))
+                })
+            })
+            .await
+    }
+
+    /// Creates the collection if it does not exist
+    ///
+    /// An existing collection is checked against the configured vector size and, if its index
+    /// exists, the configured metric.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the connection fails, the collection cannot be created, or an existing
+    /// collection has a different vector size or metric.
+    pub async fn create_collection_if_not_exists(&self) -> Result<()> {
+        let client = self.client().await?;
+
+        if client.has_collection(&self.collection_name).await? {
+            tracing::warn!("Collection {} exists", &self.collection_name);
+            let collection = client
+                .describe_collection(self.collection_name.as_str())
+                .await?;
+            let index_metric = self
+                .describe_vector_index(client)
+                .await?
+                .first()
+                .map(|index| index.params().metric_type());
+
+            return self.check_existing_collection(&collection.schema, index_metric);
+        }
+
+        tracing::info!("Creating collection {}", &self.collection_name);
+        client.create_collection(self.schema()?, None).await?;
+
+        Ok(())
+    }
+
+    /// Checks that an existing collection has vectors of the configured size, indexed with the
+    /// configured metric, instead of inserting into a collection of a different shape
+    fn check_existing_collection(
+        &self,
+        schema: &milvus::proto::schema::CollectionSchema,
+        index_metric: Option<MetricType>,
+    ) -> Result<()> {
+        let mismatch = |detail: String| {
+            anyhow::anyhow!(
+                "Collection {} {detail}, drop it or use a different collection",
+                self.collection_name
+            )
+        };
+
+        let vector_size = schema
+            .fields
+            .iter()
+            .find(|field| field.name == VECTOR_FIELD)
+            .and_then(|field| field.type_params.iter().find(|param| param.key == "dim"))
+            .and_then(|param| param.value.parse::<i64>().ok())
+            .ok_or_else(|| mismatch(format!("has no {VECTOR_FIELD} field with a dimension")))?;
+        if vector_size != self.vector_size {
+            return Err(mismatch(format!(
+                "has vectors of size {vector_size}, but {} is configured",
+                self.vector_size
+            )));
+        }
+
+        // `MetricType` only implements `Display`
+        if let Some(metric) =
+            index_metric.filter(|metric| metric.to_string() != self.metric_type.to_string())
+        {
+            return Err(mismatch(format!(
+                "is indexed with the {metric} metric, but {} is configured",
+                self.metric_type
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Creates the vector index and loads the collection, once
+    async fn create_index_if_not_exists(&self) -> Result<()> {
+        self.index_created
+            .get_or_try_init(|| async {
+                let client = self.client().await?;
+
+                if self.describe_vector_index(client).await?.is_empty() {
+                    tracing::info!("Creating index on {}", &self.collection_name);
+                    client.flush(self.collection_name.as_str()).await?;
+                    client
+                        .create_index(
+                            self.collection_name.as_str(),
+                            VECTOR_FIELD,
+                            self.index.params(self.metric_type),
+                        )
+                        .await?;
+                }
+
+                client
+                    .load_collection(self.collection_name.as_str(), None)
+                    .await?;
+
+                Ok::<_, anyhow::Error>(())
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Describes the index of the vector field, empty if the collection has no index yet
+    async fn describe_vector_index(&self, client: &Client) -> Result<Vec<IndexInfo>> {
+        match client
+            .describe_index(self.collection_name.as_str(), VECTOR_FIELD)
+            .await
+        {
+            Ok(indexes) => Ok(indexes),
+            Err(milvus::error::Error::Server(code, reason))
+                if is_index_not_found(code, &reason) =>
+            {
+                Ok(Vec::new())
+            }
+            Err(err) => Err(err).with_context(|| {
+                format!("Failed to describe the index of {}", self.collection_name)
+            }),
+        }
+    }
+
+    fn schema(&self) -> Result<CollectionSchema> {
+        let schema = CollectionSchemaBuilder::new(&self.collection_name, "Indexed by swiftide")
+            .add_field(FieldSchema::new_primary_varchar(
+                ID_FIELD, "node id", false, 36,
+            ))
+            .add_field(FieldSchema::new_varchar(
+                PATH_FIELD,
+                "node path",
+                MAX_VARCHAR_LENGTH,
+            ))
+            .add_field(FieldSchema::new_varchar(
+                CHUNK_FIELD,
+                "node chunk",
+                MAX_VARCHAR_LENGTH,
+            ))
+            .add_field(FieldSchema {
+                name: METADATA_FIELD.to_string(),
+                description: "node metadata".to_string(),
+                dtype: milvus::proto::schema::DataType::Json,
+                ..FieldSchema::empty()
+            })
+            .add_field(FieldSchema::new_float_vector(
+                VECTOR_FIELD,
+                "node vector",
+                self.vector_size,
+            ))
+            .build()?;
+
+        Ok(schema)
+    }
+}
+
+/// Milvus reports a missing index as an error
+#[allow(deprecated)]
+fn is_index_not_found(code: ErrorCode, reason: &str) -> bool {
+    code == ErrorCode::IndexNotExist || reason.contains("index not found")
+}
+
+fn default_url() -> String {
+    std::env::var("MILVUS_URL").unwrap_or(DEFAULT_MILVUS_URL.to_string())
+}
+
+// The milvus client does not implement a useful Debug
+#[allow(clippy::missing_fields_in_debug)]
+impl std::fmt::Debug for Milvus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Milvus")
+            .field("url", &self.url)
+            .field("collection_name", &self.collection_name)
+            .field("vector_size", &self.vector_size)
+            .field("batch_size", &self.batch_size)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("request_timeout", &self.request_timeout)
+            .finish()
+    }
+}
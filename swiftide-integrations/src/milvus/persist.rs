@@ -0,0 +1,334 @@
+use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+use milvus::{
+    data::FieldColumn, mutate::UpsertOptions, proto::common::ConsistencyLevel, query::QueryOptions,
+    value::ValueVec,
+};
+use swiftide_core::{
+    indexing::{IndexingStream, Node},
+    Persist,
+};
+
+use super::{
+    Milvus, CHUNK_FIELD, ID_FIELD, MAX_VARCHAR_LENGTH, METADATA_FIELD, PATH_FIELD, VECTOR_FIELD,
+};
+
+#[async_trait]
+impl Persist for Milvus {
+    #[tracing::instrument(skip_all, err)]
+    async fn setup(&self) -> Result<()> {
+        self.create_collection_if_not_exists().await
+    }
+
+    fn batch_size(&self) -> Option<usize> {
+        self.batch_size
+    }
+
+    #[tracing::instrument(skip_all, err, name = "storage.milvus.store")]
+    async fn store(&self, node: Node) -> Result<Node> {
+        self.upsert(std::slice::from_ref(&node)).await?;
+        Ok(node)
+    }
+
+    /// Upserts a batch of nodes, then creates the vector index if it does not exist yet
+    #[tracing::instrument(skip_all, name = "storage.milvus.batch_store")]
+    async fn batch_store(&self, nodes: Vec<Node>) -> IndexingStream {
+        match self.upsert(&nodes).await {
+            Ok(()) => IndexingStream::iter(nodes.into_iter().map(Ok)),
+            Err(err) => vec![Err(err)].into(),
+        }
+    }
+
+    /// Returns the number of entities in the collection
+    ///
+    /// Counts with strong consistency, so entities that are not flushed yet are included. Milvus
+    /// only counts loaded collections, so the index is created and the collection loaded first
+    /// if that did not happen yet.
+    async fn count(&self) -> Result<u64> {
+        self.create_index_if_not_exists().await?;
+
+        let count = self
+            .client()
+            .await?
+            .count_with_expr(
+                self.collection_name.as_str(),
+                "",
+                &QueryOptions::with_consistency_level(ConsistencyLevel::Strong as i32),
+            )
+            .await?;
+
+        u64::try_from(count).context("Invalid count")
+    }
+}
+
+impl Milvus {
+    /// Upserts the nodes by id, so storing a node again replaces it
+    async fn upsert(&self, nodes: &[Node]) -> Result<()> {
+        let columns = self.columns(nodes)?;
+
+        tracing::debug!("Upserting batch of {} nodes", nodes.len());
+        self.client()
+            .await?
+            .upsert(
+                self.collection_name.as_str(),
+                columns,
+                UpsertOptions::default(),
+            )
+            .await?;
+
+        self.create_index_if_not_exists().await
+    }
+
+    fn columns(&self, nodes: &[Node]) -> Result<Vec<FieldColumn>> {
+        let schema = self.schema()?;
+        let field = |name: &str| {
+            schema
+                .get_field(name)
+                .with_context(|| format!("Missing field {name} in schema"))
+        };
+
+        let mut ids = Vec::with_capacity(nodes.len());
+        let mut paths = Vec::with_capacity(nodes.len());
+        let mut chunks = Vec::with_capacity(nodes.len());
+        let mut metadata = Vec::with_capacity(nodes.len());
+        let mut vectors = Vec::with_capacity(nodes.len() * self.vector_size_usize());
+
+        for node in nodes {
+            let vector = node
+                .vectors
+                .as_ref()
+                .and_then(|vectors| vectors.get(&self.vector_field))
+                .with_context(|| format!("Node without a vector for {}", self.vector_field))?;
+
+            if vector.len() != self.vector_size_usize() {
+                anyhow::bail!(
+                    "Vector size mismatch for {} on node {}: expected {}, got {}",
+                    self.vector_field,
+                    node.path.display(),
+                    self.vector_size,
+                    vector.len()
+                );
+            }
+
+            let path = node.path.to_string_lossy().to_string();
+            let node_metadata = serde_json::to_vec(&node.metadata)?;
+            for (field, len) in [
+                (PATH_FIELD, path.len()),
+                (CHUNK_FIELD, node.chunk.len()),
+                (METADATA_FIELD, node_metadata.len()),
+            ] {
+                check_max_length(node, field, len)?;
+            }
+
+            ids.push(node.id().to_string());
+            paths.push(path);
+            chunks.push(node.chunk.clone());
+            metadata.push(node_metadata);
+            vectors.extend_from_slice(vector);
+        }
+
+        Ok(vec![
+            FieldColumn::new(field(ID_FIELD)?, ids),
+            FieldColumn::new(field(PATH_FIELD)?, paths),
+            FieldColumn::new(field(CHUNK_FIELD)?, chunks),
+            FieldColumn::new(field(METADATA_FIELD)?, ValueVec::Json(metadata)),
+            FieldColumn::new(field(VECTOR_FIELD)?, vectors),
+        ])
+    }
+
+    fn vector_size_usize(&self) -> usize {
+        usize::try_from(self.vector_size).unwrap_or_default()
+    }
+}
+
+/// Errors if a field is longer than Milvus accepts, instead of failing the whole batch on insert
+fn check_max_length(node: &Node, field: &str, len: usize) -> Result<()> {
+    if len > MAX_VARCHAR_LENGTH as usize {
+        anyhow::bail!(
+            "The {field} of node {} is {len} bytes, more than the {MAX_VARCHAR_LENGTH} Milvus \
+             accepts",
+            node.path.display()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::TryStreamExt as _;
+    use swiftide_core::indexing::EmbeddedField;
+    use testcontainers::{
+        core::{IntoContainerPort as _, WaitFor},
+        runners::AsyncRunner as _,
+        ContainerAsync, GenericImage, ImageExt as _,
+    };
+
+    use super::*;
+
+    async fn start_milvus() -> (ContainerAsync<GenericImage>, String) {
+        let container = GenericImage::new("milvusdb/milvus", "v2.4.13")
+            .with_exposed_port(19530.tcp())
+            .with_wait_for(WaitFor::message_on_stdout("Proxy successfully started"))
+            .with_env_var("ETCD_USE_EMBED", "true")
+            .with_env_var("ETCD_DATA_DIR", "/var/lib/milvus/etcd")
+            .with_env_var("COMMON_STORAGETYPE", "local")
+            .with_cmd(["milvus", "run", "standalone"])
+            .start()
+            .await
+            .expect("Milvus started");
+
+        let host = container.get_host().await.unwrap();
+        let port = container.get_host_port_ipv4(19530).await.unwrap();
+
+        (container, format!("http://{host}:{port}"))
+    }
+
+    #[tokio::test]
+    async fn test_setup_times_out_when_unresponsive() {
+        let (_server, address) = crate::timeout::unresponsive_server().await;
+        let milvus = Milvus::builder()
+            .url(format!("http://{address}"))
+            .vector_size(3)
+            .connect_timeout(std::time::Duration::from_millis(200))
+            .request_timeout(std::time::Duration::from_millis(200))
+            .build()
+            .unwrap();
+
+        let started = std::time::Instant::now();
+        let err = milvus.setup().await.unwrap_err();
+
+        // The connection is established, but the server never responds to requests
+        assert!(format!("{err:#}").contains("Timeout expired"), "{err:#}");
+        assert!(started.elapsed() < std::time::Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_existing_collection_must_match_vector_size_and_metric() {
+        use milvus::{
+            index::MetricType,
+            proto::{common::KeyValuePair, schema},
+        };
+
+        let schema = schema::CollectionSchema {
+            fields: vec![schema::FieldSchema {
+                name: VECTOR_FIELD.to_string(),
+                type_params: vec![KeyValuePair {
+                    key: "dim".to_string(),
+                    value: "3".to_string(),
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let milvus = |vector_size: i64| Milvus::builder().vector_size(vector_size).build().unwrap();
+
+        milvus(3)
+            .check_existing_collection(&schema, Some(MetricType::COSINE))
+            .unwrap();
+
+        let err = milvus(4)
+            .check_existing_collection(&schema, None)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Collection swiftide has vectors of size 3, but 4 is configured, drop it or use a \
+             different collection"
+        );
+
+        let err = milvus(3)
+            .check_existing_collection(&schema, Some(MetricType::L2))
+            .unwrap_err();
+        assert!(err.to_string().contains("the L2 metric"), "{err}");
+    }
+
+    #[test]
+    fn test_errors_on_fields_longer_than_milvus_accepts() {
+        let milvus = Milvus::builder().vector_size(3).build().unwrap();
+        let node = |chunk: String| {
+            let mut node = Node::new(chunk);
+            node.path = "doc.md".into();
+            node.with_vectors([(EmbeddedField::Combined, vec![1.0; 3])]);
+            node
+        };
+
+        assert!(milvus.columns(&[node("a".repeat(65_535))]).is_ok());
+
+        let err = milvus.columns(&[node("a".repeat(65_536))]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The chunk of node doc.md is 65536 bytes, more than the 65535 Milvus accepts"
+        );
+
+        let mut with_metadata = node("chunk".to_string());
+        with_metadata.metadata.insert("long", "a".repeat(65_535));
+        let err = milvus.columns(&[with_metadata]).unwrap_err();
+        assert!(
+            err.to_string().starts_with("The metadata of node doc.md"),
+            "{err}"
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_setup_errors_on_mismatching_collection() {
+        let (_container, url) = start_milvus().await;
+        let milvus = |vector_size: i64| {
+            Milvus::builder()
+                .url(&url)
+                .collection_name("mismatch")
+                .vector_size(vector_size)
+                .build()
+                .unwrap()
+        };
+
+        milvus(3).setup().await.unwrap();
+        milvus(3).setup().await.unwrap();
+
+        let err = milvus(4).setup().await.unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("has vectors of size 3, but 4 is configured"),
+            "{err}"
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_milvus_batch_persist() {
+        let (_container, url) = start_milvus().await;
+        let milvus = Milvus::builder()
+            .url(url)
+            .collection_name("swiftide_test")
+            .vector_size(4)
+            .build()
+            .unwrap();
+        milvus.setup().await.unwrap();
+
+        let nodes = (0..3_u8)
+            .map(|i| {
+                let mut node = Node::new(format!("chunk {i}"));
+                node.metadata.insert("index", i);
+                node.with_vectors([(EmbeddedField::Combined, vec![f32::from(i); 4])]);
+                node
+            })
+            .collect::<Vec<_>>();
+
+        let stored: Vec<Node> = milvus
+            .batch_store(nodes.clone())
+            .await
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(stored, nodes);
+        assert_eq!(milvus.count().await.unwrap(), 3);
+
+        // Storing the same nodes again replaces them
+        milvus
+            .batch_store(nodes.clone())
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(milvus.count().await.unwrap(), 3);
+    }
+}
@@ -2,7 +2,7 @@ use anyhow::{Context as _, Result};
 use async_trait::async_trait;
 
 use ollama_rs::generation::embeddings::request::GenerateEmbeddingsRequest;
-use swiftide_core::{EmbeddingModel, Embeddings};
+use swiftide_core::{util::debug_redacted, EmbeddingModel, Embeddings};
 
 use super::Ollama;
 
@@ -17,7 +17,7 @@ impl EmbeddingModel for Ollama {
 
         let request = GenerateEmbeddingsRequest::new(model.to_string(), input.into());
         tracing::debug!(
-            messages = serde_json::to_string_pretty(&request)?,
+            request = debug_redacted(&request),
             "[Embed] Request to ollama"
         );
         let response = self
@@ -26,7 +26,10 @@ impl EmbeddingModel for Ollama {
             .await
             .context("Request to Ollama Failed")?;
 
-        tracing::debug!("[Embed] Response ollama");
+        tracing::debug!(
+            num_embeddings = response.embeddings.len(),
+            "[Embed] Response ollama"
+        );
 
         Ok(response.embeddings)
     }
@@ -2,7 +2,7 @@
 //! It defines an asynchronous function to interact with the `Ollama` API, allowing prompt processing
 //! and generating responses as part of the Swiftide system.
 use async_trait::async_trait;
-use swiftide_core::{prompt::Prompt, SimplePrompt};
+use swiftide_core::{prompt::Prompt, util::debug_redacted, SimplePrompt};
 
 use super::Ollama;
 use anyhow::{Context as _, Result};
@@ -40,7 +40,7 @@ impl SimplePrompt for Ollama {
 
         // Log the request for debugging purposes.
         tracing::debug!(
-            messages = serde_json::to_string_pretty(&request)?,
+            request = debug_redacted(&request),
             "[SimplePrompt] Request to ollama"
         );
 
@@ -50,7 +50,7 @@ impl SimplePrompt for Ollama {
 
         // Log the response for debugging purposes.
         tracing::debug!(
-            response = serde_json::to_string_pretty(&response.response)?,
+            response = debug_redacted(&response),
             "[SimplePrompt] Response from ollama"
         );
 
@@ -2,10 +2,17 @@ use anyhow::{Context as _, Result};
 use async_openai::types::CreateEmbeddingRequestArgs;
 use async_trait::async_trait;
 
-use swiftide_core::{EmbeddingModel, Embeddings};
+use swiftide_core::{util::debug_redacted, EmbeddingModel, Embeddings};
 
 use super::OpenAI;
 
+/// Embedding models with a known input limit of 8191 tokens
+const EMBEDDING_MODELS_8191_TOKENS: [&str; 3] = [
+    "text-embedding-3-small",
+    "text-embedding-3-large",
+    "text-embedding-ada-002",
+];
+
 #[async_trait]
 impl EmbeddingModel for OpenAI {
     async fn embed(&self, input: Vec<String>) -> Result<Embeddings> {
@@ -22,6 +29,7 @@ impl EmbeddingModel for OpenAI {
         tracing::debug!(
             num_chunks = input.len(),
             model = &model,
+            request = debug_redacted(&request),
             "[Embed] Request to openai"
         );
         let response = self
@@ -37,4 +45,12 @@ impl EmbeddingModel for OpenAI {
         // WARN: Naively assumes that the order is preserved. Might not always be the case.
         Ok(response.data.into_iter().map(|d| d.embedding).collect())
     }
+
+    /// Known for the current `OpenAI` embedding models, which accept 8191 tokens per input
+    fn max_input_tokens(&self) -> Option<usize> {
+        let model = self.default_options.embed_model.as_deref()?;
+        EMBEDDING_MODELS_8191_TOKENS
+            .contains(&model)
+            .then_some(8191)
+    }
 }
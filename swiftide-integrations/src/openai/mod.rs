@@ -6,6 +6,7 @@ use derive_builder::Builder;
 use std::sync::Arc;
 
 mod embed;
+mod multimodal_prompt;
 mod simple_prompt;
 
 /// The `OpenAI` struct encapsulates an `OpenAI` client and default options for embedding and prompt models.
@@ -28,6 +29,19 @@ mod simple_prompt;
 ///     .default_prompt_model("gpt-4")
 ///     .client(async_openai::Client::with_config(async_openai::config::OpenAIConfig::default().with_api_key("my-api-key")))
 ///     .build().unwrap();
+///
+/// // Send the organization and project headers, i.e. for billing.
+/// let openai = OpenAI::builder()
+///     .organization("org-...")
+///     .project("proj_...")
+///     .default_prompt_model("gpt-4")
+///     .build().unwrap();
+///
+/// // Use any OpenAI compatible api, i.e. vLLM or LocalAI.
+/// let openai = OpenAI::builder()
+///     .base_url("http://localhost:8000/v1")
+///     .default_prompt_model("my-model")
+///     .build().unwrap();
 ///```
 #[derive(Debug, Builder, Clone)]
 #[builder(setter(into, strip_option))]
@@ -84,6 +98,62 @@ impl OpenAIBuilder {
         self
     }
 
+    /// Points the client at an `OpenAI` compatible api instead of `https://api.openai.com/v1`.
+    ///
+    /// The api key is still read from the `OPENAI_API_KEY` environment variable. Use
+    /// [`OpenAIBuilder::client`] to configure the key as well; whichever is set last is used.
+    ///
+    /// # Parameters
+    /// - `base_url`: The base url of the api, including the version, i.e. `http://localhost:8000/v1`.
+    ///
+    /// # Returns
+    /// A mutable reference to the `OpenAIBuilder`.
+    pub fn base_url(&mut self, base_url: impl Into<String>) -> &mut Self {
+        self.client(async_openai::Client::with_config(
+            async_openai::config::OpenAIConfig::default().with_api_base(base_url),
+        ))
+    }
+
+    /// Sets the `OpenAI-Organization` header on every request, i.e. for billing.
+    ///
+    /// Applies to the client configured so far, keeping its api key and base url. Set it after
+    /// [`OpenAIBuilder::client`] and [`OpenAIBuilder::base_url`], as those replace the client.
+    ///
+    /// # Parameters
+    /// - `organization`: The id of the organization, i.e. `org-...`.
+    ///
+    /// # Returns
+    /// A mutable reference to the `OpenAIBuilder`.
+    pub fn organization(&mut self, organization: impl Into<String>) -> &mut Self {
+        self.update_config(|config| config.with_org_id(organization))
+    }
+
+    /// Sets the `OpenAI-Project` header on every request, i.e. for billing.
+    ///
+    /// Applies to the client configured so far, like [`OpenAIBuilder::organization`].
+    ///
+    /// # Parameters
+    /// - `project`: The id of the project, i.e. `proj_...`.
+    ///
+    /// # Returns
+    /// A mutable reference to the `OpenAIBuilder`.
+    pub fn project(&mut self, project: impl Into<String>) -> &mut Self {
+        self.update_config(|config| config.with_project_id(project))
+    }
+
+    /// Replaces the client with one using the updated config of the current client
+    fn update_config(
+        &mut self,
+        update: impl FnOnce(async_openai::config::OpenAIConfig) -> async_openai::config::OpenAIConfig,
+    ) -> &mut Self {
+        let config = self
+            .client
+            .as_ref()
+            .map(|client| client.config().clone())
+            .unwrap_or_default();
+        self.client(async_openai::Client::with_config(update(config)))
+    }
+
     /// Sets the default embedding model for the `OpenAI` instance.
     ///
     /// # Parameters
@@ -125,6 +195,8 @@ impl OpenAIBuilder {
 
 #[cfg(test)]
 mod test {
+    use swiftide_core::{EmbeddingModel as _, SimplePrompt as _};
+
     use super::*;
 
     /// test default embed model
@@ -158,4 +230,71 @@ mod test {
             Some("gpt-3".to_string())
         );
     }
+
+    #[tokio::test]
+    async fn test_custom_base_url() {
+        let server = wiremock::MockServer::start().await;
+        swiftide_test_utils::mock_chat_completions(&server).await;
+        swiftide_test_utils::mock_embeddings(&server, 1).await;
+
+        let openai = OpenAI::builder()
+            .base_url(server.uri())
+            .default_embed_model("embed-model")
+            .default_prompt_model("prompt-model")
+            .build()
+            .unwrap();
+
+        openai.prompt("Hello".into()).await.unwrap();
+        openai.embed(vec!["Hello".to_string()]).await.unwrap();
+
+        let paths = server
+            .received_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|request| request.url.path().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["/chat/completions", "/embeddings"]);
+    }
+
+    #[tokio::test]
+    async fn test_organization_and_project_headers() {
+        let server = wiremock::MockServer::start().await;
+        swiftide_test_utils::mock_chat_completions(&server).await;
+
+        let with_headers = OpenAI::builder()
+            .base_url(server.uri())
+            .organization("org-swiftide")
+            .project("proj_indexing")
+            .default_prompt_model("prompt-model")
+            .build()
+            .unwrap();
+        let without_headers = OpenAI::builder()
+            .base_url(server.uri())
+            .default_prompt_model("prompt-model")
+            .build()
+            .unwrap();
+
+        with_headers.prompt("Hello".into()).await.unwrap();
+        without_headers.prompt("Hello".into()).await.unwrap();
+
+        let requests = server.received_requests().await.unwrap();
+        let header = |index: usize, name: &str| {
+            requests[index]
+                .headers
+                .get(name)
+                .map(|value| value.to_str().unwrap().to_string())
+        };
+
+        assert_eq!(
+            header(0, "OpenAI-Organization").as_deref(),
+            Some("org-swiftide")
+        );
+        assert_eq!(
+            header(0, "OpenAI-Project").as_deref(),
+            Some("proj_indexing")
+        );
+        assert_eq!(header(1, "OpenAI-Organization"), None);
+        assert_eq!(header(1, "OpenAI-Project"), None);
+    }
 }
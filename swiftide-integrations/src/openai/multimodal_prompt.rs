@@ -0,0 +1,120 @@
+//! This module provides an implementation of the `MultimodalPrompt` trait for the `OpenAI` struct,
+//! sending the image as a base64 data url alongside the prompt.
+use async_openai::types::{
+    ChatCompletionRequestMessageContentPartImage, ChatCompletionRequestMessageContentPartText,
+    ChatCompletionRequestUserMessageArgs, ChatCompletionRequestUserMessageContentPart,
+    CreateChatCompletionRequestArgs, ImageUrl,
+};
+use async_trait::async_trait;
+use swiftide_core::{indexing::Image, prompt::Prompt, util::debug_redacted, MultimodalPrompt};
+
+use super::OpenAI;
+use anyhow::{Context as _, Result};
+
+#[async_trait]
+impl MultimodalPrompt for OpenAI {
+    /// Sends the prompt with the image to the `OpenAI` API and returns the response content.
+    ///
+    /// Requires a prompt model that supports images, i.e. `gpt-4o-mini`.
+    ///
+    /// # Errors
+    /// - Returns an error if the model is not set in the default options.
+    /// - Returns an error if the image cannot be read.
+    /// - Returns an error if the request to the `OpenAI` API fails.
+    /// - Returns an error if the response does not contain the expected content.
+    #[tracing::instrument(skip_all, err)]
+    async fn prompt_with_image(&self, prompt: Prompt, image: &Image) -> Result<String> {
+        let model = self
+            .default_options
+            .prompt_model
+            .as_ref()
+            .context("Model not set")?;
+
+        let content = vec![
+            ChatCompletionRequestUserMessageContentPart::Text(
+                ChatCompletionRequestMessageContentPartText {
+                    text: prompt.render().await?,
+                },
+            ),
+            ChatCompletionRequestUserMessageContentPart::ImageUrl(
+                ChatCompletionRequestMessageContentPartImage {
+                    image_url: ImageUrl {
+                        url: image.to_data_url().await?,
+                        detail: None,
+                    },
+                },
+            ),
+        ];
+
+        let request = CreateChatCompletionRequestArgs::default()
+            .model(model)
+            .messages(vec![ChatCompletionRequestUserMessageArgs::default()
+                .content(content)
+                .build()?
+                .into()])
+            .build()?;
+
+        tracing::debug!(
+            model = model,
+            image = ?image,
+            "[MultimodalPrompt] Request to openai"
+        );
+
+        let response = self.client.chat().create(request).await?;
+
+        tracing::debug!(
+            response = debug_redacted(&response),
+            "[MultimodalPrompt] Response from openai"
+        );
+
+        response
+            .choices
+            .into_iter()
+            .next()
+            .context("Expected a choice in response")?
+            .message
+            .content
+            .context("Expected content in response")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_errors_on_response_without_choices() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1_677_652_288,
+                "model": "gpt-4o-mini",
+                "choices": []
+            })))
+            .mount(&server)
+            .await;
+
+        let openai = OpenAI::builder()
+            .base_url(server.uri())
+            .default_prompt_model("gpt-4o-mini")
+            .build()
+            .unwrap();
+        let image = Image::from_bytes("image/png", [0_u8; 4]);
+
+        let err = openai
+            .prompt_with_image("What is in the image?".into(), &image)
+            .await
+            .unwrap_err();
+
+        assert_eq!(err.to_string(), "Expected a choice in response");
+    }
+}
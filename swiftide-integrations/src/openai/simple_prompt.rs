@@ -1,9 +1,16 @@
 //! This module provides an implementation of the `SimplePrompt` trait for the `OpenAI` struct.
 //! It defines an asynchronous function to interact with the `OpenAI` API, allowing prompt processing
 //! and generating responses as part of the Swiftide system.
-use async_openai::types::{ChatCompletionRequestUserMessageArgs, CreateChatCompletionRequestArgs};
+use async_openai::types::{
+    ChatCompletionRequestUserMessageArgs, ChatCompletionTool, ChatCompletionToolType,
+    CreateChatCompletionRequestArgs, FunctionObject,
+};
 use async_trait::async_trait;
-use swiftide_core::{prompt::Prompt, util::debug_long_utf8, SimplePrompt};
+use swiftide_core::{
+    prompt::{Prompt, PromptResponse, ToolCall, ToolCallOrText, ToolSpec, Usage},
+    util::{debug_long_utf8, debug_redacted},
+    SimplePrompt,
+};
 
 use super::OpenAI;
 use anyhow::{Context as _, Result};
@@ -26,6 +33,13 @@ impl SimplePrompt for OpenAI {
     /// - Returns an error if the response does not contain the expected content.
     #[tracing::instrument(skip_all, err)]
     async fn prompt(&self, prompt: Prompt) -> Result<String> {
+        Ok(self.prompt_detailed(prompt).await?.text)
+    }
+
+    /// Sends a prompt to the OpenAI API and returns the response content, with the usage and the
+    /// model that answered.
+    #[tracing::instrument(skip_all, err)]
+    async fn prompt_detailed(&self, prompt: Prompt) -> Result<PromptResponse> {
         // Retrieve the model from the default options, returning an error if not set.
         let model = self
             .default_options
@@ -44,19 +58,21 @@ impl SimplePrompt for OpenAI {
 
         // Log the request for debugging purposes.
         tracing::debug!(
-            messages = debug_long_utf8(
-                serde_json::to_string_pretty(&request.messages.first())?,
-                100
-            ),
+            request = debug_long_utf8(debug_redacted(&request), 100),
             "[SimplePrompt] Request to openai"
         );
 
         // Send the request to the OpenAI API and await the response.
-        let response = self
-            .client
-            .chat()
-            .create(request)
-            .await?
+        let mut response = self.client.chat().create(request).await?;
+
+        // Log the raw response for debugging purposes.
+        tracing::debug!(
+            response = debug_long_utf8(debug_redacted(&response), 100),
+            "[SimplePrompt] Response from openai"
+        );
+
+        // Extract and return the content of the response, returning an error if not found.
+        let text = response
             .choices
             .remove(0)
             .message
@@ -64,13 +80,165 @@ impl SimplePrompt for OpenAI {
             .take()
             .context("Expected content in response")?;
 
-        // Log the response for debugging purposes.
+        Ok(PromptResponse {
+            text,
+            usage: response.usage.map(|usage| Usage {
+                prompt_tokens: usage.prompt_tokens,
+                completion_tokens: usage.completion_tokens,
+                total_tokens: usage.total_tokens,
+            }),
+            model: Some(response.model),
+        })
+    }
+
+    /// Sends a prompt with the tools as functions the model can call. Returns the first tool
+    /// call with its arguments parsed as JSON, or the content if the model did not call a tool.
+    #[tracing::instrument(skip_all, err)]
+    async fn prompt_with_tools(
+        &self,
+        prompt: Prompt,
+        tools: Vec<ToolSpec>,
+    ) -> Result<ToolCallOrText> {
+        let model = self
+            .default_options
+            .prompt_model
+            .as_ref()
+            .context("Model not set")?;
+
+        let tools = tools
+            .into_iter()
+            .map(|tool| ChatCompletionTool {
+                r#type: ChatCompletionToolType::Function,
+                function: FunctionObject {
+                    name: tool.name,
+                    description: Some(tool.description),
+                    parameters: Some(tool.parameters),
+                    strict: None,
+                },
+            })
+            .collect::<Vec<_>>();
+
+        let request = CreateChatCompletionRequestArgs::default()
+            .model(model)
+            .messages(vec![ChatCompletionRequestUserMessageArgs::default()
+                .content(prompt.render().await?)
+                .build()?
+                .into()])
+            .tools(tools)
+            .build()?;
+
         tracing::debug!(
-            response = debug_long_utf8(&response, 100),
-            "[SimplePrompt] Response from openai"
+            request = debug_long_utf8(debug_redacted(&request), 100),
+            "[SimplePrompt] Request with tools to openai"
         );
 
-        // Extract and return the content of the response, returning an error if not found.
-        Ok(response)
+        let mut response = self.client.chat().create(request).await?;
+
+        tracing::debug!(
+            response = debug_long_utf8(debug_redacted(&response), 100),
+            "[SimplePrompt] Response with tools from openai"
+        );
+
+        let message = response
+            .choices
+            .drain(..)
+            .next()
+            .context("Expected a choice in response")?
+            .message;
+
+        if let Some(tool_call) = message
+            .tool_calls
+            .and_then(|calls| calls.into_iter().next())
+        {
+            let arguments =
+                serde_json::from_str(&tool_call.function.arguments).with_context(|| {
+                    format!("Invalid arguments for tool {}", tool_call.function.name)
+                })?;
+            return Ok(ToolCallOrText::ToolCall(ToolCall {
+                name: tool_call.function.name,
+                arguments,
+            }));
+        }
+
+        message
+            .content
+            .map(ToolCallOrText::Text)
+            .context("Expected a tool call or content in response")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_prompt_with_tools_parses_tool_call() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/chat/completions"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1_677_652_288,
+                "model": "gpt-4o-mini",
+                "choices": [{
+                    "index": 0,
+                    "message": {
+                        "role": "assistant",
+                        "content": null,
+                        "tool_calls": [{
+                            "id": "call_1",
+                            "type": "function",
+                            "function": {
+                                "name": "split_document",
+                                "arguments": "{\"strategy\":\"markdown\",\"max_size\":512}"
+                            }
+                        }]
+                    },
+                    "finish_reason": "tool_calls"
+                }]
+            })))
+            .mount(&server)
+            .await;
+
+        let openai = OpenAI::builder()
+            .base_url(server.uri())
+            .default_prompt_model("gpt-4o-mini")
+            .build()
+            .unwrap();
+        let tool = ToolSpec::new(
+            "split_document",
+            "Splits the document into chunks",
+            json!({
+                "type": "object",
+                "properties": {
+                    "strategy": { "type": "string" },
+                    "max_size": { "type": "integer" }
+                }
+            }),
+        );
+
+        let answer = openai
+            .prompt_with_tools("How should I split this?".into(), vec![tool])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            answer,
+            ToolCallOrText::ToolCall(ToolCall {
+                name: "split_document".to_string(),
+                arguments: json!({ "strategy": "markdown", "max_size": 512 }),
+            })
+        );
+
+        let requests = server.received_requests().await.unwrap();
+        let request: serde_json::Value = requests[0].body_json().unwrap();
+        assert_eq!(request["tools"][0]["function"]["name"], "split_document");
     }
 }
@@ -67,7 +67,7 @@ impl Loader for Parquet {
             IndexingStream::iter(node_values)
         });
 
-        swiftide_stream.boxed().into()
+        IndexingStream::from_stream(swiftide_stream).with_metadata(self.metadata)
 
         // let mask = ProjectionMask::
     }
@@ -102,4 +102,24 @@ mod tests {
         let expected = [Node::new("hello"), Node::new("world")];
         assert_eq!(result, expected);
     }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn test_tags_every_node_with_metadata() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("src/parquet/test.parquet");
+
+        let loader = Parquet::builder()
+            .path(path)
+            .column_name("chunk")
+            .metadata([("source", "parquet")])
+            .build()
+            .unwrap();
+
+        let result = loader.into_stream().try_collect::<Vec<_>>().await.unwrap();
+
+        assert_eq!(result.len(), 2);
+        assert!(result
+            .iter()
+            .all(|node| node.metadata.get("source").unwrap() == "parquet"));
+    }
 }
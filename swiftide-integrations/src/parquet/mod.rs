@@ -2,6 +2,7 @@
 use std::path::PathBuf;
 
 use derive_builder::Builder;
+use swiftide_core::indexing::Metadata;
 
 pub mod loader;
 
@@ -21,6 +22,9 @@ pub struct Parquet {
     column_name: String,
     #[builder(default = "1024")]
     batch_size: usize,
+    /// Static metadata added to every node, i.e. to tag nodes with their source
+    #[builder(default)]
+    metadata: Metadata,
 }
 
 impl Parquet {
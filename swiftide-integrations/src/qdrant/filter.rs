@@ -0,0 +1,95 @@
+//! A simple filter spec over metadata, translated into a Qdrant `Filter`
+use std::ops::{Bound, RangeBounds};
+
+use qdrant_client::qdrant::{self, r#match::MatchValue, Condition};
+use swiftide_core::querying::search_strategies::SearchFilter;
+
+/// Filters stored nodes on their metadata
+///
+/// All conditions must match. Metadata is stored as is in the Qdrant payload, so keys are the
+/// metadata keys of the nodes.
+///
+/// Can be used with [`SimilaritySingleEmbedding`], or converted into a [`qdrant::Filter`] for
+/// anything more advanced.
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_core::querying::search_strategies::SimilaritySingleEmbedding;
+/// # use swiftide_integrations::qdrant::MetadataFilter;
+/// let search_strategy = SimilaritySingleEmbedding::from_filter(
+///     MetadataFilter::default()
+///         .eq("tenant", "acme".to_string())
+///         .range("year", 2020.0..),
+/// );
+/// ```
+///
+/// [`SimilaritySingleEmbedding`]: swiftide_core::querying::search_strategies::SimilaritySingleEmbedding
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetadataFilter {
+    conditions: Vec<Condition>,
+}
+
+impl MetadataFilter {
+    /// Requires the metadata value of `key` to equal `value`
+    #[must_use]
+    pub fn eq(mut self, key: impl Into<String>, value: impl Into<MatchValue>) -> Self {
+        self.conditions.push(Condition::matches(key, value));
+        self
+    }
+
+    /// Requires the numeric metadata value of `key` to be within `range`
+    #[must_use]
+    pub fn range(mut self, key: impl Into<String>, range: impl RangeBounds<f64>) -> Self {
+        let mut qdrant_range = qdrant::Range::default();
+        match range.start_bound() {
+            Bound::Included(start) => qdrant_range.gte = Some(*start),
+            Bound::Excluded(start) => qdrant_range.gt = Some(*start),
+            Bound::Unbounded => {}
+        }
+        match range.end_bound() {
+            Bound::Included(end) => qdrant_range.lte = Some(*end),
+            Bound::Excluded(end) => qdrant_range.lt = Some(*end),
+            Bound::Unbounded => {}
+        }
+
+        self.conditions.push(Condition::range(key, qdrant_range));
+        self
+    }
+}
+
+impl SearchFilter for MetadataFilter {}
+
+impl From<MetadataFilter> for qdrant::Filter {
+    fn from(filter: MetadataFilter) -> Self {
+        qdrant::Filter::must(filter.conditions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translates_into_qdrant_filter() {
+        let filter: qdrant::Filter = MetadataFilter::default()
+            .eq("tenant", "acme".to_string())
+            .range("year", 2020.0..2024.0)
+            .into();
+
+        assert_eq!(
+            filter,
+            qdrant::Filter::must([
+                Condition::matches("tenant", "acme".to_string()),
+                Condition::range(
+                    "year",
+                    qdrant::Range {
+                        gte: Some(2020.0),
+                        lt: Some(2024.0),
+                        ..Default::default()
+                    }
+                ),
+            ])
+        );
+    }
+}
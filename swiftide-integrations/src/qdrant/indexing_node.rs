@@ -35,7 +35,7 @@ impl TryInto<qdrant::PointStruct> for NodeWithVectors<'_> {
     fn try_into(self) -> Result<qdrant::PointStruct> {
         let node = self.node;
         // Calculate a unique identifier for the node.
-        let id = node.id();
+        let id = (self.id_fn)(node);
 
         // Extend the metadata with additional information.
         // TODO: The node is already cloned in the `NodeWithVectors` constructor.
@@ -43,19 +43,27 @@ impl TryInto<qdrant::PointStruct> for NodeWithVectors<'_> {
         // Data is then taken as ref and reassigned. Seems like a lot of needless allocations
 
         // Create a payload compatible with Qdrant's API.
+        let all_metadata = self.includes_payload_field("metadata");
         let mut payload: Payload = node
             .metadata
             .iter()
+            .filter(|(k, _)| all_metadata || self.includes_payload_field(&format!("metadata.{k}")))
             .map(|(k, v)| (k.clone(), Value::from(v.clone())))
             .collect::<HashMap<String, Value>>()
             .into();
 
-        payload.insert("path", node.path.to_string_lossy().to_string());
-        payload.insert("content", node.chunk.clone());
-        payload.insert(
-            "last_updated_at",
-            Value::from(chrono::Utc::now().to_rfc3339()),
-        );
+        if self.includes_payload_field("path") {
+            payload.insert("path", node.path.to_string_lossy().to_string());
+        }
+        if self.includes_payload_field("chunk") {
+            payload.insert("content", node.chunk.clone());
+        }
+        if self.includes_payload_field("last_updated_at") {
+            payload.insert(
+                "last_updated_at",
+                Value::from(chrono::Utc::now().to_rfc3339()),
+            );
+        }
 
         let Some(vectors) = node.vectors.clone() else {
             bail!("Node without vectors")
@@ -218,4 +226,30 @@ mod tests {
 
         assert_eq!(point, expected_point);
     }
+
+    #[test]
+    fn try_into_point_struct_with_payload_fields() {
+        let node = Node {
+            path: "/path".into(),
+            chunk: "data".into(),
+            vectors: Some(HashMap::from([(EmbeddedField::Combined, vec![1.0])])),
+            metadata: Metadata::from([("m1", "mv1"), ("m2", "mv2")]),
+            ..Default::default()
+        };
+        let vector_fields = HashSet::from([EmbeddedField::Combined]);
+        let payload_fields = HashSet::from(["path".to_string(), "metadata.m1".to_string()]);
+
+        let point: PointStruct = NodeWithVectors::new(&node, vector_fields.iter().collect())
+            .with_payload_fields(Some(&payload_fields))
+            .try_into()
+            .expect("Can create PointStruct");
+
+        assert_eq!(
+            point.payload,
+            HashMap::from([
+                ("path".into(), Value::from("/path")),
+                ("m1".into(), Value::from("mv1"))
+            ])
+        );
+    }
 }
@@ -4,22 +4,26 @@
 //!
 //! Qdrant can be used both in `indexing::Pipeline` and `query::Pipeline`
 
+mod filter;
 mod indexing_node;
 mod persist;
 mod retrieve;
 use std::collections::{HashMap, HashSet};
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use anyhow::{bail, Context as _, Result};
 use derive_builder::Builder;
 use qdrant_client::qdrant::{self, SparseVectorParamsBuilder, SparseVectorsConfigBuilder};
 
-use swiftide_core::indexing::{EmbeddedField, Node};
+use swiftide_core::indexing::{EmbeddedField, Node, WriteMode};
+
+pub use filter::MetadataFilter;
 
 const DEFAULT_COLLECTION_NAME: &str = "swiftide";
 const DEFAULT_QDRANT_URL: &str = "http://localhost:6334";
-const DEFAULT_BATCH_SIZE: usize = 50;
+/// The default number of nodes stored per batch
+pub const DEFAULT_BATCH_SIZE: usize = 50;
 
 /// A struct representing a Qdrant client with configuration options.
 ///
@@ -38,7 +42,15 @@ pub struct Qdrant {
     ///
     /// By default the client will be build from `QDRANT_URL` and option `QDRANT_API_KEY`.
     /// It will fall back to `http://localhost:6334` if `QDRANT_URL` is not set.
-    #[builder(setter(into), default = "self.default_client()?")]
+    ///
+    /// If timeouts are configured, the client is rebuilt from its config with the timeouts.
+    #[builder(
+        setter(custom),
+        field(
+            ty = "Option<Arc<qdrant_client::Qdrant>>",
+            build = "self.build_client()?"
+        )
+    )]
     #[allow(clippy::missing_fields_in_debug)]
     client: Arc<qdrant_client::Qdrant>,
     /// The name of the collection to be used in Qdrant. Defaults to "swiftide".
@@ -46,17 +58,73 @@ pub struct Qdrant {
     #[builder(setter(into))]
     collection_name: String,
     /// The default size of the vectors to be stored in the collection.
-    vector_size: u64,
+    ///
+    /// If neither this nor the vector configs set a size, creating the collection is deferred
+    /// until the first nodes are stored, and the size is inferred from their vectors.
+    #[builder(default)]
+    vector_size: Option<u64>,
     #[builder(default = "Distance::Cosine")]
     /// The default distance of the vectors to be stored in the collection
     vector_distance: Distance,
-    /// The batch size for operations. Optional.
+    /// The precision vectors are stored with. Defaults to [`Precision::F32`].
+    ///
+    /// With [`Precision::F16`] the collection is created with half precision vectors, halving
+    /// the storage needed. Vectors are still sent as `f32` and converted by Qdrant on store.
+    #[builder(default)]
+    vector_precision: Precision,
+    /// The number of nodes stored per batch. Defaults to [`DEFAULT_BATCH_SIZE`].
+    ///
+    /// See [`QdrantBuilder::without_batching`] to store nodes one by one.
     #[builder(default = "Some(DEFAULT_BATCH_SIZE)")]
     batch_size: Option<usize>,
+    /// Caps batches by the serialized size of their nodes, i.e. to stay below the request size
+    /// limit of Qdrant. Optional.
+    #[builder(default)]
+    batch_max_bytes: Option<usize>,
+    /// Fails connecting if it takes longer, i.e. when Qdrant is unreachable. Defaults to the
+    /// connect timeout of the client.
+    #[builder(default)]
+    connect_timeout: Option<Duration>,
+    /// Fails a request to Qdrant, i.e. storing a batch or retrieving, if it takes longer.
+    /// Defaults to the timeout of the client.
+    #[builder(default)]
+    request_timeout: Option<Duration>,
+    /// Whether upserts wait until the points are applied before returning. Defaults to `false`.
+    ///
+    /// Waiting lowers throughput, but guarantees that stored nodes are immediately visible to
+    /// searches and counts, i.e. when querying right after indexing.
+    #[builder(default)]
+    wait: bool,
     #[builder(private, default = "Self::default_vectors()")]
     pub(crate) vectors: HashMap<EmbeddedField, VectorConfig>,
     #[builder(private, default)]
     pub(crate) sparse_vectors: HashMap<EmbeddedField, SparseVectorConfig>,
+    /// Whether to keep or recreate an existing collection on setup. Defaults to append.
+    #[builder(default)]
+    write_mode: WriteMode,
+    /// Restricts the fields stored in the payload. Stores everything by default.
+    ///
+    /// See [`QdrantBuilder::payload_fields`]
+    #[builder(setter(custom), default)]
+    payload_fields: Option<HashSet<String>>,
+    /// Vector sizes inferred from the first stored nodes, when not configured
+    #[builder(setter(skip), default)]
+    inferred_vector_sizes: Arc<tokio::sync::OnceCell<HashMap<EmbeddedField, u64>>>,
+    /// Customize the id of stored points. Defaults to [`Node::id`].
+    ///
+    /// Use [`deterministic_point_id`] to ignore ids set explicitly on nodes. Changing the id of
+    /// an existing collection stores every node again as a new point.
+    #[builder(default = "Node::id")]
+    id_fn: fn(&Node) -> uuid::Uuid,
+}
+
+/// Derives a stable point id as a UUID (v5) of the path and chunk of a node
+///
+/// Unlike [`Node::id`], this ignores an explicitly set id on the node.
+pub fn deterministic_point_id(node: &Node) -> uuid::Uuid {
+    let path = node.path.to_string_lossy();
+    let bytes = [path.as_bytes(), &[0], node.chunk.as_bytes()].concat();
+    uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, &bytes)
 }
 
 impl Qdrant {
@@ -93,22 +161,45 @@ impl Qdrant {
     /// This method checks if the specified collection exists in Qdrant. If it does not exist, it creates a new collection
     /// with the specified vector size and cosine distance metric.
     ///
+    /// An existing collection is checked against the configured vectors, unless it is recreated
+    /// with [`WriteMode::Recreate`].
+    ///
     /// # Returns
     ///
     /// A `Result` indicating success or failure.
     ///
     /// # Errors
     ///
-    /// Errors if client fails build
+    /// Errors if client fails build, or if an existing collection has vectors of a different size
+    /// or distance
     pub async fn create_index_if_not_exists(&self) -> Result<()> {
         tracing::info!("Checking if collection {} exists", &self.collection_name);
 
         if self.client.collection_exists(&self.collection_name).await? {
-            tracing::warn!("Collection {} exists", &self.collection_name);
+            if self.write_mode == WriteMode::Append {
+                tracing::warn!("Collection {} exists", &self.collection_name);
+                return self.check_existing_collection(&HashMap::default()).await;
+            }
+
+            tracing::warn!("Recreating collection {}", &self.collection_name);
+            self.client
+                .delete_collection(self.collection_name.clone())
+                .await?;
+        }
+
+        if self.requires_inferred_vector_sizes() {
+            tracing::info!(
+                "No vector size configured, collection {} is created on first store",
+                &self.collection_name
+            );
             return Ok(());
         }
 
-        let vectors_config = self.create_vectors_config()?;
+        self.create_collection(&HashMap::default()).await
+    }
+
+    async fn create_collection(&self, inferred: &HashMap<EmbeddedField, u64>) -> Result<()> {
+        let vectors_config = self.create_vectors_config(inferred)?;
         tracing::debug!(?vectors_config, "Adding vectors config");
 
         let mut collection = qdrant::CreateCollectionBuilder::new(self.collection_name.clone())
@@ -124,7 +215,132 @@ impl Qdrant {
         Ok(())
     }
 
-    fn create_vectors_config(&self) -> Result<qdrant_client::qdrant::vectors_config::Config> {
+    /// Creates the collection with vector sizes inferred from the nodes if no size was
+    /// configured, and validates nodes against the inferred sizes.
+    ///
+    /// # Errors
+    ///
+    /// Errors if a size cannot be inferred, or if a node's vector does not match the inferred
+    /// size.
+    pub(crate) async fn create_index_from_nodes(&self, nodes: &[Node]) -> Result<()> {
+        if !self.requires_inferred_vector_sizes() {
+            return Ok(());
+        }
+
+        let sizes = self
+            .inferred_vector_sizes
+            .get_or_try_init(|| async {
+                let sizes = self.infer_vector_sizes(nodes)?;
+                tracing::info!(?sizes, "Inferred vector sizes, creating collection");
+
+                if self.client.collection_exists(&self.collection_name).await? {
+                    self.check_existing_collection(&sizes).await?;
+                } else {
+                    self.create_collection(&sizes).await?;
+                }
+                Ok::<_, anyhow::Error>(sizes)
+            })
+            .await?;
+
+        validate_vector_sizes(sizes, nodes)
+    }
+
+    /// Checks that the existing collection has the configured vectors, with the same sizes and
+    /// distances, instead of storing nodes in a collection of a different shape
+    async fn check_existing_collection(
+        &self,
+        inferred: &HashMap<EmbeddedField, u64>,
+    ) -> Result<()> {
+        let info = self.client.collection_info(&self.collection_name).await?;
+        let existing = info
+            .result
+            .and_then(|info| info.config?.params?.vectors_config?.config)
+            .with_context(|| format!("Collection {} has no vectors", self.collection_name))?;
+
+        self.check_vectors_config(&existing, inferred)
+    }
+
+    fn check_vectors_config(
+        &self,
+        existing: &qdrant::vectors_config::Config,
+        inferred: &HashMap<EmbeddedField, u64>,
+    ) -> Result<()> {
+        let mismatch = |detail: String| {
+            anyhow::anyhow!(
+                "Collection {} {detail}, recreate it with `WriteMode::Recreate` to migrate",
+                self.collection_name
+            )
+        };
+
+        // A single vector without sparse vectors is created unnamed
+        let unnamed = self.vectors.len() == 1 && self.sparse_vectors.is_empty();
+        let existing = match existing {
+            qdrant::vectors_config::Config::Params(params) => HashMap::from([(None, params)]),
+            qdrant::vectors_config::Config::ParamsMap(map) => map
+                .map
+                .iter()
+                .map(|(name, params)| (Some(name.as_str()), params))
+                .collect(),
+        };
+
+        for config in self.vectors.values() {
+            let name = config.embedded_field.to_string();
+            let params = existing
+                .get(&(!unnamed).then_some(name.as_str()))
+                .ok_or_else(|| mismatch(format!("has no vector for {name}")))?;
+
+            let size = config
+                .vector_size
+                .or(self.vector_size)
+                .or_else(|| inferred.get(&config.embedded_field).copied());
+            if let Some(size) = size.filter(|size| *size != params.size) {
+                return Err(mismatch(format!(
+                    "has vectors of size {} for {name}, but {size} is configured",
+                    params.size
+                )));
+            }
+
+            let distance = config.distance.unwrap_or(self.vector_distance);
+            if params.distance != distance as i32 {
+                let existing = Distance::try_from(params.distance)
+                    .map_or("Unknown", |distance| distance.as_str_name());
+                return Err(mismatch(format!(
+                    "has {existing} distance for {name}, but {} is configured",
+                    distance.as_str_name()
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn requires_inferred_vector_sizes(&self) -> bool {
+        self.vector_size.is_none() && self.vectors.values().any(|c| c.vector_size.is_none())
+    }
+
+    fn infer_vector_sizes(&self, nodes: &[Node]) -> Result<HashMap<EmbeddedField, u64>> {
+        self.vectors
+            .values()
+            .filter(|config| config.vector_size.is_none())
+            .map(|config| {
+                let field = &config.embedded_field;
+                let size = nodes
+                    .iter()
+                    .find_map(|node| node.vectors.as_ref()?.get(field))
+                    .map(|vector| vector.len() as u64)
+                    .with_context(|| {
+                        format!("Cannot infer vector size for {field}, no node has this vector")
+                    })?;
+
+                Ok((field.clone(), size))
+            })
+            .collect()
+    }
+
+    fn create_vectors_config(
+        &self,
+        inferred: &HashMap<EmbeddedField, u64>,
+    ) -> Result<qdrant_client::qdrant::vectors_config::Config> {
         if self.vectors.is_empty() {
             bail!("No configured vectors");
         } else if self.vectors.len() == 1 && self.sparse_vectors.is_empty() {
@@ -133,13 +349,13 @@ impl Qdrant {
                 .values()
                 .next()
                 .context("Has one vector config")?;
-            let vector_params = self.create_vector_params(config);
+            let vector_params = self.create_vector_params(config, inferred)?;
             return Ok(qdrant::vectors_config::Config::Params(vector_params));
         }
         let mut map = HashMap::<String, qdrant::VectorParams>::default();
         for (embedded_field, config) in &self.vectors {
             let vector_name = embedded_field.to_string();
-            let vector_params = self.create_vector_params(config);
+            let vector_params = self.create_vector_params(config, inferred)?;
 
             map.insert(vector_name, vector_params);
         }
@@ -163,11 +379,24 @@ impl Qdrant {
         Some(sparse_vectors_config.into())
     }
 
-    fn create_vector_params(&self, config: &VectorConfig) -> qdrant::VectorParams {
-        let size = config.vector_size.unwrap_or(self.vector_size);
+    fn create_vector_params(
+        &self,
+        config: &VectorConfig,
+        inferred: &HashMap<EmbeddedField, u64>,
+    ) -> Result<qdrant::VectorParams> {
+        let size = config
+            .vector_size
+            .or(self.vector_size)
+            .or_else(|| inferred.get(&config.embedded_field).copied())
+            .with_context(|| format!("No vector size for {}", config.embedded_field))?;
         let distance = config.distance.unwrap_or(self.vector_distance);
 
-        qdrant::VectorParamsBuilder::new(size, distance).build()
+        let mut params = qdrant::VectorParamsBuilder::new(size, distance);
+        if self.vector_precision == Precision::F16 {
+            params = params.datatype(qdrant::Datatype::Float16);
+        }
+
+        Ok(params.build())
     }
 
     /// Returns the inner client for custom operations
@@ -176,7 +405,64 @@ impl Qdrant {
     }
 }
 
+fn validate_vector_sizes(sizes: &HashMap<EmbeddedField, u64>, nodes: &[Node]) -> Result<()> {
+    for node in nodes {
+        let Some(vectors) = &node.vectors else {
+            continue;
+        };
+        for (field, expected) in sizes {
+            if let Some(vector) = vectors.get(field) {
+                if vector.len() as u64 != *expected {
+                    bail!(
+                        "Vector size mismatch for {field} on node {}: expected {expected}, got {}",
+                        node.path.display(),
+                        vector.len()
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
 impl QdrantBuilder {
+    /// The Qdrant client used to interact with the Qdrant vector database
+    #[must_use]
+    pub fn client(mut self, client: impl Into<Arc<qdrant_client::Qdrant>>) -> Self {
+        self.client = Some(client.into());
+        self
+    }
+
+    /// Builds the configured or default client, with the configured timeouts
+    fn build_client(&self) -> Result<Arc<qdrant_client::Qdrant>> {
+        let client = match &self.client {
+            Some(client) => Arc::clone(client),
+            None => self.default_client()?,
+        };
+
+        let connect_timeout = self.connect_timeout.flatten();
+        let request_timeout = self.request_timeout.flatten();
+        if connect_timeout.is_none() && request_timeout.is_none() {
+            return Ok(client);
+        }
+
+        // The config is not `Clone`, so it is copied field by field
+        let config = qdrant_client::config::QdrantConfig {
+            uri: client.config.uri.clone(),
+            timeout: request_timeout.unwrap_or(client.config.timeout),
+            connect_timeout: connect_timeout.unwrap_or(client.config.connect_timeout),
+            keep_alive_while_idle: client.config.keep_alive_while_idle,
+            api_key: client.config.api_key.clone(),
+            compression: client.config.compression,
+        };
+
+        Ok(Arc::new(
+            config
+                .build()
+                .context("Could not build qdrant client with timeouts")?,
+        ))
+    }
+
     #[allow(clippy::unused_self)]
     fn default_client(&self) -> Result<Arc<qdrant_client::Qdrant>> {
         let client = qdrant_client::Qdrant::from_url(
@@ -189,6 +475,21 @@ impl QdrantBuilder {
         Ok(Arc::new(client))
     }
 
+    /// Only store the given fields in the payload
+    ///
+    /// Valid fields are `path`, `chunk`, `last_updated_at`, `metadata` for all metadata, and
+    /// `metadata.<key>` for a single metadata field. By default all fields are stored.
+    ///
+    /// Note that retrieving from Qdrant requires the `chunk` to be stored.
+    #[must_use]
+    pub fn payload_fields(
+        mut self,
+        fields: impl IntoIterator<Item = impl Into<String>>,
+    ) -> QdrantBuilder {
+        self.payload_fields = Some(Some(fields.into_iter().map(Into::into).collect()));
+        self
+    }
+
     /// Configures a dense vector on the collection
     ///
     /// When not configured Pipeline by default configures vector only for [`EmbeddedField::Combined`]
@@ -229,6 +530,13 @@ impl QdrantBuilder {
         self
     }
 
+    /// Stores nodes one by one instead of in batches, overriding the default batch size
+    #[must_use]
+    pub fn without_batching(mut self) -> Self {
+        self.batch_size = Some(None);
+        self
+    }
+
     fn default_vectors() -> HashMap<EmbeddedField, VectorConfig> {
         HashMap::from([(EmbeddedField::default(), VectorConfig::default())])
     }
@@ -241,6 +549,10 @@ impl std::fmt::Debug for Qdrant {
             .field("collection_name", &self.collection_name)
             .field("vector_size", &self.vector_size)
             .field("batch_size", &self.batch_size)
+            .field("batch_max_bytes", &self.batch_max_bytes)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("request_timeout", &self.request_timeout)
+            .field("wait", &self.wait)
             .finish()
     }
 }
@@ -296,10 +608,24 @@ impl From<EmbeddedField> for SparseVectorConfig {
 
 pub type Distance = qdrant::Distance;
 
+/// The precision of stored dense vectors
+///
+/// See [`QdrantBuilder::vector_precision`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Precision {
+    /// Full precision, 4 bytes per dimension
+    #[default]
+    F32,
+    /// Half precision, 2 bytes per dimension
+    F16,
+}
+
 /// Utility struct combining `Node` with `EmbeddedField`s of configured _Qdrant_ vectors.
 struct NodeWithVectors<'a> {
     node: &'a Node,
     vector_fields: HashSet<&'a EmbeddedField>,
+    payload_fields: Option<&'a HashSet<String>>,
+    id_fn: fn(&Node) -> uuid::Uuid,
 }
 
 impl<'a> NodeWithVectors<'a> {
@@ -307,6 +633,179 @@ impl<'a> NodeWithVectors<'a> {
         Self {
             node,
             vector_fields,
+            payload_fields: None,
+            id_fn: Node::id,
         }
     }
+
+    pub fn with_id_fn(mut self, id_fn: fn(&Node) -> uuid::Uuid) -> Self {
+        self.id_fn = id_fn;
+        self
+    }
+
+    pub fn with_payload_fields(mut self, payload_fields: Option<&'a HashSet<String>>) -> Self {
+        self.payload_fields = payload_fields;
+        self
+    }
+
+    fn includes_payload_field(&self, field: &str) -> bool {
+        self.payload_fields
+            .is_none_or(|fields| fields.contains(field))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_point_ids_default_to_node_id() {
+        let client = || {
+            qdrant_client::Qdrant::from_url("http://localhost:6334")
+                .build()
+                .unwrap()
+        };
+        let mut node = Node::new("chunk");
+        node.id = Some(uuid::Uuid::new_v4());
+
+        let qdrant = Qdrant::builder().client(client()).build().unwrap();
+        assert_eq!((qdrant.id_fn)(&node), node.id());
+
+        let qdrant = Qdrant::builder()
+            .client(client())
+            .id_fn(deterministic_point_id)
+            .build()
+            .unwrap();
+        assert_eq!((qdrant.id_fn)(&node), deterministic_point_id(&node));
+    }
+
+    #[test]
+    fn test_deterministic_point_id() {
+        let node = Node::new("chunk");
+        let mut with_id = node.clone();
+        with_id.id = Some(uuid::Uuid::new_v4());
+
+        assert_eq!(
+            deterministic_point_id(&node),
+            deterministic_point_id(&with_id)
+        );
+        assert_eq!(
+            deterministic_point_id(&node).get_version(),
+            Some(uuid::Version::Sha1)
+        );
+        assert_ne!(
+            deterministic_point_id(&node),
+            deterministic_point_id(&Node::new("other"))
+        );
+    }
+
+    #[test]
+    fn test_batch_size_defaults_and_can_be_disabled() {
+        use swiftide_core::Persist as _;
+
+        let batch_size = |builder: QdrantBuilder| builder.build().unwrap().batch_size();
+
+        assert_eq!(batch_size(Qdrant::builder()), Some(DEFAULT_BATCH_SIZE));
+        assert_eq!(batch_size(Qdrant::builder().batch_size(10)), Some(10));
+        assert_eq!(batch_size(Qdrant::builder().without_batching()), None);
+    }
+
+    fn node_with_vector(size: usize) -> Node {
+        let mut node = Node {
+            path: "test".into(),
+            ..Node::new("chunk")
+        };
+        node.with_vectors([(EmbeddedField::Combined, vec![1.0; size])]);
+        node
+    }
+
+    #[test]
+    fn test_infers_vector_size_from_nodes() {
+        let qdrant = Qdrant::builder()
+            .client(
+                qdrant_client::Qdrant::from_url("http://localhost:6334")
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        assert!(qdrant.requires_inferred_vector_sizes());
+
+        let nodes = vec![node_with_vector(3), node_with_vector(3)];
+        let sizes = qdrant.infer_vector_sizes(&nodes).unwrap();
+
+        assert_eq!(sizes, HashMap::from([(EmbeddedField::Combined, 3)]));
+        assert!(validate_vector_sizes(&sizes, &nodes).is_ok());
+    }
+
+    #[test]
+    fn test_f16_precision_sets_datatype() {
+        let builder = || {
+            Qdrant::builder().client(
+                qdrant_client::Qdrant::from_url("http://localhost:6334")
+                    .build()
+                    .unwrap(),
+            )
+        };
+        let config = VectorConfig::default();
+        let sizes = HashMap::from([(EmbeddedField::Combined, 3)]);
+
+        let f32_params = builder()
+            .build()
+            .unwrap()
+            .create_vector_params(&config, &sizes)
+            .unwrap();
+        assert_eq!(f32_params.datatype, None);
+
+        let f16_params = builder()
+            .vector_precision(Precision::F16)
+            .build()
+            .unwrap()
+            .create_vector_params(&config, &sizes)
+            .unwrap();
+        assert_eq!(f16_params.datatype, Some(qdrant::Datatype::Float16.into()));
+    }
+
+    #[test]
+    fn test_existing_collection_must_match_configured_vectors() {
+        let existing = qdrant::vectors_config::Config::Params(
+            qdrant::VectorParamsBuilder::new(3, Distance::Cosine).build(),
+        );
+        let qdrant = |size: u64, distance: Distance| {
+            Qdrant::builder()
+                .vector_size(size)
+                .vector_distance(distance)
+                .build()
+                .unwrap()
+        };
+
+        qdrant(3, Distance::Cosine)
+            .check_vectors_config(&existing, &HashMap::default())
+            .unwrap();
+
+        let err = qdrant(4, Distance::Cosine)
+            .check_vectors_config(&existing, &HashMap::default())
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Collection swiftide has vectors of size 3 for Combined, but 4 is configured, \
+             recreate it with `WriteMode::Recreate` to migrate"
+        );
+
+        let err = qdrant(3, Distance::Dot)
+            .check_vectors_config(&existing, &HashMap::default())
+            .unwrap_err();
+        assert!(err.to_string().contains("has Cosine distance"), "{err}");
+    }
+
+    #[test]
+    fn test_errors_on_vector_size_mismatch() {
+        let sizes = HashMap::from([(EmbeddedField::Combined, 3)]);
+        let err = validate_vector_sizes(&sizes, &[node_with_vector(4)]).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Vector size mismatch for Combined on node test: expected 3, got 4"
+        );
+    }
 }
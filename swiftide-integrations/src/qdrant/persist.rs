@@ -8,9 +8,17 @@ use swiftide_core::{
     prelude::*,
 };
 
-use qdrant_client::qdrant::UpsertPointsBuilder;
+use std::sync::Arc;
 
-use super::{NodeWithVectors, Qdrant};
+use futures_util::stream;
+use qdrant_client::{
+    qdrant::{
+        CountPointsBuilder, GetPointsBuilder, PointId, ScrollPointsBuilder, UpsertPointsBuilder,
+    },
+    Payload,
+};
+
+use super::{NodeWithVectors, Qdrant, DEFAULT_BATCH_SIZE};
 
 #[async_trait]
 impl Persist for Qdrant {
@@ -23,6 +31,11 @@ impl Persist for Qdrant {
         self.batch_size
     }
 
+    /// Returns the maximum serialized size of a batch, if set.
+    fn batch_max_bytes(&self) -> Option<usize> {
+        self.batch_max_bytes
+    }
+
     /// Sets up the Qdrant storage by creating the necessary index if it does not exist.
     ///
     /// # Returns
@@ -40,7 +53,7 @@ impl Persist for Qdrant {
 
     /// Stores a single indexing node in the Qdrant storage.
     ///
-    /// WARN: If running debug builds, the store is blocking and will impact performance
+    /// Waits for the point to be applied if `wait` is enabled.
     ///
     /// # Parameters
     ///
@@ -55,7 +68,12 @@ impl Persist for Qdrant {
     /// This function will return an error if the node conversion or storage operation fails.
     #[tracing::instrument(skip_all, err, name = "storage.qdrant.store")]
     async fn store(&self, node: Node) -> Result<Node> {
-        let node_with_vectors = NodeWithVectors::new(&node, self.vector_fields());
+        self.create_index_from_nodes(std::slice::from_ref(&node))
+            .await?;
+
+        let node_with_vectors = NodeWithVectors::new(&node, self.vector_fields())
+            .with_payload_fields(self.payload_fields.as_ref())
+            .with_id_fn(self.id_fn);
         let point = node_with_vectors.try_into()?;
 
         tracing::debug!("Storing node");
@@ -63,7 +81,7 @@ impl Persist for Qdrant {
         self.client
             .upsert_points(
                 UpsertPointsBuilder::new(self.collection_name.to_string(), vec![point])
-                    .wait(cfg!(debug_assertions)),
+                    .wait(self.wait),
             )
             .await?;
         Ok(node)
@@ -84,9 +102,17 @@ impl Persist for Qdrant {
     /// This function will return an error if any node conversion or storage operation fails.
     #[tracing::instrument(skip_all, name = "storage.qdrant.batch_store")]
     async fn batch_store(&self, nodes: Vec<Node>) -> IndexingStream {
+        if let Err(err) = self.create_index_from_nodes(&nodes).await {
+            return vec![Err(err)].into();
+        }
+
         let points = nodes
             .iter()
-            .map(|node| NodeWithVectors::new(node, self.vector_fields()))
+            .map(|node| {
+                NodeWithVectors::new(node, self.vector_fields())
+                    .with_payload_fields(self.payload_fields.as_ref())
+                    .with_id_fn(self.id_fn)
+            })
             .map(NodeWithVectors::try_into)
             .collect::<Result<Vec<_>>>();
 
@@ -99,8 +125,7 @@ impl Persist for Qdrant {
         let result = self
             .client
             .upsert_points(
-                UpsertPointsBuilder::new(self.collection_name.to_string(), points)
-                    .wait(cfg!(debug_assertions)),
+                UpsertPointsBuilder::new(self.collection_name.to_string(), points).wait(self.wait),
             )
             .await;
 
@@ -110,6 +135,111 @@ impl Persist for Qdrant {
             vec![Err(result.unwrap_err().into())].into()
         }
     }
+
+    /// Returns the exact number of points in the collection.
+    ///
+    /// # Errors
+    ///
+    /// Errors if the collection does not exist or the request fails.
+    #[tracing::instrument(skip_all, err, name = "storage.qdrant.count")]
+    async fn count(&self) -> Result<u64> {
+        let response = self
+            .client
+            .count(CountPointsBuilder::new(&self.collection_name).exact(true))
+            .await?;
+
+        Ok(response.result.map_or(0, |result| result.count))
+    }
+
+    /// Checks which nodes are stored by looking up their point ids.
+    ///
+    /// Nodes do not exist yet if the collection has not been created.
+    #[tracing::instrument(skip_all, err, name = "storage.qdrant.exists")]
+    async fn exists(&self, nodes: &[Node]) -> Result<Vec<bool>> {
+        if !self.client.collection_exists(&self.collection_name).await? {
+            return Ok(vec![false; nodes.len()]);
+        }
+
+        let ids = nodes
+            .iter()
+            .map(|node| PointId::from((self.id_fn)(node).to_string()))
+            .collect::<Vec<_>>();
+        let response = self
+            .client
+            .get_points(
+                GetPointsBuilder::new(&self.collection_name, ids.clone())
+                    .with_payload(false)
+                    .with_vectors(false),
+            )
+            .await
+            .context("Failed to get points from qdrant")?;
+
+        let found = response
+            .result
+            .into_iter()
+            .filter_map(|point| point.id)
+            .collect::<Vec<_>>();
+        Ok(ids.iter().map(|id| found.contains(id)).collect())
+    }
+
+    /// Streams all stored nodes by scrolling through the collection in pages of `batch_size`.
+    ///
+    /// Nodes are rebuilt from the payload, so only stored payload fields are restored. Vectors
+    /// are not included.
+    async fn stream_all(&self) -> IndexingStream {
+        let client = Arc::clone(&self.client);
+        let collection_name = self.collection_name.clone();
+        #[allow(clippy::cast_possible_truncation)]
+        let limit = self.batch_size.unwrap_or(DEFAULT_BATCH_SIZE) as u32;
+
+        let pages = stream::try_unfold(Some(None), move |offset: Option<Option<PointId>>| {
+            let client = Arc::clone(&client);
+            let collection_name = collection_name.clone();
+            async move {
+                let Some(offset) = offset else {
+                    return anyhow::Ok(None);
+                };
+
+                let mut request = ScrollPointsBuilder::new(collection_name)
+                    .limit(limit)
+                    .with_payload(true);
+                if let Some(offset) = offset {
+                    request = request.offset(offset);
+                }
+                let response = client
+                    .scroll(request)
+                    .await
+                    .context("Failed to scroll qdrant")?;
+
+                let nodes = response
+                    .result
+                    .into_iter()
+                    .map(|point| Ok(node_from_payload(point.payload.into())))
+                    .collect::<Vec<_>>();
+
+                Ok(Some((nodes, response.next_page_offset.map(Some))))
+            }
+        });
+
+        IndexingStream::from_stream(pages.map_ok(stream::iter).try_flatten())
+    }
+}
+
+/// Rebuilds a node from a payload stored by [`NodeWithVectors`]
+fn node_from_payload(payload: Payload) -> Node {
+    let mut payload: serde_json::Map<String, serde_json::Value> = payload.into();
+    let mut node = Node::default();
+
+    if let Some(serde_json::Value::String(chunk)) = payload.remove("content") {
+        node.chunk = chunk;
+    }
+    if let Some(serde_json::Value::String(path)) = payload.remove("path") {
+        node.path = path.into();
+    }
+    payload.remove("last_updated_at");
+    node.metadata.extend(payload);
+
+    node
 }
 
 impl Qdrant {
@@ -117,3 +247,219 @@ impl Qdrant {
         self.vectors.keys().collect::<HashSet<_>>()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures_util::TryStreamExt as _;
+
+    use super::*;
+
+    #[test]
+    fn test_node_from_payload() {
+        let payload = Payload::try_from(serde_json::json!({
+            "content": "chunk",
+            "path": "doc.md",
+            "last_updated_at": "2024-01-01T00:00:00Z",
+            "language": "en",
+        }))
+        .unwrap();
+
+        let node = node_from_payload(payload);
+
+        assert_eq!(node.chunk, "chunk");
+        assert_eq!(node.path, std::path::PathBuf::from("doc.md"));
+        assert_eq!(node.metadata.get("language").unwrap(), "en");
+        assert!(node.metadata.get("last_updated_at").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_setup_times_out_when_unresponsive() {
+        let (_server, address) = crate::timeout::unresponsive_server().await;
+        let qdrant = Qdrant::try_from_url(format!("http://{address}"))
+            .unwrap()
+            .vector_size(3)
+            .connect_timeout(std::time::Duration::from_millis(200))
+            .request_timeout(std::time::Duration::from_millis(200))
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            qdrant.client().config.timeout,
+            std::time::Duration::from_millis(200)
+        );
+        assert_eq!(
+            qdrant.client().config.connect_timeout,
+            std::time::Duration::from_millis(200)
+        );
+
+        let started = std::time::Instant::now();
+        let err = qdrant.setup().await.unwrap_err();
+
+        assert!(err.to_string().contains("Timeout expired"), "{err:#}");
+        assert!(started.elapsed() < std::time::Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_creates_collection_with_inferred_vector_size() {
+        let (_guard, qdrant_url) = swiftide_test_utils::start_qdrant().await;
+
+        let qdrant = Qdrant::try_from_url(qdrant_url)
+            .unwrap()
+            .wait(true)
+            .collection_name("lazy")
+            .build()
+            .unwrap();
+
+        qdrant.setup().await.unwrap();
+        assert!(!qdrant.client.collection_exists("lazy").await.unwrap());
+
+        let mut node = Node::new("chunk");
+        node.with_vectors([(EmbeddedField::Combined, vec![1.0; 128])]);
+        qdrant
+            .batch_store(vec![node])
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        let info = qdrant.client.collection_info("lazy").await.unwrap();
+        let size = info
+            .result
+            .and_then(|info| info.config?.params?.vectors_config?.config)
+            .map(|config| match config {
+                qdrant_client::qdrant::vectors_config::Config::Params(params) => params.size,
+                qdrant_client::qdrant::vectors_config::Config::ParamsMap(_) => 0,
+            });
+        assert_eq!(size, Some(128));
+
+        let mut node = Node::new("other");
+        node.with_vectors([(EmbeddedField::Combined, vec![1.0; 64])]);
+        let result = qdrant.store(node).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_setup_errors_on_mismatching_collection() {
+        let (_guard, qdrant_url) = swiftide_test_utils::start_qdrant().await;
+        let qdrant = |vector_size: u64| {
+            Qdrant::try_from_url(&qdrant_url)
+                .unwrap()
+                .collection_name("mismatch")
+                .vector_size(vector_size)
+                .build()
+                .unwrap()
+        };
+
+        qdrant(3).setup().await.unwrap();
+        qdrant(3).setup().await.unwrap();
+
+        let err = qdrant(4).setup().await.unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("has vectors of size 3 for Combined, but 4 is configured"),
+            "{err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_storing_twice_upserts_in_place() {
+        let (_guard, qdrant_url) = swiftide_test_utils::start_qdrant().await;
+
+        let qdrant = Qdrant::try_from_url(qdrant_url)
+            .unwrap()
+            .wait(true)
+            .collection_name("idempotent")
+            .vector_size(4)
+            .build()
+            .unwrap();
+        qdrant.setup().await.unwrap();
+
+        let mut node = Node::new("chunk");
+        node.with_vectors([(EmbeddedField::Combined, vec![1.0; 4])]);
+
+        qdrant.store(node.clone()).await.unwrap();
+        qdrant.store(node).await.unwrap();
+
+        assert_eq!(qdrant.count().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_exists() {
+        let (_guard, qdrant_url) = swiftide_test_utils::start_qdrant().await;
+
+        let qdrant = Qdrant::try_from_url(qdrant_url)
+            .unwrap()
+            .wait(true)
+            .collection_name("exists")
+            .build()
+            .unwrap();
+        qdrant.setup().await.unwrap();
+
+        let mut node = Node::new("chunk");
+        node.with_vectors([(EmbeddedField::Combined, vec![1.0; 4])]);
+        let new = Node::new("new");
+        assert_eq!(
+            qdrant.exists(&[node.clone(), new.clone()]).await.unwrap(),
+            [false, false]
+        );
+
+        qdrant.store(node.clone()).await.unwrap();
+
+        assert_eq!(qdrant.exists(&[node, new]).await.unwrap(), [true, false]);
+    }
+
+    #[tokio::test]
+    async fn test_f16_round_trip_within_tolerance() {
+        use crate::qdrant::{Distance, Precision};
+        use qdrant_client::qdrant::{vectors::VectorsOptions, Datatype, GetPointsBuilder};
+
+        let (_guard, qdrant_url) = swiftide_test_utils::start_qdrant().await;
+
+        let qdrant = Qdrant::try_from_url(qdrant_url)
+            .unwrap()
+            .wait(true)
+            .collection_name("half")
+            .vector_size(4)
+            .vector_precision(Precision::F16)
+            // Cosine would normalize the stored vectors
+            .vector_distance(Distance::Euclid)
+            .build()
+            .unwrap();
+        qdrant.setup().await.unwrap();
+
+        let info = qdrant.client.collection_info("half").await.unwrap();
+        let datatype = info
+            .result
+            .and_then(|info| info.config?.params?.vectors_config?.config)
+            .and_then(|config| match config {
+                qdrant_client::qdrant::vectors_config::Config::Params(params) => params.datatype,
+                qdrant_client::qdrant::vectors_config::Config::ParamsMap(_) => None,
+            });
+        assert_eq!(datatype, Some(Datatype::Float16.into()));
+
+        let vector = vec![0.1, -0.25, 0.333, 1.0];
+        let mut node = Node::new("chunk");
+        node.with_vectors([(EmbeddedField::Combined, vector.clone())]);
+        qdrant.store(node.clone()).await.unwrap();
+
+        let points = qdrant
+            .client
+            .get_points(
+                GetPointsBuilder::new("half", vec![node.id().to_string().into()])
+                    .with_vectors(true),
+            )
+            .await
+            .unwrap();
+        let Some(VectorsOptions::Vector(stored)) = points.result[0]
+            .vectors
+            .clone()
+            .and_then(|vectors| vectors.vectors_options)
+        else {
+            panic!("Expected a single vector");
+        };
+
+        for (stored, original) in stored.data.iter().zip(&vector) {
+            assert!((stored - original).abs() < 1e-3);
+        }
+    }
+}
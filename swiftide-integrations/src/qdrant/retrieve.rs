@@ -9,7 +9,7 @@ use swiftide_core::{
     Retrieve,
 };
 
-use super::Qdrant;
+use super::{MetadataFilter, Qdrant};
 
 /// Implement the `Retrieve` trait for `SimilaritySingleEmbedding` search strategy.
 ///
@@ -83,6 +83,25 @@ impl Retrieve<SimilaritySingleEmbedding> for Qdrant {
     }
 }
 
+/// Retrieves with a [`MetadataFilter`], translated into a `qdrant::Filter`.
+#[async_trait]
+impl Retrieve<SimilaritySingleEmbedding<MetadataFilter>> for Qdrant {
+    async fn retrieve(
+        &self,
+        search_strategy: &SimilaritySingleEmbedding<MetadataFilter>,
+        query: Query<states::Pending>,
+    ) -> Result<Query<states::Retrieved>> {
+        let mut concrete = match search_strategy.filter() {
+            Some(filter) => SimilaritySingleEmbedding::from_filter(filter.clone().into()),
+            None => SimilaritySingleEmbedding::<qdrant::Filter>::default(),
+        };
+        concrete.with_top_k(search_strategy.top_k());
+
+        Retrieve::<SimilaritySingleEmbedding<qdrant::Filter>>::retrieve(self, &concrete, query)
+            .await
+    }
+}
+
 /// Implement the `Retrieve` trait for `HybridSearch` search strategy.
 ///
 /// Can be used in the query pipeline to retrieve documents from Qdrant.
@@ -167,6 +186,7 @@ mod tests {
 
         let qdrant_client = Qdrant::try_from_url(qdrant_url)
             .unwrap()
+            .wait(true)
             .vector_size(384)
             .with_vector(EmbeddedField::Combined)
             .with_sparse_vector(EmbeddedField::Combined)
@@ -207,6 +227,7 @@ mod tests {
     #[tokio::test]
     async fn test_retrieve_multiple_docs_and_filter() {
         let (_guard, qdrant_client) = setup().await;
+        assert_eq!(qdrant_client.count().await.unwrap(), 3);
 
         let mut query = Query::<states::Pending>::new("test_query");
         query.embedding = Some(vec![1.0; 384]);
@@ -253,6 +274,64 @@ mod tests {
         assert_eq!(result.documents().len(), 0);
     }
 
+    #[tokio::test]
+    async fn test_retrieve_with_metadata_filter() {
+        let (_guard, qdrant_url) = swiftide_test_utils::start_qdrant().await;
+        let qdrant_client = Qdrant::try_from_url(qdrant_url)
+            .unwrap()
+            .wait(true)
+            .vector_size(4)
+            .build()
+            .unwrap();
+        qdrant_client.setup().await.unwrap();
+
+        let nodes = [("acme", 2021), ("acme", 2019), ("globex", 2021)]
+            .into_iter()
+            .map(|(tenant, year)| {
+                let mut node = indexing::Node::new(format!("{tenant} {year}"))
+                    .with_metadata([
+                        ("tenant", serde_json::Value::from(tenant)),
+                        ("year", serde_json::Value::from(year)),
+                    ])
+                    .to_owned();
+                node.with_vectors([(EmbeddedField::Combined, vec![1.0; 4])]);
+                node
+            })
+            .collect();
+        qdrant_client
+            .batch_store(nodes)
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        let mut query = Query::<states::Pending>::new("test_query");
+        query.embedding = Some(vec![1.0; 4]);
+
+        let search_strategy = SimilaritySingleEmbedding::from_filter(
+            MetadataFilter::default().eq("tenant", "acme".to_string()),
+        );
+        let result = qdrant_client
+            .retrieve(&search_strategy, query.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            result.documents().iter().sorted().collect_vec(),
+            ["\"acme 2019\"", "\"acme 2021\""]
+        );
+
+        let search_strategy = SimilaritySingleEmbedding::from_filter(
+            MetadataFilter::default()
+                .eq("tenant", "acme".to_string())
+                .range("year", 2020.0..),
+        );
+        let result = qdrant_client
+            .retrieve(&search_strategy, query)
+            .await
+            .unwrap();
+        assert_eq!(result.documents(), ["\"acme 2021\""]);
+    }
+
     #[tokio::test]
     async fn test_hybrid_search() {
         let (_guard, qdrant_client) = setup().await;
@@ -270,4 +349,29 @@ mod tests {
             .unwrap();
         assert_eq!(result.documents().len(), 3);
     }
+
+    #[tokio::test]
+    async fn test_wait_makes_stored_nodes_searchable() {
+        let (_guard, qdrant_url) = swiftide_test_utils::start_qdrant().await;
+        let qdrant_client = Qdrant::try_from_url(qdrant_url)
+            .unwrap()
+            .wait(true)
+            .vector_size(4)
+            .build()
+            .unwrap();
+        qdrant_client.setup().await.unwrap();
+
+        let mut node = indexing::Node::new("stored");
+        node.with_vectors([(EmbeddedField::Combined, vec![1.0; 4])]);
+        qdrant_client.store(node).await.unwrap();
+
+        let mut query = Query::<states::Pending>::new("test_query");
+        query.embedding = Some(vec![1.0; 4]);
+        let result = qdrant_client
+            .retrieve(&SimilaritySingleEmbedding::<()>::default(), query)
+            .await
+            .unwrap();
+
+        assert_eq!(result.documents(), ["\"stored\""]);
+    }
 }
@@ -0,0 +1,93 @@
+use std::path::Path;
+
+use async_trait::async_trait;
+
+use swiftide_core::ChecksumStore;
+
+use super::Redis;
+
+#[async_trait]
+impl ChecksumStore for Redis {
+    /// Gets the checksum stored for the path under the cache key prefix
+    ///
+    /// # Errors
+    ///
+    /// Logs an error and returns `None` if the checksum cannot be read.
+    #[tracing::instrument(skip_all, name = "checksum_store.redis.get")]
+    async fn get(&self, path: &Path) -> Option<String> {
+        let mut cm = match self.lazy_connect().await {
+            Ok(cm) => cm,
+            Err(e) => {
+                tracing::error!("Failed to get checksum: {:#}", e);
+                return None;
+            }
+        };
+
+        redis::cmd("GET")
+            .arg(self.checksum_key_for_path(path))
+            .query_async(&mut cm)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::error!("Failed to get checksum: {}", e);
+                None
+            })
+    }
+
+    /// Sets the checksum for the path under the cache key prefix
+    ///
+    /// # Errors
+    ///
+    /// Logs an error if the checksum cannot be set.
+    #[tracing::instrument(skip_all, name = "checksum_store.redis.set")]
+    async fn set(&self, path: &Path, checksum: &str) {
+        let mut cm = match self.lazy_connect().await {
+            Ok(cm) => cm,
+            Err(e) => {
+                tracing::error!("Failed to set checksum: {:#}", e);
+                return;
+            }
+        };
+
+        let result: Result<(), redis::RedisError> = redis::cmd("SET")
+            .arg(self.checksum_key_for_path(path))
+            .arg(checksum)
+            .query_async(&mut cm)
+            .await;
+
+        if let Err(e) = result {
+            tracing::error!("Failed to set checksum: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use testcontainers::runners::AsyncRunner;
+
+    #[test_log::test(tokio::test)]
+    async fn test_redis_checksum_store() {
+        let redis = testcontainers::GenericImage::new("redis", "7.2.4")
+            .with_exposed_port(6379.into())
+            .with_wait_for(testcontainers::core::WaitFor::message_on_stdout(
+                "Ready to accept connections",
+            ))
+            .start()
+            .await
+            .expect("Redis started");
+
+        let host = redis.get_host().await.unwrap();
+        let port = redis.get_host_port_ipv4(6379).await.unwrap();
+        let store = Redis::try_from_url(format!("redis://{host}:{port}"), "test")
+            .expect("Could not build redis client");
+
+        let path = Path::new("docs/file.md");
+        assert_eq!(store.get(path).await, None);
+
+        store.set(path, "first").await;
+        store.set(path, "second").await;
+        assert_eq!(store.get(path).await.as_deref(), Some("second"));
+        assert_eq!(store.get(Path::new("docs/other.md")).await, None);
+    }
+}
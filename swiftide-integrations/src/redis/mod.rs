@@ -10,19 +10,62 @@
 //! - Connecting to a Redis database
 //! - Checking if a node is cached
 //! - Setting a node in the cache
+//! - Storing the checksums of files, to skip unchanged files
 //! - Resetting the cache (primarily for testing purposes)
 //!
 //! This integration is essential for ensuring efficient node management and caching in the Swiftide system.
 
+use std::{
+    io::{Read as _, Write as _},
+    time::Duration,
+};
+
 use anyhow::{Context as _, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use derive_builder::Builder;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::Serialize;
 use tokio::sync::RwLock;
 
-use swiftide_core::indexing::Node;
+use swiftide_core::indexing::{ChunkIdFormat, Node};
+
+use crate::timeout::with_timeout;
 
+mod checksum_store;
 mod node_cache;
 mod persist;
 
+/// Prefix marking a persisted value as gzip compressed. `0xFF` never occurs in valid UTF-8, so
+/// uncompressed values can not be mistaken for compressed ones.
+const COMPRESSED_PREFIX: &[u8] = b"\xFFgz";
+
+/// Delay before the first retry of a command; later retries wait a multiple of it
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// The default number of nodes persisted per batch
+pub const DEFAULT_BATCH_SIZE: usize = 10;
+
+/// Version of the layout of persisted nodes. Bump when the fields of `Node` change in a way that
+/// older values can not be read anymore.
+pub const NODE_SCHEMA_VERSION: u32 = 1;
+
+/// A node as persisted by default, tagged with the schema version it was written with
+#[derive(Serialize)]
+struct VersionedNode<'a> {
+    schema_version: u32,
+    node: &'a Node,
+}
+
+/// How the vectors of a node are encoded in the default persisted value
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VectorEncoding {
+    /// A JSON array of floats, readable but verbose
+    #[default]
+    Json,
+    /// A base64 string of the little endian `f32` bytes. Compact and bit-exact.
+    Base64,
+}
+
 /// `Redis` provides a caching mechanism for nodes using Redis.
 /// It helps in optimizing the indexing process by skipping nodes that have already been processed.
 ///
@@ -39,15 +82,44 @@ pub struct Redis {
     connection_manager: RwLock<Option<redis::aio::ConnectionManager>>,
     #[builder(default)]
     cache_key_prefix: String,
-    #[builder(default = "10")]
-    /// The batch size used for persisting nodes. Defaults to a safe 10.
-    batch_size: usize,
+    #[builder(default = "Some(DEFAULT_BATCH_SIZE)")]
+    /// The number of nodes persisted per batch. Defaults to [`DEFAULT_BATCH_SIZE`].
+    ///
+    /// See [`RedisBuilder::without_batching`] to persist nodes one by one.
+    batch_size: Option<usize>,
     #[builder(default)]
     /// Customize the key used for persisting nodes
     persist_key_fn: Option<fn(&Node) -> Result<String>>,
     #[builder(default)]
     /// Customize the value used for persisting nodes
     persist_value_fn: Option<fn(&Node) -> Result<String>>,
+    #[builder(default)]
+    /// How the key of persisted nodes is composed, if there is no `persist_key_fn`. Defaults to
+    /// `path:uuid`, with [`ChunkIdFormat::PathAndIndex`] keys are `path#index`.
+    chunk_id_format: ChunkIdFormat,
+    #[builder(default)]
+    /// Gzip compress persisted values. Values are decompressed transparently when read back, and
+    /// uncompressed values stored earlier can still be read. Defaults to false.
+    compress: bool,
+    #[builder(default)]
+    /// How vectors are encoded in persisted values. Either encoding is decoded transparently when
+    /// read back. Defaults to a JSON array.
+    vector_encoding: VectorEncoding,
+    #[builder(default)]
+    /// The logical database to use, overriding the one in the url (i.e. `redis://localhost/3`).
+    /// Defaults to the database of the url, which is 0 if not set.
+    db_index: Option<u8>,
+    #[builder(default = "3")]
+    /// How often `store` and `batch_store` are retried on connection errors, like a dropped
+    /// connection. Logical errors are never retried. Defaults to 3.
+    max_retries: usize,
+    #[builder(default)]
+    /// Fails connecting if it takes longer, i.e. when Redis is unreachable. Optional.
+    connect_timeout: Option<Duration>,
+    #[builder(default)]
+    /// Fails a command if Redis does not respond in time. Timed out commands are retried like
+    /// other connection errors. Optional.
+    request_timeout: Option<Duration>,
 }
 
 impl Redis {
@@ -71,9 +143,16 @@ impl Redis {
             client,
             connection_manager: RwLock::new(None),
             cache_key_prefix: prefix.as_ref().to_string(),
-            batch_size: 10,
+            batch_size: Some(DEFAULT_BATCH_SIZE),
             persist_key_fn: None,
             persist_value_fn: None,
+            chunk_id_format: ChunkIdFormat::default(),
+            compress: false,
+            vector_encoding: VectorEncoding::default(),
+            db_index: None,
+            max_retries: 3,
+            connect_timeout: None,
+            request_timeout: None,
         })
     }
 
@@ -94,23 +173,75 @@ impl Redis {
     ///
     /// # Returns
     ///
-    /// An `Option` containing the `ConnectionManager` if the connection is successful, or `None` if it fails.
+    /// A `Result` containing the `ConnectionManager` if the connection is successful.
     ///
     /// # Errors
     ///
-    /// Logs an error and returns `None` if the connection manager cannot be obtained.
-    async fn lazy_connect(&self) -> Option<redis::aio::ConnectionManager> {
-        if self.connection_manager.read().await.is_none() {
-            let result = self.client.get_connection_manager().await;
-            if let Err(e) = result {
-                tracing::error!("Failed to get connection manager: {}", e);
-                return None;
-            }
-            let mut cm = self.connection_manager.write().await;
-            *cm = result.ok();
+    /// Returns the underlying redis error if the connection manager cannot be obtained.
+    async fn lazy_connect(&self) -> Result<redis::aio::ConnectionManager> {
+        if let Some(cm) = self.connection_manager.read().await.clone() {
+            return Ok(cm);
         }
 
-        self.connection_manager.read().await.clone()
+        let mut cm = self.connection_manager.write().await;
+        if let Some(cm) = cm.clone() {
+            return Ok(cm);
+        }
+
+        let mut config = redis::aio::ConnectionManagerConfig::new();
+        if let Some(connect_timeout) = self.connect_timeout {
+            config = config.set_connection_timeout(connect_timeout);
+        }
+        if let Some(request_timeout) = self.request_timeout {
+            config = config.set_response_timeout(request_timeout);
+        }
+
+        let client = self.database_client()?;
+        let connection_manager = with_timeout(self.connect_timeout, "connecting to Redis", async {
+            client
+                .get_connection_manager_with_config(config)
+                .await
+                .context("Failed to connect to Redis")
+        })
+        .await?;
+        *cm = Some(connection_manager.clone());
+
+        Ok(connection_manager)
+    }
+
+    /// Returns the client, connecting to `db_index` if set.
+    ///
+    /// Selecting the database through the connection info makes the connection manager select it
+    /// again on every reconnect.
+    fn database_client(&self) -> Result<redis::Client> {
+        let Some(db_index) = self.db_index else {
+            return Ok(self.client.clone());
+        };
+
+        let mut connection_info = self.client.get_connection_info().clone();
+        connection_info.redis.db = i64::from(db_index);
+        redis::Client::open(connection_info).context("Failed to open redis client")
+    }
+
+    /// Runs a command, retrying it up to `max_retries` times on connection errors.
+    ///
+    /// The connection manager reconnects by itself when a connection drops, but the command that
+    /// ran into the drop still fails. Logical errors, like a wrong type, are returned immediately.
+    async fn query_with_retries<T: redis::FromRedisValue>(&self, cmd: &redis::Cmd) -> Result<T> {
+        let mut cm = self.lazy_connect().await?;
+        let mut attempt = 0;
+
+        loop {
+            match cmd.query_async(&mut cm).await {
+                Err(err) if attempt < self.max_retries && is_connection_error(&err) => {
+                    attempt += 1;
+                    tracing::warn!(error = %err, attempt, "Connection error, retrying redis command");
+                    tokio::time::sleep(RETRY_DELAY * u32::try_from(attempt).unwrap_or(u32::MAX))
+                        .await;
+                }
+                result => return result.map_err(Into::into),
+            }
+        }
     }
 
     /// Generates a Redis key for a given node using the key prefix and the node's hash.
@@ -126,25 +257,136 @@ impl Redis {
         format!("{}:{}", self.cache_key_prefix, node.id())
     }
 
+    /// Generates a key for the checksum of a file, see [`swiftide_core::ChecksumStore`]
+    fn checksum_key_for_path(&self, path: &std::path::Path) -> String {
+        format!("{}:checksum:{}", self.cache_key_prefix, path.display())
+    }
+
     /// Generates a key for a given node to be persisted in Redis.
     fn persist_key_for_node(&self, node: &Node) -> Result<String> {
         if let Some(key_fn) = self.persist_key_fn {
-            key_fn(node)
-        } else {
-            let hash = node.id();
-            Ok(format!("{}:{}", node.path.to_string_lossy(), hash))
+            return key_fn(node);
+        }
+
+        match self.chunk_id_format {
+            ChunkIdFormat::Uuid => Ok(format!("{}:{}", node.path.to_string_lossy(), node.id())),
+            format @ ChunkIdFormat::PathAndIndex => Ok(node.chunk_id(format)),
         }
     }
 
     /// Generates a value for a given node to be persisted in Redis.
-    /// By default, the node is serialized as JSON.
+    /// By default, the node is serialized as JSON, tagged with [`NODE_SCHEMA_VERSION`], with its
+    /// vectors encoded as configured by [`VectorEncoding`].
     /// If a custom function is provided, it is used to generate the value.
-    /// Otherwise, the node is serialized as JSON.
     fn persist_value_for_node(&self, node: &Node) -> Result<String> {
         if let Some(value_fn) = self.persist_value_fn {
-            value_fn(node)
+            return value_fn(node);
+        }
+
+        let versioned = VersionedNode {
+            schema_version: NODE_SCHEMA_VERSION,
+            node,
+        };
+        if self.vector_encoding == VectorEncoding::Json {
+            return Ok(serde_json::to_string(&versioned)?);
+        }
+
+        let mut value = serde_json::to_value(&versioned)?;
+        if let (Some(vectors), Some(encoded)) =
+            (&node.vectors, value["node"]["vectors"].as_object_mut())
+        {
+            for (field, vector) in vectors {
+                let bytes = vector
+                    .iter()
+                    .flat_map(|float| float.to_le_bytes())
+                    .collect::<Vec<_>>();
+                encoded.insert(field.to_string(), BASE64.encode(bytes).into());
+            }
+        }
+        Ok(serde_json::to_string(&value)?)
+    }
+
+    /// Decodes base64 encoded vectors in a persisted node back into JSON arrays
+    fn decode_vectors(node: &mut serde_json::Value) -> Result<()> {
+        let Some(vectors) = node
+            .get_mut("vectors")
+            .and_then(serde_json::Value::as_object_mut)
+        else {
+            return Ok(());
+        };
+
+        for vector in vectors.values_mut() {
+            let Some(encoded) = vector.as_str() else {
+                continue;
+            };
+            let bytes = BASE64
+                .decode(encoded)
+                .context("Persisted vector is not valid base64")?;
+            if bytes.len() % 4 != 0 {
+                anyhow::bail!("Persisted vector is not a sequence of f32s");
+            }
+            *vector = bytes
+                .chunks_exact(4)
+                .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+                .collect::<Vec<_>>()
+                .into();
+        }
+        Ok(())
+    }
+
+    /// Reads a node persisted with the default value format
+    ///
+    /// # Errors
+    ///
+    /// Errors if the value was written with a different schema version, or is not a valid node.
+    fn node_from_value(value: &str) -> Result<Node> {
+        let mut value: serde_json::Value =
+            serde_json::from_str(value).context("Persisted node is not valid JSON")?;
+
+        let Some(version) = value
+            .get("schema_version")
+            .and_then(serde_json::Value::as_u64)
+        else {
+            anyhow::bail!(
+                "Persisted node has no schema version and was written by an older version of swiftide, expected version {NODE_SCHEMA_VERSION}. Re-index to upgrade."
+            );
+        };
+        if version != u64::from(NODE_SCHEMA_VERSION) {
+            anyhow::bail!(
+                "Persisted node has schema version {version}, expected version {NODE_SCHEMA_VERSION}. Re-index to upgrade."
+            );
+        }
+
+        let mut node = value["node"].take();
+        Self::decode_vectors(&mut node)?;
+        serde_json::from_value(node).with_context(|| {
+            format!("Persisted node does not match schema version {NODE_SCHEMA_VERSION}")
+        })
+    }
+
+    /// Encodes a persisted value, gzip compressing it with a prefix if compression is enabled.
+    fn encode_value(&self, value: String) -> Result<Vec<u8>> {
+        if !self.compress {
+            return Ok(value.into_bytes());
+        }
+
+        let mut encoder = GzEncoder::new(COMPRESSED_PREFIX.to_vec(), Compression::default());
+        encoder
+            .write_all(value.as_bytes())
+            .context("Failed to compress value")?;
+        encoder.finish().context("Failed to compress value")
+    }
+
+    /// Decodes a persisted value, decompressing it if it has the compression prefix.
+    fn decode_value(value: Vec<u8>) -> Result<String> {
+        if let Some(compressed) = value.strip_prefix(COMPRESSED_PREFIX) {
+            let mut decoded = String::new();
+            GzDecoder::new(compressed)
+                .read_to_string(&mut decoded)
+                .context("Failed to decompress value")?;
+            Ok(decoded)
         } else {
-            Ok(serde_json::to_string(node)?)
+            String::from_utf8(value).context("Persisted value is not valid utf-8")
         }
     }
 
@@ -156,7 +398,7 @@ impl Redis {
     /// Panics if the keys cannot be retrieved or deleted.
     #[allow(dead_code)]
     async fn reset_cache(&self) {
-        if let Some(mut cm) = self.lazy_connect().await {
+        if let Ok(mut cm) = self.lazy_connect().await {
             let keys: Vec<String> = redis::cmd("KEYS")
                 .arg(format!("{}:*", self.cache_key_prefix))
                 .query_async(&mut cm)
@@ -177,20 +419,36 @@ impl Redis {
     /// Takes a node and returns a Result<Option<String>>
     #[allow(dead_code)]
     async fn get_node(&self, node: &Node) -> Result<Option<String>> {
-        if let Some(mut cm) = self.lazy_connect().await {
-            let key = self.persist_key_for_node(node)?;
-            let result: Option<String> = redis::cmd("GET")
-                .arg(key)
-                .query_async(&mut cm)
-                .await
-                .context("Error getting from redis")?;
-            Ok(result)
-        } else {
-            anyhow::bail!("Failed to connect to Redis")
-        }
+        let mut cm = self.lazy_connect().await?;
+        let key = self.persist_key_for_node(node)?;
+        let result: Option<Vec<u8>> = redis::cmd("GET")
+            .arg(key)
+            .query_async(&mut cm)
+            .await
+            .context("Error getting from redis")?;
+        result.map(Self::decode_value).transpose()
+    }
+
+    /// Gets a node persisted in Redis with the default value format
+    ///
+    /// # Errors
+    ///
+    /// Errors if the node was persisted with a different schema version.
+    #[allow(dead_code)]
+    async fn get_persisted_node(&self, node: &Node) -> Result<Option<Node>> {
+        self.get_node(node)
+            .await?
+            .as_deref()
+            .map(Self::node_from_value)
+            .transpose()
     }
 }
 
+/// Whether an error is caused by the connection rather than by the command
+fn is_connection_error(err: &redis::RedisError) -> bool {
+    err.is_io_error() || err.is_unrecoverable_error() || err.is_timeout()
+}
+
 // Redis CM does not implement debug
 #[allow(clippy::missing_fields_in_debug)]
 impl std::fmt::Debug for Redis {
@@ -201,6 +459,15 @@ impl std::fmt::Debug for Redis {
     }
 }
 
+impl RedisBuilder {
+    /// Persists nodes one by one instead of in batches, overriding the default batch size
+    #[must_use]
+    pub fn without_batching(mut self) -> Self {
+        self.batch_size = Some(None);
+        self
+    }
+}
+
 impl Clone for Redis {
     fn clone(&self) -> Self {
         Self {
@@ -210,6 +477,284 @@ impl Clone for Redis {
             batch_size: self.batch_size,
             persist_key_fn: self.persist_key_fn,
             persist_value_fn: self.persist_value_fn,
+            chunk_id_format: self.chunk_id_format,
+            compress: self.compress,
+            vector_encoding: self.vector_encoding,
+            db_index: self.db_index,
+            max_retries: self.max_retries,
+            connect_timeout: self.connect_timeout,
+            request_timeout: self.request_timeout,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use futures_util::StreamExt as _;
+    use swiftide_core::Persist as _;
+    use tokio::{
+        io::{AsyncBufReadExt as _, AsyncReadExt as _, AsyncWriteExt as _, BufReader},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::*;
+    use swiftide_core::indexing::EmbeddedField;
+
+    #[test]
+    fn test_batch_size_defaults_and_can_be_disabled() {
+        let builder = || Redis::try_build_from_url("redis://localhost:6379").unwrap();
+
+        assert_eq!(
+            Redis::try_from_url("redis://localhost:6379", "prefix")
+                .unwrap()
+                .batch_size(),
+            Some(DEFAULT_BATCH_SIZE)
+        );
+        assert_eq!(
+            builder().build().unwrap().batch_size(),
+            Some(DEFAULT_BATCH_SIZE)
+        );
+        assert_eq!(
+            builder().without_batching().build().unwrap().batch_size(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_compressed_values_round_trip() {
+        let redis = Redis::try_build_from_url("redis://localhost")
+            .unwrap()
+            .compress(true)
+            .build()
+            .unwrap();
+        let value = "chunk ".repeat(1000);
+
+        let encoded = redis.encode_value(value.clone()).unwrap();
+
+        assert!(encoded.starts_with(COMPRESSED_PREFIX));
+        assert!(encoded.len() < value.len());
+        assert_eq!(Redis::decode_value(encoded).unwrap(), value);
+    }
+
+    #[tokio::test]
+    async fn test_connection_errors_include_cause() {
+        let redis = Redis::try_build_from_url("redis://127.0.0.1:1")
+            .unwrap()
+            .build()
+            .unwrap();
+
+        let err = redis.get_node(&Node::default()).await.unwrap_err();
+
+        assert!(format!("{err:#}").contains("Connection refused"), "{err:#}");
+    }
+
+    #[tokio::test]
+    async fn test_setup_times_out_when_unresponsive() {
+        let (_server, address) = crate::timeout::unresponsive_server().await;
+        let redis = Redis::try_build_from_url(format!("redis://{address}"))
+            .unwrap()
+            .connect_timeout(Duration::from_millis(200))
+            .build()
+            .unwrap();
+
+        let started = std::time::Instant::now();
+        let err = redis.setup().await.unwrap_err();
+
+        assert!(
+            err.to_string().contains("Timed out connecting to Redis"),
+            "{err}"
+        );
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_persisted_nodes_round_trip() {
+        let redis = Redis::try_build_from_url("redis://localhost")
+            .unwrap()
+            .build()
+            .unwrap();
+        let node = Node::new("hello");
+
+        let value = redis.persist_value_for_node(&node).unwrap();
+
+        assert_eq!(Redis::node_from_value(&value).unwrap(), node);
+    }
+
+    #[test]
+    fn test_base64_vectors_round_trip_bit_exact() {
+        let redis = Redis::try_build_from_url("redis://localhost")
+            .unwrap()
+            .vector_encoding(VectorEncoding::Base64)
+            .build()
+            .unwrap();
+        let vector = vec![
+            0.1,
+            -1.0 / 3.0,
+            f32::MIN_POSITIVE,
+            f32::MAX,
+            f32::EPSILON,
+            -0.0,
+        ];
+        let mut node = Node::new("hello");
+        node.with_vectors([(EmbeddedField::Combined, vector.clone())]);
+
+        let value = redis.persist_value_for_node(&node).unwrap();
+        let persisted: serde_json::Value = serde_json::from_str(&value).unwrap();
+        assert!(persisted["node"]["vectors"]["Combined"].is_string());
+
+        let decoded = Redis::node_from_value(&value).unwrap();
+        let decoded = &decoded.vectors.unwrap()[&EmbeddedField::Combined];
+        assert_eq!(
+            decoded.iter().map(|f| f.to_bits()).collect::<Vec<_>>(),
+            vector.iter().map(|f| f.to_bits()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_rejects_nodes_from_other_schema_versions() {
+        let unversioned = serde_json::to_string(&Node::new("hello")).unwrap();
+        let err = Redis::node_from_value(&unversioned).unwrap_err();
+        assert!(err.to_string().contains("no schema version"), "{err}");
+
+        let newer = r#"{"schema_version": 2, "node": {"chunk": "hello"}}"#;
+        let err = Redis::node_from_value(newer).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("schema version 2, expected version 1"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_selects_database_index() {
+        let redis = Redis::try_build_from_url("redis://localhost/1")
+            .unwrap()
+            .db_index(3)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            redis
+                .database_client()
+                .unwrap()
+                .get_connection_info()
+                .redis
+                .db,
+            3
+        );
+    }
+
+    #[test]
+    fn test_decodes_uncompressed_values() {
+        let value = "{\"chunk\":\"hello\"}".to_string();
+
+        assert_eq!(
+            Redis::decode_value(value.clone().into_bytes()).unwrap(),
+            value
+        );
+    }
+
+    /// Starts a fake redis server that answers every command with `reply`
+    ///
+    /// The first `drops` connections are closed on their first command after the handshake,
+    /// without a reply. Returns the url and the number of commands received after handshakes.
+    async fn fake_redis(drops: usize, reply: &'static str) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("redis://{}", listener.local_addr().unwrap());
+        let commands = Arc::new(AtomicUsize::new(0));
+
+        let counter = Arc::clone(&commands);
+        tokio::spawn(async move {
+            let mut drops = drops;
+            while let Ok((stream, _)) = listener.accept().await {
+                let drop_connection = drops > 0;
+                drops = drops.saturating_sub(1);
+                let counter = Arc::clone(&counter);
+
+                tokio::spawn(async move {
+                    let mut stream = BufReader::new(stream);
+                    while let Some(command) = read_command(&mut stream).await {
+                        if command[0] != "CLIENT" {
+                            counter.fetch_add(1, Ordering::SeqCst);
+                            if drop_connection {
+                                return;
+                            }
+                        }
+                        let reply = if command[0] == "CLIENT" {
+                            "+OK\r\n"
+                        } else {
+                            reply
+                        };
+                        stream.get_mut().write_all(reply.as_bytes()).await.unwrap();
+                    }
+                });
+            }
+        });
+
+        (url, commands)
+    }
+
+    async fn read_command(reader: &mut BufReader<TcpStream>) -> Option<Vec<String>> {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await.ok()? == 0 {
+            return None;
+        }
+        let len = line.trim_end().strip_prefix('*')?.parse::<usize>().ok()?;
+
+        let mut args = Vec::with_capacity(len);
+        for _ in 0..len {
+            line.clear();
+            reader.read_line(&mut line).await.ok()?;
+            let len = line.trim_end().strip_prefix('$')?.parse::<usize>().ok()?;
+            let mut arg = vec![0; len + 2];
+            reader.read_exact(&mut arg).await.ok()?;
+            arg.truncate(len);
+            args.push(String::from_utf8_lossy(&arg).into_owned());
+        }
+
+        Some(args)
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_retries_store_after_dropped_connection() {
+        let (url, commands) = fake_redis(1, "+OK\r\n").await;
+        let redis = Redis::try_build_from_url(url).unwrap().build().unwrap();
+
+        let node = redis.store(Node::new("hello")).await.unwrap();
+
+        assert_eq!(node, Node::new("hello"));
+        assert_eq!(commands.load(Ordering::SeqCst), 2);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_gives_up_after_max_retries() {
+        let (url, commands) = fake_redis(usize::MAX, "+OK\r\n").await;
+        let redis = Redis::try_build_from_url(url)
+            .unwrap()
+            .max_retries(1)
+            .build()
+            .unwrap();
+
+        let result = redis.batch_store(vec![Node::new("hello")]).await;
+        let results = result.collect::<Vec<_>>().await;
+
+        assert!(results[0].is_err());
+        assert_eq!(commands.load(Ordering::SeqCst), 2);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_does_not_retry_logical_errors() {
+        let (url, commands) = fake_redis(0, "-WRONGTYPE not a string\r\n").await;
+        let redis = Redis::try_build_from_url(url).unwrap().build().unwrap();
+
+        let err = redis.store(Node::new("hello")).await.unwrap_err();
+
+        assert!(format!("{err:#}").contains("WRONGTYPE"), "{err:#}");
+        assert_eq!(commands.load(Ordering::SeqCst), 1);
+    }
+}
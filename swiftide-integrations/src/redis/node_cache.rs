@@ -23,26 +23,30 @@ impl NodeCache for Redis {
     /// Logs an error and returns `false` if the cache check fails.
     #[tracing::instrument(skip_all, name = "node_cache.redis.get", fields(hit))]
     async fn get(&self, node: &Node) -> bool {
-        let cache_result = if let Some(mut cm) = self.lazy_connect().await {
-            let result = redis::cmd("EXISTS")
-                .arg(self.cache_key_for_node(node))
-                .query_async(&mut cm)
-                .await;
-
-            match result {
-                Ok(1) => true,
-                Ok(0) => false,
-                Err(e) => {
-                    tracing::error!("Failed to check node cache: {}", e);
-                    false
-                }
-                _ => {
-                    tracing::error!("Unexpected response from redis");
-                    false
+        let cache_result = match self.lazy_connect().await {
+            Ok(mut cm) => {
+                let result = redis::cmd("EXISTS")
+                    .arg(self.cache_key_for_node(node))
+                    .query_async(&mut cm)
+                    .await;
+
+                match result {
+                    Ok(1) => true,
+                    Ok(0) => false,
+                    Err(e) => {
+                        tracing::error!("Failed to check node cache: {}", e);
+                        false
+                    }
+                    _ => {
+                        tracing::error!("Unexpected response from redis");
+                        false
+                    }
                 }
             }
-        } else {
-            false
+            Err(e) => {
+                tracing::error!("Failed to check node cache: {:#}", e);
+                false
+            }
         };
 
         tracing::Span::current().record("hit", cache_result);
@@ -61,16 +65,22 @@ impl NodeCache for Redis {
     /// Logs an error if the node cannot be set in the cache.
     #[tracing::instrument(skip_all, name = "node_cache.redis.get")]
     async fn set(&self, node: &Node) {
-        if let Some(mut cm) = self.lazy_connect().await {
-            let result: Result<(), redis::RedisError> = redis::cmd("SET")
-                .arg(self.cache_key_for_node(node))
-                .arg(1)
-                .query_async(&mut cm)
-                .await;
-
-            if let Err(e) = result {
-                tracing::error!("Failed to set node cache: {}", e);
+        let mut cm = match self.lazy_connect().await {
+            Ok(cm) => cm,
+            Err(e) => {
+                tracing::error!("Failed to set node cache: {:#}", e);
+                return;
             }
+        };
+
+        let result: Result<(), redis::RedisError> = redis::cmd("SET")
+            .arg(self.cache_key_for_node(node))
+            .arg(1)
+            .query_async(&mut cm)
+            .await;
+
+        if let Err(e) = result {
+            tracing::error!("Failed to set node cache: {}", e);
         }
     }
 
@@ -81,16 +91,13 @@ impl NodeCache for Redis {
             ));
         }
 
-        if let Some(mut cm) = self.lazy_connect().await {
-            redis::cmd("DEL")
-                .arg(format!("{}*", self.cache_key_prefix))
-                .query_async(&mut cm)
-                .await?;
+        let mut cm = self.lazy_connect().await?;
+        redis::cmd("DEL")
+            .arg(format!("{}*", self.cache_key_prefix))
+            .query_async(&mut cm)
+            .await?;
 
-            Ok(())
-        } else {
-            anyhow::bail!("Failed to connect to Redis");
-        }
+        Ok(())
     }
 }
 
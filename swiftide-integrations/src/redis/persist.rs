@@ -1,82 +1,174 @@
 use anyhow::{Context as _, Result};
 use async_trait::async_trait;
+use futures_util::{stream, TryStreamExt as _};
 
 use swiftide_core::{
     indexing::{IndexingStream, Node},
     Persist,
 };
 
-use super::Redis;
+use super::{Redis, DEFAULT_BATCH_SIZE};
 
 #[async_trait]
 #[allow(dependency_on_unit_never_type_fallback)]
 impl Persist for Redis {
+    /// Connects to Redis, so that an unreachable server fails early
     async fn setup(&self) -> Result<()> {
-        Ok(())
+        self.lazy_connect().await.map(|_| ())
     }
 
     fn batch_size(&self) -> Option<usize> {
-        Some(self.batch_size)
+        self.batch_size
+    }
+
+    /// Returns the number of keys in the Redis database using the DBSIZE command.
+    ///
+    /// Note that this counts all keys in the database, including any cache entries.
+    async fn count(&self) -> Result<u64> {
+        let mut cm = self.lazy_connect().await?;
+        redis::cmd("DBSIZE")
+            .query_async(&mut cm)
+            .await
+            .context("Error counting keys in redis")
     }
 
     /// Stores a node in Redis using the SET command.
     ///
-    /// By default nodes are stored with the path and hash as key and the node serialized as JSON as value.
+    /// By default nodes are stored with the path and hash as key and the node serialized as JSON,
+    /// tagged with its schema version, as value.
     ///
     /// You can customize the key and value used for storing nodes by setting the `persist_key_fn` and `persist_value_fn` fields.
+    /// If `compress` is enabled, values are gzip compressed. Connection errors are retried up to
+    /// `max_retries` times.
     async fn store(&self, node: Node) -> Result<Node> {
-        if let Some(mut cm) = self.lazy_connect().await {
-            redis::cmd("SET")
-                .arg(self.persist_key_for_node(&node)?)
-                .arg(self.persist_value_for_node(&node)?)
-                .query_async(&mut cm)
-                .await
-                .context("Error persisting to redis")?;
-
-            Ok(node)
-        } else {
-            anyhow::bail!("Failed to connect to Redis")
-        }
+        let mut cmd = redis::cmd("SET");
+        cmd.arg(self.persist_key_for_node(&node)?)
+            .arg(self.encode_value(self.persist_value_for_node(&node)?)?);
+        self.query_with_retries::<()>(&cmd)
+            .await
+            .context("Error persisting to redis")?;
+
+        Ok(node)
     }
 
     /// Stores a batch of nodes in Redis using the MSET command.
     ///
-    /// By default nodes are stored with the path and hash as key and the node serialized as JSON as value.
+    /// By default nodes are stored with the path and hash as key and the node serialized as JSON,
+    /// tagged with its schema version, as value.
     ///
     /// You can customize the key and value used for storing nodes by setting the `persist_key_fn` and `persist_value_fn` fields.
+    /// If `compress` is enabled, values are gzip compressed. Connection errors are retried up to
+    /// `max_retries` times.
     async fn batch_store(&self, nodes: Vec<Node>) -> IndexingStream {
         // use mset for batch store
-        if let Some(mut cm) = self.lazy_connect().await {
-            let args = nodes
-                .iter()
-                .map(|node| -> Result<Vec<String>> {
-                    let key = self.persist_key_for_node(node)?;
-                    let value = self.persist_value_for_node(node)?;
-
-                    Ok(vec![key, value])
-                })
-                .collect::<Result<Vec<_>>>();
-
-            if args.is_err() {
-                return vec![Err(args.unwrap_err())].into();
-            }
+        let args = nodes
+            .iter()
+            .map(|node| -> Result<(String, Vec<u8>)> {
+                let key = self.persist_key_for_node(node)?;
+                let value = self.encode_value(self.persist_value_for_node(node)?)?;
 
-            let args = args.unwrap();
+                Ok((key, value))
+            })
+            .collect::<Result<Vec<_>>>();
 
-            let result: Result<()> = redis::cmd("MSET")
-                .arg(args)
-                .query_async(&mut cm)
-                .await
-                .context("Error persisting to redis");
+        if args.is_err() {
+            return vec![Err(args.unwrap_err())].into();
+        }
 
-            if result.is_ok() {
-                IndexingStream::iter(nodes.into_iter().map(Ok))
-            } else {
-                IndexingStream::iter([Err(result.unwrap_err())])
-            }
+        let args = args.unwrap();
+
+        let mut cmd = redis::cmd("MSET");
+        cmd.arg(args);
+        let result: Result<()> = self
+            .query_with_retries(&cmd)
+            .await
+            .context("Error persisting to redis");
+
+        if result.is_ok() {
+            IndexingStream::iter(nodes.into_iter().map(Ok))
         } else {
-            IndexingStream::iter([Err(anyhow::anyhow!("Failed to connect to Redis"))])
+            IndexingStream::iter([Err(result.unwrap_err())])
+        }
+    }
+
+    /// Checks which nodes are persisted, using EXISTS on their persist keys in a pipeline.
+    async fn exists(&self, nodes: &[Node]) -> Result<Vec<bool>> {
+        let mut pipe = redis::pipe();
+        for node in nodes {
+            pipe.exists(self.persist_key_for_node(node)?);
+        }
+
+        let mut cm = self.lazy_connect().await?;
+        pipe.query_async(&mut cm)
+            .await
+            .context("Error checking for existing nodes in redis")
+    }
+
+    /// Streams all persisted nodes, using SCAN and MGET in pages of `batch_size` keys.
+    ///
+    /// Every key outside of the cache key prefix is expected to hold a node persisted with the
+    /// default value format, so the database should not be shared with other data. Not supported
+    /// with a custom `persist_value_fn`.
+    async fn stream_all(&self) -> IndexingStream {
+        if self.persist_value_fn.is_some() {
+            return anyhow::anyhow!(
+                "Streaming nodes with a custom persist_value_fn is not supported"
+            )
+            .into();
         }
+
+        let cm = match self.lazy_connect().await {
+            Ok(cm) => cm,
+            Err(err) => return err.into(),
+        };
+
+        let cache_prefix =
+            (!self.cache_key_prefix.is_empty()).then(|| format!("{}:", self.cache_key_prefix));
+        let count = self.batch_size.unwrap_or(DEFAULT_BATCH_SIZE);
+
+        let pages = stream::try_unfold((cm, Some(0_u64)), move |(mut cm, cursor)| {
+            let cache_prefix = cache_prefix.clone();
+            async move {
+                let Some(cursor) = cursor else {
+                    return anyhow::Ok(None);
+                };
+
+                let (next, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+                    .arg(cursor)
+                    .arg("COUNT")
+                    .arg(count)
+                    .query_async(&mut cm)
+                    .await
+                    .context("Error scanning redis")?;
+                let keys = keys
+                    .into_iter()
+                    .filter(|key| {
+                        !cache_prefix
+                            .as_ref()
+                            .is_some_and(|prefix| key.starts_with(prefix))
+                    })
+                    .collect::<Vec<_>>();
+
+                let values: Vec<Option<Vec<u8>>> = if keys.is_empty() {
+                    Vec::new()
+                } else {
+                    redis::cmd("MGET")
+                        .arg(keys)
+                        .query_async(&mut cm)
+                        .await
+                        .context("Error getting from redis")?
+                };
+                let nodes = values
+                    .into_iter()
+                    .flatten()
+                    .map(|value| Redis::node_from_value(&Redis::decode_value(value)?))
+                    .collect::<Vec<_>>();
+
+                Ok(Some((nodes, (cm, (next != 0).then_some(next)))))
+            }
+        });
+
+        IndexingStream::from_stream(pages.map_ok(stream::iter).try_flatten())
     }
 }
 
@@ -84,6 +176,7 @@ impl Persist for Redis {
 mod tests {
     use super::*;
     use futures_util::TryStreamExt;
+    use swiftide_core::{indexing::ChunkIdFormat, NodeCache as _};
     use testcontainers::{runners::AsyncRunner, ContainerAsync, GenericImage};
 
     async fn start_redis() -> ContainerAsync<GenericImage> {
@@ -116,9 +209,9 @@ mod tests {
         };
 
         redis.store(node.clone()).await.unwrap();
-        let stored_node = serde_json::from_str(&redis.get_node(&node).await.unwrap().unwrap());
+        let stored_node = redis.get_persisted_node(&node).await.unwrap().unwrap();
 
-        assert_eq!(node, stored_node.unwrap());
+        assert_eq!(node, stored_node);
     }
 
     // test batch store
@@ -149,13 +242,84 @@ mod tests {
         let streamed_nodes: Vec<Node> = stream.try_collect().await.unwrap();
 
         assert_eq!(streamed_nodes.len(), 2);
+        assert_eq!(redis.count().await.unwrap(), 2);
 
         for node in streamed_nodes {
-            let stored_node = serde_json::from_str(&redis.get_node(&node).await.unwrap().unwrap());
-            assert_eq!(node, stored_node.unwrap());
+            let stored_node = redis.get_persisted_node(&node).await.unwrap().unwrap();
+            assert_eq!(node, stored_node);
         }
     }
 
+    #[test_log::test(tokio::test)]
+    async fn test_redis_stream_all() {
+        let redis_container = start_redis().await;
+        let host = redis_container.get_host().await.unwrap();
+        let port = redis_container.get_host_port_ipv4(6379).await.unwrap();
+        let redis = Redis::try_build_from_url(format!("redis://{host}:{port}"))
+            .unwrap()
+            .cache_key_prefix("cache".to_string())
+            .batch_size(2)
+            .build()
+            .unwrap();
+        let nodes = (0..5)
+            .map(|i| Node {
+                path: format!("doc_{i}.md").into(),
+                chunk: format!("chunk {i}"),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+
+        redis
+            .batch_store(nodes.clone())
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        redis.set(&nodes[0]).await;
+
+        let mut streamed: Vec<Node> = redis.stream_all().await.try_collect().await.unwrap();
+        streamed.sort_by(|a, b| a.chunk.cmp(&b.chunk));
+
+        assert_eq!(streamed, nodes);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_redis_persist_in_database_index() {
+        let redis_container = start_redis().await;
+        let host = redis_container.get_host().await.unwrap();
+        let port = redis_container.get_host_port_ipv4(6379).await.unwrap();
+        let url = format!("redis://{host}:{port}");
+        let redis = Redis::try_build_from_url(&url)
+            .unwrap()
+            .db_index(3)
+            .build()
+            .unwrap();
+        let default_db = Redis::try_build_from_url(&url).unwrap().build().unwrap();
+        let node = Node::new("chunk");
+
+        redis.store(node.clone()).await.unwrap();
+
+        assert!(redis.get_node(&node).await.unwrap().is_some());
+        assert!(default_db.get_node(&node).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_persist_key_with_chunk_id_format() {
+        let redis = Redis::try_build_from_url("redis://localhost")
+            .unwrap()
+            .chunk_id_format(ChunkIdFormat::PathAndIndex)
+            .build()
+            .unwrap();
+        let mut node = Node::new("chunk");
+        node.path = "docs/intro.md".into();
+        node.chunk_index = Some(2);
+
+        assert_eq!(
+            redis.persist_key_for_node(&node).unwrap(),
+            "docs/intro.md#2"
+        );
+    }
+
     #[test_log::test(tokio::test)]
     async fn test_redis_custom_persist() {
         let redis_container = start_redis().await;
@@ -181,4 +345,27 @@ mod tests {
             "test".to_string()
         );
     }
+
+    #[test_log::test(tokio::test)]
+    async fn test_redis_compressed_persist() {
+        let redis_container = start_redis().await;
+        let host = redis_container.get_host().await.unwrap();
+        let port = redis_container.get_host_port_ipv4(6379).await.unwrap();
+        let redis = Redis::try_build_from_url(format!("redis://{host}:{port}"))
+            .unwrap()
+            .compress(true)
+            .build()
+            .unwrap();
+        let node = Node {
+            id: None,
+            path: "large".into(),
+            chunk: "A large chunk that compresses well. ".repeat(10_000),
+            ..Default::default()
+        };
+
+        redis.store(node.clone()).await.unwrap();
+        let stored_node = redis.get_persisted_node(&node).await.unwrap().unwrap();
+
+        assert_eq!(node, stored_node);
+    }
 }
@@ -2,28 +2,64 @@ use std::sync::Arc;
 
 use anyhow::Result;
 use async_trait::async_trait;
-use htmd::HtmlToMarkdown;
+use htmd::{
+    options::{BulletListMarker, Options},
+    HtmlToMarkdown,
+};
+use html5ever::{
+    parse_document,
+    serialize::{serialize, SerializeOpts, TraversalScope},
+    tendril::TendrilSink as _,
+};
+use markup5ever_rcdom::{Handle, NodeData, RcDom, SerializableHandle};
 
 use swiftide_core::{indexing::Node, Transformer};
 
+/// Tags that are always dropped by the default converter, with their content
+const DEFAULT_SKIP_TAGS: [&str; 2] = ["script", "style"];
+
+/// Tags of which the content is kept with `main_content_only`
+const MAIN_CONTENT_TAGS: [&str; 2] = ["main", "article"];
+
 /// Transforms HTML content into markdown.
 ///
-/// Useful for converting scraping results into markdown.
+/// Useful for converting scraping results into markdown. Headings, lists and links are preserved,
+/// script and style tags are dropped. Sets `content_type` to `markdown` in the metadata.
+///
+/// Boilerplate can be dropped by skipping more tags, i.e. `nav`, `footer` and `aside`, or by only
+/// keeping the content of `<main>` and `<article>` elements. Both are applied to the HTML before
+/// it is converted, so they also work with a custom `htmd` converter.
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_integrations::scraping::HtmlToMarkdownTransformer;
+/// let transformer = HtmlToMarkdownTransformer::default()
+///     .with_skip_tags(["nav", "footer", "aside"])
+///     .with_main_content_only(true)
+///     .with_collapse_whitespace(true);
+/// ```
 #[swiftide_macros::indexing_transformer(derive(skip_default, skip_debug))]
 pub struct HtmlToMarkdownTransformer {
     /// The `HtmlToMarkdown` instance used to convert HTML to markdown.
     ///
     /// Sets a sane default, but can be customized.
     htmd: Arc<HtmlToMarkdown>,
+    #[builder(setter(skip), default)]
+    skip_tags: Vec<String>,
+    #[builder(setter(skip), default)]
+    main_content_only: bool,
+    #[builder(setter(skip), default)]
+    collapse_whitespace: bool,
 }
 
 impl Default for HtmlToMarkdownTransformer {
     fn default() -> Self {
         Self {
-            htmd: HtmlToMarkdown::builder()
-                .skip_tags(vec!["script", "style"])
-                .build()
-                .into(),
+            htmd: build_htmd().into(),
+            skip_tags: Vec::new(),
+            main_content_only: false,
+            collapse_whitespace: false,
             concurrency: None,
             client: None,
             indexing_defaults: None,
@@ -33,22 +69,186 @@ impl Default for HtmlToMarkdownTransformer {
 
 impl std::fmt::Debug for HtmlToMarkdownTransformer {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("HtmlToMarkdownTransformer").finish()
+        f.debug_struct("HtmlToMarkdownTransformer")
+            .field("skip_tags", &self.skip_tags)
+            .field("main_content_only", &self.main_content_only)
+            .field("collapse_whitespace", &self.collapse_whitespace)
+            .finish()
+    }
+}
+
+impl HtmlToMarkdownTransformer {
+    /// Drops the given tags and their content, in addition to script and style tags
+    ///
+    /// Only tag names are supported, not css selectors.
+    #[must_use]
+    pub fn with_skip_tags(mut self, tags: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.skip_tags.extend(tags.into_iter().map(Into::into));
+        self
+    }
+
+    /// Only keeps the content of `<main>` and `<article>` elements, if the document has any
+    ///
+    /// Every element is converted on its own, separated by a blank line, and anything in between
+    /// is dropped.
+    #[must_use]
+    pub fn with_main_content_only(mut self, main_content_only: bool) -> Self {
+        self.main_content_only = main_content_only;
+        self
+    }
+
+    /// Collapses runs of spaces within lines into one and runs of blank lines into a single blank
+    /// line
+    ///
+    /// The indentation of lines is kept, so that nested lists and code blocks keep their
+    /// structure. Trailing whitespace is removed.
+    #[must_use]
+    pub fn with_collapse_whitespace(mut self, collapse_whitespace: bool) -> Self {
+        self.collapse_whitespace = collapse_whitespace;
+        self
+    }
+
+    fn convert(&self, html: &str) -> Result<String> {
+        let mut markdown = if self.skip_tags.is_empty() && !self.main_content_only {
+            self.htmd.convert(html)?
+        } else {
+            self.html_parts(html)?
+                .iter()
+                .map(|part| self.htmd.convert(part))
+                .collect::<std::io::Result<Vec<_>>>()?
+                .into_iter()
+                .filter(|markdown| !markdown.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n\n")
+        };
+
+        if self.collapse_whitespace {
+            markdown = collapse_whitespace(&markdown);
+        }
+
+        Ok(markdown)
+    }
+
+    /// Drops the skipped tags and returns the HTML of the parts to convert
+    ///
+    /// The parts are the main content elements if only main content is kept and the document has
+    /// any, otherwise the whole document.
+    fn html_parts(&self, html: &str) -> Result<Vec<String>> {
+        let dom = parse_document(RcDom::default(), html5ever::ParseOpts::default())
+            .from_utf8()
+            .read_from(&mut html.as_bytes())?;
+
+        remove_tags(&dom.document, &self.skip_tags);
+
+        let mut main_content = Vec::new();
+        if self.main_content_only {
+            collect_tags(&dom.document, &MAIN_CONTENT_TAGS, &mut main_content);
+        }
+
+        if main_content.is_empty() {
+            return Ok(vec![serialize_html(
+                &dom.document,
+                TraversalScope::ChildrenOnly(None),
+            )?]);
+        }
+
+        main_content
+            .iter()
+            .map(|element| serialize_html(element, TraversalScope::IncludeNode))
+            .collect()
     }
 }
 
+fn build_htmd() -> HtmlToMarkdown {
+    HtmlToMarkdown::builder()
+        .skip_tags(DEFAULT_SKIP_TAGS.to_vec())
+        .options(Options {
+            bullet_list_marker: BulletListMarker::Dash,
+            ..Default::default()
+        })
+        .build()
+}
+
+/// Returns true if the node is an element with one of the tag names
+fn is_element_in(handle: &Handle, tags: &[impl AsRef<str>]) -> bool {
+    let NodeData::Element { name, .. } = &handle.data else {
+        return false;
+    };
+
+    tags.iter()
+        .any(|tag| tag.as_ref().eq_ignore_ascii_case(&name.local))
+}
+
+/// Removes elements with one of the tag names and their content, recursively
+fn remove_tags(handle: &Handle, tags: &[String]) {
+    handle
+        .children
+        .borrow_mut()
+        .retain(|child| !is_element_in(child, tags));
+
+    for child in handle.children.borrow().iter() {
+        remove_tags(child, tags);
+    }
+}
+
+/// Collects the outermost elements with one of the tag names, in document order
+fn collect_tags(handle: &Handle, tags: &[&str], elements: &mut Vec<Handle>) {
+    for child in handle.children.borrow().iter() {
+        if is_element_in(child, tags) {
+            elements.push(child.clone());
+        } else {
+            collect_tags(child, tags, elements);
+        }
+    }
+}
+
+fn serialize_html(handle: &Handle, traversal_scope: TraversalScope) -> Result<String> {
+    let mut html = Vec::new();
+    serialize(
+        &mut html,
+        &SerializableHandle::from(handle.clone()),
+        SerializeOpts {
+            traversal_scope,
+            ..Default::default()
+        },
+    )?;
+
+    Ok(String::from_utf8(html)?)
+}
+
+fn collapse_whitespace(markdown: &str) -> String {
+    let mut collapsed = String::with_capacity(markdown.len());
+    let mut blank_lines = 0;
+
+    for line in markdown.lines() {
+        let content = line.trim_start();
+        if content.trim_end().is_empty() {
+            blank_lines += 1;
+            continue;
+        }
+
+        if !collapsed.is_empty() {
+            collapsed.push_str(if blank_lines > 0 { "\n\n" } else { "\n" });
+        }
+        collapsed.push_str(&line[..line.len() - content.len()]);
+        collapsed.push_str(&content.split_whitespace().collect::<Vec<_>>().join(" "));
+        blank_lines = 0;
+    }
+
+    collapsed
+}
+
 #[async_trait]
 impl Transformer for HtmlToMarkdownTransformer {
     /// Converts the HTML content in the `Node` to markdown.
     ///
     /// Will Err the node if the conversion fails.
     #[tracing::instrument(skip_all, name = "transformer.html_to_markdown")]
-    async fn transform_node(&self, node: Node) -> Result<Node> {
-        let chunk = self.htmd.convert(&node.chunk);
-        Ok(Node {
-            chunk: chunk?,
-            ..node
-        })
+    async fn transform_node(&self, mut node: Node) -> Result<Node> {
+        node.chunk = self.convert(&node.chunk)?;
+        node.metadata.insert("content_type", "markdown");
+
+        Ok(node)
     }
 
     fn concurrency(&self) -> Option<usize> {
@@ -66,5 +266,146 @@ mod test {
         let transformer = HtmlToMarkdownTransformer::default();
         let transformed_node = transformer.transform_node(node).await.unwrap();
         assert_eq!(transformed_node.chunk, "# Hello, World!");
+        assert_eq!(
+            transformed_node.metadata.get("content_type").unwrap(),
+            "markdown"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_html_to_markdown_structure() {
+        let node = Node::new(
+            r#"<html><head><style>h1 { color: red; }</style></head><body>
+            <h1>Title</h1>
+            <h2>Section</h2>
+            <ul><li>One</li><li>Two</li></ul>
+            <p>See <a href="https://swiftide.rs">the docs</a></p>
+            <script>alert("hi")</script>
+            </body></html>"#,
+        );
+        let transformer = HtmlToMarkdownTransformer::default();
+        let markdown = transformer.transform_node(node).await.unwrap().chunk;
+
+        assert!(markdown.contains("# Title"), "{markdown}");
+        assert!(markdown.contains("## Section"), "{markdown}");
+        assert!(markdown.contains("-   One\n-   Two"), "{markdown}");
+        assert!(
+            markdown.contains("[the docs](https://swiftide.rs)"),
+            "{markdown}"
+        );
+        assert!(!markdown.contains("alert"), "{markdown}");
+        assert!(!markdown.contains("color"), "{markdown}");
+    }
+
+    const PAGE: &str = r#"<html><body>
+        <nav><a href="/">Home</a> <a href="/blog">Blog</a></nav>
+        <main>
+            <h1>Title</h1>
+            <p>The     actual
+
+
+            content.</p>
+        </main>
+        <aside>Related posts</aside>
+        <footer>Copyright Acme</footer>
+        </body></html>"#;
+
+    #[tokio::test]
+    async fn test_skips_boilerplate_tags() {
+        let transformer =
+            HtmlToMarkdownTransformer::default().with_skip_tags(["nav", "footer", "aside"]);
+
+        let markdown = transformer
+            .transform_node(Node::new(PAGE))
+            .await
+            .unwrap()
+            .chunk;
+
+        assert!(markdown.contains("# Title"), "{markdown}");
+        assert!(markdown.contains("content."), "{markdown}");
+        assert!(!markdown.contains("Home"), "{markdown}");
+        assert!(!markdown.contains("Related"), "{markdown}");
+        assert!(!markdown.contains("Copyright"), "{markdown}");
+    }
+
+    #[tokio::test]
+    async fn test_keeps_main_content_only() {
+        let transformer = HtmlToMarkdownTransformer::default()
+            .with_main_content_only(true)
+            .with_collapse_whitespace(true);
+
+        let markdown = transformer
+            .transform_node(Node::new(PAGE))
+            .await
+            .unwrap()
+            .chunk;
+
+        assert_eq!(markdown, "# Title\n\nThe actual content.");
+    }
+
+    #[tokio::test]
+    async fn test_keeps_each_main_content_block_separately() {
+        let html = "<html><body>
+            <article><h2>First</h2><main><p>Nested</p></main></article>
+            <div>Newsletter signup</div>
+            <article><h2>Second</h2></article>
+            </body></html>";
+        let transformer = HtmlToMarkdownTransformer::default().with_main_content_only(true);
+
+        let markdown = transformer
+            .transform_node(Node::new(html))
+            .await
+            .unwrap()
+            .chunk;
+
+        assert_eq!(markdown, "## First\n\nNested\n\n## Second");
+    }
+
+    #[tokio::test]
+    async fn test_skip_tags_keep_a_custom_converter() {
+        let htmd = HtmlToMarkdown::builder()
+            .add_handler(vec!["h1"], |element: htmd::Element| {
+                Some(format!("TITLE {}", element.content))
+            })
+            .build();
+        let transformer = HtmlToMarkdownTransformer::builder()
+            .htmd(htmd)
+            .build()
+            .unwrap()
+            .with_skip_tags(["nav", "footer", "aside"])
+            .with_main_content_only(true);
+
+        let markdown = transformer
+            .transform_node(Node::new(PAGE))
+            .await
+            .unwrap()
+            .chunk;
+
+        assert!(markdown.starts_with("TITLE Title"), "{markdown}");
+        assert!(!markdown.contains("Home"), "{markdown}");
+        assert!(!markdown.contains("Copyright"), "{markdown}");
+    }
+
+    #[tokio::test]
+    async fn test_collapsing_whitespace_keeps_nested_lists() {
+        let transformer = HtmlToMarkdownTransformer::default().with_collapse_whitespace(true);
+        let html = "<ul><li>First   item<ul><li>Nested   item</li></ul></li><li>Second</li></ul>";
+
+        let markdown = transformer
+            .transform_node(Node::new(html))
+            .await
+            .unwrap()
+            .chunk;
+
+        assert_eq!(markdown, "- First item\n    - Nested item\n- Second");
+    }
+
+    #[test]
+    fn test_collapses_whitespace() {
+        assert_eq!(collapse_whitespace("a   b  \n\n\n\nc\t d\n"), "a b\n\nc d");
+        assert_eq!(
+            collapse_whitespace("-  a\n    -   b  c \n"),
+            "- a\n    - b c"
+        );
     }
 }
@@ -5,7 +5,7 @@ use spider::website::Website;
 use tokio::{runtime::Handle, sync::RwLock};
 
 use swiftide_core::{
-    indexing::{IndexingStream, Node},
+    indexing::{IndexingStream, Metadata, Node},
     Loader,
 };
 
@@ -17,6 +17,9 @@ use swiftide_core::{
 /// For more configuration options see their documentation.
 pub struct ScrapingLoader {
     spider_website: Arc<RwLock<Website>>,
+    /// Static metadata added to every node, i.e. to tag nodes with their source
+    #[builder(default, setter(into))]
+    metadata: Metadata,
 }
 
 impl ScrapingLoader {
@@ -29,6 +32,7 @@ impl ScrapingLoader {
     pub fn from_spider(spider_website: Website) -> Self {
         Self {
             spider_website: Arc::new(RwLock::new(spider_website)),
+            metadata: Metadata::default(),
         }
     }
 
@@ -36,6 +40,13 @@ impl ScrapingLoader {
     pub fn from_url(url: impl AsRef<str>) -> Self {
         Self::from_spider(Website::new(url.as_ref()))
     }
+
+    /// Adds static metadata to every scraped node, i.e. to tag nodes with their source
+    #[must_use]
+    pub fn with_metadata(mut self, metadata: impl Into<Metadata>) -> Self {
+        self.metadata.extend(metadata.into());
+        self
+    }
 }
 
 impl Loader for ScrapingLoader {
@@ -76,7 +87,7 @@ impl Loader for ScrapingLoader {
 
         // NOTE: Handles should stay alive because of rx, but feels a bit fishy
 
-        IndexingStream::iter(rx)
+        IndexingStream::iter(rx).with_metadata(self.metadata)
     }
 
     fn into_stream_boxed(self: Box<Self>) -> IndexingStream {
@@ -0,0 +1,236 @@
+use anyhow::{Context as _, Result};
+use futures_util::TryStreamExt as _;
+use sqlx::{any::AnyRow, AnyPool, Row as _};
+use swiftide_core::{indexing::IndexingStream, indexing::Node, Loader};
+use tokio::sync::mpsc;
+
+use super::SqlLoader;
+
+/// Number of rows buffered before the database stream is paused
+const BUFFER_SIZE: usize = 256;
+
+/// Name of the server side cursor rows are fetched from on Postgres
+const CURSOR: &str = "swiftide_sql_loader";
+
+impl Loader for SqlLoader {
+    #[tracing::instrument(skip_all)]
+    fn into_stream(self) -> IndexingStream {
+        let metadata = self.metadata.clone();
+        let (tx, rx) = mpsc::channel(BUFFER_SIZE);
+
+        tokio::spawn(async move {
+            if let Err(err) = self.send_rows(&tx).await {
+                let _ = tx.send(Err(err)).await;
+            }
+        });
+
+        IndexingStream::from_stream(futures_util::stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|row| (row, rx))
+        }))
+        .with_metadata(metadata)
+    }
+
+    fn into_stream_boxed(self: Box<Self>) -> IndexingStream {
+        self.into_stream()
+    }
+}
+
+impl SqlLoader {
+    async fn send_rows(&self, tx: &mpsc::Sender<Result<Node>>) -> Result<()> {
+        sqlx::any::install_default_drivers();
+
+        let pool = sqlx::any::AnyPoolOptions::new()
+            .max_connections(1)
+            .connect(&self.connection_string)
+            .await
+            .context("Failed to connect to database")?;
+
+        if self.is_postgres() {
+            return self.send_rows_with_cursor(&pool, tx).await;
+        }
+
+        let mut rows = sqlx::query(&self.query).fetch(&pool);
+        while let Some(row) = rows.try_next().await.context("Failed to fetch row")? {
+            if tx.send(self.row_to_node(&row)).await.is_err() {
+                tracing::debug!("Stream dropped, stopping query");
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fetches the rows from a server side cursor in batches of `fetch_size`, so that Postgres
+    /// does not send the full result at once
+    async fn send_rows_with_cursor(
+        &self,
+        pool: &AnyPool,
+        tx: &mpsc::Sender<Result<Node>>,
+    ) -> Result<()> {
+        let mut transaction = pool.begin().await.context("Failed to start transaction")?;
+        sqlx::query(&format!(
+            "DECLARE {CURSOR} NO SCROLL CURSOR FOR {}",
+            self.query
+        ))
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to declare cursor")?;
+
+        let fetch = format!("FETCH {} FROM {CURSOR}", self.fetch_size.max(1));
+        'fetch: loop {
+            let rows = sqlx::query(&fetch)
+                .fetch_all(&mut *transaction)
+                .await
+                .context("Failed to fetch rows")?;
+            if rows.is_empty() {
+                break;
+            }
+
+            for row in &rows {
+                if tx.send(self.row_to_node(row)).await.is_err() {
+                    tracing::debug!("Stream dropped, stopping query");
+                    break 'fetch;
+                }
+            }
+        }
+
+        // The cursor is closed with the transaction, which only reads
+        transaction
+            .rollback()
+            .await
+            .context("Failed to close cursor")?;
+        Ok(())
+    }
+
+    fn is_postgres(&self) -> bool {
+        ["postgres:", "postgresql:"]
+            .iter()
+            .any(|scheme| self.connection_string.starts_with(scheme))
+    }
+
+    fn row_to_node(&self, row: &AnyRow) -> Result<Node> {
+        let chunk: String = row
+            .try_get(self.chunk_column.as_str())
+            .with_context(|| format!("Failed to read chunk from {}", self.chunk_column))?;
+        let mut node = Node::new(chunk);
+
+        if let Some(path_column) = &self.path_column {
+            let path: String = row
+                .try_get(path_column.as_str())
+                .with_context(|| format!("Failed to read path from {path_column}"))?;
+            node.path = path.into();
+        }
+
+        for column in &self.metadata_columns {
+            node.metadata.insert(column, column_value(row, column)?);
+        }
+
+        Ok(node)
+    }
+}
+
+/// Decodes a column into a json value, trying the types supported by the any driver
+fn column_value(row: &AnyRow, column: &str) -> Result<serde_json::Value> {
+    if let Ok(value) = row.try_get::<Option<String>, _>(column) {
+        return Ok(value.into());
+    }
+    if let Ok(value) = row.try_get::<Option<i64>, _>(column) {
+        return Ok(value.into());
+    }
+    if let Ok(value) = row.try_get::<Option<i32>, _>(column) {
+        return Ok(value.into());
+    }
+    if let Ok(value) = row.try_get::<Option<f64>, _>(column) {
+        return Ok(value.into());
+    }
+    if let Ok(value) = row.try_get::<Option<bool>, _>(column) {
+        return Ok(value.into());
+    }
+
+    anyhow::bail!("Unsupported type for metadata column {column}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testcontainers::{runners::AsyncRunner, ContainerAsync, GenericImage, ImageExt};
+
+    async fn start_postgres() -> (ContainerAsync<GenericImage>, String) {
+        let container = GenericImage::new("postgres", "16-alpine")
+            .with_exposed_port(5432.into())
+            .with_wait_for(testcontainers::core::WaitFor::message_on_stderr(
+                "database system is ready to accept connections",
+            ))
+            .with_env_var("POSTGRES_PASSWORD", "postgres")
+            .start()
+            .await
+            .expect("Postgres started");
+
+        let host = container.get_host().await.unwrap();
+        let port = container.get_host_port_ipv4(5432).await.unwrap();
+
+        (
+            container,
+            format!("postgres://postgres:postgres@{host}:{port}/postgres"),
+        )
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_sql_loader() {
+        let (_guard, url) = start_postgres().await;
+
+        sqlx::any::install_default_drivers();
+        let pool = sqlx::AnyPool::connect(&url).await.unwrap();
+        sqlx::query("CREATE TABLE docs (id SERIAL, body TEXT, source TEXT, stars BIGINT)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO docs (body, source, stars) VALUES ('first', 'a.md', 1), ('second', 'b.md', 2)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let loader = SqlLoader::builder()
+            .connection_string(url.clone())
+            .query("SELECT body, source, stars FROM docs ORDER BY id")
+            .chunk_column("body")
+            .path_column("source")
+            .metadata_columns(vec!["stars".to_string()])
+            .build()
+            .unwrap();
+
+        let nodes: Vec<Node> = loader.into_stream().try_collect().await.unwrap();
+
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0].chunk, "first");
+        assert_eq!(nodes[0].path.to_str(), Some("a.md"));
+        assert_eq!(nodes[1].metadata.get("stars"), Some(&serde_json::json!(2)));
+
+        let loader = SqlLoader::builder()
+            .connection_string(url)
+            .query("SELECT 'row ' || n AS body FROM generate_series(1, 25) AS n")
+            .chunk_column("body")
+            .fetch_size(10_usize)
+            .build()
+            .unwrap();
+        let nodes: Vec<Node> = loader.into_stream().try_collect().await.unwrap();
+        assert_eq!(nodes.len(), 25);
+    }
+
+    #[test]
+    fn test_uses_cursor_for_postgres() {
+        let loader = |connection_string: &str| {
+            SqlLoader::builder()
+                .connection_string(connection_string)
+                .query("SELECT 1")
+                .build()
+                .unwrap()
+        };
+
+        assert!(loader("postgres://localhost/swiftide").is_postgres());
+        assert!(loader("postgresql://localhost/swiftide").is_postgres());
+        assert!(!loader("mysql://localhost/swiftide").is_postgres());
+    }
+}
@@ -0,0 +1,33 @@
+//! Timeouts for requests to storage backends
+#[cfg(any(feature = "milvus", feature = "redis"))]
+use std::{future::Future, time::Duration};
+
+#[cfg(any(feature = "milvus", feature = "redis"))]
+use anyhow::Result;
+
+/// Awaits the future, failing if it does not complete within the timeout, if any
+///
+/// The error reads "Timed out {action} after {timeout}", i.e. "Timed out connecting to Redis
+/// after 5s".
+#[cfg(any(feature = "milvus", feature = "redis"))]
+pub(crate) async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    action: &str,
+    future: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let Some(timeout) = timeout else {
+        return future.await;
+    };
+
+    tokio::time::timeout(timeout, future)
+        .await
+        .map_err(|_| anyhow::anyhow!("Timed out {action} after {timeout:?}"))?
+}
+
+/// Accepts connections on a local port without ever responding, to test timeouts
+#[cfg(test)]
+pub(crate) async fn unresponsive_server() -> (tokio::net::TcpListener, String) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    (listener, address)
+}
@@ -0,0 +1,202 @@
+//! This module provides integration with the Typesense search engine.
+//!
+//! Typesense can be used as storage in an `indexing::Pipeline`, i.e. for keyword heavy search
+//! combined with vector search.
+use anyhow::{Context as _, Result};
+use derive_builder::Builder;
+use reqwest::StatusCode;
+use secrecy::{ExposeSecret as _, Secret};
+use serde::Deserialize;
+use serde_json::json;
+use swiftide_core::indexing::{ChunkIdFormat, EmbeddedField};
+
+mod persist;
+
+const DEFAULT_TYPESENSE_URL: &str = "http://localhost:8108";
+const DEFAULT_COLLECTION_NAME: &str = "swiftide";
+/// The default number of nodes imported per batch
+pub const DEFAULT_BATCH_SIZE: usize = 100;
+
+const API_KEY_HEADER: &str = "X-TYPESENSE-API-KEY";
+
+const PATH_FIELD: &str = "path";
+const CHUNK_FIELD: &str = "chunk";
+const METADATA_FIELD: &str = "metadata";
+const VECTOR_FIELD: &str = "vector";
+
+/// Stores nodes in a Typesense collection
+///
+/// On setup a collection is created with `path`, `chunk`, `metadata` and `vector` fields, if it
+/// does not exist. The node id is used as document id, composed as configured with
+/// `chunk_id_format`, and the metadata is stored as a json
+/// string. Nodes are imported in batches with the bulk import endpoint, upserting existing
+/// documents.
+///
+/// Only a single embedded field is stored, `EmbeddedField::Combined` by default.
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_integrations::typesense::Typesense;
+/// let typesense = Typesense::builder()
+///     .url("http://localhost:8108")
+///     .api_key("xyz".to_string())
+///     .collection_name("swiftide")
+///     .vector_size(1536)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Builder, Clone)]
+#[builder(
+    pattern = "owned",
+    setter(strip_option),
+    build_fn(error = "anyhow::Error")
+)]
+pub struct Typesense {
+    /// The http client to use
+    #[builder(default)]
+    client: reqwest::Client,
+    /// The url of the Typesense server. Defaults to `TYPESENSE_URL` or `http://localhost:8108`.
+    #[builder(setter(into), default = "default_url()")]
+    url: String,
+    /// The api key. Defaults to the `TYPESENSE_API_KEY` environment variable.
+    #[builder(setter(into), default = "default_api_key()")]
+    api_key: Secret<String>,
+    /// The name of the collection. Defaults to "swiftide".
+    #[builder(setter(into), default = "DEFAULT_COLLECTION_NAME.to_string()")]
+    collection_name: String,
+    /// The dimension of the stored vectors
+    vector_size: usize,
+    /// The embedded field of the node to store as vector. Defaults to `EmbeddedField::Combined`.
+    #[builder(default)]
+    vector_field: EmbeddedField,
+    /// How document ids are composed. Defaults to the uuid of the node, see [`ChunkIdFormat`].
+    #[builder(default)]
+    chunk_id_format: ChunkIdFormat,
+    /// The number of nodes imported per batch. Defaults to [`DEFAULT_BATCH_SIZE`].
+    #[builder(default = "Some(DEFAULT_BATCH_SIZE)")]
+    batch_size: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CollectionResponse {
+    #[serde(default)]
+    fields: Vec<FieldResponse>,
+    #[serde(default)]
+    num_documents: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct FieldResponse {
+    name: String,
+    num_dim: Option<usize>,
+}
+
+impl Typesense {
+    pub fn builder() -> TypesenseBuilder {
+        TypesenseBuilder::default()
+    }
+
+    /// Creates the collection if it does not exist
+    ///
+    /// An existing collection is checked against the configured vector size.
+    ///
+    /// # Errors
+    ///
+    /// Errors if Typesense is unreachable, the collection cannot be created, or an existing
+    /// collection has a different vector size.
+    pub async fn create_collection_if_not_exists(&self) -> Result<()> {
+        if let Some(collection) = self.collection().await? {
+            tracing::warn!("Collection {} exists", self.collection_name);
+            return self.check_existing_collection(&collection);
+        }
+
+        tracing::info!("Creating collection {}", self.collection_name);
+        self.request(reqwest::Method::POST, "/collections")
+            .json(&self.schema())
+            .send()
+            .await
+            .context("Request to Typesense failed")?
+            .error_for_status()
+            .with_context(|| format!("Failed to create collection {}", self.collection_name))?;
+
+        Ok(())
+    }
+
+    /// Returns the collection, or `None` if it does not exist
+    async fn collection(&self) -> Result<Option<CollectionResponse>> {
+        let response = self
+            .request(
+                reqwest::Method::GET,
+                &format!("/collections/{}", self.collection_name),
+            )
+            .send()
+            .await
+            .context("Request to Typesense failed")?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let collection = response
+            .error_for_status()
+            .context("Typesense returned an error")?
+            .json()
+            .await
+            .context("Failed to parse collection from Typesense")?;
+        Ok(Some(collection))
+    }
+
+    /// Checks that an existing collection has vectors of the configured size, instead of
+    /// importing into a collection of a different shape
+    fn check_existing_collection(&self, collection: &CollectionResponse) -> Result<()> {
+        let vector_size = collection
+            .fields
+            .iter()
+            .find(|field| field.name == VECTOR_FIELD)
+            .and_then(|field| field.num_dim);
+
+        match vector_size {
+            Some(vector_size) if vector_size == self.vector_size => Ok(()),
+            Some(vector_size) => anyhow::bail!(
+                "Collection {} has vectors of size {vector_size}, but {} is configured, drop it \
+                 or use a different collection",
+                self.collection_name,
+                self.vector_size
+            ),
+            None => anyhow::bail!(
+                "Collection {} has no {VECTOR_FIELD} field with a dimension, drop it or use a \
+                 different collection",
+                self.collection_name
+            ),
+        }
+    }
+
+    fn schema(&self) -> serde_json::Value {
+        json!({
+            "name": self.collection_name,
+            "fields": [
+                { "name": PATH_FIELD, "type": "string", "facet": true },
+                { "name": CHUNK_FIELD, "type": "string" },
+                { "name": METADATA_FIELD, "type": "string", "index": false, "optional": true },
+                { "name": VECTOR_FIELD, "type": "float[]", "num_dim": self.vector_size },
+            ]
+        })
+    }
+
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        self.client
+            .request(method, format!("{}{path}", self.url.trim_end_matches('/')))
+            .header(API_KEY_HEADER, self.api_key.expose_secret())
+    }
+}
+
+fn default_url() -> String {
+    std::env::var("TYPESENSE_URL").unwrap_or(DEFAULT_TYPESENSE_URL.to_string())
+}
+
+fn default_api_key() -> Secret<String> {
+    std::env::var("TYPESENSE_API_KEY")
+        .unwrap_or_else(|_| String::new())
+        .into()
+}
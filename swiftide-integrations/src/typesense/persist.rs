@@ -0,0 +1,379 @@
+use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+use swiftide_core::{
+    indexing::{IndexingStream, Node},
+    Persist,
+};
+
+use super::{Typesense, CHUNK_FIELD, METADATA_FIELD, PATH_FIELD, VECTOR_FIELD};
+
+/// The result of importing a single document, one per line
+#[derive(Debug, Deserialize)]
+struct ImportResult {
+    success: bool,
+    error: Option<String>,
+}
+
+#[async_trait]
+impl Persist for Typesense {
+    #[tracing::instrument(skip_all, err)]
+    async fn setup(&self) -> Result<()> {
+        self.create_collection_if_not_exists().await
+    }
+
+    fn batch_size(&self) -> Option<usize> {
+        self.batch_size
+    }
+
+    #[tracing::instrument(skip_all, err, name = "storage.typesense.store")]
+    async fn store(&self, node: Node) -> Result<Node> {
+        let mut results = self.import(vec![node]).await?;
+        results
+            .pop()
+            .context("Typesense did not return an import result")?
+    }
+
+    /// Imports a batch of nodes, failing only the nodes Typesense rejects
+    #[tracing::instrument(skip_all, name = "storage.typesense.batch_store")]
+    async fn batch_store(&self, nodes: Vec<Node>) -> IndexingStream {
+        match self.import(nodes).await {
+            Ok(results) => IndexingStream::iter(results),
+            Err(err) => err.into(),
+        }
+    }
+
+    /// Returns the number of documents in the collection
+    async fn count(&self) -> Result<u64> {
+        let collection = self
+            .collection()
+            .await?
+            .with_context(|| format!("Collection {} does not exist", self.collection_name))?;
+        Ok(collection.num_documents)
+    }
+}
+
+impl Typesense {
+    /// Imports the nodes with the bulk import endpoint, returning the result per node
+    ///
+    /// Typesense reports the result of every document on its own line, in the order of the
+    /// documents. Nodes that cannot be converted to a document fail on their own and are not
+    /// sent.
+    async fn import(&self, nodes: Vec<Node>) -> Result<Vec<Result<Node>>> {
+        let mut results = Vec::with_capacity(nodes.len());
+        let mut imported = Vec::with_capacity(nodes.len());
+        let mut body = String::new();
+        for node in nodes {
+            match self.document(&node) {
+                Ok(document) => {
+                    body.push_str(&document);
+                    body.push('\n');
+                    imported.push(results.len());
+                    results.push(Ok(node));
+                }
+                Err(err) => results.push(Err(
+                    err.context(format!("Failed to import node {}", node.path.display()))
+                )),
+            }
+        }
+
+        if imported.is_empty() {
+            return Ok(results);
+        }
+
+        tracing::debug!("Importing batch of {} nodes", imported.len());
+        let response = self
+            .request(
+                reqwest::Method::POST,
+                &format!(
+                    "/collections/{}/documents/import?action=upsert",
+                    self.collection_name
+                ),
+            )
+            .header(reqwest::header::CONTENT_TYPE, "text/plain")
+            .body(body)
+            .send()
+            .await
+            .context("Request to Typesense failed")?
+            .error_for_status()
+            .context("Typesense returned an error")?
+            .text()
+            .await
+            .context("Failed to read import response from Typesense")?;
+
+        let import_results = response
+            .lines()
+            .map(serde_json::from_str::<ImportResult>)
+            .collect::<Result<Vec<_>, _>>()
+            .context("Failed to parse import response from Typesense")?;
+
+        if import_results.len() != imported.len() {
+            anyhow::bail!(
+                "Expected {} import results from Typesense, got {}",
+                imported.len(),
+                import_results.len()
+            );
+        }
+
+        for (index, result) in imported.into_iter().zip(import_results) {
+            if result.success {
+                continue;
+            }
+
+            if let Some(Ok(node)) = results.get(index) {
+                let err = anyhow::anyhow!(
+                    "Failed to import node {}: {}",
+                    node.path.display(),
+                    result.error.unwrap_or_default()
+                );
+                results[index] = Err(err);
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Serializes the node as a Typesense document, one line of the import body
+    fn document(&self, node: &Node) -> Result<String> {
+        let vector = node
+            .vectors
+            .as_ref()
+            .and_then(|vectors| vectors.get(&self.vector_field))
+            .with_context(|| format!("Node without a vector for {}", self.vector_field))?;
+
+        let document = json!({
+            "id": node.chunk_id(self.chunk_id_format),
+            PATH_FIELD: node.path.to_string_lossy(),
+            CHUNK_FIELD: node.chunk,
+            METADATA_FIELD: serde_json::to_string(&node.metadata)?,
+            VECTOR_FIELD: vector,
+        });
+        Ok(serde_json::to_string(&document)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::{StreamExt as _, TryStreamExt as _};
+    use secrecy::ExposeSecret as _;
+    use swiftide_core::indexing::{ChunkIdFormat, EmbeddedField};
+    use testcontainers::{
+        core::{IntoContainerPort as _, WaitFor},
+        runners::AsyncRunner as _,
+        ContainerAsync, GenericImage, ImageExt as _,
+    };
+    use wiremock::{
+        matchers::{body_string_contains, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use super::*;
+
+    const API_KEY: &str = "swiftide";
+
+    async fn start_typesense() -> (ContainerAsync<GenericImage>, String) {
+        let container = GenericImage::new("typesense/typesense", "27.1")
+            .with_exposed_port(8108.tcp())
+            .with_wait_for(WaitFor::message_on_stdout("Peer refresh succeeded"))
+            .with_cmd(["--data-dir", "/tmp", "--api-key", API_KEY])
+            .start()
+            .await
+            .expect("Typesense started");
+
+        let host = container.get_host().await.unwrap();
+        let port = container.get_host_port_ipv4(8108).await.unwrap();
+
+        (container, format!("http://{host}:{port}"))
+    }
+
+    fn node(i: u8) -> Node {
+        let mut node = Node::new(format!("chunk {i}"));
+        node.path = format!("file_{i}.md").into();
+        node.metadata.insert("index", i);
+        node.with_vectors([(EmbeddedField::Combined, vec![f32::from(i); 3])]);
+        node
+    }
+
+    #[tokio::test]
+    async fn test_surfaces_import_errors_per_node() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/collections/swiftide/documents/import"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(
+                "{\"success\": true}\n{\"success\": false, \"error\": \"Bad vector\"}",
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let typesense = Typesense::builder()
+            .url(server.uri())
+            .vector_size(3)
+            .build()
+            .unwrap();
+
+        let results = typesense
+            .batch_store(vec![node(0), node(1)])
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(results[0].as_ref().unwrap().chunk, "chunk 0");
+        assert_eq!(
+            results[1].as_ref().unwrap_err().to_string(),
+            "Failed to import node file_1.md: Bad vector"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fails_nodes_without_a_vector_and_imports_the_rest() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/collections/swiftide/documents/import"))
+            .and(body_string_contains("chunk 2"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string("{\"success\": true}\n{\"success\": true}"),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let typesense = Typesense::builder()
+            .url(server.uri())
+            .vector_size(3)
+            .build()
+            .unwrap();
+
+        let mut without_vector = node(1);
+        without_vector.vectors = None;
+        let results = typesense
+            .batch_store(vec![node(0), without_vector, node(2)])
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(results[0].as_ref().unwrap().chunk, "chunk 0");
+        assert_eq!(
+            format!("{:#}", results[1].as_ref().unwrap_err()),
+            "Failed to import node file_1.md: Node without a vector for Combined"
+        );
+        assert_eq!(results[2].as_ref().unwrap().chunk, "chunk 2");
+
+        let requests = server.received_requests().await.unwrap();
+        let body = String::from_utf8_lossy(&requests[0].body);
+        assert_eq!(body.lines().count(), 2);
+        assert!(!body.contains("chunk 1"));
+    }
+
+    #[tokio::test]
+    async fn test_does_not_import_without_documents() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let typesense = Typesense::builder()
+            .url(server.uri())
+            .vector_size(3)
+            .build()
+            .unwrap();
+
+        let mut without_vector = node(1);
+        without_vector.vectors = None;
+
+        assert!(typesense.store(without_vector).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_composes_document_ids() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/collections/swiftide/documents/import"))
+            .and(body_string_contains(r#""id":"file_1.md#4""#))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"success": true}"#))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let typesense = Typesense::builder()
+            .url(server.uri())
+            .vector_size(3)
+            .chunk_id_format(ChunkIdFormat::PathAndIndex)
+            .build()
+            .unwrap();
+        let mut node = node(1);
+        node.chunk_index = Some(4);
+
+        typesense.store(node).await.unwrap();
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_typesense_batch_persist() {
+        let (_container, url) = start_typesense().await;
+        let typesense = Typesense::builder()
+            .url(&url)
+            .api_key(API_KEY.to_string())
+            .collection_name("swiftide_test")
+            .vector_size(3)
+            .build()
+            .unwrap();
+        typesense.setup().await.unwrap();
+        // Setting up an existing collection is fine
+        typesense.setup().await.unwrap();
+
+        let nodes = (0..3).map(node).collect::<Vec<_>>();
+        let stored = typesense
+            .batch_store(nodes.clone())
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(stored.len(), 3);
+        assert_eq!(typesense.count().await.unwrap(), 3);
+
+        let document: serde_json::Value = reqwest::Client::new()
+            .get(format!(
+                "{url}/collections/swiftide_test/documents/{}",
+                nodes[1].id()
+            ))
+            .header("X-TYPESENSE-API-KEY", typesense.api_key.expose_secret())
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(document[CHUNK_FIELD], "chunk 1");
+        assert_eq!(document[PATH_FIELD], "file_1.md");
+        assert_eq!(document[METADATA_FIELD], r#"{"index":1}"#);
+        assert_eq!(document[VECTOR_FIELD], json!([1.0, 1.0, 1.0]));
+
+        // A node with a vector of the wrong size is rejected by Typesense
+        let mut invalid = node(4);
+        invalid.with_vectors([(EmbeddedField::Combined, vec![1.0])]);
+        let err = typesense.store(invalid).await.unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("Failed to import node file_4.md"),
+            "{err}"
+        );
+
+        let mismatch = Typesense::builder()
+            .url(&url)
+            .api_key(API_KEY.to_string())
+            .collection_name("swiftide_test")
+            .vector_size(4)
+            .build()
+            .unwrap();
+        let err = mismatch.setup().await.unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("has vectors of size 3, but 4 is configured"),
+            "{err}"
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use std::sync::atomic::Ordering;
+
+use anyhow::{Context as _, Result};
+use async_trait::async_trait;
+use secrecy::ExposeSecret as _;
+use serde::{Deserialize, Serialize};
+use swiftide_core::{util::debug_redacted, EmbeddingModel, Embeddings};
+
+use super::{InputType, Voyage};
+
+/// Characters per token used to estimate the tokens of an input
+const CHARS_PER_TOKEN: usize = 3;
+
+#[derive(Debug, Serialize)]
+struct EmbeddingsRequest<'a> {
+    model: &'a str,
+    input_type: InputType,
+    input: &'a [String],
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingData>,
+    usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Usage {
+    total_tokens: u64,
+}
+
+#[async_trait]
+impl EmbeddingModel for Voyage {
+    /// Embeds the input in sub-batches that respect the batch size and token limits
+    ///
+    /// Sub-batches run concurrently and the embeddings are returned in input order.
+    #[tracing::instrument(skip_all)]
+    async fn embed(&self, input: Vec<String>) -> Result<Embeddings> {
+        let responses = futures_util::future::try_join_all(
+            self.sub_batches(&input)
+                .into_iter()
+                .map(|texts| self.embed_batch(texts)),
+        )
+        .await?;
+
+        let embeddings = responses.into_iter().flatten().collect::<Embeddings>();
+        if embeddings.len() != input.len() {
+            anyhow::bail!(
+                "Expected {} embeddings, got {}",
+                input.len(),
+                embeddings.len()
+            );
+        }
+
+        Ok(embeddings)
+    }
+}
+
+impl Voyage {
+    /// Splits the input into consecutive batches within the configured limits
+    ///
+    /// A single input over the token limit still gets its own batch, so that Voyage can report
+    /// the error.
+    fn sub_batches<'a>(&self, input: &'a [String]) -> Vec<&'a [String]> {
+        let mut batches = Vec::new();
+        let mut start = 0;
+        let mut tokens = 0;
+
+        for (index, text) in input.iter().enumerate() {
+            let text_tokens = text.chars().count().div_ceil(CHARS_PER_TOKEN);
+            let len = index - start;
+            if len > 0
+                && (len == self.max_batch_size || tokens + text_tokens > self.max_batch_tokens)
+            {
+                batches.push(&input[start..index]);
+                start = index;
+                tokens = 0;
+            }
+            tokens += text_tokens;
+        }
+
+        if start < input.len() {
+            batches.push(&input[start..]);
+        }
+
+        batches
+    }
+
+    async fn embed_batch(&self, texts: &[String]) -> Result<Embeddings> {
+        let request = EmbeddingsRequest {
+            model: &self.model,
+            input_type: self.input_type,
+            input: texts,
+        };
+        tracing::debug!(
+            model = self.model,
+            num_input = texts.len(),
+            request = debug_redacted(&request),
+            "[Embed] Request to voyage"
+        );
+
+        let mut response: EmbeddingsResponse = self
+            .client
+            .post(format!("{}/embeddings", self.api_base))
+            .bearer_auth(self.api_key.expose_secret())
+            .json(&request)
+            .send()
+            .await
+            .context("Request to Voyage failed")?
+            .error_for_status()
+            .context("Voyage returned an error")?
+            .json()
+            .await
+            .context("Failed to parse response from Voyage")?;
+
+        let total_tokens = response.usage.map(|usage| usage.total_tokens);
+        tracing::debug!(
+            num_embeddings = response.data.len(),
+            total_tokens,
+            "[Embed] Response voyage"
+        );
+        if let Some(total_tokens) = total_tokens {
+            self.total_tokens.fetch_add(total_tokens, Ordering::Relaxed);
+        }
+
+        // Embeddings are not guaranteed to be returned in order
+        response.data.sort_by_key(|data| data.index);
+        Ok(response
+            .data
+            .into_iter()
+            .map(|data| data.embedding)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voyage::VoyageBuilder;
+    use wiremock::{
+        matchers::{body_partial_json, header, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    async fn mock_embeddings(
+        server: &MockServer,
+        input_type: &str,
+        input: &[&str],
+        embeddings: &[f32],
+    ) {
+        // Return the embeddings in reverse, as the api does not guarantee order
+        let data = embeddings
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(index, embedding)| serde_json::json!({ "index": index, "embedding": [embedding] }))
+            .collect::<Vec<_>>();
+
+        Mock::given(method("POST"))
+            .and(path("/embeddings"))
+            .and(header("authorization", "Bearer test"))
+            .and(body_partial_json(serde_json::json!({
+                "model": "voyage-3",
+                "input_type": input_type,
+                "input": input
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": data,
+                "usage": { "total_tokens": 10 }
+            })))
+            .expect(1)
+            .mount(server)
+            .await;
+    }
+
+    fn voyage(server: &MockServer) -> VoyageBuilder {
+        Voyage::builder()
+            .api_base(server.uri())
+            .api_key("test".to_string())
+            .to_owned()
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_embeds_sub_batches_in_order() {
+        let server = MockServer::start().await;
+        mock_embeddings(&server, "document", &["first", "second"], &[1.0, 2.0]).await;
+        mock_embeddings(&server, "document", &["third"], &[3.0]).await;
+
+        let voyage = voyage(&server).max_batch_size(2_usize).build().unwrap();
+        let embeddings = voyage
+            .embed(vec!["first".into(), "second".into(), "third".into()])
+            .await
+            .unwrap();
+
+        assert_eq!(embeddings, vec![vec![1.0], vec![2.0], vec![3.0]]);
+        assert_eq!(voyage.total_tokens(), 20);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_embed_queries() {
+        let server = MockServer::start().await;
+        mock_embeddings(&server, "query", &["first", "second"], &[1.0, 2.0]).await;
+
+        let embeddings = voyage(&server)
+            .input_type(InputType::Query)
+            .build()
+            .unwrap()
+            .embed(vec!["first".into(), "second".into()])
+            .await
+            .unwrap();
+
+        assert_eq!(embeddings, vec![vec![1.0], vec![2.0]]);
+    }
+
+    #[test]
+    fn test_sub_batches_respect_token_limit() {
+        let voyage = Voyage::builder()
+            .api_key("test".to_string())
+            .max_batch_tokens(3_usize)
+            .build()
+            .unwrap();
+        let input = ["abcdef", "abc", "abc", "abcdefghijklmno"].map(String::from);
+
+        let batches = voyage.sub_batches(&input);
+
+        assert_eq!(batches, vec![&input[..2], &input[2..3], &input[3..]]);
+    }
+}
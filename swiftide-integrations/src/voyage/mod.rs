@@ -0,0 +1,116 @@
+//! This module provides integration with `Voyage AI`'s embeddings API.
+//! The module is conditionally compiled based on the "voyage" feature flag.
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use derive_builder::Builder;
+use secrecy::Secret;
+use serde::Serialize;
+
+mod embed;
+
+const VOYAGE_API_BASE: &str = "https://api.voyageai.com/v1";
+const DEFAULT_MODEL: &str = "voyage-3";
+const DEFAULT_MAX_BATCH_SIZE: usize = 128;
+const DEFAULT_MAX_BATCH_TOKENS: usize = 120_000;
+
+/// The `Voyage` struct implements [`swiftide_core::EmbeddingModel`] using the `Voyage AI`
+/// embeddings API.
+///
+/// By default it will look for a `VOYAGE_API_KEY` environment variable and use the `voyage-3`
+/// model.
+///
+/// Voyage limits the number of texts and tokens per request. Larger inputs are split into
+/// sub-batches of at most `max_batch_size` texts and an estimated `max_batch_tokens` tokens,
+/// which are embedded concurrently. Tokens are estimated at three characters per token, as there
+/// is no tokenizer available.
+///
+/// The tokens used, as reported by Voyage, are added up and available with
+/// [`Voyage::total_tokens`]. Clones share the count.
+///
+/// # Example
+///
+/// ```no_run
+/// # use swiftide_integrations::voyage::{InputType, Voyage};
+/// let voyage = Voyage::builder()
+///     .input_type(InputType::Query)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Builder, Clone)]
+#[builder(setter(into, strip_option))]
+pub struct Voyage {
+    /// The http client to use
+    #[builder(default)]
+    client: reqwest::Client,
+    /// The base url of the api. Defaults to `https://api.voyageai.com/v1`.
+    #[builder(default = "VOYAGE_API_BASE.to_string()")]
+    api_base: String,
+    /// The api key. Defaults to the `VOYAGE_API_KEY` environment variable.
+    #[builder(default = "default_api_key()")]
+    api_key: Secret<String>,
+    /// The embedding model to use. Defaults to `voyage-3`.
+    #[builder(default = "DEFAULT_MODEL.to_string()")]
+    model: String,
+    /// Whether the input are documents or queries. Defaults to `document`.
+    #[builder(default)]
+    input_type: InputType,
+    /// The maximum number of texts per request. Defaults to 128.
+    #[builder(default = "DEFAULT_MAX_BATCH_SIZE")]
+    max_batch_size: usize,
+    /// The maximum estimated number of tokens per request. Defaults to 120,000.
+    #[builder(default = "DEFAULT_MAX_BATCH_TOKENS")]
+    max_batch_tokens: usize,
+    #[builder(setter(skip), default)]
+    total_tokens: Arc<AtomicU64>,
+}
+
+/// The kind of input that is embedded
+///
+/// Voyage prepends a prompt for the input type, which improves retrieval. Indexing should use
+/// `Document`, while a query pipeline should use a client with `Query`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputType {
+    /// Embedding documents for retrieval
+    #[default]
+    Document,
+    /// Embedding queries for retrieval
+    Query,
+}
+
+impl Default for Voyage {
+    fn default() -> Self {
+        Self {
+            client: reqwest::Client::default(),
+            api_base: VOYAGE_API_BASE.to_string(),
+            api_key: default_api_key(),
+            model: DEFAULT_MODEL.to_string(),
+            input_type: InputType::default(),
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+            max_batch_tokens: DEFAULT_MAX_BATCH_TOKENS,
+            total_tokens: Arc::default(),
+        }
+    }
+}
+
+impl Voyage {
+    /// Creates a new `VoyageBuilder` for constructing `Voyage` instances.
+    pub fn builder() -> VoyageBuilder {
+        VoyageBuilder::default()
+    }
+
+    /// The total number of tokens used by all requests so far, as reported by Voyage
+    pub fn total_tokens(&self) -> u64 {
+        self.total_tokens.load(Ordering::Relaxed)
+    }
+}
+
+fn default_api_key() -> Secret<String> {
+    std::env::var("VOYAGE_API_KEY")
+        .unwrap_or_else(|_| String::new())
+        .into()
+}
@@ -50,7 +50,8 @@
 //!                  .build()?,
 //!          )
 //!          .run()
-//!          .await
+//!          .await?;
+//! # Ok(())
 //! # }
 //! ```
 //!
@@ -124,11 +125,27 @@ pub mod indexing {
     #[doc(inline)]
     pub use swiftide_indexing::*;
 
+    pub mod loaders {
+        #[cfg(feature = "sqlx")]
+        #[doc(inline)]
+        pub use swiftide_integrations::sqlx::SqlLoader;
+
+        pub use swiftide_indexing::loaders::*;
+    }
+
     pub mod transformers {
         #[cfg(feature = "tree-sitter")]
         #[doc(inline)]
         pub use swiftide_integrations::treesitter::transformers::*;
 
+        #[cfg(feature = "http")]
+        #[doc(inline)]
+        pub use swiftide_integrations::http::HttpEnrich;
+
+        #[cfg(feature = "scraping")]
+        #[doc(inline)]
+        pub use swiftide_integrations::scraping::HtmlToMarkdownTransformer;
+
         pub use swiftide_indexing::transformers::*;
     }
 }
@@ -59,6 +59,7 @@ async fn test_indexing_pipeline() {
             .then_store_with(
                 integrations::qdrant::Qdrant::try_from_url(&qdrant_url)
                     .unwrap()
+                    .wait(true)
                     .vector_size(1536)
                     .collection_name("swiftide-test".to_string())
                     .build()
@@ -188,6 +189,7 @@ async fn test_named_vectors() {
             .then_store_with(
                 integrations::qdrant::Qdrant::try_from_url(&qdrant_url)
                     .unwrap()
+                    .wait(true)
                     .vector_size(1536)
                     .collection_name("named-vectors-test".to_string())
                     .with_vector(EmbeddedField::Chunk)
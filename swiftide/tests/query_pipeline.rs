@@ -25,6 +25,7 @@ async fn test_query_pipeline() {
 
     let qdrant_client = integrations::qdrant::Qdrant::try_from_url(&qdrant_url)
         .unwrap()
+        .wait(true)
         .vector_size(384)
         .collection_name("swiftide-test".to_string())
         .build()
@@ -81,6 +82,7 @@ async fn test_hybrid_search_qdrant() {
 
     let qdrant_client = integrations::qdrant::Qdrant::try_from_url(&qdrant_url)
         .unwrap()
+        .wait(true)
         .vector_size(384)
         .batch_size(batch_size)
         .with_vector(EmbeddedField::Combined)
@@ -54,6 +54,7 @@ async fn test_sparse_indexing_pipeline() {
             .then_store_with(
                 integrations::qdrant::Qdrant::try_from_url(&qdrant_url)
                     .unwrap()
+                    .wait(true)
                     .vector_size(384)
                     .with_vector(EmbeddedField::Combined)
                     .with_sparse_vector(EmbeddedField::Combined)